@@ -165,6 +165,15 @@ function __reef_execute
         return
     end
 
+    # Unfinished multi-line bash (open `do`/`then`/`{`, quote, heredoc,
+    # trailing `\` or `&&`): insert a newline and keep editing, like bash's
+    # PS2. Complete fish blocks (`if ...; end`) are left alone.
+    if not commandline --is-valid
+        and reef detect --incomplete -- (commandline | string collect) 2>/dev/null
+        commandline -i \n
+        return
+    end
+
     # Intercept `source <file>` / `. <file>` for bash scripts.
     # Can't use a source.fish function wrapper — it changes variable scope
     # for all sourced files, breaking conf.d variable definitions.
@@ -1,9 +1,167 @@
 //! Abstract syntax tree for bash commands.
 //!
-//! All AST nodes borrow from the input string (`&'a str`) — zero-copy.
+//! Text in AST nodes is a `Cow<'a, str>`, normally borrowed from the input
+//! string. The parser only owns text it had to rewrite: a quoted heredoc
+//! delimiter, or commands that came from an alias expansion. `into_owned()` on
+//! any node detaches it into a `'static` tree that can be cached or sent to
+//! another thread.
 //! The parser produces a `Vec<Cmd<'a>>` representing the top-level command list.
+//!
+//! Command-level nodes carry a [`Span`] — the byte range they were parsed
+//! from — so tooling can point back at the exact construct in the input.
+//! Words, word parts, atoms, parameters and redirections are located through
+//! a [`SpanTable`] instead, which keeps them as light as they are.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+// ---------------------------------------------------------------------------
+// Source spans
+// ---------------------------------------------------------------------------
+
+/// A half-open byte range `start..end` into the parser input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// Byte offset of the first byte of the node.
+    pub start: usize,
+    /// Byte offset just past the last byte of the node.
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span covering `start..end`.
+    #[must_use]
+    pub const fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Length of the span in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// True if the span covers no bytes.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    #[must_use]
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// The text this span covers in `input`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// let input = "echo a; ls -l";
+    /// let cmds = Parser::new(input).parse().unwrap();
+    /// assert_eq!(cmds[1].span().slice(input), "ls -l");
+    /// ```
+    #[must_use]
+    pub fn slice(self, input: &str) -> &str {
+        &input[self.start..self.end]
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+/// Spans of the nodes without a `span` field: [`Word`]s, [`WordPart`]s,
+/// [`Atom`]s, [`Param`]s and [`Redir`]s.
+///
+/// [`Parser::parse_with_spans`](crate::parser::Parser::parse_with_spans)
+/// returns one alongside the tree. Nodes are looked up by address, so the
+/// table answers for that tree for as long as its nodes stay in place; a
+/// clone or an `into_owned()` copy has no entries. A parameter spans its
+/// name (`x` in `${x:-y}`), a bare word part the same text as its atom.
+///
+/// # Examples
+///
+/// ```
+/// use reef::ast::{SpanTable, Word};
+/// use reef::parser::Parser;
+/// use reef::visit::Visit;
+///
+/// struct Words<'s>(&'s SpanTable, &'s str, Vec<&'s str>);
+///
+/// impl<'a> Visit<'a> for Words<'_> {
+///     fn visit_word(&mut self, word: &Word<'a>) {
+///         let span = self.0.word(word).unwrap();
+///         self.2.push(span.slice(self.1));
+///     }
+/// }
+///
+/// let input = "echo \"$HOME\"/bin >out";
+/// let (cmds, spans) = Parser::new(input).parse_with_spans().unwrap();
+/// let mut words = Words(&spans, input, Vec::new());
+/// words.visit_cmds(&cmds);
+/// assert_eq!(words.2, ["echo", "\"$HOME\"/bin", "out"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SpanTable {
+    spans: HashMap<(SpannedNode, usize), Span>,
+}
+
+/// The node types a [`SpanTable`] holds spans for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum SpannedNode {
+    Word,
+    WordPart,
+    Atom,
+    Param,
+    Redir,
+}
+
+impl SpanTable {
+    pub(crate) fn insert<T>(&mut self, kind: SpannedNode, node: &T, span: Span) {
+        self.spans.insert((kind, std::ptr::from_ref(node).addr()), span);
+    }
+
+    fn get<T>(&self, kind: SpannedNode, node: &T) -> Option<Span> {
+        self.spans.get(&(kind, std::ptr::from_ref(node).addr())).copied()
+    }
+
+    /// Source span of `word`.
+    #[must_use]
+    pub fn word(&self, word: &Word<'_>) -> Option<Span> {
+        self.get(SpannedNode::Word, word)
+    }
+
+    /// Source span of `part`, quotes included.
+    #[must_use]
+    pub fn word_part(&self, part: &WordPart<'_>) -> Option<Span> {
+        self.get(SpannedNode::WordPart, part)
+    }
+
+    /// Source span of `atom`, its `$`, backslash or brackets included.
+    #[must_use]
+    pub fn atom(&self, atom: &Atom<'_>) -> Option<Span> {
+        self.get(SpannedNode::Atom, atom)
+    }
+
+    /// Source span of the name of `param`.
+    #[must_use]
+    pub fn param(&self, param: &Param<'_>) -> Option<Span> {
+        self.get(SpannedNode::Param, param)
+    }
+
+    /// Source span of `redir`, from its fd number or operator to the end of
+    /// its target. A heredoc's body is not included.
+    #[must_use]
+    pub fn redir(&self, redir: &Redir<'_>) -> Option<Span> {
+        self.get(SpannedNode::Redir, redir)
+    }
+}
 
 // ---------------------------------------------------------------------------
 // Commands
@@ -19,6 +177,16 @@ pub enum Cmd<'a> {
     Job(AndOrList<'a>),
 }
 
+impl Cmd<'_> {
+    /// Source span of the command (excluding a trailing `&`).
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            Cmd::List(list) | Cmd::Job(list) => list.span,
+        }
+    }
+}
+
 /// A chain of commands connected by `&&` and `||`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AndOrList<'a> {
@@ -26,6 +194,8 @@ pub struct AndOrList<'a> {
     pub first: Pipeline<'a>,
     /// Subsequent `&&` / `||` pipelines.
     pub rest: Vec<AndOr<'a>>,
+    /// Source span of the whole chain.
+    pub span: Span,
 }
 
 /// A single `&&` or `||` link in an and-or chain.
@@ -38,6 +208,16 @@ pub enum AndOr<'a> {
     Or(Pipeline<'a>),
 }
 
+impl AndOr<'_> {
+    /// Source span of the linked pipeline (excluding the operator).
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            AndOr::And(p) | AndOr::Or(p) => p.span(),
+        }
+    }
+}
+
 /// A pipeline: one or more commands connected by `|`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
@@ -46,6 +226,28 @@ pub enum Pipeline<'a> {
     Single(Executable<'a>),
     /// `[!] cmd1 | cmd2 | ...` — bool is true if negated.
     Pipe(bool, Vec<Executable<'a>>),
+    /// `time [-p] pipeline` — the whole pipeline is timed.
+    Timed {
+        /// `-p`: report in the POSIX `real`/`user`/`sys` format.
+        posix: bool,
+        /// The timed pipeline.
+        pipeline: Box<Pipeline<'a>>,
+    },
+}
+
+impl Pipeline<'_> {
+    /// Source span from the first to the last command of the pipeline.
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            Pipeline::Single(exec) => exec.span(),
+            Pipeline::Pipe(_, cmds) => match (cmds.first(), cmds.last()) {
+                (Some(first), Some(last)) => first.span().to(last.span()),
+                _ => Span::default(),
+            },
+            Pipeline::Timed { pipeline, .. } => pipeline.span(),
+        }
+    }
 }
 
 /// An executable unit: simple command, compound command, or function definition.
@@ -57,7 +259,18 @@ pub enum Executable<'a> {
     /// A compound command (`if`, `for`, `while`, etc.).
     Compound(CompoundCmd<'a>),
     /// A function definition: `name() { body; }`.
-    FuncDef(&'a str, CompoundCmd<'a>),
+    FuncDef(Cow<'a, str>, CompoundCmd<'a>),
+}
+
+impl Executable<'_> {
+    /// Source span of the executable unit.
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            Executable::Simple(cmd) => cmd.span,
+            Executable::Compound(cmd) | Executable::FuncDef(_, cmd) => cmd.span,
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -71,6 +284,8 @@ pub struct SimpleCmd<'a> {
     pub prefix: Vec<CmdPrefix<'a>>,
     /// Arguments and redirections after the command name.
     pub suffix: Vec<CmdSuffix<'a>>,
+    /// Source span from the first prefix item to the last suffix item.
+    pub span: Span,
 }
 
 /// A prefix element: variable assignment or redirection.
@@ -78,11 +293,13 @@ pub struct SimpleCmd<'a> {
 #[non_exhaustive]
 pub enum CmdPrefix<'a> {
     /// `NAME=value` — scalar assignment.
-    Assign(&'a str, Option<Word<'a>>),
+    Assign(Cow<'a, str>, Option<Word<'a>>),
     /// `arr=(word ...)` — array assignment.
-    ArrayAssign(&'a str, Vec<Word<'a>>),
+    ArrayAssign(Cow<'a, str>, Vec<Word<'a>>),
     /// `arr+=(word ...)` — array append.
-    ArrayAppend(&'a str, Vec<Word<'a>>),
+    ArrayAppend(Cow<'a, str>, Vec<Word<'a>>),
+    /// `arr[index]=value` — single element assignment.
+    ElementAssign(Cow<'a, str>, Word<'a>, Option<Word<'a>>),
     /// An I/O redirection.
     Redirect(Redir<'a>),
 }
@@ -93,6 +310,8 @@ pub enum CmdPrefix<'a> {
 pub enum CmdSuffix<'a> {
     /// A regular argument word.
     Word(Word<'a>),
+    /// `arr=(word ...)` argument of `declare`, `local` and friends.
+    ArrayAssign(Cow<'a, str>, Vec<Word<'a>>),
     /// An I/O redirection.
     Redirect(Redir<'a>),
 }
@@ -108,6 +327,9 @@ pub struct CompoundCmd<'a> {
     pub kind: CompoundKind<'a>,
     /// Redirections applied to the entire compound command.
     pub redirects: Vec<Redir<'a>>,
+    /// Source span including trailing redirections. For a function
+    /// definition this covers the whole definition, name included.
+    pub span: Span,
 }
 
 /// The body of a compound command.
@@ -117,12 +339,21 @@ pub enum CompoundKind<'a> {
     /// `for var [in words]; do body; done`
     For {
         /// Loop variable name.
-        var: &'a str,
+        var: Cow<'a, str>,
         /// Word list (None = `"$@"`).
         words: Option<Vec<Word<'a>>>,
         /// Loop body commands.
         body: Vec<Cmd<'a>>,
     },
+    /// `select var [in words]; do body; done` — interactive menu loop.
+    Select {
+        /// Variable set to the chosen word.
+        var: Cow<'a, str>,
+        /// Menu entries (None = `"$@"`).
+        words: Option<Vec<Word<'a>>>,
+        /// Loop body commands.
+        body: Vec<Cmd<'a>>,
+    },
     /// `while guard; do body; done`
     While(GuardBody<'a>),
     /// `until guard; do body; done`
@@ -152,12 +383,21 @@ pub enum CompoundKind<'a> {
         /// Loop body commands.
         body: Vec<Cmd<'a>>,
     },
+    /// `coproc [NAME] command` — run a command as a coprocess with
+    /// bidirectional pipes in `NAME[0]` / `NAME[1]`.
+    Coproc {
+        /// Coprocess name (None = `COPROC`). Only allowed before a compound
+        /// command.
+        name: Option<Cow<'a, str>>,
+        /// The command run as the coprocess.
+        body: Box<Executable<'a>>,
+    },
     /// `{ body; }` — brace group.
     Brace(Vec<Cmd<'a>>),
     /// `( body )` — subshell.
     Subshell(Vec<Cmd<'a>>),
     /// `[[ expression ]]` — extended test command.
-    DoubleBracket(Vec<Cmd<'a>>),
+    DoubleBracket(CondExpr<'a>),
     /// `(( expression ))` — arithmetic command.
     Arithmetic(Arith<'a>),
 }
@@ -169,6 +409,8 @@ pub struct GuardBody<'a> {
     pub guard: Vec<Cmd<'a>>,
     /// The body commands.
     pub body: Vec<Cmd<'a>>,
+    /// Source span from the start of the guard to the end of the body.
+    pub span: Span,
 }
 
 /// A single arm in a `case` statement.
@@ -178,6 +420,24 @@ pub struct CaseArm<'a> {
     pub patterns: Vec<Word<'a>>,
     /// Commands to execute if a pattern matches.
     pub body: Vec<Cmd<'a>>,
+    /// How the arm ends (`;;`, `;&` or `;;&`).
+    pub terminator: CaseTerminator,
+    /// Source span from the first pattern through the arm terminator.
+    pub span: Span,
+}
+
+/// The operator that ends a `case` arm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CaseTerminator {
+    /// `;;` — stop after this arm. Also used when the last arm has no
+    /// terminator before `esac`.
+    #[default]
+    Break,
+    /// `;&` — run the next arm's body without testing its patterns.
+    FallThrough,
+    /// `;;&` — keep testing the patterns of the following arms.
+    Continue,
 }
 
 // ---------------------------------------------------------------------------
@@ -203,7 +463,7 @@ pub enum WordPart<'a> {
     /// Double-quoted content (may contain expansions).
     DQuoted(Vec<Atom<'a>>),
     /// Single-quoted content (literal text, no expansions).
-    SQuoted(&'a str),
+    SQuoted(Cow<'a, str>),
 }
 
 /// An atomic element within a word: literal text, expansion, or glob.
@@ -211,7 +471,7 @@ pub enum WordPart<'a> {
 #[non_exhaustive]
 pub enum Atom<'a> {
     /// Literal text.
-    Lit(&'a str),
+    Lit(Cow<'a, str>),
     /// Backslash-escaped character.
     Escaped(Cow<'a, str>),
     /// Parameter reference (`$var`, `$1`, `$@`, etc.).
@@ -230,29 +490,57 @@ pub enum Atom<'a> {
     Tilde,
     /// `<(cmd)` — process substitution (input).
     ProcSubIn(Vec<Cmd<'a>>),
+    /// `>(cmd)` — process substitution (output).
+    ProcSubOut(Vec<Cmd<'a>>),
     /// ANSI-C `$'...'` — raw content between the quotes (escape sequences unresolved).
-    AnsiCQuoted(&'a str),
+    AnsiCQuoted(Cow<'a, str>),
+    /// Extended glob: `?(…)`, `*(…)`, `+(…)`, `@(…)` or `!(…)`.
+    ExtGlob {
+        /// Which operator precedes the parenthesized list.
+        kind: ExtGlobKind,
+        /// `|`-separated patterns; an empty alternative is an empty `Concat`.
+        alternatives: Vec<Word<'a>>,
+    },
+    /// Brace list expansion: `{a,b,c}`. Alternatives may themselves contain
+    /// nested lists or ranges; an empty alternative is an empty `Concat`.
+    BraceList(Vec<Word<'a>>),
     /// Brace range expansion: `{start..end[..step]}`.
     BraceRange {
         /// Range start value.
-        start: &'a str,
+        start: Cow<'a, str>,
         /// Range end value.
-        end: &'a str,
+        end: Cow<'a, str>,
         /// Optional step value.
-        step: Option<&'a str>,
+        step: Option<Cow<'a, str>>,
     },
 }
 
+/// Operator of an extended glob pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExtGlobKind {
+    /// `?(…)` — zero or one occurrence.
+    ZeroOrOne,
+    /// `*(…)` — zero or more occurrences.
+    ZeroOrMore,
+    /// `+(…)` — one or more occurrences.
+    OneOrMore,
+    /// `@(…)` — exactly one of the patterns.
+    ExactlyOne,
+    /// `!(…)` — anything except the patterns.
+    Not,
+}
+
 // ---------------------------------------------------------------------------
 // Parameters
 // ---------------------------------------------------------------------------
 
 /// A shell parameter reference.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum Param<'a> {
     /// Named variable (`$var`).
-    Var(&'a str),
+    Var(Cow<'a, str>),
     /// Positional parameter (`$1`, `$2`, ...).
     Positional(u32),
     /// `$@` — all positional parameters (separate words).
@@ -286,11 +574,11 @@ pub enum Subst<'a> {
     /// String length: `${#var}`.
     Len(Param<'a>),
     /// `${!var}` — indirect variable expansion.
-    Indirect(&'a str),
+    Indirect(Cow<'a, str>),
     /// `${!prefix*}` / `${!prefix@}` — list variables matching prefix.
-    PrefixList(&'a str),
+    PrefixList(Cow<'a, str>),
     /// `${var@Q}` — parameter transformation (quoting).
-    Transform(&'a str, u8),
+    Transform(Cow<'a, str>, u8),
     /// `${var:-word}` / `${var-word}` — default value.
     Default(Param<'a>, Option<Word<'a>>),
     /// `${var:=word}` / `${var=word}` — assign default.
@@ -316,19 +604,23 @@ pub enum Subst<'a> {
     /// `${var/%pattern/replacement}` — replace suffix match.
     ReplaceSuffix(Param<'a>, Option<Word<'a>>, Option<Word<'a>>),
     /// `${var:offset:length}` — substring extraction.
-    Substring(Param<'a>, &'a str, Option<&'a str>),
-    /// `${var^}` / `${var^^}` — uppercase (bool: all if true).
-    Upper(bool, Param<'a>),
-    /// `${var,}` / `${var,,}` — lowercase (bool: all if true).
-    Lower(bool, Param<'a>),
+    Substring(Param<'a>, Cow<'a, str>, Option<Cow<'a, str>>),
+    /// `${var^}` / `${var^^}` — uppercase (bool: all if true). With a
+    /// pattern (`${var^^[aeiou]}`) only characters matching it change.
+    Upper(bool, Param<'a>, Option<Word<'a>>),
+    /// `${var,}` / `${var,,}` — lowercase (bool: all if true), optionally
+    /// limited to characters matching a pattern.
+    Lower(bool, Param<'a>, Option<Word<'a>>),
     /// `${arr[index]}` — array element access (index is a Word for $((expr)) support).
-    ArrayElement(&'a str, Word<'a>),
+    ArrayElement(Cow<'a, str>, Word<'a>),
     /// `${arr[@]}` or `${arr[*]}` — all array elements.
-    ArrayAll(&'a str),
+    ArrayAll(Cow<'a, str>),
+    /// `${!arr[@]}` or `${!arr[*]}` — array indices or associative keys.
+    ArrayKeys(Cow<'a, str>),
     /// `${#arr[@]}` — array length.
-    ArrayLen(&'a str),
+    ArrayLen(Cow<'a, str>),
     /// `${arr[@]:offset:length}` — array slice.
-    ArraySlice(&'a str, &'a str, Option<&'a str>),
+    ArraySlice(Cow<'a, str>, Cow<'a, str>, Option<Cow<'a, str>>),
 }
 
 // ---------------------------------------------------------------------------
@@ -340,8 +632,10 @@ pub enum Subst<'a> {
 #[non_exhaustive]
 pub enum Arith<'a> {
     /// Variable reference.
-    Var(&'a str),
-    /// Integer literal.
+    Var(Cow<'a, str>),
+    /// Array element (`arr[i + 1]`).
+    Elem(Cow<'a, str>, Box<Arith<'a>>),
+    /// Integer literal; `16#ff`, `0x1f` and `017` are stored as their value.
     Lit(i64),
 
     /// Addition.
@@ -395,30 +689,139 @@ pub enum Arith<'a> {
     BitNot(Box<Arith<'a>>),
 
     /// Pre-increment (`++var`).
-    PreInc(&'a str),
+    PreInc(Cow<'a, str>),
     /// Post-increment (`var++`).
-    PostInc(&'a str),
+    PostInc(Cow<'a, str>),
     /// Pre-decrement (`--var`).
-    PreDec(&'a str),
+    PreDec(Cow<'a, str>),
     /// Post-decrement (`var--`).
-    PostDec(&'a str),
+    PostDec(Cow<'a, str>),
+    /// Array element pre-increment (`++arr[i]`).
+    ElemPreInc(Cow<'a, str>, Box<Arith<'a>>),
+    /// Array element post-increment (`arr[i]++`).
+    ElemPostInc(Cow<'a, str>, Box<Arith<'a>>),
+    /// Array element pre-decrement (`--arr[i]`).
+    ElemPreDec(Cow<'a, str>, Box<Arith<'a>>),
+    /// Array element post-decrement (`arr[i]--`).
+    ElemPostDec(Cow<'a, str>, Box<Arith<'a>>),
 
     /// Ternary operator (`cond ? then : else`).
     Ternary(Box<Arith<'a>>, Box<Arith<'a>>, Box<Arith<'a>>),
-    /// Assignment (`var = expr`).
-    Assign(&'a str, Box<Arith<'a>>),
+    /// Assignment (`var = expr`). Compound forms like `var <<= n` are
+    /// stored as `var = var << n`.
+    Assign(Cow<'a, str>, Box<Arith<'a>>),
+    /// Array element assignment (`arr[i] = expr`), with compound forms
+    /// stored like [`Arith::Assign`]: `arr[i] += n` is `arr[i] = arr[i] + n`.
+    ElemAssign(Cow<'a, str>, Box<Arith<'a>>, Box<Arith<'a>>),
+    /// Comma operator (`a, b`): evaluates both, yields `b`.
+    Comma(Box<Arith<'a>>, Box<Arith<'a>>),
+}
+
+// ---------------------------------------------------------------------------
+// Conditional expressions
+// ---------------------------------------------------------------------------
+
+/// A conditional expression inside `[[ ]]`. Operands are single words —
+/// `[[ ]]` neither splits nor globs them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CondExpr<'a> {
+    /// `-X word` — file, string, variable or option test; holds the letter
+    /// (`'f'` for `-f`, `'z'` for `-z`, ...).
+    Unary(char, Word<'a>),
+    /// `word op word` — string, pattern, regex, integer or file comparison.
+    Binary(Word<'a>, CondOp, Word<'a>),
+    /// A lone word — true if non-empty.
+    Word(Word<'a>),
+    /// `! expr`.
+    Not(Box<CondExpr<'a>>),
+    /// `expr && expr`.
+    And(Box<CondExpr<'a>>, Box<CondExpr<'a>>),
+    /// `expr || expr`.
+    Or(Box<CondExpr<'a>>, Box<CondExpr<'a>>),
+    /// `( expr )`.
+    Group(Box<CondExpr<'a>>),
+}
+
+/// Binary operator of a [`CondExpr`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CondOp {
+    /// `==` or `=` — the right side is a glob pattern.
+    Match,
+    /// `!=` — negated glob match.
+    NotMatch,
+    /// `=~` — the right side is an extended regex.
+    Regex,
+    /// `<` — sorts before.
+    StrLt,
+    /// `>` — sorts after.
+    StrGt,
+    /// `-eq`.
+    IntEq,
+    /// `-ne`.
+    IntNe,
+    /// `-lt`.
+    IntLt,
+    /// `-le`.
+    IntLe,
+    /// `-gt`.
+    IntGt,
+    /// `-ge`.
+    IntGe,
+    /// `-nt` — file is newer.
+    NewerThan,
+    /// `-ot` — file is older.
+    OlderThan,
+    /// `-ef` — same device and inode.
+    SameFile,
+}
+
+impl CondOp {
+    /// The operator as written in bash (`==` for [`CondOp::Match`]).
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            CondOp::Match => "==",
+            CondOp::NotMatch => "!=",
+            CondOp::Regex => "=~",
+            CondOp::StrLt => "<",
+            CondOp::StrGt => ">",
+            CondOp::IntEq => "-eq",
+            CondOp::IntNe => "-ne",
+            CondOp::IntLt => "-lt",
+            CondOp::IntLe => "-le",
+            CondOp::IntGt => "-gt",
+            CondOp::IntGe => "-ge",
+            CondOp::NewerThan => "-nt",
+            CondOp::OlderThan => "-ot",
+            CondOp::SameFile => "-ef",
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
-// Heredoc body
+// Heredoc
 // ---------------------------------------------------------------------------
 
+/// A here-document: the `<<[-]DELIM` operator and the body lines after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heredoc<'a> {
+    /// Delimiter word with quoting removed (`'EOF'` and `\EOF` are both `EOF`).
+    pub delimiter: Cow<'a, str>,
+    /// `<<-` — leading tabs are stripped from the body lines and the
+    /// delimiter line. The body is stored as written, tabs included.
+    pub strip_tabs: bool,
+    /// The lines between the operator's line and the delimiter.
+    pub body: HeredocBody<'a>,
+}
+
 /// The body of a heredoc (here-document).
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum HeredocBody<'a> {
     /// Quoted delimiter — no expansion (literal text).
-    Literal(&'a str),
+    Literal(Cow<'a, str>),
     /// Unquoted delimiter — variable and command expansion.
     Interpolated(Vec<Atom<'a>>),
 }
@@ -447,10 +850,611 @@ pub enum Redir<'a> {
     DupWrite(Option<u16>, Word<'a>),
     /// `<<< word` — here-string.
     HereString(Word<'a>),
-    /// `<< [-]DELIM ... DELIM` — here-document.
-    Heredoc(HeredocBody<'a>),
+    /// `[n]<< [-]DELIM ... DELIM` — here-document.
+    Heredoc(Option<u16>, Heredoc<'a>),
     /// `&> word` — redirect both stdout and stderr.
     WriteAll(Word<'a>),
     /// `&>> word` — append both stdout and stderr.
     AppendAll(Word<'a>),
 }
+
+// ---------------------------------------------------------------------------
+// Owned conversion
+// ---------------------------------------------------------------------------
+
+fn owned(s: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+fn owned_opt(s: Option<Cow<'_, str>>) -> Option<Cow<'static, str>> {
+    s.map(owned)
+}
+
+fn owned_cmds(cmds: Vec<Cmd<'_>>) -> Vec<Cmd<'static>> {
+    cmds.into_iter().map(Cmd::into_owned).collect()
+}
+
+fn owned_words(words: Vec<Word<'_>>) -> Vec<Word<'static>> {
+    words.into_iter().map(Word::into_owned).collect()
+}
+
+fn owned_word(word: Option<Word<'_>>) -> Option<Word<'static>> {
+    word.map(Word::into_owned)
+}
+
+fn owned_atoms(atoms: Vec<Atom<'_>>) -> Vec<Atom<'static>> {
+    atoms.into_iter().map(Atom::into_owned).collect()
+}
+
+#[allow(clippy::boxed_local, clippy::unnecessary_box_returns)] // maps the AST's boxed operands
+fn owned_arith(arith: Box<Arith<'_>>) -> Box<Arith<'static>> {
+    Box::new(arith.into_owned())
+}
+
+#[allow(clippy::boxed_local, clippy::unnecessary_box_returns)] // maps the AST's boxed operands
+fn owned_cond(expr: Box<CondExpr<'_>>) -> Box<CondExpr<'static>> {
+    Box::new(expr.into_owned())
+}
+
+impl Cmd<'_> {
+    /// Copy every borrowed string so the command no longer depends on the
+    /// parser input, e.g. to cache it or send it to another thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::ast::Cmd;
+    /// use reef::parser::Parser;
+    ///
+    /// let owned: Vec<Cmd<'static>> = {
+    ///     let input = String::from("echo \"$HOME\" | wc -c");
+    ///     let cmds = Parser::new(&input).parse().unwrap();
+    ///     cmds.into_iter().map(Cmd::into_owned).collect()
+    /// };
+    /// let handle = std::thread::spawn(move || owned.len());
+    /// assert_eq!(handle.join().unwrap(), 1);
+    /// ```
+    #[must_use]
+    pub fn into_owned(self) -> Cmd<'static> {
+        match self {
+            Cmd::List(list) => Cmd::List(list.into_owned()),
+            Cmd::Job(list) => Cmd::Job(list.into_owned()),
+        }
+    }
+}
+
+impl AndOrList<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> AndOrList<'static> {
+        AndOrList {
+            first: self.first.into_owned(),
+            rest: self.rest.into_iter().map(AndOr::into_owned).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl AndOr<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> AndOr<'static> {
+        match self {
+            AndOr::And(p) => AndOr::And(p.into_owned()),
+            AndOr::Or(p) => AndOr::Or(p.into_owned()),
+        }
+    }
+}
+
+impl Pipeline<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Pipeline<'static> {
+        match self {
+            Pipeline::Single(exec) => Pipeline::Single(exec.into_owned()),
+            Pipeline::Pipe(negated, execs) => {
+                Pipeline::Pipe(negated, execs.into_iter().map(Executable::into_owned).collect())
+            }
+            Pipeline::Timed { posix, pipeline } => Pipeline::Timed {
+                posix,
+                pipeline: Box::new(pipeline.into_owned()),
+            },
+        }
+    }
+}
+
+impl Executable<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Executable<'static> {
+        match self {
+            Executable::Simple(cmd) => Executable::Simple(cmd.into_owned()),
+            Executable::Compound(cmd) => Executable::Compound(cmd.into_owned()),
+            Executable::FuncDef(name, body) => Executable::FuncDef(owned(name), body.into_owned()),
+        }
+    }
+}
+
+impl SimpleCmd<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> SimpleCmd<'static> {
+        SimpleCmd {
+            prefix: self.prefix.into_iter().map(CmdPrefix::into_owned).collect(),
+            suffix: self.suffix.into_iter().map(CmdSuffix::into_owned).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl CmdPrefix<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> CmdPrefix<'static> {
+        match self {
+            CmdPrefix::Assign(name, value) => CmdPrefix::Assign(owned(name), owned_word(value)),
+            CmdPrefix::ArrayAssign(name, words) => {
+                CmdPrefix::ArrayAssign(owned(name), owned_words(words))
+            }
+            CmdPrefix::ArrayAppend(name, words) => {
+                CmdPrefix::ArrayAppend(owned(name), owned_words(words))
+            }
+            CmdPrefix::ElementAssign(name, index, value) => {
+                CmdPrefix::ElementAssign(owned(name), index.into_owned(), owned_word(value))
+            }
+            CmdPrefix::Redirect(r) => CmdPrefix::Redirect(r.into_owned()),
+        }
+    }
+}
+
+impl CmdSuffix<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> CmdSuffix<'static> {
+        match self {
+            CmdSuffix::Word(w) => CmdSuffix::Word(w.into_owned()),
+            CmdSuffix::ArrayAssign(name, words) => {
+                CmdSuffix::ArrayAssign(owned(name), owned_words(words))
+            }
+            CmdSuffix::Redirect(r) => CmdSuffix::Redirect(r.into_owned()),
+        }
+    }
+}
+
+impl CompoundCmd<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> CompoundCmd<'static> {
+        CompoundCmd {
+            kind: self.kind.into_owned(),
+            redirects: self.redirects.into_iter().map(Redir::into_owned).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl CompoundKind<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> CompoundKind<'static> {
+        match self {
+            CompoundKind::For { var, words, body } => CompoundKind::For {
+                var: owned(var),
+                words: words.map(owned_words),
+                body: owned_cmds(body),
+            },
+            CompoundKind::Select { var, words, body } => CompoundKind::Select {
+                var: owned(var),
+                words: words.map(owned_words),
+                body: owned_cmds(body),
+            },
+            CompoundKind::While(gb) => CompoundKind::While(gb.into_owned()),
+            CompoundKind::Until(gb) => CompoundKind::Until(gb.into_owned()),
+            CompoundKind::If { conditionals, else_branch } => CompoundKind::If {
+                conditionals: conditionals.into_iter().map(GuardBody::into_owned).collect(),
+                else_branch: else_branch.map(owned_cmds),
+            },
+            CompoundKind::Case { word, arms } => CompoundKind::Case {
+                word: word.into_owned(),
+                arms: arms.into_iter().map(CaseArm::into_owned).collect(),
+            },
+            CompoundKind::CFor { init, cond, step, body } => CompoundKind::CFor {
+                init: init.map(Arith::into_owned),
+                cond: cond.map(Arith::into_owned),
+                step: step.map(Arith::into_owned),
+                body: owned_cmds(body),
+            },
+            CompoundKind::Coproc { name, body } => CompoundKind::Coproc {
+                name: owned_opt(name),
+                body: Box::new(body.into_owned()),
+            },
+            CompoundKind::Brace(body) => CompoundKind::Brace(owned_cmds(body)),
+            CompoundKind::Subshell(body) => CompoundKind::Subshell(owned_cmds(body)),
+            CompoundKind::DoubleBracket(expr) => CompoundKind::DoubleBracket(expr.into_owned()),
+            CompoundKind::Arithmetic(a) => CompoundKind::Arithmetic(a.into_owned()),
+        }
+    }
+}
+
+impl GuardBody<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> GuardBody<'static> {
+        GuardBody {
+            guard: owned_cmds(self.guard),
+            body: owned_cmds(self.body),
+            span: self.span,
+        }
+    }
+}
+
+impl CaseArm<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> CaseArm<'static> {
+        CaseArm {
+            patterns: owned_words(self.patterns),
+            body: owned_cmds(self.body),
+            terminator: self.terminator,
+            span: self.span,
+        }
+    }
+}
+
+impl Word<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Word<'static> {
+        match self {
+            Word::Simple(part) => Word::Simple(part.into_owned()),
+            Word::Concat(parts) => {
+                Word::Concat(parts.into_iter().map(WordPart::into_owned).collect())
+            }
+        }
+    }
+}
+
+impl WordPart<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> WordPart<'static> {
+        match self {
+            WordPart::Bare(atom) => WordPart::Bare(atom.into_owned()),
+            WordPart::DQuoted(atoms) => WordPart::DQuoted(owned_atoms(atoms)),
+            WordPart::SQuoted(s) => WordPart::SQuoted(owned(s)),
+        }
+    }
+}
+
+impl Atom<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Atom<'static> {
+        match self {
+            Atom::Lit(s) => Atom::Lit(owned(s)),
+            Atom::Escaped(s) => Atom::Escaped(owned(s)),
+            Atom::Param(p) => Atom::Param(p.into_owned()),
+            Atom::Subst(s) => Atom::Subst(Box::new(s.into_owned())),
+            Atom::Star => Atom::Star,
+            Atom::Question => Atom::Question,
+            Atom::SquareOpen => Atom::SquareOpen,
+            Atom::SquareClose => Atom::SquareClose,
+            Atom::Tilde => Atom::Tilde,
+            Atom::ProcSubIn(cmds) => Atom::ProcSubIn(owned_cmds(cmds)),
+            Atom::ProcSubOut(cmds) => Atom::ProcSubOut(owned_cmds(cmds)),
+            Atom::AnsiCQuoted(s) => Atom::AnsiCQuoted(owned(s)),
+            Atom::ExtGlob { kind, alternatives } => Atom::ExtGlob {
+                kind,
+                alternatives: owned_words(alternatives),
+            },
+            Atom::BraceList(alternatives) => Atom::BraceList(owned_words(alternatives)),
+            Atom::BraceRange { start, end, step } => Atom::BraceRange {
+                start: owned(start),
+                end: owned(end),
+                step: owned_opt(step),
+            },
+        }
+    }
+}
+
+impl Param<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Param<'static> {
+        match self {
+            Param::Var(name) => Param::Var(owned(name)),
+            Param::Positional(n) => Param::Positional(n),
+            Param::At => Param::At,
+            Param::Star => Param::Star,
+            Param::Pound => Param::Pound,
+            Param::Status => Param::Status,
+            Param::Pid => Param::Pid,
+            Param::Bang => Param::Bang,
+            Param::Dash => Param::Dash,
+        }
+    }
+}
+
+impl Subst<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Subst<'static> {
+        match self {
+            Subst::Cmd(cmds) => Subst::Cmd(owned_cmds(cmds)),
+            Subst::Arith(a) => Subst::Arith(a.map(Arith::into_owned)),
+            Subst::Len(p) => Subst::Len(p.into_owned()),
+            Subst::Indirect(name) => Subst::Indirect(owned(name)),
+            Subst::PrefixList(prefix) => Subst::PrefixList(owned(prefix)),
+            Subst::Transform(name, op) => Subst::Transform(owned(name), op),
+            Subst::Default(p, w) => Subst::Default(p.into_owned(), owned_word(w)),
+            Subst::Assign(p, w) => Subst::Assign(p.into_owned(), owned_word(w)),
+            Subst::Error(p, w) => Subst::Error(p.into_owned(), owned_word(w)),
+            Subst::Alt(p, w) => Subst::Alt(p.into_owned(), owned_word(w)),
+            Subst::TrimSuffixSmall(p, w) => Subst::TrimSuffixSmall(p.into_owned(), owned_word(w)),
+            Subst::TrimSuffixLarge(p, w) => Subst::TrimSuffixLarge(p.into_owned(), owned_word(w)),
+            Subst::TrimPrefixSmall(p, w) => Subst::TrimPrefixSmall(p.into_owned(), owned_word(w)),
+            Subst::TrimPrefixLarge(p, w) => Subst::TrimPrefixLarge(p.into_owned(), owned_word(w)),
+            Subst::Replace(p, pat, rep) => {
+                Subst::Replace(p.into_owned(), owned_word(pat), owned_word(rep))
+            }
+            Subst::ReplaceAll(p, pat, rep) => {
+                Subst::ReplaceAll(p.into_owned(), owned_word(pat), owned_word(rep))
+            }
+            Subst::ReplacePrefix(p, pat, rep) => {
+                Subst::ReplacePrefix(p.into_owned(), owned_word(pat), owned_word(rep))
+            }
+            Subst::ReplaceSuffix(p, pat, rep) => {
+                Subst::ReplaceSuffix(p.into_owned(), owned_word(pat), owned_word(rep))
+            }
+            Subst::Substring(p, offset, length) => {
+                Subst::Substring(p.into_owned(), owned(offset), owned_opt(length))
+            }
+            Subst::Upper(all, p, w) => Subst::Upper(all, p.into_owned(), owned_word(w)),
+            Subst::Lower(all, p, w) => Subst::Lower(all, p.into_owned(), owned_word(w)),
+            Subst::ArrayElement(name, index) => {
+                Subst::ArrayElement(owned(name), index.into_owned())
+            }
+            Subst::ArrayAll(name) => Subst::ArrayAll(owned(name)),
+            Subst::ArrayKeys(name) => Subst::ArrayKeys(owned(name)),
+            Subst::ArrayLen(name) => Subst::ArrayLen(owned(name)),
+            Subst::ArraySlice(name, offset, length) => {
+                Subst::ArraySlice(owned(name), owned(offset), owned_opt(length))
+            }
+        }
+    }
+}
+
+impl Arith<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Arith<'static> {
+        match self {
+            Arith::Var(name) => Arith::Var(owned(name)),
+            Arith::Elem(name, idx) => Arith::Elem(owned(name), owned_arith(idx)),
+            Arith::Lit(n) => Arith::Lit(n),
+            Arith::Add(l, r) => Arith::Add(owned_arith(l), owned_arith(r)),
+            Arith::Sub(l, r) => Arith::Sub(owned_arith(l), owned_arith(r)),
+            Arith::Mul(l, r) => Arith::Mul(owned_arith(l), owned_arith(r)),
+            Arith::Div(l, r) => Arith::Div(owned_arith(l), owned_arith(r)),
+            Arith::Rem(l, r) => Arith::Rem(owned_arith(l), owned_arith(r)),
+            Arith::Pow(l, r) => Arith::Pow(owned_arith(l), owned_arith(r)),
+            Arith::Lt(l, r) => Arith::Lt(owned_arith(l), owned_arith(r)),
+            Arith::Le(l, r) => Arith::Le(owned_arith(l), owned_arith(r)),
+            Arith::Gt(l, r) => Arith::Gt(owned_arith(l), owned_arith(r)),
+            Arith::Ge(l, r) => Arith::Ge(owned_arith(l), owned_arith(r)),
+            Arith::Eq(l, r) => Arith::Eq(owned_arith(l), owned_arith(r)),
+            Arith::Ne(l, r) => Arith::Ne(owned_arith(l), owned_arith(r)),
+            Arith::BitAnd(l, r) => Arith::BitAnd(owned_arith(l), owned_arith(r)),
+            Arith::BitOr(l, r) => Arith::BitOr(owned_arith(l), owned_arith(r)),
+            Arith::BitXor(l, r) => Arith::BitXor(owned_arith(l), owned_arith(r)),
+            Arith::LogAnd(l, r) => Arith::LogAnd(owned_arith(l), owned_arith(r)),
+            Arith::LogOr(l, r) => Arith::LogOr(owned_arith(l), owned_arith(r)),
+            Arith::Shl(l, r) => Arith::Shl(owned_arith(l), owned_arith(r)),
+            Arith::Shr(l, r) => Arith::Shr(owned_arith(l), owned_arith(r)),
+            Arith::Pos(e) => Arith::Pos(owned_arith(e)),
+            Arith::Neg(e) => Arith::Neg(owned_arith(e)),
+            Arith::LogNot(e) => Arith::LogNot(owned_arith(e)),
+            Arith::BitNot(e) => Arith::BitNot(owned_arith(e)),
+            Arith::PreInc(name) => Arith::PreInc(owned(name)),
+            Arith::PostInc(name) => Arith::PostInc(owned(name)),
+            Arith::PreDec(name) => Arith::PreDec(owned(name)),
+            Arith::PostDec(name) => Arith::PostDec(owned(name)),
+            Arith::ElemPreInc(name, idx) => Arith::ElemPreInc(owned(name), owned_arith(idx)),
+            Arith::ElemPostInc(name, idx) => Arith::ElemPostInc(owned(name), owned_arith(idx)),
+            Arith::ElemPreDec(name, idx) => Arith::ElemPreDec(owned(name), owned_arith(idx)),
+            Arith::ElemPostDec(name, idx) => Arith::ElemPostDec(owned(name), owned_arith(idx)),
+            Arith::Ternary(c, t, f) => {
+                Arith::Ternary(owned_arith(c), owned_arith(t), owned_arith(f))
+            }
+            Arith::Assign(name, e) => Arith::Assign(owned(name), owned_arith(e)),
+            Arith::ElemAssign(name, idx, e) => {
+                Arith::ElemAssign(owned(name), owned_arith(idx), owned_arith(e))
+            }
+            Arith::Comma(l, r) => Arith::Comma(owned_arith(l), owned_arith(r)),
+        }
+    }
+}
+
+impl CondExpr<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> CondExpr<'static> {
+        match self {
+            CondExpr::Unary(op, w) => CondExpr::Unary(op, w.into_owned()),
+            CondExpr::Binary(l, op, r) => CondExpr::Binary(l.into_owned(), op, r.into_owned()),
+            CondExpr::Word(w) => CondExpr::Word(w.into_owned()),
+            CondExpr::Not(e) => CondExpr::Not(owned_cond(e)),
+            CondExpr::And(l, r) => CondExpr::And(owned_cond(l), owned_cond(r)),
+            CondExpr::Or(l, r) => CondExpr::Or(owned_cond(l), owned_cond(r)),
+            CondExpr::Group(e) => CondExpr::Group(owned_cond(e)),
+        }
+    }
+}
+
+impl Heredoc<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Heredoc<'static> {
+        Heredoc {
+            delimiter: owned(self.delimiter),
+            strip_tabs: self.strip_tabs,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+impl HeredocBody<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> HeredocBody<'static> {
+        match self {
+            HeredocBody::Literal(s) => HeredocBody::Literal(owned(s)),
+            HeredocBody::Interpolated(atoms) => HeredocBody::Interpolated(owned_atoms(atoms)),
+        }
+    }
+}
+
+impl Redir<'_> {
+    /// Copy every borrowed string; see [`Cmd::into_owned`].
+    #[must_use]
+    pub fn into_owned(self) -> Redir<'static> {
+        match self {
+            Redir::Read(fd, w) => Redir::Read(fd, w.into_owned()),
+            Redir::Write(fd, w) => Redir::Write(fd, w.into_owned()),
+            Redir::Append(fd, w) => Redir::Append(fd, w.into_owned()),
+            Redir::ReadWrite(fd, w) => Redir::ReadWrite(fd, w.into_owned()),
+            Redir::Clobber(fd, w) => Redir::Clobber(fd, w.into_owned()),
+            Redir::DupRead(fd, w) => Redir::DupRead(fd, w.into_owned()),
+            Redir::DupWrite(fd, w) => Redir::DupWrite(fd, w.into_owned()),
+            Redir::HereString(w) => Redir::HereString(w.into_owned()),
+            Redir::Heredoc(fd, doc) => Redir::Heredoc(fd, doc.into_owned()),
+            Redir::WriteAll(w) => Redir::WriteAll(w.into_owned()),
+            Redir::AppendAll(w) => Redir::AppendAll(w.into_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::printer::Printer;
+
+    #[test]
+    fn into_owned_preserves_tree() {
+        let input = String::from(concat!(
+            "f() { for x in \"$@\"; do case $x in a|b) echo ${x%.*} ${arr[@]:1:2};; esac; done; }\n",
+            "coproc w { cat; } 2>&1; (( i += 2 ** n )); [[ -n $a ]] && echo $'\\t' {a,b} {1..3}\n",
+            "cat <<EOF > out\nhi $USER $(date)\nEOF\n",
+        ));
+        let cmds = Parser::new(&input).parse().unwrap();
+        let owned: Vec<Cmd<'static>> = cmds.clone().into_iter().map(Cmd::into_owned).collect();
+        assert_eq!(owned, cmds);
+        let printed = Printer::new().print(&cmds);
+        drop(cmds);
+        drop(input);
+        assert_eq!(Printer::new().print(&owned), printed);
+    }
+
+    /// The only command of `cmds`, which must be a plain one.
+    fn exec<'x, 'a>(cmds: &'x [Cmd<'a>]) -> &'x Executable<'a> {
+        let [Cmd::List(AndOrList { first: Pipeline::Single(exec), .. })] = cmds else {
+            panic!("expected one command: {cmds:?}");
+        };
+        exec
+    }
+
+    /// `parsed` points into the input and `owned` is a copy of the same text.
+    #[allow(clippy::ptr_arg)] // the Cow variant is what's under test
+    fn assert_detached(parsed: &Cow<'_, str>, owned: &Cow<'_, str>) {
+        assert!(matches!(parsed, Cow::Borrowed(_)), "not borrowed: {parsed:?}");
+        assert!(matches!(owned, Cow::Owned(_)), "not owned: {owned:?}");
+        assert_eq!(parsed, owned);
+    }
+
+    fn word_texts<'x>(cmds: &'x [Cmd<'_>]) -> (&'x Cow<'x, str>, &'x Cow<'x, str>) {
+        let Executable::Simple(cmd) = exec(cmds) else { panic!("{cmds:?}") };
+        let Some(CmdSuffix::Word(Word::Concat(parts))) = cmd.suffix.get(1) else {
+            panic!("{cmds:?}")
+        };
+        let [WordPart::Bare(Atom::Lit(lit)), WordPart::Bare(Atom::Param(Param::Var(var)))] =
+            parts.as_slice()
+        else {
+            panic!("{parts:?}")
+        };
+        (lit, var)
+    }
+
+    #[test]
+    fn into_owned_copies_words() {
+        let cmds = Parser::new("echo foo$x").parse().unwrap();
+        let owned = cmds.clone().into_iter().map(Cmd::into_owned).collect::<Vec<_>>();
+        let (lit, var) = word_texts(&cmds);
+        let (owned_lit, owned_var) = word_texts(&owned);
+        assert_detached(lit, owned_lit);
+        assert_detached(var, owned_var);
+    }
+
+    fn arith_names<'x>(cmds: &'x [Cmd<'_>]) -> (&'x Cow<'x, str>, &'x Cow<'x, str>) {
+        let Executable::Compound(CompoundCmd { kind: CompoundKind::Arithmetic(expr), .. }) =
+            exec(cmds)
+        else {
+            panic!("{cmds:?}")
+        };
+        let Arith::Assign(target, value) = expr else { panic!("{expr:?}") };
+        let Arith::Add(_, right) = value.as_ref() else { panic!("{value:?}") };
+        let Arith::Var(operand) = right.as_ref() else { panic!("{right:?}") };
+        (target, operand)
+    }
+
+    #[test]
+    fn into_owned_copies_arithmetic() {
+        let cmds = Parser::new("(( i += n ))").parse().unwrap();
+        let owned = cmds.clone().into_iter().map(Cmd::into_owned).collect::<Vec<_>>();
+        let (target, operand) = arith_names(&cmds);
+        let (owned_target, owned_operand) = arith_names(&owned);
+        assert_detached(target, owned_target);
+        assert_detached(operand, owned_operand);
+    }
+
+    fn heredoc_texts<'x>(cmds: &'x [Cmd<'_>]) -> (&'x Cow<'x, str>, &'x Cow<'x, str>) {
+        let Executable::Simple(cmd) = exec(cmds) else { panic!("{cmds:?}") };
+        let Some(CmdSuffix::Redirect(Redir::Heredoc(_, doc))) = cmd.suffix.get(1) else {
+            panic!("{cmds:?}")
+        };
+        let HeredocBody::Interpolated(atoms) = &doc.body else { panic!("{doc:?}") };
+        let Some(Atom::Lit(lit)) = atoms.first() else { panic!("{atoms:?}") };
+        (&doc.delimiter, lit)
+    }
+
+    #[test]
+    fn into_owned_copies_heredocs() {
+        let cmds = Parser::new("cat <<EOF\nhi $x\nEOF\n").parse().unwrap();
+        let owned = cmds.clone().into_iter().map(Cmd::into_owned).collect::<Vec<_>>();
+        let (delimiter, lit) = heredoc_texts(&cmds);
+        let (owned_delimiter, owned_lit) = heredoc_texts(&owned);
+        assert_detached(delimiter, owned_delimiter);
+        assert_detached(lit, owned_lit);
+    }
+
+    fn cond_operand<'x>(cmds: &'x [Cmd<'_>]) -> &'x Cow<'x, str> {
+        let Executable::Compound(CompoundCmd { kind: CompoundKind::DoubleBracket(expr), .. }) =
+            exec(cmds)
+        else {
+            panic!("{cmds:?}")
+        };
+        let CondExpr::Unary('n', Word::Simple(WordPart::Bare(Atom::Param(Param::Var(name))))) =
+            expr
+        else {
+            panic!("{expr:?}")
+        };
+        name
+    }
+
+    #[test]
+    fn into_owned_copies_conditions() {
+        let cmds = Parser::new("[[ -n $a ]]").parse().unwrap();
+        let owned = cmds.clone().into_iter().map(Cmd::into_owned).collect::<Vec<_>>();
+        assert_detached(cond_operand(&cmds), cond_operand(&owned));
+    }
+}
@@ -8,7 +8,10 @@
 //!   - `status()` checks if the daemon is alive by pinging the socket.
 //!
 //! The daemon runs single-threaded — one command at a time, matching
-//! interactive shell semantics. Zero external dependencies.
+//! interactive shell semantics. Because every command runs in the same bash
+//! process, state that fish can't hold survives between commands — notably
+//! `coproc` coprocesses, whose `NAME[0]`/`NAME[1]` fds stay open until the
+//! daemon stops. Zero external dependencies.
 
 use std::io::{self, BufRead, BufReader, Read, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
@@ -19,6 +22,7 @@ use crate::env_diff::{self, EnvSnapshot};
 
 /// Null-delimited sentinel markers used in the bash protocol.
 /// Null bytes avoid collisions with any command output.
+const CMD_SENTINEL: &str = "\0__REEF_DAEMON_CMD__\0";
 const ENV_SENTINEL: &str = "\0__REEF_DAEMON_ENV__\0";
 const CWD_SENTINEL: &str = "\0__REEF_DAEMON_CWD__\0";
 const EXIT_SENTINEL: &str = "\0__REEF_DAEMON_EXIT__\0";
@@ -27,6 +31,10 @@ const DONE_SENTINEL: &str = "\0__REEF_DAEMON_DONE__\0";
 /// Magic command sent by `stop()` to shut down the daemon.
 const SHUTDOWN_CMD: &str = "__REEF_SHUTDOWN__";
 
+/// Script sent to bash on shutdown: SIGHUP every job (including
+/// coprocesses) with job-status noise silenced, then exit.
+const HANGUP_JOBS: &str = "exec 2>/dev/null\nkill -HUP $(jobs -p)\nexit 0\n";
+
 /// Magic command sent by `status()` to check if the daemon is alive.
 const PING_CMD: &str = "__REEF_PING__";
 const PONG_RESPONSE: &[u8] = b"__REEF_PONG__\n";
@@ -83,7 +91,7 @@ pub fn exec(socket_path: &str, command: &str) -> i32 {
         }
     }
 
-    parse_and_print_response(&before, &response)
+    parse_and_print_response(before, &response)
 }
 
 /// Tell the daemon to shut down.
@@ -127,11 +135,20 @@ pub fn status(socket_path: &str) -> bool {
 }
 
 /// Parse the daemon response: extract user output, env diff, and exit code.
-fn parse_and_print_response(before: &EnvSnapshot, response: &[u8]) -> i32 {
+fn parse_and_print_response(before: EnvSnapshot, response: &[u8]) -> i32 {
     let data = String::from_utf8_lossy(response);
 
     // Response format:
-    //   <user_output>ENV_SENTINEL<env_data>CWD_SENTINEL<cwd>EXIT_SENTINEL<code>DONE_SENTINEL
+    //   <assoc_before>CMD_SENTINEL<user_output>ENV_SENTINEL<env_data>
+    //   CWD_SENTINEL<cwd>EXIT_SENTINEL<code>DONE_SENTINEL
+
+    let (before, output_start) = match data.find(CMD_SENTINEL) {
+        Some(cmd_pos) => (
+            before.with_assoc_dump(&data[..cmd_pos]),
+            cmd_pos + CMD_SENTINEL.len(),
+        ),
+        None => (before, 0),
+    };
 
     let Some(env_pos) = data.find(ENV_SENTINEL) else {
         // No sentinels — dump everything as output
@@ -163,16 +180,13 @@ fn parse_and_print_response(before: &EnvSnapshot, response: &[u8]) -> i32 {
     }
 
     // Print user output to stderr (so user sees it)
-    let user_output = &response[..env_pos];
+    let user_output = &response[output_start..env_pos];
     if !user_output.is_empty() {
         let _ = io::stderr().write_all(user_output);
     }
 
     // Build env snapshot and diff
-    let after = EnvSnapshot::new(
-        env_diff::parse_null_separated_env(env_section),
-        cwd_section.to_string(),
-    );
+    let after = EnvSnapshot::from_dump(env_section, cwd_section.to_string());
 
     let mut buf = String::new();
     before.diff_into(&after, &mut buf);
@@ -290,7 +304,14 @@ pub fn serve(socket_path: &str) {
 
         // Handle special commands
         if *command == *SHUTDOWN_CMD {
-            let _ = bash.kill();
+            // Coprocesses (`coproc NAME ...`) and other background jobs live
+            // on between commands, so their fds in `NAME[0]`/`NAME[1]` stay
+            // usable. Hang them up like a closing terminal would before
+            // bash goes away, or they outlive the daemon.
+            if writer.write_all(HANGUP_JOBS.as_bytes()).is_err() || writer.flush().is_err()
+            {
+                let _ = bash.kill();
+            }
             let _ = bash.wait();
             let _ = fs::remove_file(socket_path);
             return;
@@ -342,9 +363,10 @@ pub fn serve(socket_path: &str) {
 /// Build a bash script block for the daemon to eval.
 ///
 /// The script:
-/// 1. Evals the user's command with output to stdout (inherited as stderr)
-/// 2. Captures exit code
-/// 3. Prints env dump with null-delimited sentinels
+/// 1. Dumps associative arrays, so the client can see what the command changed
+/// 2. Evals the user's command with output to stdout (inherited as stderr)
+/// 3. Captures exit code
+/// 4. Prints env and associative array dumps with null-delimited sentinels
 fn build_daemon_script(command: &str) -> String {
     // Escape command for eval (single-quote it)
     let mut escaped = String::with_capacity(command.len() + 2);
@@ -358,13 +380,16 @@ fn build_daemon_script(command: &str) -> String {
     }
     escaped.push('\'');
 
-    let mut s = String::with_capacity(escaped.len() + 256);
+    let mut s = String::with_capacity(escaped.len() + 2 * env_diff::ASSOC_DUMP.len() + 256);
+    s.push_str(env_diff::ASSOC_DUMP);
+    s.push_str("printf '\\0__REEF_DAEMON_CMD__\\0'\n");
     s.push_str("eval ");
     s.push_str(&escaped);
     s.push_str(" >&2\n");
     s.push_str("__reef_exit=$?\n");
     s.push_str("printf '\\0__REEF_DAEMON_ENV__\\0'\n");
     s.push_str("env -0\n");
+    s.push_str(env_diff::ASSOC_DUMP);
     s.push_str("printf '\\0__REEF_DAEMON_CWD__\\0'\n");
     s.push_str("pwd\n");
     s.push_str("printf '\\0__REEF_DAEMON_EXIT__\\0%d\\0__REEF_DAEMON_DONE__\\0\\n' $__reef_exit\n");
@@ -426,7 +451,7 @@ mod tests {
         response.extend_from_slice(b"42");
         response.extend_from_slice(DONE_SENTINEL.as_bytes());
 
-        let exit_code = parse_and_print_response(&before, &response);
+        let exit_code = parse_and_print_response(before, &response);
         assert_eq!(exit_code, 42);
     }
 }
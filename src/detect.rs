@@ -61,6 +61,8 @@ pub fn looks_like_bash(input: &str) -> bool {
             },
             b'<' if matches!(next, b'<' | b'(') => return true,
             b'>' if next == b'(' => return true,
+            // Extglob `!(…)`, `@(…)`, `+(…)` — not fish syntax outside quotes.
+            b'!' | b'@' | b'+' if next == b'(' && !in_dquote => return true,
             b'[' if next == b'[' => return true,
             b'(' if next == b'(' && (i == 0 || bytes[i - 1] != b'$') => return true,
             b'(' => has_paren = true,
@@ -113,6 +115,7 @@ pub fn looks_like_bash(input: &str) -> bool {
             "eval ",
             "select ",
             "getopts ",
+            "coproc ",
         ];
         // Control-flow keywords checked with word boundaries to avoid
         // false positives (e.g. " fi" inside "file", " done" in "done!").
@@ -440,6 +443,19 @@ mod tests {
         assert!(looks_like_bash(r#"echo "Hello $(whoami), it's $((2+2)) o'clock""#));
     }
 
+    #[test]
+    fn detects_coproc() {
+        assert!(looks_like_bash("coproc cat"));
+        assert!(looks_like_bash("coproc UP { tr a-z A-Z; }"));
+    }
+
+    #[test]
+    fn detects_extglob() {
+        assert!(looks_like_bash("ls !(*.tmp)"));
+        assert!(looks_like_bash("ls *.@(jpg|png)"));
+        assert!(!looks_like_bash("echo \"a+(b)\""));
+    }
+
     #[test]
     fn detects_double_brackets() {
         assert!(looks_like_bash("[[ -n \"$HOME\" ]] && echo yes"));
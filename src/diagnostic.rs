@@ -0,0 +1,272 @@
+//! Human-readable diagnostics with line/column and a source snippet.
+//!
+//! [`ParseError`] stays small and `Copy` so the parser can return it cheaply.
+//! A [`Diagnostic`] is built on demand from an error plus the original input:
+//! it resolves the byte offset to a line and column, captures the offending
+//! token, and renders a caret snippet in the familiar compiler style:
+//!
+//! ```text
+//! error: expected 'fi' to close if statement
+//!  --> line 2, column 8
+//!   |
+//! 2 | echo hi
+//!   |        ^ found end of input
+//!   = expected: 'fi'
+//! ```
+
+use std::fmt::{self, Write};
+
+use crate::ast::Span;
+use crate::lexer::{ParseError, is_meta};
+
+/// A located, renderable error message.
+///
+/// Owns everything needed to render itself, so it can outlive the input it
+/// was built from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    message: String,
+    span: Span,
+    line: usize,
+    column: usize,
+    source_line: String,
+    found: Option<String>,
+    expected: Vec<&'static str>,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic for `span` within `input`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::ast::Span;
+    /// use reef::diagnostic::Diagnostic;
+    ///
+    /// let diag = Diagnostic::new("ls\nfoo bar", Span::new(7, 10), "unknown argument");
+    /// assert_eq!((diag.line(), diag.column()), (2, 5));
+    /// ```
+    #[must_use]
+    pub fn new(input: &str, span: Span, message: impl Into<String>) -> Self {
+        let start = floor_char_boundary(input, span.start.min(input.len()));
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+        Diagnostic {
+            message: message.into(),
+            span: Span::new(start, span.end.max(start)),
+            line: input[..line_start].matches('\n').count() + 1,
+            column: input[line_start..start].chars().count() + 1,
+            source_line: input[line_start..line_end].to_string(),
+            found: None,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Build a diagnostic from a parser error. The span covers the token at
+    /// the error position, which is also reported as "found".
+    #[must_use]
+    pub fn from_parse_error(err: &ParseError, input: &str) -> Self {
+        let pos = floor_char_boundary(input, err.position().min(input.len()));
+        let (end, found) = token_at(input, pos);
+        let mut diag = Diagnostic::new(input, Span::new(pos, end), err.message());
+        diag.found = Some(found);
+        diag.expected = err.expected().to_vec();
+        diag
+    }
+
+    /// Set the tokens that would have been accepted at this position.
+    #[must_use]
+    pub fn with_expected(mut self, expected: &[&'static str]) -> Self {
+        self.expected = expected.to_vec();
+        self
+    }
+
+    /// The error message, without location.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Byte range in the original input this diagnostic points at.
+    #[must_use]
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// 1-based line number of the span start.
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 1-based column of the span start, counted in characters.
+    #[must_use]
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Description of the token at the error position, if known
+    /// (e.g. `'done'`, `newline`, `end of input`).
+    #[must_use]
+    pub fn found(&self) -> Option<&str> {
+        self.found.as_deref()
+    }
+
+    /// Tokens that would have been accepted at this position.
+    #[must_use]
+    pub fn expected(&self) -> &[&'static str] {
+        &self.expected
+    }
+
+    /// Render the diagnostic as a multi-line snippet with a caret under the
+    /// offending span.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    ///
+    /// let input = "if true; then echo hi";
+    /// let diag = Parser::new(input).parse().unwrap_err().diagnostic(input);
+    /// let text = diag.render();
+    /// assert!(text.starts_with("error: expected 'fi' to close if statement"));
+    /// assert!(text.contains("1 | if true; then echo hi\n"));
+    /// assert!(text.contains("= expected: 'fi'"));
+    /// ```
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(self.source_line.len() * 2 + 64);
+        let line_no = self.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+
+        let _ = writeln!(out, "error: {}", self.message);
+        let _ = writeln!(
+            out,
+            "{gutter}--> line {}, column {}",
+            self.line, self.column
+        );
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{line_no} | {}", self.source_line);
+
+        // Carets only underline what is visible on this line.
+        let line_rest: usize = self.source_line.chars().count() + 1 - self.column;
+        let width = self.span.len().clamp(1, line_rest.max(1));
+        let _ = write!(
+            out,
+            "{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(width)
+        );
+        if let Some(found) = &self.found {
+            let _ = write!(out, " found {found}");
+        }
+        out.push('\n');
+
+        if !self.expected.is_empty() {
+            let _ = write!(out, "{gutter} = expected: ");
+            for (i, tok) in self.expected.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(if i + 1 == self.expected.len() {
+                        " or "
+                    } else {
+                        ", "
+                    });
+                }
+                let _ = write!(out, "'{tok}'");
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// End offset and description of the token starting at `pos`.
+fn token_at(input: &str, pos: usize) -> (usize, String) {
+    let bytes = input.as_bytes();
+    match bytes.get(pos) {
+        None => (pos, "end of input".to_string()),
+        Some(b'\n') => (pos + 1, "newline".to_string()),
+        Some(&b) if is_meta(b) => (pos + 1, format!("'{}'", b as char)),
+        Some(_) => {
+            let end = bytes[pos..]
+                .iter()
+                .position(|&b| is_meta(b))
+                .map_or(input.len(), |i| pos + i);
+            (end, format!("'{}'", &input[pos..end]))
+        }
+    }
+}
+
+/// Largest char boundary `<= pos`, so slicing never splits a UTF-8 sequence.
+fn floor_char_boundary(input: &str, mut pos: usize) -> usize {
+    while !input.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn diag(input: &str) -> Diagnostic {
+        Parser::new(input).parse().unwrap_err().diagnostic(input)
+    }
+
+    #[test]
+    fn line_and_column_on_later_line() {
+        let d = diag("echo a\necho b\nif true; then\n  echo c\n");
+        assert_eq!(d.line(), 5);
+        assert_eq!(d.column(), 1);
+        assert_eq!(d.found(), Some("end of input"));
+        assert_eq!(d.expected(), ["fi"]);
+    }
+
+    #[test]
+    fn found_token_is_underlined() {
+        let d = diag("for x in a b; echo $x; done");
+        assert_eq!(d.found(), Some("'echo'"));
+        assert_eq!(d.span().len(), 4);
+        let text = d.render();
+        assert!(
+            text.contains("  |               ^^^^ found 'echo'\n"),
+            "got:\n{text}"
+        );
+        assert!(text.contains("= expected: 'do'"));
+    }
+
+    #[test]
+    fn multiple_expected_tokens() {
+        let d = diag("function foo bar");
+        assert_eq!(d.expected(), ["{", "("]);
+        assert!(d.render().contains("= expected: '{' or '('"));
+    }
+
+    #[test]
+    fn column_counts_chars_not_bytes() {
+        let d = Diagnostic::new("echo héllo x", Span::new(12, 13), "here");
+        assert_eq!(d.column(), 12);
+    }
+
+    #[test]
+    fn gutter_widens_with_line_number() {
+        let input = format!("{}echo ${{x", "true\n".repeat(11));
+        let text = diag(&input).render();
+        assert!(text.contains("  --> line 12, column 9\n"), "got:\n{text}");
+        assert!(text.contains("12 | echo ${x\n"));
+    }
+
+    #[test]
+    fn no_expected_line_without_expected_tokens() {
+        let d = diag("echo 'abc");
+        assert!(d.expected().is_empty());
+        assert!(!d.render().contains("expected:"));
+    }
+}
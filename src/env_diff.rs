@@ -2,6 +2,11 @@
 //!
 //! Captures environment state before and after a bash command, then generates
 //! fish shell commands (`set -gx`, `set -e`, `cd`) to apply the differences.
+//!
+//! Bash can't export associative arrays, so the bash side also runs
+//! [`ASSOC_DUMP`] and their contents come back as the same pair of fish lists
+//! the translator uses for `declare -A`: values in `NAME`, keys in
+//! `__reef_keys_NAME`.
 
 use std::borrow::Cow;
 use std::collections::HashMap;
@@ -58,18 +63,90 @@ const SKIP_VARS: &[&str] = &[
     "_",
 ];
 
+/// Prefix of the fish list holding an emulated associative array's keys.
+pub(crate) const ASSOC_KEYS_PREFIX: &str = "__reef_keys_";
+
+/// Separates `env -0` output from the associative array dump.
+const ASSOC_MARKER: &str = "\0__REEF_ASSOC__\0";
+
+/// Bash snippet printing every user-defined associative array after
+/// [`ASSOC_MARKER`]: `NAME\0`, then `KEY\0VALUE\0` per element, then `\0`.
+/// Bash keys are never empty, so an empty key ends a record. Needs bash 4.4
+/// for `${var@a}`; older versions print the marker only.
+pub(crate) const ASSOC_DUMP: &str = r#"printf '\0__REEF_ASSOC__\0'
+if ((BASH_VERSINFO[0] * 100 + BASH_VERSINFO[1] >= 404)); then
+for __reef_n in $(compgen -A arrayvar); do
+case $__reef_n in BASH_*|__reef_*) continue;; esac
+declare -n __reef_r=$__reef_n
+if [[ ${__reef_r@a} == *A* ]]; then
+printf '%s\0' "$__reef_n"
+for __reef_k in "${!__reef_r[@]}"; do
+printf '%s\0%s\0' "$__reef_k" "${__reef_r[$__reef_k]}"
+done
+printf '\0'
+fi
+unset -n __reef_r
+done
+unset __reef_n __reef_k
+fi
+"#;
+
+/// Contents of one associative array, as `(key, value)` pairs.
+pub type AssocEntries = Vec<(String, String)>;
+
 /// A snapshot of the shell environment at a point in time.
 #[derive(Debug, Clone)]
 pub struct EnvSnapshot {
     vars: HashMap<String, String>,
     cwd: String,
+    assoc: HashMap<String, AssocEntries>,
 }
 
 impl EnvSnapshot {
     /// Create a snapshot from the given variables and working directory.
     #[must_use]
     pub fn new(vars: HashMap<String, String>, cwd: String) -> Self {
-        EnvSnapshot { vars, cwd }
+        EnvSnapshot {
+            vars,
+            cwd,
+            assoc: HashMap::new(),
+        }
+    }
+
+    /// Build a snapshot from a dumped env section: `env -0` output,
+    /// optionally followed by [`ASSOC_DUMP`] output.
+    pub(crate) fn from_dump(section: &str, cwd: String) -> Self {
+        let (env, assoc) = split_assoc_dump(section);
+        EnvSnapshot::new(parse_null_separated_env(env), cwd)
+            .with_assoc_arrays(parse_assoc_dump(assoc))
+    }
+
+    /// Take this snapshot's associative arrays from [`ASSOC_DUMP`] output.
+    /// Bash only knows its own arrays, so the "before" side of a diff gets
+    /// them from a dump taken in the same bash just before the command.
+    #[must_use]
+    pub(crate) fn with_assoc_dump(self, dump: &str) -> Self {
+        let (_, assoc) = split_assoc_dump(dump);
+        self.with_assoc_arrays(parse_assoc_dump(assoc))
+    }
+
+    /// Attach associative arrays to this snapshot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use reef::env_diff::EnvSnapshot;
+    ///
+    /// let before = EnvSnapshot::new(HashMap::new(), "/".to_string());
+    /// let arrays = HashMap::from([("m".to_string(), vec![("k".to_string(), "v".to_string())])]);
+    /// let after = EnvSnapshot::new(HashMap::new(), "/".to_string()).with_assoc_arrays(arrays);
+    /// assert_eq!(before.diff(&after), "set -g __reef_keys_m k\nset -g m v\n");
+    /// ```
+    #[must_use]
+    pub fn with_assoc_arrays(mut self, assoc: HashMap<String, AssocEntries>) -> Self {
+        self.assoc = assoc;
+        self
     }
 
     /// Capture the current process environment, skipping bash-internal vars.
@@ -81,7 +158,7 @@ impl EnvSnapshot {
         let cwd = std::env::current_dir()
             .map(|p| p.to_string_lossy().into_owned())
             .unwrap_or_default();
-        EnvSnapshot { vars, cwd }
+        EnvSnapshot::new(vars, cwd)
     }
 
     /// The environment variables in this snapshot.
@@ -162,6 +239,42 @@ impl EnvSnapshot {
             }
         }
 
+        // Associative arrays: rewrite both lists of a map that changed
+        for (name, entries) in &after.assoc {
+            if self.assoc.get(name) == Some(entries) {
+                continue;
+            }
+            out.push_str("set -g ");
+            out.push_str(ASSOC_KEYS_PREFIX);
+            out.push_str(name);
+            for (key, _) in entries {
+                out.push(' ');
+                out.push_str(&shell_escape(key));
+            }
+            out.push_str("\nset -g ");
+            out.push_str(name);
+            for (_, value) in entries {
+                out.push(' ');
+                // An empty value still takes a slot, or the lists misalign
+                if value.is_empty() {
+                    out.push_str("''");
+                } else {
+                    out.push_str(&shell_escape(value));
+                }
+            }
+            out.push('\n');
+        }
+        for name in self.assoc.keys() {
+            if !after.assoc.contains_key(name) {
+                out.push_str("set -e ");
+                out.push_str(name);
+                out.push_str("\nset -e ");
+                out.push_str(ASSOC_KEYS_PREFIX);
+                out.push_str(name);
+                out.push('\n');
+            }
+        }
+
         // Changed directory
         if !after.cwd.is_empty() && self.cwd != after.cwd {
             out.push_str("cd ");
@@ -207,6 +320,36 @@ pub fn parse_null_separated_env(data: &str) -> HashMap<String, String> {
     vars
 }
 
+/// Split a dumped env section at [`ASSOC_MARKER`] into the `env -0` part
+/// and the associative array part (empty if there is none).
+#[must_use]
+pub(crate) fn split_assoc_dump(section: &str) -> (&str, &str) {
+    match section.find(ASSOC_MARKER) {
+        Some(pos) => (&section[..pos], &section[pos + ASSOC_MARKER.len()..]),
+        None => (section, ""),
+    }
+}
+
+/// Parse the records printed by [`ASSOC_DUMP`] (after its marker).
+#[must_use]
+pub fn parse_assoc_dump(data: &str) -> HashMap<String, AssocEntries> {
+    let mut arrays = HashMap::new();
+    let mut fields = data.split('\0');
+    while let Some(name) = fields.next() {
+        let name = name.trim_start_matches('\n');
+        if name.is_empty() {
+            break;
+        }
+        let mut entries = Vec::new();
+        while let Some(key) = fields.next().filter(|k| !k.is_empty()) {
+            let value = fields.next().unwrap_or_default();
+            entries.push((key.to_string(), value.to_string()));
+        }
+        arrays.insert(name.to_string(), entries);
+    }
+    arrays
+}
+
 /// Check if a variable should be skipped during env sync.
 #[must_use]
 pub(crate) fn should_skip_var(name: &str) -> bool {
@@ -334,6 +477,48 @@ mod tests {
         assert_eq!(shell_escape("it's"), "'it'\\''s'");
     }
 
+    #[test]
+    fn parse_assoc_records() {
+        let data = "colors\0red\0#f00\0blank\0\0\0empty\0\0";
+        let arrays = parse_assoc_dump(data);
+        assert_eq!(arrays.len(), 2);
+        assert_eq!(
+            arrays["colors"],
+            [
+                ("red".to_string(), "#f00".to_string()),
+                ("blank".to_string(), String::new())
+            ]
+        );
+        assert!(arrays["empty"].is_empty());
+    }
+
+    #[test]
+    fn from_dump_splits_env_and_assoc() {
+        let section = format!("FOO=bar\0{ASSOC_MARKER}m\0k\0a=b\0\0");
+        let snap = EnvSnapshot::from_dump(&section, "/home".to_string());
+        assert_eq!(snap.vars().len(), 1);
+        assert_eq!(snap.assoc["m"], [("k".to_string(), "a=b".to_string())]);
+    }
+
+    #[test]
+    fn diff_assoc_arrays() {
+        let old = HashMap::from([
+            ("same".to_string(), vec![("k".to_string(), "v".to_string())]),
+            ("gone".to_string(), Vec::new()),
+        ]);
+        let new = HashMap::from([
+            ("same".to_string(), vec![("k".to_string(), "v".to_string())]),
+            ("m".to_string(), vec![("a b".to_string(), String::new())]),
+        ]);
+        let before = EnvSnapshot::new(HashMap::new(), "/home".to_string()).with_assoc_arrays(old);
+        let after = EnvSnapshot::new(HashMap::new(), "/home".to_string()).with_assoc_arrays(new);
+
+        let out = before.diff(&after);
+        assert!(!out.contains("same"), "got: {out}");
+        assert!(out.contains("set -g __reef_keys_m 'a b'\nset -g m ''\n"), "got: {out}");
+        assert!(out.contains("set -e gone\nset -e __reef_keys_gone\n"), "got: {out}");
+    }
+
     #[test]
     fn capture_current_env() {
         let snap = EnvSnapshot::capture_current();
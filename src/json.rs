@@ -0,0 +1,1019 @@
+//! JSON serialization of the AST.
+//!
+//! A small hand-written serializer so tools outside Rust (editor plugins,
+//! lint jobs) can consume the parser through `reef parse --json`.
+//!
+//! Every node is an object whose `"kind"` is the name of its AST variant
+//! (`"Simple"`, `"If"`, `"DQuoted"`, `"Var"`, ...). Nodes that carry a
+//! [`Span`] — commands, pipelines, executables, guard/body pairs and case
+//! arms — have a `"span": {"start": .., "end": ..}` field with byte offsets
+//! into the input. [`to_json_with_spans`] adds the same field to words, word
+//! parts, atoms, parameters and redirections found in a [`SpanTable`].
+//! Words are `{"kind": "Word", "parts": [..]}`; a bare atom appears directly
+//! in `parts`, without a wrapper. Absent optional values are `null`. Struct
+//! fields keep their AST names.
+//!
+//! ```
+//! use reef::json;
+//! use reef::parser::Parser;
+//!
+//! let cmds = Parser::new("ls").parse().unwrap();
+//! assert_eq!(
+//!     json::to_json(&cmds),
+//!     concat!(
+//!         r#"[{"kind":"List","span":{"start":0,"end":2},"first":"#,
+//!         r#"{"kind":"Single","span":{"start":0,"end":2},"command":"#,
+//!         r#"{"kind":"Simple","span":{"start":0,"end":2},"prefix":[],"suffix":"#,
+//!         r#"[{"kind":"Word","parts":[{"kind":"Lit","value":"ls"}]}]}},"rest":[]}]"#,
+//!     ),
+//! );
+//! ```
+
+use std::fmt::Write as _;
+
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+
+/// Serialize a command list as a JSON array of command nodes.
+#[must_use]
+pub fn to_json(cmds: &[Cmd<'_>]) -> String {
+    let mut w = Writer::default();
+    w.cmds(cmds);
+    w.out
+}
+
+/// Serialize a command list like [`to_json`], with a `"span"` field on
+/// every node located in `spans`.
+///
+/// # Examples
+///
+/// ```
+/// use reef::json;
+/// use reef::parser::Parser;
+///
+/// let (cmds, spans) = Parser::new("ls").parse_with_spans().unwrap();
+/// assert!(json::to_json_with_spans(&cmds, &spans).contains(
+///     r#"{"kind":"Word","span":{"start":0,"end":2},"parts":"#
+/// ));
+/// ```
+#[must_use]
+pub fn to_json_with_spans(cmds: &[Cmd<'_>], spans: &SpanTable) -> String {
+    let mut w = Writer { spans: Some(spans), ..Writer::default() };
+    w.cmds(cmds);
+    w.out
+}
+
+/// Serialize a diagnostic as a JSON object with `message`, `span`, `line`,
+/// `column`, `found` and `expected` fields.
+///
+/// # Examples
+///
+/// ```
+/// use reef::json;
+/// use reef::parser::Parser;
+///
+/// let input = "echo (";
+/// let diag = Parser::new(input).parse().unwrap_err().diagnostic(input);
+/// assert!(json::diagnostic_to_json(&diag).starts_with(r#"{"message":"#));
+/// ```
+#[must_use]
+pub fn diagnostic_to_json(diag: &Diagnostic) -> String {
+    let mut w = Writer::default();
+    w.out.push_str("{\"message\":");
+    w.string(diag.message());
+    w.span(diag.span());
+    let _ = write!(w.out, ",\"line\":{},\"column\":{}", diag.line(), diag.column());
+    w.key("found");
+    match diag.found() {
+        Some(found) => w.string(found),
+        None => w.null(),
+    }
+    w.key("expected");
+    w.list(diag.expected(), |w, s| w.string(s));
+    w.out.push('}');
+    w.out
+}
+
+#[derive(Default)]
+struct Writer<'s> {
+    out: String,
+    spans: Option<&'s SpanTable>,
+    /// Span of the node about to be opened, from `spans`.
+    pending: Option<Span>,
+}
+
+impl Writer<'_> {
+    // --- Primitives ---
+
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if c < ' ' => {
+                    let _ = write!(self.out, "\\u{:04x}", u32::from(c));
+                }
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn null(&mut self) {
+        self.out.push_str("null");
+    }
+
+    fn bool(&mut self, b: bool) {
+        self.out.push_str(if b { "true" } else { "false" });
+    }
+
+    fn number(&mut self, n: impl std::fmt::Display) {
+        let _ = write!(self.out, "{n}");
+    }
+
+    /// Start a node object. Fields added afterwards with [`Writer::key`]
+    /// are comma-prefixed, since `kind` always comes first.
+    fn open(&mut self, kind: &str) {
+        self.out.push_str("{\"kind\":");
+        self.string(kind);
+        if let Some(span) = self.pending.take() {
+            self.span(span);
+        }
+    }
+
+    /// Look up the span of the node about to be opened.
+    fn locate(&mut self, lookup: impl FnOnce(&SpanTable) -> Option<Span>) {
+        self.pending = self.spans.and_then(lookup);
+    }
+
+    fn close(&mut self) {
+        self.out.push('}');
+    }
+
+    /// A node with no fields besides its kind.
+    fn unit(&mut self, kind: &str) {
+        self.open(kind);
+        self.close();
+    }
+
+    fn key(&mut self, key: &str) {
+        self.out.push_str(",\"");
+        self.out.push_str(key);
+        self.out.push_str("\":");
+    }
+
+    fn span(&mut self, span: Span) {
+        self.key("span");
+        let _ = write!(self.out, "{{\"start\":{},\"end\":{}}}", span.start, span.end);
+    }
+
+    fn list<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.out.push('[');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            f(self, item);
+        }
+        self.out.push(']');
+    }
+
+    fn opt<T>(&mut self, value: Option<T>, f: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(v) => f(self, v),
+            None => self.null(),
+        }
+    }
+
+    fn str_field(&mut self, key: &str, value: &str) {
+        self.key(key);
+        self.string(value);
+    }
+
+    fn opt_str_field(&mut self, key: &str, value: Option<&str>) {
+        self.key(key);
+        self.opt(value, Self::string);
+    }
+
+    fn word_field(&mut self, key: &str, word: &Word<'_>) {
+        self.key(key);
+        self.word(word);
+    }
+
+    fn opt_word_field(&mut self, key: &str, word: Option<&Word<'_>>) {
+        self.key(key);
+        self.opt(word, Self::word);
+    }
+
+    fn words_field(&mut self, key: &str, words: &[Word<'_>]) {
+        self.key(key);
+        self.list(words, Self::word);
+    }
+
+    fn cmds_field(&mut self, key: &str, cmds: &[Cmd<'_>]) {
+        self.key(key);
+        self.cmds(cmds);
+    }
+
+    // --- Commands ---
+
+    fn cmds(&mut self, cmds: &[Cmd<'_>]) {
+        self.list(cmds, Self::cmd);
+    }
+
+    fn cmd(&mut self, cmd: &Cmd<'_>) {
+        let (kind, list) = match cmd {
+            Cmd::List(list) => ("List", list),
+            Cmd::Job(list) => ("Job", list),
+        };
+        self.open(kind);
+        self.span(list.span);
+        self.key("first");
+        self.pipeline(&list.first);
+        self.key("rest");
+        self.list(&list.rest, |w, link| {
+            let (kind, p) = match link {
+                AndOr::And(p) => ("And", p),
+                AndOr::Or(p) => ("Or", p),
+            };
+            w.open(kind);
+            w.key("pipeline");
+            w.pipeline(p);
+            w.close();
+        });
+        self.close();
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline<'_>) {
+        match pipeline {
+            Pipeline::Single(exec) => {
+                self.open("Single");
+                self.span(pipeline.span());
+                self.key("command");
+                self.executable(exec);
+            }
+            Pipeline::Pipe(negated, execs) => {
+                self.open("Pipe");
+                self.span(pipeline.span());
+                self.key("negated");
+                self.bool(*negated);
+                self.key("commands");
+                self.list(execs, Self::executable);
+            }
+            Pipeline::Timed { posix, pipeline: inner } => {
+                self.open("Timed");
+                self.span(pipeline.span());
+                self.key("posix");
+                self.bool(*posix);
+                self.key("pipeline");
+                self.pipeline(inner);
+            }
+        }
+        self.close();
+    }
+
+    fn executable(&mut self, exec: &Executable<'_>) {
+        match exec {
+            Executable::Simple(cmd) => self.simple(cmd),
+            Executable::Compound(cmd) => self.compound(cmd),
+            Executable::FuncDef(name, body) => {
+                self.open("FuncDef");
+                self.span(exec.span());
+                self.str_field("name", name);
+                self.key("body");
+                self.compound(body);
+                self.close();
+            }
+        }
+    }
+
+    fn simple(&mut self, cmd: &SimpleCmd<'_>) {
+        self.open("Simple");
+        self.span(cmd.span);
+        self.key("prefix");
+        self.list(&cmd.prefix, |w, prefix| match prefix {
+            CmdPrefix::Assign(name, value) => {
+                w.open("Assign");
+                w.str_field("name", name);
+                w.opt_word_field("value", value.as_ref());
+                w.close();
+            }
+            CmdPrefix::ArrayAssign(name, words) => w.array_assign("ArrayAssign", name, words),
+            CmdPrefix::ArrayAppend(name, words) => w.array_assign("ArrayAppend", name, words),
+            CmdPrefix::ElementAssign(name, index, value) => {
+                w.open("ElementAssign");
+                w.str_field("name", name);
+                w.word_field("index", index);
+                w.opt_word_field("value", value.as_ref());
+                w.close();
+            }
+            CmdPrefix::Redirect(r) => w.redir(r),
+        });
+        self.key("suffix");
+        self.list(&cmd.suffix, |w, suffix| match suffix {
+            CmdSuffix::Word(word) => w.word(word),
+            CmdSuffix::ArrayAssign(name, words) => w.array_assign("ArrayAssign", name, words),
+            CmdSuffix::Redirect(r) => w.redir(r),
+        });
+        self.close();
+    }
+
+    fn array_assign(&mut self, kind: &str, name: &str, words: &[Word<'_>]) {
+        self.open(kind);
+        self.str_field("name", name);
+        self.words_field("values", words);
+        self.close();
+    }
+
+    /// A compound command is flattened into one object: the kind of its
+    /// body, the command's span, the body's fields and `redirects`.
+    fn compound(&mut self, cmd: &CompoundCmd<'_>) {
+        match &cmd.kind {
+            CompoundKind::For { var, words, body } => {
+                self.for_loop("For", cmd.span, var, words.as_deref(), body);
+            }
+            CompoundKind::Select { var, words, body } => {
+                self.for_loop("Select", cmd.span, var, words.as_deref(), body);
+            }
+            CompoundKind::While(gb) => {
+                self.open("While");
+                self.span(cmd.span);
+                self.guard_body_fields(gb);
+            }
+            CompoundKind::Until(gb) => {
+                self.open("Until");
+                self.span(cmd.span);
+                self.guard_body_fields(gb);
+            }
+            CompoundKind::If { conditionals, else_branch } => {
+                self.open("If");
+                self.span(cmd.span);
+                self.key("conditionals");
+                self.list(conditionals, |w, gb| {
+                    w.open("GuardBody");
+                    w.span(gb.span);
+                    w.guard_body_fields(gb);
+                    w.close();
+                });
+                self.key("else_branch");
+                self.opt(else_branch.as_deref(), Self::cmds);
+            }
+            CompoundKind::Case { word, arms } => {
+                self.open("Case");
+                self.span(cmd.span);
+                self.word_field("word", word);
+                self.key("arms");
+                self.list(arms, |w, arm| {
+                    w.open("CaseArm");
+                    w.span(arm.span);
+                    w.words_field("patterns", &arm.patterns);
+                    w.cmds_field("body", &arm.body);
+                    w.str_field("terminator", match arm.terminator {
+                        CaseTerminator::Break => "Break",
+                        CaseTerminator::FallThrough => "FallThrough",
+                        CaseTerminator::Continue => "Continue",
+                    });
+                    w.close();
+                });
+            }
+            CompoundKind::CFor { init, cond, step, body } => {
+                self.open("CFor");
+                self.span(cmd.span);
+                for (key, a) in [("init", init), ("cond", cond), ("step", step)] {
+                    self.key(key);
+                    self.opt(a.as_ref(), Self::arith);
+                }
+                self.cmds_field("body", body);
+            }
+            CompoundKind::Coproc { name, body } => {
+                self.open("Coproc");
+                self.span(cmd.span);
+                self.opt_str_field("name", name.as_deref());
+                self.key("body");
+                self.executable(body);
+            }
+            CompoundKind::Brace(body) => {
+                self.open("Brace");
+                self.span(cmd.span);
+                self.cmds_field("body", body);
+            }
+            CompoundKind::Subshell(body) => {
+                self.open("Subshell");
+                self.span(cmd.span);
+                self.cmds_field("body", body);
+            }
+            CompoundKind::DoubleBracket(expr) => {
+                self.open("DoubleBracket");
+                self.span(cmd.span);
+                self.key("expr");
+                self.cond(expr);
+            }
+            CompoundKind::Arithmetic(a) => {
+                self.open("Arithmetic");
+                self.span(cmd.span);
+                self.key("expr");
+                self.arith(a);
+            }
+        }
+        self.key("redirects");
+        self.list(&cmd.redirects, Self::redir);
+        self.close();
+    }
+
+    fn for_loop(
+        &mut self,
+        kind: &str,
+        span: Span,
+        var: &str,
+        words: Option<&[Word<'_>]>,
+        body: &[Cmd<'_>],
+    ) {
+        self.open(kind);
+        self.span(span);
+        self.str_field("var", var);
+        self.key("words");
+        self.opt(words, |w, words| w.list(words, Self::word));
+        self.cmds_field("body", body);
+    }
+
+    fn guard_body_fields(&mut self, gb: &GuardBody<'_>) {
+        self.cmds_field("guard", &gb.guard);
+        self.cmds_field("body", &gb.body);
+    }
+
+    // --- Redirects ---
+
+    fn redir(&mut self, redir: &Redir<'_>) {
+        self.locate(|spans| spans.redir(redir));
+        let (kind, fd, target) = match redir {
+            Redir::Read(fd, w) => ("Read", Some(fd), w),
+            Redir::Write(fd, w) => ("Write", Some(fd), w),
+            Redir::Append(fd, w) => ("Append", Some(fd), w),
+            Redir::ReadWrite(fd, w) => ("ReadWrite", Some(fd), w),
+            Redir::Clobber(fd, w) => ("Clobber", Some(fd), w),
+            Redir::DupRead(fd, w) => ("DupRead", Some(fd), w),
+            Redir::DupWrite(fd, w) => ("DupWrite", Some(fd), w),
+            Redir::HereString(w) => ("HereString", None, w),
+            Redir::WriteAll(w) => ("WriteAll", None, w),
+            Redir::AppendAll(w) => ("AppendAll", None, w),
+            Redir::Heredoc(fd, doc) => {
+                self.open("Heredoc");
+                self.key("fd");
+                self.opt(*fd, Self::number);
+                self.str_field("delimiter", &doc.delimiter);
+                self.key("strip_tabs");
+                self.bool(doc.strip_tabs);
+                self.key("body");
+                match &doc.body {
+                    HeredocBody::Literal(s) => {
+                        self.open("Literal");
+                        self.str_field("value", s);
+                    }
+                    HeredocBody::Interpolated(atoms) => {
+                        self.open("Interpolated");
+                        self.key("atoms");
+                        self.list(atoms, Self::atom);
+                    }
+                }
+                self.close();
+                self.close();
+                return;
+            }
+        };
+        self.open(kind);
+        if let Some(fd) = fd {
+            self.key("fd");
+            self.opt(*fd, Self::number);
+        }
+        self.word_field("target", target);
+        self.close();
+    }
+
+    // --- Words ---
+
+    fn word(&mut self, word: &Word<'_>) {
+        self.locate(|spans| spans.word(word));
+        self.open("Word");
+        self.key("parts");
+        match word {
+            Word::Simple(part) => self.list(std::slice::from_ref(part), Self::word_part),
+            Word::Concat(parts) => self.list(parts, Self::word_part),
+        }
+        self.close();
+    }
+
+    fn word_part(&mut self, part: &WordPart<'_>) {
+        match part {
+            WordPart::Bare(atom) => self.atom(atom),
+            WordPart::DQuoted(atoms) => {
+                self.locate(|spans| spans.word_part(part));
+                self.open("DQuoted");
+                self.key("atoms");
+                self.list(atoms, Self::atom);
+                self.close();
+            }
+            WordPart::SQuoted(s) => {
+                self.locate(|spans| spans.word_part(part));
+                self.open("SQuoted");
+                self.str_field("value", s);
+                self.close();
+            }
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom<'_>) {
+        self.locate(|spans| spans.atom(atom));
+        match atom {
+            Atom::Lit(s) => {
+                self.open("Lit");
+                self.str_field("value", s);
+            }
+            Atom::Escaped(s) => {
+                self.open("Escaped");
+                self.str_field("value", s);
+            }
+            Atom::Param(p) => {
+                self.open("Param");
+                self.key("param");
+                self.param(p);
+            }
+            Atom::Subst(s) => {
+                self.subst(s);
+                return;
+            }
+            Atom::Star => self.open("Star"),
+            Atom::Question => self.open("Question"),
+            Atom::SquareOpen => self.open("SquareOpen"),
+            Atom::SquareClose => self.open("SquareClose"),
+            Atom::Tilde => self.open("Tilde"),
+            Atom::ProcSubIn(cmds) => {
+                self.open("ProcSubIn");
+                self.cmds_field("commands", cmds);
+            }
+            Atom::ProcSubOut(cmds) => {
+                self.open("ProcSubOut");
+                self.cmds_field("commands", cmds);
+            }
+            Atom::AnsiCQuoted(s) => {
+                self.open("AnsiCQuoted");
+                self.str_field("value", s);
+            }
+            Atom::ExtGlob { kind, alternatives } => {
+                self.open("ExtGlob");
+                self.str_field("op", match kind {
+                    ExtGlobKind::ZeroOrOne => "ZeroOrOne",
+                    ExtGlobKind::ZeroOrMore => "ZeroOrMore",
+                    ExtGlobKind::OneOrMore => "OneOrMore",
+                    ExtGlobKind::ExactlyOne => "ExactlyOne",
+                    ExtGlobKind::Not => "Not",
+                });
+                self.words_field("alternatives", alternatives);
+            }
+            Atom::BraceList(alternatives) => {
+                self.open("BraceList");
+                self.words_field("alternatives", alternatives);
+            }
+            Atom::BraceRange { start, end, step } => {
+                self.open("BraceRange");
+                self.str_field("start", start);
+                self.str_field("end", end);
+                self.opt_str_field("step", step.as_deref());
+            }
+        }
+        self.close();
+    }
+
+    fn param(&mut self, param: &Param<'_>) {
+        self.locate(|spans| spans.param(param));
+        match param {
+            Param::Var(name) => {
+                self.open("Var");
+                self.str_field("name", name);
+                self.close();
+            }
+            Param::Positional(n) => {
+                self.open("Positional");
+                self.key("index");
+                self.number(n);
+                self.close();
+            }
+            Param::At => self.unit("At"),
+            Param::Star => self.unit("Star"),
+            Param::Pound => self.unit("Pound"),
+            Param::Status => self.unit("Status"),
+            Param::Pid => self.unit("Pid"),
+            Param::Bang => self.unit("Bang"),
+            Param::Dash => self.unit("Dash"),
+        }
+    }
+
+    fn param_field(&mut self, param: &Param<'_>) {
+        self.key("param");
+        self.param(param);
+    }
+
+    fn subst(&mut self, subst: &Subst<'_>) {
+        match subst {
+            Subst::Cmd(cmds) => {
+                self.open("Cmd");
+                self.cmds_field("commands", cmds);
+            }
+            Subst::Arith(a) => {
+                self.open("Arith");
+                self.key("expr");
+                self.opt(a.as_ref(), Self::arith);
+            }
+            Subst::Len(p) => {
+                self.open("Len");
+                self.param_field(p);
+            }
+            Subst::Indirect(name) => {
+                self.open("Indirect");
+                self.str_field("name", name);
+            }
+            Subst::PrefixList(prefix) => {
+                self.open("PrefixList");
+                self.str_field("prefix", prefix);
+            }
+            Subst::Transform(name, op) => {
+                self.open("Transform");
+                self.str_field("name", name);
+                self.str_field("op", char::from(*op).encode_utf8(&mut [0; 4]));
+            }
+            Subst::Default(p, w) => self.param_word("Default", p, w.as_ref()),
+            Subst::Assign(p, w) => self.param_word("Assign", p, w.as_ref()),
+            Subst::Error(p, w) => self.param_word("Error", p, w.as_ref()),
+            Subst::Alt(p, w) => self.param_word("Alt", p, w.as_ref()),
+            Subst::TrimSuffixSmall(p, w) => self.param_word("TrimSuffixSmall", p, w.as_ref()),
+            Subst::TrimSuffixLarge(p, w) => self.param_word("TrimSuffixLarge", p, w.as_ref()),
+            Subst::TrimPrefixSmall(p, w) => self.param_word("TrimPrefixSmall", p, w.as_ref()),
+            Subst::TrimPrefixLarge(p, w) => self.param_word("TrimPrefixLarge", p, w.as_ref()),
+            Subst::Replace(p, pat, rep) => self.replace("Replace", p, pat.as_ref(), rep.as_ref()),
+            Subst::ReplaceAll(p, pat, rep) => {
+                self.replace("ReplaceAll", p, pat.as_ref(), rep.as_ref());
+            }
+            Subst::ReplacePrefix(p, pat, rep) => {
+                self.replace("ReplacePrefix", p, pat.as_ref(), rep.as_ref());
+            }
+            Subst::ReplaceSuffix(p, pat, rep) => {
+                self.replace("ReplaceSuffix", p, pat.as_ref(), rep.as_ref());
+            }
+            Subst::Substring(p, offset, length) => {
+                self.open("Substring");
+                self.param_field(p);
+                self.str_field("offset", offset);
+                self.opt_str_field("length", length.as_deref());
+            }
+            Subst::Upper(all, p, pat) | Subst::Lower(all, p, pat) => {
+                self.open(if matches!(subst, Subst::Upper(..)) { "Upper" } else { "Lower" });
+                self.key("all");
+                self.bool(*all);
+                self.param_field(p);
+                self.opt_word_field("pattern", pat.as_ref());
+            }
+            Subst::ArrayElement(name, index) => {
+                self.open("ArrayElement");
+                self.str_field("name", name);
+                self.word_field("index", index);
+            }
+            Subst::ArrayAll(name) => {
+                self.open("ArrayAll");
+                self.str_field("name", name);
+            }
+            Subst::ArrayKeys(name) => {
+                self.open("ArrayKeys");
+                self.str_field("name", name);
+            }
+            Subst::ArrayLen(name) => {
+                self.open("ArrayLen");
+                self.str_field("name", name);
+            }
+            Subst::ArraySlice(name, offset, length) => {
+                self.open("ArraySlice");
+                self.str_field("name", name);
+                self.str_field("offset", offset);
+                self.opt_str_field("length", length.as_deref());
+            }
+        }
+        self.close();
+    }
+
+    /// Fields of a `${param<op>word}` expansion; the caller closes the node.
+    fn param_word(&mut self, kind: &str, param: &Param<'_>, word: Option<&Word<'_>>) {
+        self.open(kind);
+        self.param_field(param);
+        self.opt_word_field("word", word);
+    }
+
+    /// Fields of a `${param/pattern/replacement}` expansion; the caller
+    /// closes the node.
+    fn replace(
+        &mut self,
+        kind: &str,
+        param: &Param<'_>,
+        pattern: Option<&Word<'_>>,
+        replacement: Option<&Word<'_>>,
+    ) {
+        self.open(kind);
+        self.param_field(param);
+        self.opt_word_field("pattern", pattern);
+        self.opt_word_field("replacement", replacement);
+    }
+
+    // --- Arithmetic ---
+
+    fn arith(&mut self, arith: &Arith<'_>) {
+        let binary = |kind, l: &Arith<'_>, r: &Arith<'_>, w: &mut Self| {
+            w.open(kind);
+            w.key("left");
+            w.arith(l);
+            w.key("right");
+            w.arith(r);
+        };
+        let unary = |kind, e: &Arith<'_>, w: &mut Self| {
+            w.open(kind);
+            w.key("operand");
+            w.arith(e);
+        };
+        match arith {
+            Arith::Var(name) => {
+                self.open("Var");
+                self.str_field("name", name);
+            }
+            Arith::Elem(name, index) => self.arith_elem("Elem", name, index),
+            Arith::Lit(n) => {
+                self.open("Lit");
+                self.key("value");
+                self.number(n);
+            }
+            Arith::Add(l, r) => binary("Add", l, r, self),
+            Arith::Sub(l, r) => binary("Sub", l, r, self),
+            Arith::Mul(l, r) => binary("Mul", l, r, self),
+            Arith::Div(l, r) => binary("Div", l, r, self),
+            Arith::Rem(l, r) => binary("Rem", l, r, self),
+            Arith::Pow(l, r) => binary("Pow", l, r, self),
+            Arith::Lt(l, r) => binary("Lt", l, r, self),
+            Arith::Le(l, r) => binary("Le", l, r, self),
+            Arith::Gt(l, r) => binary("Gt", l, r, self),
+            Arith::Ge(l, r) => binary("Ge", l, r, self),
+            Arith::Eq(l, r) => binary("Eq", l, r, self),
+            Arith::Ne(l, r) => binary("Ne", l, r, self),
+            Arith::BitAnd(l, r) => binary("BitAnd", l, r, self),
+            Arith::BitOr(l, r) => binary("BitOr", l, r, self),
+            Arith::BitXor(l, r) => binary("BitXor", l, r, self),
+            Arith::LogAnd(l, r) => binary("LogAnd", l, r, self),
+            Arith::LogOr(l, r) => binary("LogOr", l, r, self),
+            Arith::Shl(l, r) => binary("Shl", l, r, self),
+            Arith::Shr(l, r) => binary("Shr", l, r, self),
+            Arith::Comma(l, r) => binary("Comma", l, r, self),
+            Arith::Pos(e) => unary("Pos", e, self),
+            Arith::Neg(e) => unary("Neg", e, self),
+            Arith::LogNot(e) => unary("LogNot", e, self),
+            Arith::BitNot(e) => unary("BitNot", e, self),
+            Arith::PreInc(name) => self.arith_name("PreInc", name),
+            Arith::PostInc(name) => self.arith_name("PostInc", name),
+            Arith::PreDec(name) => self.arith_name("PreDec", name),
+            Arith::PostDec(name) => self.arith_name("PostDec", name),
+            Arith::ElemPreInc(name, index) => self.arith_elem("ElemPreInc", name, index),
+            Arith::ElemPostInc(name, index) => self.arith_elem("ElemPostInc", name, index),
+            Arith::ElemPreDec(name, index) => self.arith_elem("ElemPreDec", name, index),
+            Arith::ElemPostDec(name, index) => self.arith_elem("ElemPostDec", name, index),
+            Arith::Ternary(c, t, f) => {
+                self.open("Ternary");
+                self.key("cond");
+                self.arith(c);
+                self.key("then");
+                self.arith(t);
+                self.key("else");
+                self.arith(f);
+            }
+            Arith::Assign(name, e) => {
+                self.open("Assign");
+                self.str_field("name", name);
+                self.key("value");
+                self.arith(e);
+            }
+            Arith::ElemAssign(name, index, e) => {
+                self.arith_elem("ElemAssign", name, index);
+                self.key("value");
+                self.arith(e);
+            }
+        }
+        self.close();
+    }
+
+    /// Fields of an increment or decrement; the caller closes the node.
+    fn arith_name(&mut self, kind: &str, name: &str) {
+        self.open(kind);
+        self.str_field("name", name);
+    }
+
+    /// Fields of an array element node; the caller closes the node.
+    fn arith_elem(&mut self, kind: &str, name: &str, index: &Arith<'_>) {
+        self.open(kind);
+        self.str_field("name", name);
+        self.key("index");
+        self.arith(index);
+    }
+
+    // --- Conditional expressions ---
+
+    fn cond(&mut self, expr: &CondExpr<'_>) {
+        let binary = |kind, l: &CondExpr<'_>, r: &CondExpr<'_>, w: &mut Self| {
+            w.open(kind);
+            w.key("left");
+            w.cond(l);
+            w.key("right");
+            w.cond(r);
+        };
+        match expr {
+            CondExpr::Unary(op, w) => {
+                self.open("Unary");
+                self.str_field("op", &format!("-{op}"));
+                self.word_field("operand", w);
+            }
+            CondExpr::Binary(l, op, r) => {
+                self.open("Binary");
+                self.word_field("left", l);
+                self.str_field("op", op.as_str());
+                self.word_field("right", r);
+            }
+            CondExpr::Word(w) => {
+                self.open("Word");
+                self.word_field("word", w);
+            }
+            CondExpr::Not(e) => {
+                self.open("Not");
+                self.key("operand");
+                self.cond(e);
+            }
+            CondExpr::And(l, r) => binary("And", l, r, self),
+            CondExpr::Or(l, r) => binary("Or", l, r, self),
+            CondExpr::Group(e) => {
+                self.open("Group");
+                self.key("expr");
+                self.cond(e);
+            }
+        }
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn json(input: &str) -> String {
+        to_json(&Parser::new(input).parse().unwrap())
+    }
+
+    #[test]
+    fn string_escapes() {
+        let mut w = Writer::default();
+        w.string("a\"b\\c\nd\te\u{1}é");
+        assert_eq!(w.out, r#""a\"b\\c\nd\te\u0001é""#);
+    }
+
+    #[test]
+    fn empty_script() {
+        assert_eq!(json(""), "[]");
+    }
+
+    #[test]
+    fn spans_on_commands() {
+        let out = json("a | b &");
+        assert!(out.starts_with(r#"[{"kind":"Job","span":{"start":0,"end":5}"#), "{out}");
+        assert!(out.contains(r#"{"kind":"Pipe","span":{"start":0,"end":5},"negated":false"#),
+            "{out}");
+        assert!(out.contains(r#"{"kind":"Simple","span":{"start":4,"end":5}"#), "{out}");
+    }
+
+    #[test]
+    fn spans_on_words() {
+        let input = "cat \"$x\" <in";
+        let (cmds, spans) = Parser::new(input).parse_with_spans().unwrap();
+        let out = to_json_with_spans(&cmds, &spans);
+        assert!(out.contains(concat!(
+            r#"{"kind":"Word","span":{"start":4,"end":8},"parts":[{"kind":"DQuoted","#,
+            r#""span":{"start":4,"end":8},"atoms":[{"kind":"Param","span":{"start":5,"end":7},"#,
+            r#""param":{"kind":"Var","span":{"start":6,"end":7},"name":"x"}}]}]}"#,
+        )), "{out}");
+        assert!(out.contains(r#"{"kind":"Read","span":{"start":9,"end":12},"fd":null"#), "{out}");
+        assert!(!json(input).contains(r#"{"kind":"Word","span""#));
+    }
+
+    #[test]
+    fn case_modification_pattern() {
+        let out = json("echo ${x^^a}");
+        assert!(out.contains(concat!(
+            r#"{"kind":"Upper","all":true,"param":{"kind":"Var","name":"x"},"#,
+            r#""pattern":{"kind":"Word","parts":[{"kind":"Lit","value":"a"}]}}"#,
+        )), "{out}");
+    }
+
+    #[test]
+    fn timed_pipeline() {
+        let out = json("time -p a");
+        assert!(out.contains(concat!(
+            r#""first":{"kind":"Timed","span":{"start":8,"end":9},"posix":true,"#,
+            r#""pipeline":{"kind":"Single","span":{"start":8,"end":9},"command":"#,
+        )), "{out}");
+    }
+
+    #[test]
+    fn compound_flattened_with_redirects() {
+        let out = json("while x; do y; done > out");
+        assert!(out.contains(r#"{"kind":"While","span":{"start":0,"end":25},"guard":["#), "{out}");
+        assert!(out.contains(concat!(
+            r#""redirects":[{"kind":"Write","fd":null,"#,
+            r#""target":{"kind":"Word","parts":[{"kind":"Lit","value":"out"}]}}]}"#,
+        )), "{out}");
+    }
+
+    #[test]
+    fn words_and_expansions() {
+        let out = json(r#"echo "$HOME" ${x:-d} $((1 + y))"#);
+        assert!(out.contains(
+            r#"{"kind":"DQuoted","atoms":[{"kind":"Param","param":{"kind":"Var","name":"HOME"}}]}"#
+        ), "{out}");
+        assert!(out.contains(concat!(
+            r#"{"kind":"Default","param":{"kind":"Var","name":"x"},"#,
+            r#""word":{"kind":"Word","parts":[{"kind":"Lit","value":"d"}]}}"#,
+        )), "{out}");
+        assert!(out.contains(concat!(
+            r#"{"kind":"Arith","expr":{"kind":"Add","left":{"kind":"Lit","value":1},"#,
+            r#""right":{"kind":"Var","name":"y"}}}"#,
+        )), "{out}");
+    }
+
+    #[test]
+    fn heredoc_body() {
+        let out = json("cat <<'E'\nhi \"x\"\nE");
+        let body = concat!(
+            r#"{"kind":"Heredoc","fd":null,"delimiter":"E","strip_tabs":false,"#,
+            r#""body":{"kind":"Literal","value":"hi \"x\"\n"}}"#,
+        );
+        assert!(out.contains(body), "{out}");
+        let out = json("cat 3<<-E\n\tx\nE");
+        assert!(out.contains(r#""fd":3,"delimiter":"E","strip_tabs":true"#), "{out}");
+    }
+
+    #[test]
+    fn double_bracket_tree() {
+        let out = json("[[ ! -f a || $x == y ]]");
+        assert!(out.contains(concat!(
+            r#""expr":{"kind":"Or","left":{"kind":"Not","operand":{"kind":"Unary","op":"-f","#,
+            r#""operand":{"kind":"Word","parts":[{"kind":"Lit","value":"a"}]}}},"#,
+            r#""right":{"kind":"Binary","left":"#,
+        )), "{out}");
+        assert!(out.contains(r#""op":"==","right":"#), "{out}");
+    }
+
+    #[test]
+    fn diagnostic_fields() {
+        let input = "if true; then echo hi";
+        let diag = Parser::new(input).parse().unwrap_err().diagnostic(input);
+        let out = diagnostic_to_json(&diag);
+        let message = r#"{"message":"expected 'fi' to close if statement","span":"#;
+        assert!(out.starts_with(message), "{out}");
+        assert!(out.ends_with(r#""found":"end of input","expected":["fi"]}"#), "{out}");
+    }
+
+    #[test]
+    fn brackets_balance() {
+        // Every construct the parser knows, checked for well-formed nesting
+        let out = json(concat!(
+            "f() { for x in a; do case $x in a) ;; esac; done; }; ",
+            "coproc c { cat; }; for ((i=0;i<1;i++)); do :; done; ",
+            "[[ -f a ]] && (( x++ )); a=(1 2) b[0]=1 c= cmd <(x) 2>&1 <<< w; ",
+            "echo ${#a} ${a[@]:1} ${x/a/b} ${x@Q} ${x^^} {a,b} {1..3} @(x|y) $'q' ~"
+        ));
+        let mut depth = 0i32;
+        let mut in_str = false;
+        let mut escaped = false;
+        for c in out.chars() {
+            if in_str {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_str = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_str = true,
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth -= 1,
+                _ => {}
+            }
+            assert!(depth >= 0, "{out}");
+        }
+        assert_eq!(depth, 0, "{out}");
+    }
+}
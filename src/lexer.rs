@@ -6,6 +6,8 @@
 
 use std::fmt;
 
+use crate::diagnostic::Diagnostic;
+
 /// Byte-oriented scanner for bash input. Operates on `&[u8]` with a position
 /// cursor. No token enum — the parser calls methods directly (peek/eat/read).
 /// Every read method returns `&'a str` — a zero-copy slice of the input.
@@ -20,12 +22,25 @@ pub(crate) struct Lexer<'a> {
 pub struct ParseError {
     pos: usize,
     msg: &'static str,
+    expected: &'static [&'static str],
+    incomplete: bool,
 }
 
 impl ParseError {
     /// Create a new parse error at the given byte offset.
     pub(crate) fn new(pos: usize, msg: &'static str) -> Self {
-        ParseError { pos, msg }
+        ParseError {
+            pos,
+            msg,
+            expected: &[],
+            incomplete: false,
+        }
+    }
+
+    /// Attach the tokens that would have been accepted at this position.
+    pub(crate) fn with_expected(mut self, expected: &'static [&'static str]) -> Self {
+        self.expected = expected;
+        self
     }
 
     /// Byte offset in the input where the error occurred.
@@ -57,6 +72,67 @@ impl ParseError {
     pub fn message(&self) -> &'static str {
         self.msg
     }
+
+    /// Tokens the parser would have accepted at [`position`](Self::position).
+    /// Empty when the error is not about a specific missing token.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// let err = Parser::new("if true; then echo hi").parse().unwrap_err();
+    /// assert_eq!(err.expected(), ["fi"]);
+    /// ```
+    #[must_use]
+    pub fn expected(&self) -> &'static [&'static str] {
+        self.expected
+    }
+
+    /// True when the parser ran out of input inside an open construct — an
+    /// unclosed `do`, `then`, `{`, quote, heredoc or trailing `&&` — so more
+    /// lines could still complete the command. False for errors that no
+    /// further input can fix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// assert!(Parser::new("for f in *; do").parse().unwrap_err().is_incomplete());
+    /// assert!(!Parser::new("echo )").parse().unwrap_err().is_incomplete());
+    /// ```
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    /// Mark the error as incomplete if it was raised at the end of an input
+    /// of `input_len` bytes.
+    pub(crate) fn at_end_of(mut self, input_len: usize) -> Self {
+        self.incomplete = self.pos >= input_len;
+        self
+    }
+
+    /// Move the error to byte offset `pos`, keeping everything else.
+    pub(crate) fn at(mut self, pos: usize) -> Self {
+        self.pos = pos;
+        self
+    }
+
+    /// Render this error against the input it came from, resolving line,
+    /// column and the offending token. See [`Diagnostic`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// let input = "echo ok\nfor x in a b; echo $x; done";
+    /// let diag = Parser::new(input).parse().unwrap_err().diagnostic(input);
+    /// assert_eq!((diag.line(), diag.column()), (2, 15));
+    /// ```
+    #[must_use]
+    pub fn diagnostic(&self, input: &str) -> Diagnostic {
+        Diagnostic::from_parse_error(self, input)
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -95,6 +171,11 @@ impl<'a> Lexer<'a> {
         self.pos >= self.src.len()
     }
 
+    /// Length of the input in bytes.
+    pub(crate) fn input_len(&self) -> usize {
+        self.src.len()
+    }
+
     /// Peek current byte. Returns 0 at EOF — NUL never appears in shell input.
     #[inline]
     #[must_use]
@@ -160,6 +241,16 @@ impl<'a> Lexer<'a> {
         self.pos += 1;
     }
 
+    /// Advance the cursor past one whole UTF-8 character. Used by error
+    /// recovery, which may skip bytes that no scanner has claimed.
+    #[inline]
+    pub(crate) fn bump_char(&mut self) {
+        self.pos += 1;
+        while self.pos < self.src.len() && !self.input.is_char_boundary(self.pos) {
+            self.pos += 1;
+        }
+    }
+
     /// Advance the cursor by `n` bytes.
     #[inline]
     pub(crate) fn bump_n(&mut self, n: usize) {
@@ -297,6 +388,17 @@ impl<'a> Lexer<'a> {
     pub(crate) fn err(&self, msg: &'static str) -> ParseError {
         ParseError::new(self.pos, msg)
     }
+
+    /// Create a parse error at the current position that names the tokens
+    /// which would have been accepted here.
+    #[must_use]
+    pub(crate) fn err_expected(
+        &self,
+        msg: &'static str,
+        expected: &'static [&'static str],
+    ) -> ParseError {
+        ParseError::new(self.pos, msg).with_expected(expected)
+    }
 }
 
 /// Shell metacharacters — terminate words and act as delimiters.
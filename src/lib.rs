@@ -8,7 +8,11 @@
 //! - [`detect`] — fast heuristic for identifying bash-specific syntax
 //! - [`translate`] — bash-to-fish translation via AST
 //! - [`parser`] — recursive-descent bash parser (produces [`ast`] nodes)
-//! - [`ast`] — zero-copy abstract syntax tree types
+//! - [`ast`] — abstract syntax tree types, borrowing from the input
+//! - [`visit`] — `Visit` / `VisitMut` traversal traits for the AST
+//! - [`printer`] — AST-to-bash pretty printer
+//! - [`json`] — JSON serialization of the AST for non-Rust tooling
+//! - [`diagnostic`] — line/column error reports with a caret snippet
 //! - [`passthrough`] — bash subprocess execution with environment diffing
 //! - [`daemon`] — persistent bash coprocess over a Unix domain socket
 //! - [`env_diff`] — environment snapshot capture and diffing
@@ -25,9 +29,13 @@
 pub mod ast;
 pub mod daemon;
 pub mod detect;
+pub mod diagnostic;
 pub mod env_diff;
+pub mod json;
 pub mod lexer;
 pub mod parser;
 pub mod passthrough;
+pub mod printer;
 pub mod state;
 pub mod translate;
+pub mod visit;
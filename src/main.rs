@@ -1,13 +1,19 @@
 //! Reef CLI — binary entry point.
 
+use std::io::Read;
 use std::path::Path;
 use std::process;
 
+use reef::diagnostic::Diagnostic;
+use reef::parser::Parser;
+use reef::printer::Printer;
+use reef::translate::TranslateError;
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("usage: reef <detect|translate|bash-exec|daemon> [flags] -- <command>");
+        eprintln!("usage: reef <detect|translate|fmt|parse|bash-exec|daemon> [flags] -- <command>");
         process::exit(2);
     }
 
@@ -19,6 +25,11 @@ fn main() {
             // --quick and full detection use the same heuristic for now;
             // full AST-based detection may be added later.
             let cmd = collect_after_dashdash(&args[2..]);
+            // --incomplete: exit 0 when the command needs more lines (an
+            // open `do`, quote, heredoc, `\` continuation, ...)
+            if has_flag(&args[2..], "--incomplete") {
+                process::exit(if Parser::new(&cmd).is_incomplete() { 0 } else { 1 });
+            }
             if reef::detect::looks_like_bash(&cmd) {
                 process::exit(0);
             } else {
@@ -27,16 +38,80 @@ fn main() {
         }
         "translate" => {
             let cmd = collect_after_dashdash(&args[2..]);
+            let explain = has_flag(&args[2..], "--explain");
             match reef::translate::translate_bash_to_fish(&cmd) {
                 Ok(fish_code) => print!("{fish_code}"),
+                Err(TranslateError::Parse(_)) => {
+                    // Re-parse with recovery so one run reports every error
+                    let (_, errors) = Parser::new(&cmd).parse_recovering();
+                    eprintln!("reef: translation failed");
+                    for err in errors {
+                        eprint!("{}", err.diagnostic(&cmd));
+                    }
+                    process::exit(1);
+                }
+                // --explain: say where the construct is and what to write
+                // instead
+                Err(TranslateError::Unsupported(u)) if explain => {
+                    let at = u.span().map_or_else(String::new, |span| {
+                        let diag = Diagnostic::new(&cmd, span, "");
+                        format!(" at {}:{}", diag.line(), diag.column())
+                    });
+                    let hint =
+                        u.kind().suggestion().map_or_else(String::new, |h| format!(" — {h}"));
+                    eprintln!("unsupported: {u}{at}{hint}");
+                    process::exit(1);
+                }
                 Err(e) => {
                     eprintln!("reef: translation failed: {e}");
                     process::exit(1);
                 }
             }
         }
+        "fmt" => {
+            let mut printer = Printer::new();
+            if let Some(width) = find_flag_value(&args[2..], "--indent") {
+                let width = width.parse().unwrap_or_else(|_| {
+                    eprintln!("reef fmt: --indent expects a number, got '{width}'");
+                    process::exit(2);
+                });
+                printer = printer.with_indent(width);
+            }
+            let src = read_source(&args[2..], "reef fmt");
+            let (cmds, errors) = Parser::new(&src).parse_recovering();
+            if !errors.is_empty() {
+                eprintln!("reef: formatting failed");
+                for err in errors {
+                    eprint!("{}", err.diagnostic(&src));
+                }
+                process::exit(1);
+            }
+            print!("{}", printer.print(&cmds));
+        }
+        "parse" => {
+            if !has_flag(&args[2..], "--json") {
+                eprintln!("usage: reef parse --json [-- <command>]");
+                process::exit(2);
+            }
+            let src = read_source(&args[2..], "reef parse");
+            // Report the recovered AST alongside every error, so editors
+            // still get structure for the parts that parsed
+            let (cmds, errors, spans) = Parser::new(&src).parse_recovering_with_spans();
+            let errors: Vec<_> = errors
+                .iter()
+                .map(|err| reef::json::diagnostic_to_json(&err.diagnostic(&src)))
+                .collect();
+            println!(
+                "{{\"commands\":{},\"errors\":[{}]}}",
+                reef::json::to_json_with_spans(&cmds, &spans),
+                errors.join(",")
+            );
+            if !errors.is_empty() {
+                process::exit(1);
+            }
+        }
         "bash-exec" => {
-            let env_diff = args[2..].iter().any(|a| a == "--env-diff");
+            let env_diff = has_flag(&args[2..], "--env-diff");
             let state_file = find_flag_value(&args[2..], "--state-file");
             let cmd = collect_after_dashdash(&args[2..]);
             let exit_code = if env_diff {
@@ -97,6 +172,26 @@ fn collect_after_dashdash(args: &[String]) -> String {
     }
 }
 
+/// Read the input for `fmt` / `parse`: a snippet after `--`, otherwise a
+/// whole script from stdin.
+fn read_source(args: &[String], cmd: &str) -> String {
+    if args.iter().any(|a| a == "--") {
+        return collect_after_dashdash(args);
+    }
+    let mut buf = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut buf) {
+        eprintln!("{cmd}: failed to read stdin: {e}");
+        process::exit(1);
+    }
+    buf
+}
+
+/// Whether `flag` is given as an option: before the `--` that starts the
+/// command, so the command itself may contain the same text.
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().take_while(|a| *a != "--").any(|a| a == flag)
+}
+
 /// Find the value of a `--flag value` pair in an argument list.
 fn find_flag_value(args: &[String], flag: &str) -> Option<String> {
     args.windows(2).find_map(|pair| {
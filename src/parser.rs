@@ -4,14 +4,98 @@
 //! Uses Pratt parsing for arithmetic expressions.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 
 use crate::ast::*;
 use crate::lexer::{Lexer, ParseError, is_meta};
+use crate::visit::{Visit, VisitMut, walk_atom, walk_redir, walk_word, walk_word_part};
 
 /// Recursive-descent parser for bash syntax. Produces an AST of [`Cmd`] nodes.
 pub struct Parser<'a> {
     lex: Lexer<'a>,
     heredoc_resume: Option<usize>,
+    /// The input ended in an unescaped `\`, which bash reads as a line
+    /// continuation when the line is entered interactively.
+    trailing_backslash: bool,
+    aliases: Option<Aliases<'a>>,
+    /// Aliases found in command position. Parsing goes on past one whose
+    /// value is just words and stops with an error at any other; it then
+    /// resumes at `stmt_start` on the text with all of them substituted.
+    alias_hits: Vec<AliasHit>,
+    /// Start of the top-level statement being parsed when the first alias
+    /// was found. The text before it is parsed for good.
+    stmt_start: usize,
+    /// Spans of the nodes kept in a [`SpanTable`], noted as each node is
+    /// finished: the order a post-order walk of the tree meets them.
+    noted: Vec<(SpannedNode, Span)>,
+}
+
+/// A position to backtrack to, with the nodes noted up to it.
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    pos: usize,
+    noted: usize,
+}
+
+/// The alias table, plus the stretches of text that came from expanding one.
+#[derive(Clone, Copy)]
+struct Aliases<'a> {
+    table: &'a HashMap<String, String>,
+    /// Sorted by start, an enclosing region before the ones inside it.
+    regions: &'a [AliasRegion],
+}
+
+/// Text substituted for an alias. The alias is not expanded again inside it.
+struct AliasRegion {
+    name: String,
+    start: usize,
+    end: usize,
+    /// The value ended in a blank, so the word after it is checked too.
+    trailing_blank: bool,
+    /// Index of the innermost region around this one.
+    parent: Option<usize>,
+}
+
+/// Index of the innermost region around `pos`. Regions nest, so it is the
+/// last one starting at or before `pos`, or one of that one's ancestors.
+fn innermost_region(regions: &[AliasRegion], pos: usize) -> Option<usize> {
+    let mut i = regions.partition_point(|r| r.start <= pos).checked_sub(1)?;
+    while pos >= regions[i].end {
+        i = regions[i].parent?;
+    }
+    Some(i)
+}
+
+/// The regions around `pos`, innermost first.
+fn regions_around(regions: &[AliasRegion], pos: usize) -> impl Iterator<Item = &AliasRegion> {
+    std::iter::successors(innermost_region(regions, pos), |&i| regions[i].parent)
+        .map(|i| &regions[i])
+}
+
+/// An alias name at `start..end` that should be substituted.
+#[derive(Clone, Copy)]
+struct AliasHit {
+    start: usize,
+    end: usize,
+    /// The value is just words, see [`is_plain_alias`].
+    plain: bool,
+}
+
+/// Whether an alias value is just words: substituting it cannot turn a
+/// later word into a command name or out of one, so the parse can go on
+/// past it and find the rest of the aliases in the same pass.
+fn is_plain_alias(value: &str) -> bool {
+    !value.contains([';', '&', '|', '<', '>', '(', ')', '`', '\\', '#', '\n'])
+        && Parser::new(value).top_level(&mut Vec::new()).is_ok()
+}
+
+/// One substitution of an alias batch: `old_len` bytes at `old` in the text
+/// before it became `new_len` bytes at `new` in the text after.
+struct Edit {
+    old: usize,
+    old_len: usize,
+    new: usize,
+    new_len: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -30,13 +114,52 @@ impl<'a> Parser<'a> {
         Parser {
             lex: Lexer::new(input),
             heredoc_resume: None,
+            trailing_backslash: false,
+            aliases: None,
+            alias_hits: Vec::new(),
+            stmt_start: 0,
+            noted: Vec::new(),
         }
     }
 
+    /// Expand aliases from `aliases` (name to replacement text) the way bash
+    /// does before it parses: an unquoted first word of a simple command
+    /// that names an alias is replaced by its value, which may hold several
+    /// words, pipes or keywords. A value ending in a blank makes the next
+    /// word an alias candidate too, and an alias is never expanded inside
+    /// its own replacement.
+    ///
+    /// Spans and error positions still point into the original input; nodes
+    /// that came from an alias value cover the alias name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use reef::parser::Parser;
+    /// use reef::printer::Printer;
+    ///
+    /// let aliases = HashMap::from([
+    ///     ("ll".to_string(), "ls -la".to_string()),
+    ///     ("sudo".to_string(), "sudo ".to_string()),
+    /// ]);
+    /// let cmds = Parser::new("sudo ll | head").with_aliases(&aliases).parse().unwrap();
+    /// assert_eq!(Printer::new().print(&cmds), "sudo ls -la | head\n");
+    /// ```
+    #[must_use]
+    pub fn with_aliases(mut self, aliases: &'a HashMap<String, String>) -> Self {
+        self.aliases = Some(Aliases {
+            table: aliases,
+            regions: &[],
+        });
+        self
+    }
+
     /// Parse the input into a list of commands.
     ///
-    /// Returns a `Vec<Cmd>` representing the top-level command list. Each
-    /// command borrows from the input string — no copying occurs.
+    /// Returns a `Vec<Cmd>` representing the top-level command list. Text
+    /// borrows from the input string, except for quoted heredoc delimiters and
+    /// commands produced by alias expansion, which are owned.
     ///
     /// # Errors
     ///
@@ -60,12 +183,325 @@ impl<'a> Parser<'a> {
     /// ```
     #[must_use = "parsing produces a result that should be inspected"]
     pub fn parse(mut self) -> Result<Vec<Cmd<'a>>, ParseError> {
-        self.cmd_list(&[])
+        self.parse_noting()
+    }
+
+    /// Parse the input like [`parse`](Self::parse), and locate the words,
+    /// word parts, atoms, parameters and redirections of the tree in a
+    /// [`SpanTable`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError`] for the same input as [`parse`](Self::parse).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::ast::{Cmd, CmdSuffix, Executable, Pipeline};
+    /// use reef::parser::Parser;
+    ///
+    /// let input = "cat <in.txt";
+    /// let (cmds, spans) = Parser::new(input).parse_with_spans().unwrap();
+    /// let Cmd::List(list) = &cmds[0] else { unreachable!() };
+    /// let Pipeline::Single(Executable::Simple(cmd)) = &list.first else { unreachable!() };
+    /// let CmdSuffix::Redirect(redir) = &cmd.suffix[1] else { unreachable!() };
+    /// assert_eq!(spans.redir(redir).unwrap().slice(input), "<in.txt");
+    /// ```
+    #[must_use = "parsing produces a result that should be inspected"]
+    pub fn parse_with_spans(mut self) -> Result<(Vec<Cmd<'a>>, SpanTable), ParseError> {
+        let cmds = self.parse_noting()?;
+        let spans = span_table(&cmds, &self.noted);
+        Ok((cmds, spans))
+    }
+
+    /// The body of [`parse`](Self::parse), which leaves the spans of the
+    /// nodes of the tree in `noted`.
+    fn parse_noting(&mut self) -> Result<Vec<Cmd<'a>>, ParseError> {
+        let mut cmds = Vec::new();
+        let result = self.top_level(&mut cmds);
+        if self.alias_hits.is_empty() {
+            return result.map(|()| cmds);
+        }
+        let mut exp = Expansion::new(self);
+        let mut done = owned(cmds);
+        let hits = std::mem::take(&mut self.alias_hits);
+        let result = exp.run(hits, self.stmt_start, |p| {
+            let mut cmds = Vec::new();
+            let result = p.top_level(&mut cmds);
+            done.extend(owned(cmds));
+            self.noted.append(&mut p.noted);
+            result
+        });
+        exp.restore_noted(&mut self.noted);
+        result.map(|()| exp.restore_spans(done)).map_err(|e| exp.restore_error(e))
+    }
+
+    /// Check whether the input stops in the middle of a command, the way
+    /// an interactive bash would show its `PS2` prompt: an open `do`,
+    /// `then`, `{`, quote or heredoc, a trailing `&&` / `||` / `|`, or a
+    /// trailing `\` continuation.
+    ///
+    /// Returns false both for complete input and for input that is invalid
+    /// no matter what follows; see [`ParseError::is_incomplete`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// assert!(Parser::new("for f in *; do").is_incomplete());
+    /// assert!(Parser::new("echo \"unclosed").is_incomplete());
+    /// assert!(Parser::new("ls -la \\").is_incomplete());
+    /// assert!(!Parser::new("for f in *; do echo $f; done").is_incomplete());
+    /// assert!(!Parser::new("echo )").is_incomplete());
+    /// ```
+    #[must_use]
+    pub fn is_incomplete(mut self) -> bool {
+        fn check(p: &mut Parser<'_>) -> bool {
+            match p.top_level(&mut Vec::new()) {
+                Ok(()) => p.trailing_backslash,
+                Err(e) => e.is_incomplete(),
+            }
+        }
+        let incomplete = check(&mut self);
+        if self.alias_hits.is_empty() {
+            return incomplete;
+        }
+        let hits = std::mem::take(&mut self.alias_hits);
+        Expansion::new(&self).run(hits, self.stmt_start, check)
+    }
+
+    /// Parse the input, recovering from errors instead of stopping at the
+    /// first one.
+    ///
+    /// After an error the parser resynchronizes at the next statement
+    /// boundary (`;` or newline). If the broken statement opened compound
+    /// commands, it first skips to their closers: the body of a broken `for`
+    /// or `if` is dropped rather than kept as top-level statements.
+    ///
+    /// Returns every command that parsed cleanly, in source order, plus
+    /// every error encountered. The error list is empty exactly when
+    /// [`parse`](Self::parse) would succeed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// let input = "echo ok\nif true; then echo ${x; fi\nfor; done\necho still ok";
+    /// let (cmds, errors) = Parser::new(input).parse_recovering();
+    /// assert_eq!(cmds.len(), 2);
+    /// assert_eq!(errors.len(), 2);
+    /// ```
+    #[must_use]
+    pub fn parse_recovering(mut self) -> (Vec<Cmd<'a>>, Vec<ParseError>) {
+        self.recover_noting()
+    }
+
+    /// Parse the input like [`parse_recovering`](Self::parse_recovering),
+    /// and locate the words, word parts, atoms, parameters and
+    /// redirections of the commands that parsed in a [`SpanTable`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// let (cmds, errors, _spans) = Parser::new("echo (\necho ok").parse_recovering_with_spans();
+    /// assert_eq!((cmds.len(), errors.len()), (1, 1));
+    /// ```
+    #[must_use]
+    pub fn parse_recovering_with_spans(mut self) -> (Vec<Cmd<'a>>, Vec<ParseError>, SpanTable) {
+        let (cmds, errors) = self.recover_noting();
+        let spans = span_table(&cmds, &self.noted);
+        (cmds, errors, spans)
+    }
+
+    /// The body of [`parse_recovering`](Self::parse_recovering), which
+    /// leaves the spans of the nodes of the tree in `noted`.
+    fn recover_noting(&mut self) -> (Vec<Cmd<'a>>, Vec<ParseError>) {
+        let mut cmds = Vec::new();
+        let mut errors = Vec::new();
+        self.recover(&mut cmds, &mut errors);
+        if self.alias_hits.is_empty() {
+            return (cmds, errors);
+        }
+        let mut exp = Expansion::new(self);
+        let mut done = owned(cmds);
+        let hits = std::mem::take(&mut self.alias_hits);
+        exp.run(hits, self.stmt_start, |p| {
+            let mut cmds = Vec::new();
+            p.recover(&mut cmds, &mut errors);
+            done.extend(owned(cmds));
+            self.noted.append(&mut p.noted);
+        });
+        exp.restore_noted(&mut self.noted);
+        let errors = errors.into_iter().map(|e| exp.restore_error(e)).collect();
+        (exp.restore_spans(done), errors)
+    }
+
+    /// The top-level command list, parsed into `cmds`. On an alias hit the
+    /// commands from `stmt_start` on are dropped again, to be parsed anew.
+    fn top_level(&mut self, cmds: &mut Vec<Cmd<'a>>) -> Result<(), ParseError> {
+        let mut kept = (cmds.len(), self.noted.len());
+        let result = loop {
+            self.skip_separators();
+            if self.mark_statement() {
+                kept = (cmds.len(), self.noted.len());
+            }
+            if self.lex.is_eof() {
+                break Ok(());
+            }
+            let before = self.lex.pos();
+            match self.cmd() {
+                Ok(_) if self.lex.pos() == before => break Err(self.lex.err("unexpected token")),
+                Ok(cmd) => cmds.push(cmd),
+                Err(e) => break Err(e),
+            }
+        };
+        if !self.alias_hits.is_empty() {
+            cmds.truncate(kept.0);
+            self.noted.truncate(kept.1);
+        }
+        result.map_err(|e| e.at_end_of(self.lex.input_len()))
+    }
+
+    /// Record the cursor as the start of the next top-level statement,
+    /// unless a heredoc body is still to come or an alias was found already.
+    /// Returns whether it did.
+    fn mark_statement(&mut self) -> bool {
+        let mark = self.heredoc_resume.is_none() && self.alias_hits.is_empty();
+        if mark {
+            self.stmt_start = self.lex.pos();
+        }
+        mark
+    }
+
+    /// The body of [`parse_recovering`](Self::parse_recovering), stopping
+    /// early on an alias hit with the results from `stmt_start` on dropped.
+    fn recover(&mut self, cmds: &mut Vec<Cmd<'a>>, errors: &mut Vec<ParseError>) {
+        const DANGLING: &[&[u8]] =
+            &[b"fi", b"done", b"esac", b"}", b"then", b"do", b"else"];
+        let mut kept = (cmds.len(), errors.len(), self.noted.len());
+        loop {
+            self.skip_separators();
+            if self.mark_statement() {
+                kept = (cmds.len(), errors.len(), self.noted.len());
+            }
+            if self.lex.is_eof() {
+                break;
+            }
+            if self.lex.peek() == b'#' {
+                self.lex.skip_comment();
+                continue;
+            }
+            // A closer (or `then`/`do`/`else`) at statement level belongs to
+            // a compound command we already gave up on.
+            if !errors.is_empty()
+                && let Some(kw) = DANGLING.iter().find(|kw| self.lex.at_keyword(kw))
+            {
+                self.lex.bump_n(kw.len());
+                continue;
+            }
+            let before = self.checkpoint();
+            let err = match self.cmd() {
+                Ok(_) if self.lex.pos() == before.pos => self.lex.err("unexpected token"),
+                Ok(cmd) => {
+                    cmds.push(cmd);
+                    continue;
+                }
+                Err(_) if !self.alias_hits.is_empty() => break,
+                Err(e) => e,
+            };
+            let err = err.at_end_of(self.lex.input_len());
+            errors.push(err);
+            self.heredoc_resume = None;
+            self.resync(before.pos, err.position());
+            // The broken statement is dropped, with the nodes noted in it
+            // and while skipping it
+            self.noted.truncate(before.noted);
+        }
+        if !self.alias_hits.is_empty() {
+            cmds.truncate(kept.0);
+            errors.truncate(kept.1);
+            self.noted.truncate(kept.2);
+        }
+    }
+
+    /// Skip ahead past the statement that failed at `err_pos`: rescan it
+    /// from `stmt_start`, counting the compound commands it opens, and stop
+    /// at the first statement boundary after the error where all of them
+    /// are closed again. Always makes progress past `stmt_start`.
+    fn resync(&mut self, stmt_start: usize, err_pos: usize) {
+        const OPENERS: &[&[u8]] = &[b"if", b"for", b"select", b"case", b"while", b"until", b"{"];
+        const CLOSERS: &[&[u8]] = &[b"fi", b"done", b"esac", b"}"];
+        self.lex.set_pos(stmt_start);
+        let mut depth = 0usize;
+        // Whether the next word is in command position, where keywords count
+        let mut cmd_pos = true;
+        loop {
+            self.lex.skip_blanks();
+            match self.lex.peek() {
+                0 if self.lex.is_eof() => break,
+                b';' | b'\n' | b'&' | b'|' | b'(' | b')' => {
+                    let boundary = matches!(self.lex.peek(), b';' | b'\n');
+                    if self.lex.peek() == b'\n' {
+                        self.newline();
+                    } else {
+                        self.lex.bump();
+                    }
+                    if boundary && depth == 0 && self.lex.pos() > err_pos {
+                        break;
+                    }
+                    cmd_pos = true;
+                }
+                b'#' => self.lex.skip_comment(),
+                _ => {
+                    if cmd_pos {
+                        if let Some(kw) = OPENERS.iter().find(|kw| self.lex.at_keyword(kw)) {
+                            self.lex.bump_n(kw.len());
+                            depth += 1;
+                            // `for`/`select`/`case` are followed by words, not commands
+                            cmd_pos = matches!(*kw, b"if" | b"while" | b"until" | b"{");
+                            continue;
+                        }
+                        if let Some(kw) = CLOSERS.iter().find(|kw| self.lex.at_keyword(kw)) {
+                            self.lex.bump_n(kw.len());
+                            depth = depth.saturating_sub(1);
+                            cmd_pos = false;
+                            continue;
+                        }
+                        if [&b"then"[..], b"do", b"else", b"elif", b"!"]
+                            .iter()
+                            .any(|kw| self.lex.at_keyword(kw))
+                        {
+                            self.word().ok();
+                            continue;
+                        }
+                    }
+                    cmd_pos = false;
+                    // Step over whole redirections (so heredoc bodies are
+                    // skipped at the newline) and words (so quoted
+                    // separators don't end the statement early); fall back
+                    // to a single byte.
+                    let pos = self.lex.pos();
+                    if matches!(self.try_redirect(), Ok(Some(_))) {
+                        continue;
+                    }
+                    self.lex.set_pos(pos);
+                    if self.word().is_err() || self.lex.pos() == pos {
+                        self.lex.set_pos(pos);
+                        self.lex.bump_char();
+                    }
+                }
+            }
+        }
+        if self.lex.pos() <= stmt_start {
+            self.lex.set_pos(stmt_start);
+            self.lex.bump_char();
+        }
     }
 
     /// Parse a heredoc body with variable/command expansion (unquoted delimiter).
     /// Similar to double-quoted parsing but stops at EOF.
-    pub(crate) fn parse_heredoc_body(mut self) -> Result<Vec<Atom<'a>>, ParseError> {
+    pub(crate) fn parse_heredoc_body(&mut self) -> Result<Vec<Atom<'a>>, ParseError> {
         let mut atoms = Vec::new();
         let mut lit_start = self.lex.pos();
 
@@ -73,9 +509,11 @@ impl<'a> Parser<'a> {
             match self.lex.peek() {
                 b'$' => {
                     if self.lex.pos() > lit_start {
-                        atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+                        atoms.push(self.lit(lit_start));
                     }
+                    let start = self.lex.pos();
                     atoms.push(self.dollar()?);
+                    self.note(SpannedNode::Atom, start);
                     lit_start = self.lex.pos();
                 }
                 b'\\' => {
@@ -83,8 +521,9 @@ impl<'a> Parser<'a> {
                     let next = self.lex.peek_at(1);
                     if matches!(next, b'$' | b'\\' | b'`' | b'\n') {
                         if self.lex.pos() > lit_start {
-                            atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+                            atoms.push(self.lit(lit_start));
                         }
+                        let start = self.lex.pos();
                         self.lex.bump(); // skip backslash
                         if self.lex.peek() == b'\n' {
                             // line continuation — skip newline
@@ -93,6 +532,7 @@ impl<'a> Parser<'a> {
                             let esc_start = self.lex.pos();
                             self.lex.bump();
                             atoms.push(Atom::Escaped(Cow::Borrowed(self.lex.slice(esc_start))));
+                            self.note(SpannedNode::Atom, start);
                         }
                         lit_start = self.lex.pos();
                     } else {
@@ -102,9 +542,11 @@ impl<'a> Parser<'a> {
                 }
                 b'`' => {
                     if self.lex.pos() > lit_start {
-                        atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+                        atoms.push(self.lit(lit_start));
                     }
+                    let start = self.lex.pos();
                     atoms.push(self.backtick()?);
+                    self.note(SpannedNode::Atom, start);
                     lit_start = self.lex.pos();
                 }
                 _ => {
@@ -114,7 +556,7 @@ impl<'a> Parser<'a> {
         }
 
         if self.lex.pos() > lit_start {
-            atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+            atoms.push(self.lit(lit_start));
         }
         Ok(atoms)
     }
@@ -152,10 +594,6 @@ impl<'a> Parser<'a> {
     fn cmd(&mut self) -> Result<Cmd<'a>, ParseError> {
         let list = self.and_or()?;
         self.lex.skip_blanks();
-        // If a heredoc was encountered, jump past its body
-        if let Some(pos) = self.heredoc_resume.take() {
-            self.lex.set_pos(pos);
-        }
         if self.lex.eat(b'&') && self.lex.peek() != b'&' && self.lex.peek() != b'>' {
             Ok(Cmd::Job(list))
         } else {
@@ -165,6 +603,8 @@ impl<'a> Parser<'a> {
 
     /// Parse an and-or list: `pipeline ( && pipeline | || pipeline )*`
     fn and_or(&mut self) -> Result<AndOrList<'a>, ParseError> {
+        self.lex.skip_blanks();
+        let start = self.lex.pos();
         let first = self.pipeline()?;
         let mut rest = Vec::new();
         loop {
@@ -172,21 +612,48 @@ impl<'a> Parser<'a> {
             if self.lex.peek() == b'&' && self.lex.peek_at(1) == b'&' {
                 self.lex.bump_n(2);
                 self.skip_separators();
+                if self.lex.is_eof() {
+                    return Err(self.lex.err("expected command after '&&'"));
+                }
                 rest.push(AndOr::And(self.pipeline()?));
             } else if self.lex.peek() == b'|' && self.lex.peek_at(1) == b'|' {
                 self.lex.bump_n(2);
                 self.skip_separators();
+                if self.lex.is_eof() {
+                    return Err(self.lex.err("expected command after '||'"));
+                }
                 rest.push(AndOr::Or(self.pipeline()?));
             } else {
                 break;
             }
         }
-        Ok(AndOrList { first, rest })
+        let end = rest.last().map_or(first.span().end, |link| link.span().end);
+        Ok(AndOrList {
+            first,
+            rest,
+            span: Span::new(start, end.max(start)),
+        })
     }
 
-    /// Parse a pipeline: `[!] executable ( | executable )*`
+    /// Parse a pipeline: `[time [-p]] [!] executable ( | executable )*`
     fn pipeline(&mut self) -> Result<Pipeline<'a>, ParseError> {
         self.lex.skip_blanks();
+        if self.lex.at_keyword(b"time") {
+            let start = self.checkpoint();
+            self.lex.bump_n(4);
+            self.lex.skip_blanks();
+            let posix = self.lex.at_keyword(b"-p");
+            if posix {
+                self.lex.bump_n(2);
+                self.lex.skip_blanks();
+            }
+            // A bare `time` (or `time -p`) is left to run as a command
+            if !self.at_terminator() {
+                let pipeline = Box::new(self.pipeline()?);
+                return Ok(Pipeline::Timed { posix, pipeline });
+            }
+            self.rewind(start);
+        }
         let negated = self.lex.peek() == b'!' && is_meta(self.lex.peek_at(1));
         if negated {
             self.lex.bump();
@@ -208,15 +675,21 @@ impl<'a> Parser<'a> {
             self.lex.bump(); // skip |
             if pipe_stderr {
                 self.lex.bump(); // skip &
-                // Add 2>&1 redirect to previous command
-                let redir_2to1 =
-                    Redir::DupWrite(Some(2), Word::Simple(WordPart::Bare(Atom::Lit("1"))));
+                // Add 2>&1 redirect to previous command, located at the `|&`
+                let start = self.lex.pos() - 2;
+                let part = self.bare(Atom::Lit(Cow::Borrowed("1")), start);
+                let target = self.finish_word(vec![part], start);
+                self.note(SpannedNode::Redir, start);
+                let redir_2to1 = Redir::DupWrite(Some(2), target);
                 Self::add_redirect_to_exec(
                     cmds.last_mut().expect("pipe has at least one command"),
                     redir_2to1,
                 );
             }
             self.skip_separators();
+            if self.lex.is_eof() {
+                return Err(self.lex.err("expected command after '|'"));
+            }
             cmds.push(self.executable()?);
             self.lex.skip_blanks();
         }
@@ -237,49 +710,55 @@ impl<'a> Parser<'a> {
     /// Parse a single executable: compound command, function def, or simple command.
     fn executable(&mut self) -> Result<Executable<'a>, ParseError> {
         self.lex.skip_blanks();
+        let start = self.lex.pos();
 
         // Standalone (( )) arithmetic
         if self.lex.peek() == b'(' && self.lex.peek_at(1) == b'(' {
             let kind = self.standalone_arith()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
 
         // Compound commands by keyword / delimiter
         let b = self.lex.peek();
         if b == b'{' && is_meta(self.lex.peek_at(1)) {
             let kind = self.brace_group()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if b == b'(' && self.lex.peek_at(1) != b'(' {
             let kind = self.subshell()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"for") {
             let kind = self.for_cmd()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"while") {
             let kind = self.while_cmd()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"until") {
             let kind = self.until_cmd()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"if") {
             let kind = self.if_cmd()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"case") {
             let kind = self.case_cmd()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"select") {
-            return Err(self.lex.err("unsupported: select loop"));
+            let kind = self.select_cmd()?;
+            return self.wrap_compound(kind, start);
         }
         if self.lex.at_keyword(b"[[") {
             let kind = self.double_bracket()?;
-            return self.wrap_compound(kind);
+            return self.wrap_compound(kind, start);
+        }
+        if self.lex.at_keyword(b"coproc") {
+            let kind = self.coproc_cmd()?;
+            return self.wrap_compound(kind, start);
         }
 
         // Check for function definition: name()
@@ -292,10 +771,16 @@ impl<'a> Parser<'a> {
     }
 
     /// Wrap a compound kind with trailing redirects into an Executable.
-    fn wrap_compound(&mut self, kind: CompoundKind<'a>) -> Result<Executable<'a>, ParseError> {
+    fn wrap_compound(
+        &mut self,
+        kind: CompoundKind<'a>,
+        start: usize,
+    ) -> Result<Executable<'a>, ParseError> {
+        let (redirects, end) = self.collect_redirects()?;
         Ok(Executable::Compound(CompoundCmd {
             kind,
-            redirects: self.collect_redirects()?,
+            redirects,
+            span: Span::new(start, end),
         }))
     }
 
@@ -317,26 +802,92 @@ impl<'a> Parser<'a> {
             return Err(self.lex.err("expected variable name after 'for'"));
         }
         self.lex.skip_blanks();
+        let words = self.in_word_list()?;
 
-        let words = if self.lex.at_keyword(b"in") {
-            self.lex.eat_str(b"in");
-            self.lex.skip_blanks();
-            let mut words = Vec::new();
-            while !self.at_terminator() && !self.lex.at_keyword(b"do") {
-                words.push(self.word()?);
-                self.lex.skip_blanks();
-            }
-            Some(words)
-        } else {
-            None
-        };
+        self.eat_separator();
+        self.expect(&["do"], "expected 'do' after for loop header")?;
+        let body = self.cmd_list(&[b"done"])?;
+        self.expect(&["done"], "expected 'done' to close for loop")?;
+
+        Ok(CompoundKind::For { var: Cow::Borrowed(var), words, body })
+    }
+
+    fn select_cmd(&mut self) -> Result<CompoundKind<'a>, ParseError> {
+        self.lex.eat_str(b"select");
+        self.lex.skip_blanks();
+
+        let var = self.lex.read_name();
+        if var.is_empty() {
+            return Err(self.lex.err("expected variable name after 'select'"));
+        }
+        self.lex.skip_blanks();
+        let words = self.in_word_list()?;
 
         self.eat_separator();
-        self.expect(b"do", "expected 'do' after for loop header")?;
+        self.expect(&["do"], "expected 'do' after select header")?;
         let body = self.cmd_list(&[b"done"])?;
-        self.expect(b"done", "expected 'done' to close for loop")?;
+        self.expect(&["done"], "expected 'done' to close select loop")?;
+
+        Ok(CompoundKind::Select { var: Cow::Borrowed(var), words, body })
+    }
+
+    fn coproc_cmd(&mut self) -> Result<CompoundKind<'a>, ParseError> {
+        self.lex.eat_str(b"coproc");
+        self.lex.skip_blanks();
+
+        // `coproc NAME cmd` only names the coprocess when `cmd` is compound;
+        // otherwise the word is the simple command's name.
+        let save = self.checkpoint();
+        let candidate = self.lex.read_name();
+        let mut name = None;
+        if !candidate.is_empty()
+            && !matches!(
+                candidate,
+                "if" | "while" | "until" | "for" | "case" | "select" | "function"
+            )
+            && matches!(self.lex.peek(), b' ' | b'\t')
+        {
+            self.lex.skip_blanks();
+            if self.at_compound_start() {
+                name = Some(candidate);
+            }
+        }
+        if name.is_none() {
+            self.rewind(save);
+        }
+
+        if self.at_terminator() {
+            return Err(self.lex.err("expected command after 'coproc'"));
+        }
+        let body = Box::new(self.executable()?);
+        Ok(CompoundKind::Coproc { name: name.map(Cow::Borrowed), body })
+    }
+
+    /// True if the cursor is at the start of a compound command.
+    fn at_compound_start(&self) -> bool {
+        match self.lex.peek() {
+            b'(' => true,
+            b'{' => matches!(self.lex.peek_at(1), b' ' | b'\t' | b'\n'),
+            _ => self.lex.at_any_keyword(&[
+                b"if", b"while", b"until", b"for", b"case", b"select", b"[[",
+            ]),
+        }
+    }
 
-        Ok(CompoundKind::For { var, words, body })
+    /// Optional `in word...` list of a `for`/`select` header.
+    /// `None` means the clause was absent (iterate over `"$@"`).
+    fn in_word_list(&mut self) -> Result<Option<Vec<Word<'a>>>, ParseError> {
+        if !self.lex.at_keyword(b"in") {
+            return Ok(None);
+        }
+        self.lex.eat_str(b"in");
+        self.lex.skip_blanks();
+        let mut words = Vec::new();
+        while !self.at_terminator() && !self.lex.at_keyword(b"do") {
+            words.push(self.word()?);
+            self.lex.skip_blanks();
+        }
+        Ok(Some(words))
     }
 
     fn c_style_for(&mut self) -> Result<CompoundKind<'a>, ParseError> {
@@ -350,7 +901,7 @@ impl<'a> Parser<'a> {
         };
         self.lex.skip_blanks();
         if !self.lex.eat(b';') {
-            return Err(self.lex.err("expected ';' in C-style for"));
+            return Err(self.lex.err_expected("expected ';' in C-style for", &[";"]));
         }
         self.lex.skip_blanks();
 
@@ -361,7 +912,7 @@ impl<'a> Parser<'a> {
         };
         self.lex.skip_blanks();
         if !self.lex.eat(b';') {
-            return Err(self.lex.err("expected ';' in C-style for"));
+            return Err(self.lex.err_expected("expected ';' in C-style for", &[";"]));
         }
         self.lex.skip_blanks();
 
@@ -373,14 +924,14 @@ impl<'a> Parser<'a> {
         self.lex.skip_blanks();
 
         if !(self.lex.peek() == b')' && self.lex.peek_at(1) == b')') {
-            return Err(self.lex.err("expected '))' in C-style for"));
+            return Err(self.lex.err_expected("expected '))' in C-style for", &["))"]));
         }
         self.lex.bump_n(2);
 
         self.eat_separator();
-        self.expect(b"do", "expected 'do' after for((...)) header")?;
+        self.expect(&["do"], "expected 'do' after for((...)) header")?;
         let body = self.cmd_list(&[b"done"])?;
-        self.expect(b"done", "expected 'done' to close for loop")?;
+        self.expect(&["done"], "expected 'done' to close for loop")?;
 
         Ok(CompoundKind::CFor {
             init,
@@ -393,21 +944,25 @@ impl<'a> Parser<'a> {
     fn while_cmd(&mut self) -> Result<CompoundKind<'a>, ParseError> {
         self.lex.eat_str(b"while");
         self.skip_separators();
+        let start = self.lex.pos();
         let guard = self.cmd_list(&[b"do"])?;
-        self.expect(b"do", "expected 'do' after while condition")?;
+        self.expect(&["do"], "expected 'do' after while condition")?;
         let body = self.cmd_list(&[b"done"])?;
-        self.expect(b"done", "expected 'done' to close while loop")?;
-        Ok(CompoundKind::While(GuardBody { guard, body }))
+        let span = Self::guard_body_span(&guard, &body, start);
+        self.expect(&["done"], "expected 'done' to close while loop")?;
+        Ok(CompoundKind::While(GuardBody { guard, body, span }))
     }
 
     fn until_cmd(&mut self) -> Result<CompoundKind<'a>, ParseError> {
         self.lex.eat_str(b"until");
         self.skip_separators();
+        let start = self.lex.pos();
         let guard = self.cmd_list(&[b"do"])?;
-        self.expect(b"do", "expected 'do' after until condition")?;
+        self.expect(&["do"], "expected 'do' after until condition")?;
         let body = self.cmd_list(&[b"done"])?;
-        self.expect(b"done", "expected 'done' to close until loop")?;
-        Ok(CompoundKind::Until(GuardBody { guard, body }))
+        let span = Self::guard_body_span(&guard, &body, start);
+        self.expect(&["done"], "expected 'done' to close until loop")?;
+        Ok(CompoundKind::Until(GuardBody { guard, body, span }))
     }
 
     fn if_cmd(&mut self) -> Result<CompoundKind<'a>, ParseError> {
@@ -415,18 +970,22 @@ impl<'a> Parser<'a> {
         self.skip_separators();
 
         let mut conditionals = Vec::new();
+        let start = self.lex.pos();
         let guard = self.cmd_list(&[b"then"])?;
-        self.expect(b"then", "expected 'then' after if condition")?;
+        self.expect(&["then"], "expected 'then' after if condition")?;
         let body = self.cmd_list(&[b"elif", b"else", b"fi"])?;
-        conditionals.push(GuardBody { guard, body });
+        let span = Self::guard_body_span(&guard, &body, start);
+        conditionals.push(GuardBody { guard, body, span });
 
         while self.lex.at_keyword(b"elif") {
             self.lex.eat_str(b"elif");
             self.skip_separators();
+            let start = self.lex.pos();
             let guard = self.cmd_list(&[b"then"])?;
-            self.expect(b"then", "expected 'then' after elif condition")?;
+            self.expect(&["then"], "expected 'then' after elif condition")?;
             let body = self.cmd_list(&[b"elif", b"else", b"fi"])?;
-            conditionals.push(GuardBody { guard, body });
+            let span = Self::guard_body_span(&guard, &body, start);
+            conditionals.push(GuardBody { guard, body, span });
         }
 
         let else_branch = if self.lex.at_keyword(b"else") {
@@ -437,7 +996,7 @@ impl<'a> Parser<'a> {
             None
         };
 
-        self.expect(b"fi", "expected 'fi' to close if statement")?;
+        self.expect(&["fi"], "expected 'fi' to close if statement")?;
         Ok(CompoundKind::If {
             conditionals,
             else_branch,
@@ -449,13 +1008,14 @@ impl<'a> Parser<'a> {
         self.lex.skip_blanks();
         let word = self.word()?;
         self.lex.skip_blanks();
-        self.expect(b"in", "expected 'in' after case word")?;
+        self.expect(&["in"], "expected 'in' after case word")?;
         self.skip_separators();
 
         let mut arms = Vec::new();
         while !self.lex.at_keyword(b"esac") && !self.lex.is_eof() {
             // Optional ( before patterns
             self.lex.skip_blanks();
+            let arm_start = self.lex.pos();
             self.lex.eat(b'(');
             self.lex.skip_blanks();
 
@@ -470,33 +1030,43 @@ impl<'a> Parser<'a> {
 
             self.lex.skip_blanks();
             self.lex.eat(b')');
+            let pattern_end = self.lex.pos();
             // Only skip whitespace/newlines here — NOT semicolons.
             // A bare ;; right after ) means an empty body; skip_separators
             // would eat the ;; and break the terminator check.
             self.lex.skip_blanks();
             while self.lex.peek() == b'\n' {
-                self.lex.bump();
+                self.newline();
                 self.lex.skip_blanks();
             }
 
             let body = self.case_body()?;
+            let mut arm_end = body.last().map_or(pattern_end, |c| c.span().end);
 
-            // Eat ;; if present, error on ;& and ;;&
+            // Eat the terminator: ;; / ;& / ;;& (optional before esac)
             self.lex.skip_blanks();
-            if self.lex.peek() == b';' && self.lex.peek_at(1) == b';' {
-                if self.lex.peek_at(2) == b'&' {
-                    return Err(self.lex.err("unsupported: case ;;&"));
-                }
-                self.lex.bump_n(2);
-            } else if self.lex.peek() == b';' && self.lex.peek_at(1) == b'&' {
-                return Err(self.lex.err("unsupported: case fallthrough ;&"));
-            }
+            let terminator = match (self.lex.peek(), self.lex.peek_at(1), self.lex.peek_at(2)) {
+                (b';', b';', b'&') => Some((3, CaseTerminator::Continue)),
+                (b';', b';', _) => Some((2, CaseTerminator::Break)),
+                (b';', b'&', _) => Some((2, CaseTerminator::FallThrough)),
+                _ => None,
+            };
+            let terminator = terminator.map_or(CaseTerminator::Break, |(len, term)| {
+                self.lex.bump_n(len);
+                arm_end = self.lex.pos();
+                term
+            });
             self.skip_separators();
 
-            arms.push(CaseArm { patterns, body });
+            arms.push(CaseArm {
+                patterns,
+                body,
+                terminator,
+                span: Span::new(arm_start, arm_end),
+            });
         }
 
-        self.expect(b"esac", "expected 'esac' to close case statement")?;
+        self.expect(&["esac"], "expected 'esac' to close case statement")?;
         Ok(CompoundKind::Case { word, arms })
     }
 
@@ -511,7 +1081,8 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 match self.lex.peek() {
-                    b';' | b'\n' => self.lex.bump(),
+                    b';' => self.lex.bump(),
+                    b'\n' => self.newline(),
                     b'#' => self.lex.skip_comment(),
                     _ => break,
                 }
@@ -533,7 +1104,7 @@ impl<'a> Parser<'a> {
         let body = self.cmd_list(&[b"}"])?;
         self.lex.skip_blanks();
         if !self.lex.eat(b'}') {
-            return Err(self.lex.err("expected '}'"));
+            return Err(self.lex.err_expected("expected '}'", &["}"]));
         }
         Ok(CompoundKind::Brace(body))
     }
@@ -544,106 +1115,142 @@ impl<'a> Parser<'a> {
         let body = self.cmd_list(&[b")"])?;
         self.lex.skip_blanks();
         if !self.lex.eat(b')') {
-            return Err(self.lex.err("expected ')'"));
+            return Err(self.lex.err_expected("expected ')'", &[")"]));
         }
         Ok(CompoundKind::Subshell(body))
     }
 
-    /// Parse `[[ ... ]]` — split internal `&&`/`||` into an and-or list.
+    /// Parse `[[ ... ]]` into a conditional expression tree.
     fn double_bracket(&mut self) -> Result<CompoundKind<'a>, ParseError> {
-        fn build_test_cmd(words: Vec<Word<'_>>) -> Cmd<'_> {
-            let mut suffix = Vec::new();
-            suffix.push(CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::Lit(
-                "[[",
-            )))));
-            for w in words {
-                suffix.push(CmdSuffix::Word(w));
-            }
-            suffix.push(CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::Lit(
-                "]]",
-            )))));
-            Cmd::List(AndOrList {
-                first: Pipeline::Single(Executable::Simple(SimpleCmd {
-                    prefix: Vec::new(),
-                    suffix,
-                })),
-                rest: Vec::new(),
-            })
-        }
-
         self.lex.eat_str(b"[[");
+        let expr = self.cond_or()?;
         self.lex.skip_blanks();
+        if self.lex.is_eof() {
+            return Err(self.lex.err("unterminated [["));
+        }
+        if !self.lex.eat_str(b"]]") {
+            return Err(self.lex.err_expected("expected ']]'", &["]]", "&&", "||"]));
+        }
+        Ok(CompoundKind::DoubleBracket(expr))
+    }
 
-        // Collect all tokens inside [[ ]] as a command list.
-        // We need to handle && and || inside [[ ]] as splitting points.
-        let mut segments: Vec<(Vec<Word<'a>>, Option<&'a str>)> = Vec::new();
-        let mut current_words = Vec::new();
-
+    /// `cond_and ( || cond_and )*` — `||` binds loosest.
+    fn cond_or(&mut self) -> Result<CondExpr<'a>, ParseError> {
+        let mut left = self.cond_and()?;
         loop {
             self.lex.skip_blanks();
-            if self.lex.is_eof() {
-                return Err(self.lex.err("unterminated [["));
-            }
-            // Check for ]]
-            if self.lex.peek() == b']' && self.lex.peek_at(1) == b']' {
-                self.lex.bump_n(2);
-                segments.push((current_words, None));
-                break;
-            }
-            // Check for && or || inside [[ ]]
-            if self.lex.peek() == b'&' && self.lex.peek_at(1) == b'&' {
-                segments.push((current_words, Some("&&")));
-                current_words = Vec::new();
-                self.lex.bump_n(2);
-                continue;
-            }
-            if self.lex.peek() == b'|' && self.lex.peek_at(1) == b'|' {
-                segments.push((current_words, Some("||")));
-                current_words = Vec::new();
-                self.lex.bump_n(2);
-                continue;
+            if !self.lex.eat_str(b"||") {
+                return Ok(left);
             }
-            current_words.push(self.word_bracket()?);
+            let right = self.cond_and()?;
+            left = CondExpr::Or(Box::new(left), Box::new(right));
         }
+    }
 
-        if segments.len() == 1 {
-            let (words, _) = segments.into_iter().next().expect("len checked == 1");
-            return Ok(CompoundKind::DoubleBracket(vec![build_test_cmd(words)]));
+    /// `cond_not ( && cond_not )*`
+    fn cond_and(&mut self) -> Result<CondExpr<'a>, ParseError> {
+        let mut left = self.cond_not()?;
+        loop {
+            self.lex.skip_blanks();
+            if !self.lex.eat_str(b"&&") {
+                return Ok(left);
+            }
+            let right = self.cond_not()?;
+            left = CondExpr::And(Box::new(left), Box::new(right));
         }
+    }
 
-        // Multiple segments — build an and-or list
-        let mut iter = segments.into_iter();
-        let (first_words, first_op) = iter.next().expect("segments is non-empty");
-        let first_cmd = build_test_cmd(first_words);
-
-        let first_pipeline = Pipeline::Single(Executable::Compound(CompoundCmd {
-            kind: CompoundKind::DoubleBracket(vec![first_cmd]),
-            redirects: Vec::new(),
-        }));
-
-        let mut rest = Vec::new();
-        let mut pending_op = first_op;
+    /// `! cond_not | ( cond_or ) | -X word | word [op word]`
+    fn cond_not(&mut self) -> Result<CondExpr<'a>, ParseError> {
+        self.lex.skip_blanks();
+        if self.lex.is_eof() {
+            return Err(self.lex.err("unterminated [["));
+        }
+        if self.lex.peek() == b'!' && matches!(self.lex.peek_at(1), b' ' | b'\t') {
+            self.lex.bump();
+            let inner = self.cond_not()?;
+            return Ok(CondExpr::Not(Box::new(inner)));
+        }
+        if self.lex.eat(b'(') {
+            let inner = self.cond_or()?;
+            self.lex.skip_blanks();
+            if !self.lex.eat(b')') {
+                return Err(self.lex.err_expected("expected ')' in [[ ]]", &[")"]));
+            }
+            return Ok(CondExpr::Group(Box::new(inner)));
+        }
+        if self.at_cond_end() {
+            return Err(self.lex.err("expected conditional expression"));
+        }
 
-        for (words, op) in iter {
-            let test_cmd = build_test_cmd(words);
-            let pipe = Pipeline::Single(Executable::Compound(CompoundCmd {
-                kind: CompoundKind::DoubleBracket(vec![test_cmd]),
-                redirects: Vec::new(),
-            }));
-            match pending_op {
-                Some("||") => rest.push(AndOr::Or(pipe)),
-                _ => rest.push(AndOr::And(pipe)),
+        // -X word, unless -X is itself the left operand (`[[ -n == x ]]`)
+        // or a lone word (`[[ -f ]]`)
+        let start = self.checkpoint();
+        if self.lex.peek() == b'-'
+            && self.lex.peek_at(1).is_ascii_alphabetic()
+            && matches!(self.lex.peek_at(2), b' ' | b'\t')
+        {
+            let flag = char::from(self.lex.peek_at(1));
+            self.lex.bump_n(2);
+            self.lex.skip_blanks();
+            if !self.at_cond_end() && self.cond_binary_op().is_none() {
+                let operand = self.word_bracket(false)?;
+                return Ok(CondExpr::Unary(flag, operand));
             }
-            pending_op = op;
+            self.rewind(start);
         }
 
-        // Wrap the whole and-or list as a single command
-        let combined = Cmd::List(AndOrList {
-            first: first_pipeline,
-            rest,
-        });
+        let left = self.word_bracket(false)?;
+        self.lex.skip_blanks();
+        let Some((op, len)) = self.cond_binary_op() else {
+            return Ok(CondExpr::Word(left));
+        };
+        self.lex.bump_n(len);
+        self.lex.skip_blanks();
+        if self.at_cond_end() {
+            return Err(self.lex.err("expected operand after conditional operator"));
+        }
+        let right = self.word_bracket(op == CondOp::Regex)?;
+        Ok(CondExpr::Binary(left, op, right))
+    }
+
+    /// At `]]`, `&&`, `||`, `)` or end of input.
+    fn at_cond_end(&self) -> bool {
+        matches!(
+            (self.lex.peek(), self.lex.peek_at(1)),
+            (b']', b']') | (b'&', b'&') | (b'|', b'|') | (b')', _)
+        ) || self.lex.is_eof()
+    }
 
-        Ok(CompoundKind::DoubleBracket(vec![combined]))
+    /// The binary `[[ ]]` operator at the current position, with its length.
+    fn cond_binary_op(&self) -> Option<(CondOp, usize)> {
+        const OPS: &[(&str, CondOp)] = &[
+            ("==", CondOp::Match),
+            ("=~", CondOp::Regex),
+            ("!=", CondOp::NotMatch),
+            ("=", CondOp::Match),
+            ("-eq", CondOp::IntEq),
+            ("-ne", CondOp::IntNe),
+            ("-lt", CondOp::IntLt),
+            ("-le", CondOp::IntLe),
+            ("-gt", CondOp::IntGt),
+            ("-ge", CondOp::IntGe),
+            ("-nt", CondOp::NewerThan),
+            ("-ot", CondOp::OlderThan),
+            ("-ef", CondOp::SameFile),
+        ];
+        // `<` and `>` are operators even without surrounding blanks
+        match self.lex.peek() {
+            b'<' => return Some((CondOp::StrLt, 1)),
+            b'>' => return Some((CondOp::StrGt, 1)),
+            _ => {}
+        }
+        let rest = self.lex.remaining().as_bytes();
+        OPS.iter().find_map(|&(text, op)| {
+            let len = text.len();
+            let bounded = rest.get(len).is_none_or(|&b| matches!(b, b' ' | b'\t' | b'\n'));
+            (rest.starts_with(text.as_bytes()) && bounded).then_some((op, len))
+        })
     }
 
     /// Parse `(( expr ))` at command position.
@@ -658,11 +1265,12 @@ impl<'a> Parser<'a> {
             self.lex.bump_n(2);
             Ok(CompoundKind::Arithmetic(arith))
         } else {
-            Err(self.lex.err("expected '))'"))
+            Err(self.lex.err_expected("expected '))'", &["))"]))
         }
     }
 
     fn func_def(&mut self) -> Result<Executable<'a>, ParseError> {
+        let start = self.lex.pos();
         // Optional 'function' keyword
         if self.lex.at_keyword(b"function") {
             self.lex.eat_str(b"function");
@@ -677,7 +1285,7 @@ impl<'a> Parser<'a> {
         if self.lex.eat(b'(') {
             self.lex.skip_blanks();
             if !self.lex.eat(b')') {
-                return Err(self.lex.err("expected ')' in function definition"));
+                return Err(self.lex.err_expected("expected ')' in function definition", &[")"]));
             }
         }
         self.skip_separators();
@@ -688,14 +1296,19 @@ impl<'a> Parser<'a> {
         } else if self.lex.eat(b'(') {
             self.subshell()?
         } else {
-            return Err(self.lex.err("expected '{' or '(' after function name"));
+            return Err(self.lex.err_expected(
+                "expected '{' or '(' after function name",
+                &["{", "("],
+            ));
         };
 
+        let (redirects, end) = self.collect_redirects()?;
         Ok(Executable::FuncDef(
-            name,
+            Cow::Borrowed(name),
             CompoundCmd {
                 kind,
-                redirects: self.collect_redirects()?,
+                redirects,
+                span: Span::new(start, end),
             },
         ))
     }
@@ -708,6 +1321,8 @@ impl<'a> Parser<'a> {
         let mut prefix = Vec::new();
         let mut suffix = Vec::new();
         let mut saw_word = false;
+        let start = self.lex.pos();
+        let mut end = start;
 
         loop {
             self.lex.skip_blanks();
@@ -722,33 +1337,111 @@ impl<'a> Parser<'a> {
                 } else {
                     prefix.push(CmdPrefix::Redirect(redir));
                 }
+                end = self.lex.pos();
                 continue;
             }
 
             // Before the command name: assignments are possible
             if !saw_word && let Some(assign) = self.try_assignment()? {
                 prefix.push(assign);
+                end = self.lex.pos();
                 continue;
             }
 
+            // `declare arr=(...)` — declaration builtins take array assignments
+            if is_decl_builtin(&suffix) {
+                let word_start = self.checkpoint();
+                if let Some(CmdPrefix::ArrayAssign(name, words)) = self.try_assignment()? {
+                    suffix.push(CmdSuffix::ArrayAssign(name, words));
+                    end = self.lex.pos();
+                    continue;
+                }
+                self.rewind(word_start);
+            }
+
+            if (!saw_word || self.after_blank_alias())
+                && let Some(hit) = self.alias_at_cursor()
+            {
+                self.alias_hits.push(hit);
+                if !hit.plain {
+                    return Err(self.lex.err("alias expansion restarts the parse"));
+                }
+            }
+
             // Regular word
             suffix.push(CmdSuffix::Word(self.word()?));
+            end = self.lex.pos();
             saw_word = true;
         }
 
-        Ok(SimpleCmd { prefix, suffix })
+        Ok(SimpleCmd {
+            prefix,
+            suffix,
+            span: Span::new(start, end),
+        })
+    }
+
+    /// The alias named by the word at the cursor: a plain unquoted word in
+    /// the table that is not already being expanded here.
+    fn alias_at_cursor(&self) -> Option<AliasHit> {
+        let aliases = self.aliases?;
+        let start = self.lex.pos();
+        let mut len = 0;
+        while !is_meta(self.lex.peek_at(len)) {
+            if matches!(self.lex.peek_at(len), b'\'' | b'"' | b'\\' | b'$' | b'`' | b'/' | b'=') {
+                return None;
+            }
+            len += 1;
+        }
+        let name = self.lex.slice_range(start, start + len);
+        let value = aliases.table.get(name)?;
+        let expanding = regions_around(aliases.regions, start).any(|r| r.name == name);
+        (!expanding).then(|| AliasHit { start, end: start + len, plain: is_plain_alias(value) })
+    }
+
+    /// Whether the cursor is at the word right after an alias value that
+    /// ended in a blank: such a value ends inside the blanks before the
+    /// cursor.
+    fn after_blank_alias(&self) -> bool {
+        let Some(aliases) = self.aliases else { return false };
+        let pos = self.lex.pos();
+        let text = self.lex.slice_range(0, pos).as_bytes();
+        let blanks = text.iter().rev().take_while(|&&b| b == b' ' || b == b'\t').count();
+        (pos - blanks..pos).any(|blank| {
+            regions_around(aliases.regions, blank).any(|r| r.trailing_blank && r.end <= pos)
+        })
     }
 
-    /// Try to parse an assignment: `NAME=value`, `NAME=(word ...)`, or `NAME+=(word ...)`.
+    /// Try to parse an assignment: `NAME=value`, `NAME=(word ...)`, `NAME+=(word ...)`,
+    /// or `NAME[index]=value`.
     /// Returns None if not at an assignment (doesn't consume anything).
     fn try_assignment(&mut self) -> Result<Option<CmdPrefix<'a>>, ParseError> {
-        let start = self.lex.pos();
+        let start = self.checkpoint();
         let name = self.lex.read_name();
         if name.is_empty() {
             self.rewind(start);
             return Ok(None);
         }
 
+        // NAME[index]=value — anything else starting with `NAME[` is a word
+        if self.lex.peek() == b'[' {
+            self.lex.bump();
+            let index = match self.array_index_word() {
+                Ok(index) if self.lex.peek() == b']' && self.lex.peek_at(1) == b'=' => index,
+                _ => {
+                    self.rewind(start);
+                    return Ok(None);
+                }
+            };
+            self.lex.bump_n(2); // skip ]=
+            let value = if self.lex.peek() == 0 || is_meta(self.lex.peek()) {
+                None
+            } else {
+                Some(self.word()?)
+            };
+            return Ok(Some(CmdPrefix::ElementAssign(Cow::Borrowed(name), index, value)));
+        }
+
         // Check for += (array append)
         let is_append = self.lex.peek() == b'+' && self.lex.peek_at(1) == b'=';
         if is_append {
@@ -765,9 +1458,9 @@ impl<'a> Parser<'a> {
             self.lex.bump(); // skip (
             let words = self.array_elements()?;
             if is_append {
-                return Ok(Some(CmdPrefix::ArrayAppend(name, words)));
+                return Ok(Some(CmdPrefix::ArrayAppend(Cow::Borrowed(name), words)));
             }
-            return Ok(Some(CmdPrefix::ArrayAssign(name, words)));
+            return Ok(Some(CmdPrefix::ArrayAssign(Cow::Borrowed(name), words)));
         }
 
         // NAME=value or NAME+=value — parse the value if present
@@ -776,7 +1469,7 @@ impl<'a> Parser<'a> {
         } else {
             Some(self.word()?)
         };
-        Ok(Some(CmdPrefix::Assign(name, value)))
+        Ok(Some(CmdPrefix::Assign(Cow::Borrowed(name), value)))
     }
 
     /// Parse array elements inside `(...)`.
@@ -800,20 +1493,44 @@ impl<'a> Parser<'a> {
     // Words
     // -----------------------------------------------------------------------
 
-    /// Parse a complete word (may be a concatenation of multiple parts).
-    /// Parse a word inside `[[ ]]` — `(` and `)` are not metacharacters here.
-    fn word_bracket(&mut self) -> Result<Word<'a>, ParseError> {
+    /// Parse a word inside `[[ ]]`. Parens group, so they end a word — except
+    /// with `regex` set, where they (and blanks and `|` inside them) are part of
+    /// the pattern and extglob operators are plain characters.
+    fn word_bracket(&mut self, regex: bool) -> Result<Word<'a>, ParseError> {
+        let word_start = self.lex.pos();
         let mut parts = Vec::new();
+        let mut depth = 0usize;
         loop {
             if self.lex.is_eof() {
                 break;
             }
             let b = self.lex.peek();
-            // Inside [[ ]], ( and ) are literal (used in regex patterns)
-            if b == b'(' || b == b')' {
+            if regex && self.extglob_kind().is_some() {
+                let start = self.lex.pos();
+                self.lex.bump();
+                let part = self.bare(Atom::Lit(Cow::Borrowed(self.lex.slice(start))), start);
+                parts.push(part);
+                continue;
+            }
+            // Parens belong to a regex (and may hold blanks and `|`);
+            // elsewhere they group the expression
+            if regex && (b == b'(' || (b == b')' && depth > 0)) {
+                let start = self.lex.pos();
+                self.lex.bump();
+                if b == b'(' {
+                    depth += 1;
+                } else {
+                    depth -= 1;
+                }
+                let part = self.bare(Atom::Lit(Cow::Borrowed(self.lex.slice(start))), start);
+                parts.push(part);
+                continue;
+            }
+            if depth > 0 && matches!(b, b' ' | b'\t' | b'|') {
                 let start = self.lex.pos();
                 self.lex.bump();
-                parts.push(WordPart::Bare(Atom::Lit(self.lex.slice(start))));
+                let part = self.bare(Atom::Lit(Cow::Borrowed(self.lex.slice(start))), start);
+                parts.push(part);
                 continue;
             }
             if is_meta(b) {
@@ -824,14 +1541,11 @@ impl<'a> Parser<'a> {
         if parts.is_empty() {
             return Err(self.lex.err("expected word"));
         }
-        if parts.len() == 1 {
-            Ok(Word::Simple(parts.into_iter().next().expect("len checked == 1")))
-        } else {
-            Ok(Word::Concat(parts))
-        }
+        Ok(self.finish_word(parts, word_start))
     }
 
     fn word(&mut self) -> Result<Word<'a>, ParseError> {
+        let word_start = self.lex.pos();
         let mut parts = Vec::new();
         loop {
             if self.lex.is_eof() {
@@ -839,21 +1553,26 @@ impl<'a> Parser<'a> {
             }
             let b = self.lex.peek();
             // Process substitution <( or >( — parse even though < and > are meta
-            if b == b'<' && self.lex.peek_at(1) == b'(' {
+            if matches!(b, b'<' | b'>') && self.lex.peek_at(1) == b'(' {
+                let start = self.lex.pos();
                 self.lex.bump_n(2);
                 let cmds = self.cmd_list(&[b")"])?;
                 self.lex.skip_blanks();
                 if !self.lex.eat(b')') {
-                    return Err(self.lex.err("expected ')' for process substitution"));
+                    return Err(self.lex.err_expected(
+                        "expected ')' for process substitution",
+                        &[")"],
+                    ));
                 }
-                parts.push(WordPart::Bare(Atom::ProcSubIn(cmds)));
+                let atom = if b == b'<' {
+                    Atom::ProcSubIn(cmds)
+                } else {
+                    Atom::ProcSubOut(cmds)
+                };
+                let part = self.bare(atom, start);
+                parts.push(part);
                 continue;
             }
-            if b == b'>' && self.lex.peek_at(1) == b'(' {
-                return Err(self
-                    .lex
-                    .err("unsupported: output process substitution >(...)"));
-            }
             if is_meta(b) {
                 break;
             }
@@ -862,29 +1581,28 @@ impl<'a> Parser<'a> {
         if parts.is_empty() {
             return Err(self.lex.err("expected word"));
         }
-        if parts.len() == 1 {
-            Ok(Word::Simple(parts.into_iter().next().expect("len checked == 1")))
-        } else {
-            Ok(Word::Concat(parts))
-        }
+        Ok(self.finish_word(parts, word_start))
     }
 
     /// Parse a single word part: bare atoms, double-quoted, or single-quoted.
     fn word_part(&mut self) -> Result<WordPart<'a>, ParseError> {
+        let start = self.lex.pos();
         match self.lex.peek() {
             b'"' => {
                 self.lex.bump();
                 let atoms = self.dquoted()?;
+                self.note(SpannedNode::WordPart, start);
                 Ok(WordPart::DQuoted(atoms))
             }
             b'\'' => {
                 self.lex.bump();
                 let content = self.lex.scan_squote()?;
-                Ok(WordPart::SQuoted(content))
+                self.note(SpannedNode::WordPart, start);
+                Ok(WordPart::SQuoted(Cow::Borrowed(content)))
             }
             _ => {
                 let atom = self.atom()?;
-                Ok(WordPart::Bare(atom))
+                Ok(self.bare(atom, start))
             }
         }
     }
@@ -899,16 +1617,19 @@ impl<'a> Parser<'a> {
                 b'$' => {
                     // Flush accumulated literal
                     if self.lex.pos() > lit_start {
-                        atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+                        atoms.push(self.lit(lit_start));
                     }
+                    let start = self.lex.pos();
                     atoms.push(self.dollar()?);
+                    self.note(SpannedNode::Atom, start);
                     lit_start = self.lex.pos();
                 }
                 b'\\' => {
                     // Flush accumulated literal
                     if self.lex.pos() > lit_start {
-                        atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+                        atoms.push(self.lit(lit_start));
                     }
+                    let start = self.lex.pos();
                     self.lex.bump(); // skip backslash
                     if self.lex.is_eof() {
                         break;
@@ -916,13 +1637,16 @@ impl<'a> Parser<'a> {
                     let escaped_start = self.lex.pos();
                     self.lex.bump();
                     atoms.push(Atom::Escaped(Cow::Borrowed(self.lex.slice(escaped_start))));
+                    self.note(SpannedNode::Atom, start);
                     lit_start = self.lex.pos();
                 }
                 b'`' => {
                     if self.lex.pos() > lit_start {
-                        atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+                        atoms.push(self.lit(lit_start));
                     }
+                    let start = self.lex.pos();
                     atoms.push(self.backtick()?);
+                    self.note(SpannedNode::Atom, start);
                     lit_start = self.lex.pos();
                 }
                 _ => {
@@ -933,7 +1657,7 @@ impl<'a> Parser<'a> {
 
         // Flush trailing literal
         if self.lex.pos() > lit_start {
-            atoms.push(Atom::Lit(self.lex.slice(lit_start)));
+            atoms.push(self.lit(lit_start));
         }
 
         if !self.lex.eat(b'"') {
@@ -944,12 +1668,16 @@ impl<'a> Parser<'a> {
 
     /// Parse a single atom in an unquoted context.
     fn atom(&mut self) -> Result<Atom<'a>, ParseError> {
+        if let Some(kind) = self.extglob_kind() {
+            return self.extglob(kind);
+        }
         match self.lex.peek() {
             b'$' => self.dollar(),
             b'\\' => {
                 self.lex.bump();
                 if self.lex.is_eof() {
-                    Ok(Atom::Lit(""))
+                    self.trailing_backslash = true;
+                    Ok(Atom::Lit(Cow::Borrowed("")))
                 } else {
                     let start = self.lex.pos();
                     self.lex.bump();
@@ -980,11 +1708,12 @@ impl<'a> Parser<'a> {
                 // Try brace range {1..5}
                 if let Some(br) = self.try_brace_range() {
                     Ok(br)
+                } else if let Some(list) = self.try_brace_list()? {
+                    Ok(list)
                 } else {
-                    // Adjacent brace expansion check: }{
                     let start = self.lex.pos();
                     self.lex.bump();
-                    Ok(Atom::Lit(self.lex.slice(start)))
+                    Ok(Atom::Lit(Cow::Borrowed(self.lex.slice(start))))
                 }
             }
             b'`' => self.backtick(),
@@ -1007,6 +1736,7 @@ impl<'a> Parser<'a> {
                                 | b'{'
                                 | b'`'
                         )
+                        || self.extglob_kind().is_some()
                     {
                         break;
                     }
@@ -1016,7 +1746,51 @@ impl<'a> Parser<'a> {
                 if s.is_empty() {
                     return Err(self.lex.err("unexpected character"));
                 }
-                Ok(Atom::Lit(s))
+                Ok(Atom::Lit(Cow::Borrowed(s)))
+            }
+        }
+    }
+
+    /// Extglob operator at the cursor: one of `?*+@!` directly followed by `(`.
+    fn extglob_kind(&self) -> Option<ExtGlobKind> {
+        if self.lex.peek_at(1) != b'(' {
+            return None;
+        }
+        match self.lex.peek() {
+            b'?' => Some(ExtGlobKind::ZeroOrOne),
+            b'*' => Some(ExtGlobKind::ZeroOrMore),
+            b'+' => Some(ExtGlobKind::OneOrMore),
+            b'@' => Some(ExtGlobKind::ExactlyOne),
+            b'!' => Some(ExtGlobKind::Not),
+            _ => None,
+        }
+    }
+
+    /// Parse `op(pat|pat...)` after [`Self::extglob_kind`] matched.
+    fn extglob(&mut self, kind: ExtGlobKind) -> Result<Atom<'a>, ParseError> {
+        self.lex.bump_n(2); // skip operator and (
+        let mut alternatives = Vec::new();
+        let mut parts = Vec::new();
+        let mut start = self.lex.pos();
+        loop {
+            match self.lex.peek() {
+                b'|' | b')' => {
+                    let close = self.lex.peek() == b')';
+                    let word = self.finish_word(std::mem::take(&mut parts), start);
+                    alternatives.push(word);
+                    self.lex.bump();
+                    start = self.lex.pos();
+                    if close {
+                        return Ok(Atom::ExtGlob { kind, alternatives });
+                    }
+                }
+                b if self.lex.is_eof() || is_meta(b) => {
+                    return Err(self.lex.err_expected(
+                        "expected ')' to close extended glob",
+                        &[")", "|"],
+                    ));
+                }
+                _ => parts.push(self.word_part()?),
             }
         }
     }
@@ -1024,6 +1798,7 @@ impl<'a> Parser<'a> {
     /// Parse `$...` expansion: `$var`, `${...}`, `$(...)`, `$((...))`, or special param.
     fn dollar(&mut self) -> Result<Atom<'a>, ParseError> {
         self.lex.bump(); // skip $
+        let name_start = self.lex.pos();
 
         match self.lex.peek() {
             b'{' => {
@@ -1038,33 +1813,35 @@ impl<'a> Parser<'a> {
                         if (idx_byte == b'@' || idx_byte == b'*') && self.lex.peek_at(1) == b']' {
                             self.lex.bump_n(2); // skip @] or *]
                             if !self.lex.eat(b'}') {
-                                return Err(self.lex.err("expected '}'"));
+                                return Err(self.lex.err_expected("expected '}'", &["}"]));
                             }
-                            return Err(self.lex.err("unsupported: ${!arr[@]} indirect/keys"));
+                            return Ok(Atom::Subst(Box::new(Subst::ArrayKeys(Cow::Borrowed(name)))));
                         }
                     }
                     // ${!prefix*} or ${!prefix@} — list variable names matching prefix
                     if !name.is_empty() && matches!(self.lex.peek(), b'*' | b'@') {
                         self.lex.bump(); // skip * or @
                         if !self.lex.eat(b'}') {
-                            return Err(self.lex.err("expected '}'"));
+                            return Err(self.lex.err_expected("expected '}'", &["}"]));
                         }
-                        return Ok(Atom::Subst(Box::new(Subst::PrefixList(name))));
+                        return Ok(Atom::Subst(Box::new(Subst::PrefixList(Cow::Borrowed(name)))));
                     }
                     if name.is_empty() {
                         return Err(self.lex.err("expected variable name after ${!"));
                     }
                     if !self.lex.eat(b'}') {
-                        return Err(self.lex.err("expected '}'"));
+                        return Err(self.lex.err_expected("expected '}'", &["}"]));
                     }
-                    return Ok(Atom::Subst(Box::new(Subst::Indirect(name))));
+                    return Ok(Atom::Subst(Box::new(Subst::Indirect(Cow::Borrowed(name)))));
                 }
                 // ${#param} or ${#arr[@]} — length
                 if self.lex.peek() == b'#' && self.lex.peek_at(1) != b'}' {
                     self.lex.bump();
+                    let param_start = self.lex.pos();
                     let param = self.read_param()?;
+                    let param_end = self.lex.pos();
                     // Check for ${#arr[@]} — array length
-                    if let Param::Var(name) = param
+                    if let Param::Var(name) = &param
                         && self.lex.peek() == b'['
                     {
                         self.lex.bump(); // skip [
@@ -1072,23 +1849,32 @@ impl<'a> Parser<'a> {
                         if (idx_byte == b'@' || idx_byte == b'*') && self.lex.peek_at(1) == b']' {
                             self.lex.bump_n(2); // skip @] or *]
                             if !self.lex.eat(b'}') {
-                                return Err(self.lex.err("expected '}'"));
+                                return Err(self.lex.err_expected("expected '}'", &["}"]));
                             }
-                            return Ok(Atom::Subst(Box::new(Subst::ArrayLen(name))));
+                            return Ok(Atom::Subst(Box::new(Subst::ArrayLen(name.clone()))));
                         }
-                        return Err(self.lex.err("expected '@]' or '*]' after '#arr['"));
+                        return Err(self.lex.err_expected(
+                            "expected '@]' or '*]' after '#arr['",
+                            &["@]", "*]"],
+                        ));
                     }
                     if !self.lex.eat(b'}') {
-                        return Err(self.lex.err("expected '}'"));
+                        return Err(self.lex.err_expected("expected '}'", &["}"]));
                     }
+                    self.noted.push((SpannedNode::Param, Span::new(param_start, param_end)));
                     return Ok(Atom::Subst(Box::new(Subst::Len(param))));
                 }
+                let param_start = self.lex.pos();
                 let param = self.read_param()?;
                 // Check for array indexing: ${arr[...]}
-                if let Param::Var(name) = param
+                if let Param::Var(name) = &param
                     && self.lex.peek() == b'['
                 {
-                    return self.brace_array_op(name);
+                    return self.brace_array_op(name.clone());
+                }
+                // `${var@op}` keeps just the name
+                if self.lex.peek() != b'@' {
+                    self.note(SpannedNode::Param, param_start);
                 }
                 if self.lex.peek() == b'}' {
                     // Bare ${var} — same as $var
@@ -1111,6 +1897,17 @@ impl<'a> Parser<'a> {
                     Ok(Atom::Subst(Box::new(subst)))
                 }
             }
+            b'[' => {
+                // $[ arithmetic ] — legacy form of $(( ))
+                self.lex.bump();
+                self.lex.skip_blanks();
+                let expr = if self.lex.peek() == b']' { None } else { Some(self.arith(0)?) };
+                self.lex.skip_blanks();
+                if !self.lex.eat(b']') {
+                    return Err(self.lex.err_expected("expected ']' for arithmetic", &["]"]));
+                }
+                Ok(Atom::Subst(Box::new(Subst::Arith(expr))))
+            }
             b'\'' => {
                 // $'...' ANSI-C quoting — scan to closing ', handling \'
                 self.lex.bump(); // skip opening '
@@ -1129,38 +1926,38 @@ impl<'a> Parser<'a> {
                     if self.lex.peek() == b'\'' {
                         let content = self.lex.slice(start);
                         self.lex.bump();
-                        return Ok(Atom::AnsiCQuoted(content));
+                        return Ok(Atom::AnsiCQuoted(Cow::Borrowed(content)));
                     }
                     self.lex.bump();
                 }
             }
             b'@' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::At))
+                Ok(self.param_atom(Param::At, name_start))
             }
             b'*' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::Star))
+                Ok(self.param_atom(Param::Star, name_start))
             }
             b'#' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::Pound))
+                Ok(self.param_atom(Param::Pound, name_start))
             }
             b'?' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::Status))
+                Ok(self.param_atom(Param::Status, name_start))
             }
             b'$' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::Pid))
+                Ok(self.param_atom(Param::Pid, name_start))
             }
             b'!' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::Bang))
+                Ok(self.param_atom(Param::Bang, name_start))
             }
             b'-' => {
                 self.lex.bump();
-                Ok(Atom::Param(Param::Dash))
+                Ok(self.param_atom(Param::Dash, name_start))
             }
             b'0'..=b'9' => {
                 let start = self.lex.pos();
@@ -1168,21 +1965,27 @@ impl<'a> Parser<'a> {
                 // Multi-digit only for ${N} syntax, bare $N is single digit
                 let s = self.lex.slice(start);
                 let n: u32 = s.parse().unwrap_or(0);
-                Ok(Atom::Param(Param::Positional(n)))
+                Ok(self.param_atom(Param::Positional(n), name_start))
             }
             _ => {
                 // $NAME
                 let name = self.lex.read_name();
                 if name.is_empty() {
                     // Bare $ — emit as literal
-                    Ok(Atom::Lit("$"))
+                    Ok(Atom::Lit(Cow::Borrowed("$")))
                 } else {
-                    Ok(Atom::Param(Param::Var(name)))
+                    Ok(self.param_atom(Param::Var(Cow::Borrowed(name)), name_start))
                 }
             }
         }
     }
 
+    /// The atom of a `$name` parameter whose name starts at `start`.
+    fn param_atom(&mut self, param: Param<'a>, start: usize) -> Atom<'a> {
+        self.note(SpannedNode::Param, start);
+        Atom::Param(param)
+    }
+
     /// Parse the operator part of `${param OP word}`. The param has already been
     /// read; cursor is on the operator byte.
     fn brace_param_op(&mut self, param: Param<'a>) -> Result<Subst<'a>, ParseError> {
@@ -1204,9 +2007,10 @@ impl<'a> Parser<'a> {
                         None
                     };
                     if !self.lex.eat(b'}') {
-                        return Err(self.lex.err("expected '}'"));
+                        return Err(self.lex.err_expected("expected '}'", &["}"]));
                     }
-                    return Ok(Subst::Substring(param, offset, length));
+                    let length = length.map(Cow::Borrowed);
+                    return Ok(Subst::Substring(param, Cow::Borrowed(offset), length));
                 }
             }
         }
@@ -1218,7 +2022,7 @@ impl<'a> Parser<'a> {
                 self.lex.bump();
                 let word = self.brace_param_word()?;
                 if !self.lex.eat(b'}') {
-                    return Err(self.lex.err("expected '}'"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
                 match op {
                     b'-' => Ok(Subst::Default(param, word)),
@@ -1233,7 +2037,7 @@ impl<'a> Parser<'a> {
                 let large = self.lex.eat(b'%');
                 let word = self.brace_param_word()?;
                 if !self.lex.eat(b'}') {
-                    return Err(self.lex.err("expected '}'"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
                 if large {
                     Ok(Subst::TrimSuffixLarge(param, word))
@@ -1246,7 +2050,7 @@ impl<'a> Parser<'a> {
                 let large = self.lex.eat(b'#');
                 let word = self.brace_param_word()?;
                 if !self.lex.eat(b'}') {
-                    return Err(self.lex.err("expected '}'"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
                 if large {
                     Ok(Subst::TrimPrefixLarge(param, word))
@@ -1254,25 +2058,19 @@ impl<'a> Parser<'a> {
                     Ok(Subst::TrimPrefixSmall(param, word))
                 }
             }
-            b'^' => {
+            b'^' | b',' => {
+                let op = self.lex.peek();
                 self.lex.bump();
-                let all = self.lex.eat(b'^');
+                let all = self.lex.eat(op);
+                let pattern = self.brace_param_word()?;
                 if !self.lex.eat(b'}') {
-                    return Err(self
-                        .lex
-                        .err("expected '}' (patterned case modification unsupported)"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
-                Ok(Subst::Upper(all, param))
-            }
-            b',' => {
-                self.lex.bump();
-                let all = self.lex.eat(b',');
-                if !self.lex.eat(b'}') {
-                    return Err(self
-                        .lex
-                        .err("expected '}' (patterned case modification unsupported)"));
+                if op == b'^' {
+                    Ok(Subst::Upper(all, param, pattern))
+                } else {
+                    Ok(Subst::Lower(all, param, pattern))
                 }
-                Ok(Subst::Lower(all, param))
             }
             b'/' => {
                 self.lex.bump();
@@ -1298,7 +2096,7 @@ impl<'a> Parser<'a> {
                     None
                 };
                 if !self.lex.eat(b'}') {
-                    return Err(self.lex.err("expected '}'"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
                 if prefix {
                     Ok(Subst::ReplacePrefix(param, pattern, replacement))
@@ -1328,7 +2126,7 @@ impl<'a> Parser<'a> {
                         .err("parameter transformation requires a named variable"));
                 };
                 if !self.lex.eat(b'}') {
-                    return Err(self.lex.err("expected '}'"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
                 Ok(Subst::Transform(name, op))
             }
@@ -1338,14 +2136,14 @@ impl<'a> Parser<'a> {
 
     /// Parse array indexing after `${name[`.
     /// Handles `${arr[n]}`, `${arr[@]}`, `${arr[*]}`, `${arr[@]:offset:len}`.
-    fn brace_array_op(&mut self, name: &'a str) -> Result<Atom<'a>, ParseError> {
+    fn brace_array_op(&mut self, name: Cow<'a, str>) -> Result<Atom<'a>, ParseError> {
         self.lex.bump(); // skip [
 
         let idx_byte = self.lex.peek();
         if idx_byte == b'@' || idx_byte == b'*' {
             self.lex.bump();
             if !self.lex.eat(b']') {
-                return Err(self.lex.err("expected ']'"));
+                return Err(self.lex.err_expected("expected ']'", &["]"]));
             }
             // Check for slice: ${arr[@]:offset:length}
             if self.lex.peek() == b':' {
@@ -1357,14 +2155,16 @@ impl<'a> Parser<'a> {
                     None
                 };
                 if !self.lex.eat(b'}') {
-                    return Err(self.lex.err("expected '}'"));
+                    return Err(self.lex.err_expected("expected '}'", &["}"]));
                 }
                 return Ok(Atom::Subst(Box::new(Subst::ArraySlice(
-                    name, offset, length,
+                    name,
+                    Cow::Borrowed(offset),
+                    length.map(Cow::Borrowed),
                 ))));
             }
             if !self.lex.eat(b'}') {
-                return Err(self.lex.err("expected '}'"));
+                return Err(self.array_op_err());
             }
             return Ok(Atom::Subst(Box::new(Subst::ArrayAll(name))));
         }
@@ -1373,14 +2173,25 @@ impl<'a> Parser<'a> {
         // Read index as a word (supports $var, $((expr)), etc.)
         let idx_word = self.array_index_word()?;
         if !self.lex.eat(b']') {
-            return Err(self.lex.err("expected ']'"));
+            return Err(self.lex.err_expected("expected ']'", &["]"]));
         }
         if !self.lex.eat(b'}') {
-            return Err(self.lex.err("expected '}'"));
+            return Err(self.array_op_err());
         }
         Ok(Atom::Subst(Box::new(Subst::ArrayElement(name, idx_word))))
     }
 
+    /// The error for what follows `${arr[...]` when it is not `}`: operators
+    /// other than a slice are only parsed on plain variables.
+    fn array_op_err(&self) -> ParseError {
+        match self.lex.peek() {
+            b'-' | b'=' | b'?' | b'+' | b'%' | b'#' | b'/' | b'^' | b',' | b'@' | b':' => self
+                .lex
+                .err("parameter operators on array elements are not supported"),
+            _ => self.lex.err_expected("expected '}'", &["}"]),
+        }
+    }
+
     /// Read a number in `${arr[@]:offset:length}` context.
     fn read_brace_number(&mut self) -> Result<&'a str, ParseError> {
         let start = self.lex.pos();
@@ -1400,22 +2211,26 @@ impl<'a> Parser<'a> {
 
     /// Parse an array index word (inside `[...]`), stopping at `]`.
     fn array_index_word(&mut self) -> Result<Word<'a>, ParseError> {
+        let word_start = self.lex.pos();
         let mut parts = Vec::new();
         loop {
             let b = self.lex.peek();
             if self.lex.is_eof() || b == b']' {
                 break;
             }
+            let start = self.lex.pos();
             match b {
                 b'$' => {
-                    parts.push(WordPart::Bare(self.dollar()?));
+                    let atom = self.dollar()?;
+                    parts.push(self.bare(atom, start));
                 }
                 b'"' => {
                     self.lex.bump();
-                    parts.push(WordPart::DQuoted(self.dquoted()?));
+                    let atoms = self.dquoted()?;
+                    self.note(SpannedNode::WordPart, start);
+                    parts.push(WordPart::DQuoted(atoms));
                 }
                 _ => {
-                    let start = self.lex.pos();
                     while !self.lex.is_eof()
                         && self.lex.peek() != b']'
                         && self.lex.peek() != b'$'
@@ -1425,7 +2240,7 @@ impl<'a> Parser<'a> {
                     }
                     let s = self.lex.slice(start);
                     if !s.is_empty() {
-                        parts.push(WordPart::Bare(Atom::Lit(s)));
+                        parts.push(self.bare(Atom::Lit(Cow::Borrowed(s)), start));
                     }
                 }
             }
@@ -1433,11 +2248,7 @@ impl<'a> Parser<'a> {
         if parts.is_empty() {
             return Err(self.lex.err("empty array index"));
         }
-        if parts.len() == 1 {
-            Ok(Word::Simple(parts.into_iter().next().expect("len checked == 1")))
-        } else {
-            Ok(Word::Concat(parts))
-        }
+        Ok(self.finish_word(parts, word_start))
     }
 
     /// Read a Param from the current position (for ${...} parsing).
@@ -1481,7 +2292,7 @@ impl<'a> Parser<'a> {
                 if name.is_empty() {
                     Err(self.lex.err("expected parameter name"))
                 } else {
-                    Ok(Param::Var(name))
+                    Ok(Param::Var(Cow::Borrowed(name)))
                 }
             }
         }
@@ -1504,45 +2315,50 @@ impl<'a> Parser<'a> {
         if self.lex.peek() == b'}' || (extra != 0 && self.lex.peek() == extra) {
             return Ok(None);
         }
+        let word_start = self.lex.pos();
         let mut parts = Vec::new();
         loop {
             let b = self.lex.peek();
             if self.lex.is_eof() || b == b'}' || (extra != 0 && b == extra) {
                 break;
             }
+            let start = self.lex.pos();
             match b {
                 b'"' => {
                     self.lex.bump();
-                    parts.push(WordPart::DQuoted(self.dquoted()?));
+                    let atoms = self.dquoted()?;
+                    self.note(SpannedNode::WordPart, start);
+                    parts.push(WordPart::DQuoted(atoms));
                 }
                 b'\'' => {
                     self.lex.bump();
-                    parts.push(WordPart::SQuoted(self.lex.scan_squote()?));
+                    let content = self.lex.scan_squote()?;
+                    self.note(SpannedNode::WordPart, start);
+                    parts.push(WordPart::SQuoted(Cow::Borrowed(content)));
                 }
                 b'$' => {
-                    parts.push(WordPart::Bare(self.dollar()?));
+                    let atom = self.dollar()?;
+                    parts.push(self.bare(atom, start));
                 }
                 b'\\' => {
                     self.lex.bump();
                     if self.lex.is_eof() {
                         break;
                     }
-                    let start = self.lex.pos();
+                    let escaped_start = self.lex.pos();
                     self.lex.bump();
-                    parts.push(WordPart::Bare(Atom::Escaped(Cow::Borrowed(
-                        self.lex.slice(start),
-                    ))));
+                    let escaped = Atom::Escaped(Cow::Borrowed(self.lex.slice(escaped_start)));
+                    parts.push(self.bare(escaped, start));
                 }
                 b'*' => {
                     self.lex.bump();
-                    parts.push(WordPart::Bare(Atom::Star));
+                    parts.push(self.bare(Atom::Star, start));
                 }
                 b'?' => {
                     self.lex.bump();
-                    parts.push(WordPart::Bare(Atom::Question));
+                    parts.push(self.bare(Atom::Question, start));
                 }
                 _ => {
-                    let start = self.lex.pos();
                     while !self.lex.is_eof() {
                         let c = self.lex.peek();
                         if c == b'}'
@@ -1559,16 +2375,16 @@ impl<'a> Parser<'a> {
                         self.lex.bump();
                     }
                     if self.lex.pos() > start {
-                        parts.push(WordPart::Bare(Atom::Lit(self.lex.slice(start))));
+                        let lit = Atom::Lit(Cow::Borrowed(self.lex.slice(start)));
+                        parts.push(self.bare(lit, start));
                     }
                 }
             }
         }
-        match parts.len() {
-            0 => Ok(None),
-            1 => Ok(Some(Word::Simple(parts.into_iter().next().expect("len checked == 1")))),
-            _ => Ok(Some(Word::Concat(parts))),
+        if parts.is_empty() {
+            return Ok(None);
         }
+        Ok(Some(self.finish_word(parts, word_start)))
     }
 
     /// Scan substring offset/length — stops at unquoted `:` or `}`, tracking nesting.
@@ -1621,7 +2437,7 @@ impl<'a> Parser<'a> {
         let cmds = self.cmd_list(&[b")"])?;
         self.lex.skip_blanks();
         if !self.lex.eat(b')') {
-            return Err(self.lex.err("expected ')' for command substitution"));
+            return Err(self.lex.err_expected("expected ')' for command substitution", &[")"]));
         }
         Ok(Subst::Cmd(cmds))
     }
@@ -1639,7 +2455,7 @@ impl<'a> Parser<'a> {
             self.lex.bump_n(2);
             Ok(Subst::Arith(Some(expr)))
         } else {
-            Err(self.lex.err("expected '))' for arithmetic"))
+            Err(self.lex.err_expected("expected '))' for arithmetic", &["))"]))
         }
     }
 
@@ -1653,13 +2469,16 @@ impl<'a> Parser<'a> {
             }
             self.lex.bump();
         }
-        let content = self.lex.slice(start);
+        let end = self.lex.pos();
         if !self.lex.eat(b'`') {
             return Err(self.lex.err("unterminated backtick"));
         }
         // Re-parse the content as a command
-        let sub_parser = Parser::new(content);
-        let cmds = sub_parser.parse()?;
+        let mut sub = self.sub_parser(start, end);
+        let cmds = sub.cmd_list(&[]);
+        self.alias_hits.append(&mut sub.alias_hits);
+        self.noted.append(&mut sub.noted);
+        let cmds = cmds.map_err(|e| e.at_end_of(end))?;
         Ok(Atom::Subst(Box::new(Subst::Cmd(cmds))))
     }
 
@@ -1733,23 +2552,50 @@ impl<'a> Parser<'a> {
         // Advance past the }
         self.lex.bump_n(inner_end + 1 - start_pos);
 
-        // Check for adjacent brace expansion }{
-        if self.lex.peek() == b'{' {
-            self.rewind(start_pos);
-            return None; // will be caught as unsupported
-        }
-
         let start_slice = self.lex.slice_range(first_start, first_end);
         let end_slice = self.lex.slice_range(end_start, end_end);
         let step_slice = step_range.map(|(s, e)| self.lex.slice_range(s, e));
 
         Some(Atom::BraceRange {
-            start: start_slice,
-            end: end_slice,
-            step: step_slice,
+            start: Cow::Borrowed(start_slice),
+            end: Cow::Borrowed(end_slice),
+            step: step_slice.map(Cow::Borrowed),
         })
     }
 
+    /// Try to parse a brace list `{a,b}` at `{`. Each alternative is re-parsed
+    /// as a word, so nested lists and ranges become a tree. Returns `None`
+    /// without consuming anything when there is no top-level comma or the
+    /// braces are not closed before a blank or operator — bash leaves those
+    /// as literal text.
+    fn try_brace_list(&mut self) -> Result<Option<Atom<'a>>, ParseError> {
+        let start = self.lex.pos();
+        let Some((commas, close)) = scan_brace_list(self.lex.remaining().as_bytes()) else {
+            return Ok(None);
+        };
+        let mut alternatives = Vec::with_capacity(commas.len() + 1);
+        let mut from = start + 1;
+        for end in commas.into_iter().chain(std::iter::once(close)) {
+            let mut sub = self.sub_parser(from, start + end);
+            let mut parts = Vec::new();
+            while !sub.lex.is_eof() {
+                match sub.word_part() {
+                    Ok(part) => parts.push(part),
+                    Err(e) => {
+                        self.alias_hits.append(&mut sub.alias_hits);
+                        return Err(e);
+                    }
+                }
+            }
+            alternatives.push(sub.finish_word(parts, from));
+            self.alias_hits.append(&mut sub.alias_hits);
+            self.noted.append(&mut sub.noted);
+            from = start + end + 1;
+        }
+        self.lex.bump_n(close + 1);
+        Ok(Some(Atom::BraceList(alternatives)))
+    }
+
     // -----------------------------------------------------------------------
     // Redirects
     // -----------------------------------------------------------------------
@@ -1758,9 +2604,18 @@ impl<'a> Parser<'a> {
     /// Returns None if not at a redirect operator.
     fn try_redirect(&mut self) -> Result<Option<Redir<'a>>, ParseError> {
         self.lex.skip_blanks();
+        let start = self.lex.pos();
+        let redir = self.redirect()?;
+        if redir.is_some() {
+            self.note(SpannedNode::Redir, start);
+        }
+        Ok(redir)
+    }
 
+    /// The body of [`Self::try_redirect`], after the blanks.
+    fn redirect(&mut self) -> Result<Option<Redir<'a>>, ParseError> {
         // Read optional fd number
-        let start = self.lex.pos();
+        let start = self.checkpoint();
         let fd_str = self.lex.read_number();
         let fd: Option<u16> = if fd_str.is_empty() {
             None
@@ -1787,16 +2642,21 @@ impl<'a> Parser<'a> {
                 self.lex.skip_blanks();
 
                 // Parse delimiter — check if quoted
-                let (tag, quoted) = self.read_heredoc_delimiter()?;
+                let (delimiter, quoted) = self.read_heredoc_delimiter()?;
 
-                // Save position, scan ahead to find body
+                // Save position, scan ahead to find body. A second heredoc
+                // on the same line starts where the previous body ended.
                 let save_pos = self.lex.pos();
-                // Skip to end of current line
-                while !self.lex.is_eof() && self.lex.peek() != b'\n' {
-                    self.lex.bump();
-                }
-                if self.lex.peek() == b'\n' {
-                    self.lex.bump();
+                if let Some(resume) = self.heredoc_resume {
+                    self.lex.set_pos(resume);
+                } else {
+                    // Skip to end of current line
+                    while !self.lex.is_eof() && self.lex.peek() != b'\n' {
+                        self.lex.bump();
+                    }
+                    if self.lex.peek() == b'\n' {
+                        self.lex.bump();
+                    }
                 }
                 // Read lines until delimiter
                 let body_start = self.lex.pos();
@@ -1813,7 +2673,7 @@ impl<'a> Parser<'a> {
                     } else {
                         line
                     };
-                    if trimmed == tag {
+                    if trimmed == delimiter {
                         body_end = line_start;
                         if self.lex.peek() == b'\n' {
                             self.lex.bump();
@@ -1833,15 +2693,17 @@ impl<'a> Parser<'a> {
                 self.lex.set_pos(save_pos);
                 self.heredoc_resume = Some(after_heredoc);
 
-                let heredoc_body = if quoted {
-                    HeredocBody::Literal(body)
+                let body = if quoted {
+                    HeredocBody::Literal(Cow::Borrowed(body))
                 } else {
                     // Parse body for variable/command expansions
-                    let atoms = Parser::new(body).parse_heredoc_body()?;
-                    HeredocBody::Interpolated(atoms)
+                    let mut sub = self.sub_parser(body_start, body_end);
+                    let atoms = sub.parse_heredoc_body();
+                    self.alias_hits.append(&mut sub.alias_hits);
+                    self.noted.append(&mut sub.noted);
+                    HeredocBody::Interpolated(atoms?)
                 };
-                let _ = (fd, strip_tabs); // fd always 0, tab-stripping not translated
-                Ok(Some(Redir::Heredoc(heredoc_body)))
+                Ok(Some(Redir::Heredoc(fd, Heredoc { delimiter, strip_tabs, body })))
             }
             (b'<', b'>') => {
                 // <>
@@ -1931,16 +2793,20 @@ impl<'a> Parser<'a> {
     }
 
     /// Collect any trailing redirects after a compound command.
-    fn collect_redirects(&mut self) -> Result<Vec<Redir<'a>>, ParseError> {
+    /// Also returns the byte offset just past the last redirect (or the
+    /// current position if there were none), for span bookkeeping.
+    fn collect_redirects(&mut self) -> Result<(Vec<Redir<'a>>, usize), ParseError> {
         let mut redirects = Vec::new();
+        let mut end = self.lex.pos();
         loop {
             self.lex.skip_blanks();
             let Some(redir) = self.try_redirect()? else {
                 break;
             };
             redirects.push(redir);
+            end = self.lex.pos();
         }
-        Ok(redirects)
+        Ok((redirects, end))
     }
 
     // -----------------------------------------------------------------------
@@ -1966,49 +2832,48 @@ impl<'a> Parser<'a> {
                     let then_val = self.arith(0)?;
                     self.lex.skip_blanks();
                     if !self.lex.eat(b':') {
-                        return Err(self.lex.err("expected ':' in ternary"));
+                        return Err(self.lex.err_expected("expected ':' in ternary", &[":"]));
                     }
                     self.lex.skip_blanks();
-                    let else_val = self.arith(0)?;
+                    let else_val = self.arith(1)?;
                     left = Arith::Ternary(Box::new(left), Box::new(then_val), Box::new(else_val));
                     continue;
                 }
 
                 // Assignment special case
                 if op_len == 1 && self.lex.slice_range(op_end - 1, op_end) == "=" {
-                    if let Arith::Var(name) = left {
-                        let right = self.arith(prec)?;
-                        left = Arith::Assign(name, Box::new(right));
-                        continue;
-                    }
-                    return Err(self.lex.err("expected variable for assignment"));
+                    left = match left {
+                        Arith::Var(name) => Arith::Assign(name, Box::new(self.arith(prec)?)),
+                        Arith::Elem(name, index) => {
+                            Arith::ElemAssign(name, index, Box::new(self.arith(prec)?))
+                        }
+                        _ => return Err(self.lex.err("expected variable for assignment")),
+                    };
+                    continue;
                 }
 
                 let right = self.arith(prec + 1)?;
                 left = constructor(Box::new(left), Box::new(right));
             } else {
-                // Compound assignment: +=, -=, *=, /=, %=
-                let b1 = self.lex.peek();
-                let b2 = self.lex.peek_at(1);
-                if b2 == b'='
-                    && matches!(b1, b'+' | b'-' | b'*' | b'/' | b'%')
-                    && let Arith::Var(name) = &left
+                // Compound assignment: `x op= y` is stored as `x = x op y`
+                if let Some((op_len, make_op)) = self.arith_compound_assign_op()
+                    && matches!(left, Arith::Var(_) | Arith::Elem(..))
                 {
-                    let name = *name;
-                    let make_op: fn(Box<Arith<'a>>, Box<Arith<'a>>) -> Arith<'a> = match b1 {
-                        b'+' => Arith::Add,
-                        b'-' => Arith::Sub,
-                        b'*' => Arith::Mul,
-                        b'/' => Arith::Div,
-                        _ => Arith::Rem,
-                    };
-                    self.lex.bump_n(2);
+                    self.lex.bump_n(op_len);
                     self.lex.skip_blanks();
-                    let right = self.arith(0)?;
-                    left = Arith::Assign(
-                        name,
-                        Box::new(make_op(Box::new(Arith::Var(name)), Box::new(right))),
-                    );
+                    let right = self.arith(1)?;
+                    left = match left {
+                        Arith::Var(name) => Arith::Assign(
+                            name.clone(),
+                            Box::new(make_op(Box::new(Arith::Var(name)), Box::new(right))),
+                        ),
+                        Arith::Elem(name, index) => {
+                            let elem = Arith::Elem(name.clone(), index.clone());
+                            let value = make_op(Box::new(elem), Box::new(right));
+                            Arith::ElemAssign(name, index, Box::new(value))
+                        }
+                        _ => unreachable!("checked above"),
+                    };
                     continue;
                 }
                 break;
@@ -2028,7 +2893,7 @@ impl<'a> Parser<'a> {
                 let expr = self.arith(0)?;
                 self.lex.skip_blanks();
                 if !self.lex.eat(b')') {
-                    return Err(self.lex.err("expected ')' in arithmetic"));
+                    return Err(self.lex.err_expected("expected ')' in arithmetic", &[")"]));
                 }
                 Ok(expr)
             }
@@ -2040,7 +2905,11 @@ impl<'a> Parser<'a> {
                 if name.is_empty() {
                     return Err(self.lex.err("expected variable after '++'"));
                 }
-                Ok(Arith::PreInc(name))
+                if self.lex.peek() == b'[' {
+                    let index = self.arith_index()?;
+                    return Ok(Arith::ElemPreInc(Cow::Borrowed(name), Box::new(index)));
+                }
+                Ok(Arith::PreInc(Cow::Borrowed(name)))
             }
             b'-' if self.lex.peek_at(1) == b'-' => {
                 // --var
@@ -2050,7 +2919,11 @@ impl<'a> Parser<'a> {
                 if name.is_empty() {
                     return Err(self.lex.err("expected variable after '--'"));
                 }
-                Ok(Arith::PreDec(name))
+                if self.lex.peek() == b'[' {
+                    let index = self.arith_index()?;
+                    return Ok(Arith::ElemPreDec(Cow::Borrowed(name), Box::new(index)));
+                }
+                Ok(Arith::PreDec(Cow::Borrowed(name)))
             }
             b'+' => {
                 self.lex.bump();
@@ -2080,7 +2953,10 @@ impl<'a> Parser<'a> {
                     let expr = self.arith(0)?;
                     self.lex.skip_blanks();
                     if !self.lex.eat(b')') || !self.lex.eat(b')') {
-                        return Err(self.lex.err("expected '))' in nested arithmetic"));
+                        return Err(self.lex.err_expected(
+                            "expected '))' in nested arithmetic",
+                            &["))"],
+                        ));
                     }
                     return Ok(expr);
                 }
@@ -2099,7 +2975,7 @@ impl<'a> Parser<'a> {
                     while self.lex.peek().is_ascii_digit() {
                         self.lex.bump();
                     }
-                    Ok(Arith::Var(self.lex.slice(start)))
+                    Ok(Arith::Var(Cow::Borrowed(self.lex.slice(start))))
                 } else {
                     Err(self.lex.err("expected variable after '$' in arithmetic"))
                 }
@@ -2135,8 +3011,26 @@ impl<'a> Parser<'a> {
                     self.lex.bump();
                 }
                 let num_str = self.lex.slice(start);
+                if self.lex.peek() == b'#' {
+                    // base#digits, base 2..=64
+                    self.lex.bump();
+                    let digits_start = self.lex.pos();
+                    while self.lex.peek().is_ascii_alphanumeric()
+                        || matches!(self.lex.peek(), b'@' | b'_')
+                    {
+                        self.lex.bump();
+                    }
+                    let digits = self.lex.slice(digits_start);
+                    return num_str
+                        .parse()
+                        .ok()
+                        .and_then(|base| parse_based_int(base, digits))
+                        .map(Arith::Lit)
+                        .ok_or_else(|| self.lex.err("invalid number in arithmetic base"));
+                }
                 let n: i64 = if num_str.starts_with('0') && num_str.len() > 1 {
-                    i64::from_str_radix(num_str, 8).unwrap_or(0) // octal
+                    i64::from_str_radix(num_str, 8)
+                        .map_err(|_| self.lex.err("invalid octal number in arithmetic"))?
                 } else {
                     num_str.parse().unwrap_or(0)
                 };
@@ -2146,6 +3040,19 @@ impl<'a> Parser<'a> {
                 let name = self.lex.read_name();
                 if name.is_empty() {
                     Err(self.lex.err("expected arithmetic expression"))
+                } else if self.lex.peek() == b'[' {
+                    // arr[index], arr[index]++, arr[index]--
+                    let name = Cow::Borrowed(name);
+                    let index = Box::new(self.arith_index()?);
+                    Ok(if self.lex.peek() == b'+' && self.lex.peek_at(1) == b'+' {
+                        self.lex.bump_n(2);
+                        Arith::ElemPostInc(name, index)
+                    } else if self.lex.peek() == b'-' && self.lex.peek_at(1) == b'-' {
+                        self.lex.bump_n(2);
+                        Arith::ElemPostDec(name, index)
+                    } else {
+                        Arith::Elem(name, index)
+                    })
                 } else {
                     Ok(self.check_postfix(name))
                 }
@@ -2153,17 +3060,28 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse `[index]` after an array name in arithmetic.
+    fn arith_index(&mut self) -> Result<Arith<'a>, ParseError> {
+        self.lex.bump();
+        let index = self.arith(0)?;
+        self.lex.skip_blanks();
+        if !self.lex.eat(b']') {
+            return Err(self.lex.err_expected("expected ']' after array index", &["]"]));
+        }
+        Ok(index)
+    }
+
     /// Check for postfix ++ or -- after a variable name.
     #[inline]
     fn check_postfix(&mut self, name: &'a str) -> Arith<'a> {
         if self.lex.peek() == b'+' && self.lex.peek_at(1) == b'+' {
             self.lex.bump_n(2);
-            Arith::PostInc(name)
+            Arith::PostInc(Cow::Borrowed(name))
         } else if self.lex.peek() == b'-' && self.lex.peek_at(1) == b'-' {
             self.lex.bump_n(2);
-            Arith::PostDec(name)
+            Arith::PostDec(Cow::Borrowed(name))
         } else {
-            Arith::Var(name)
+            Arith::Var(Cow::Borrowed(name))
         }
     }
 
@@ -2177,45 +3095,65 @@ impl<'a> Parser<'a> {
     ) -> Option<(u8, usize, fn(Box<Arith<'a>>, Box<Arith<'a>>) -> Arith<'a>)> {
         let b1 = self.lex.peek();
         let b2 = self.lex.peek_at(1);
-        let b3 = self.lex.peek_at(2);
 
-        // 3-char ops
-        match (b1, b2, b3) {
-            (b'<', b'<', b'=') | (b'>', b'>', b'=') => return None, // compound assignment, bail
-            _ => {}
+        if self.arith_compound_assign_op().is_some() {
+            return None; // compound assignment, handled in arith()
         }
 
         // 2-char ops (check before 1-char)
         match (b1, b2) {
-            (b'|', b'|') => return Some((1, 2, |l, r| Arith::LogOr(l, r))),
-            (b'&', b'&') => return Some((2, 2, |l, r| Arith::LogAnd(l, r))),
-            (b'=', b'=') => return Some((7, 2, |l, r| Arith::Eq(l, r))),
-            (b'!', b'=') => return Some((7, 2, |l, r| Arith::Ne(l, r))),
-            (b'<', b'=') => return Some((8, 2, |l, r| Arith::Le(l, r))),
-            (b'>', b'=') => return Some((8, 2, |l, r| Arith::Ge(l, r))),
-            (b'<', b'<') => return Some((9, 2, |l, r| Arith::Shl(l, r))),
-            (b'>', b'>') => return Some((9, 2, |l, r| Arith::Shr(l, r))),
-            (b'*', b'*') => return Some((13, 2, |l, r| Arith::Pow(l, r))),
-            (b'+' | b'-' | b'*' | b'/' | b'%', b'=') => {
-                return None; // compound assignment, bail
-            }
+            (b'|', b'|') => return Some((2, 2, |l, r| Arith::LogOr(l, r))),
+            (b'&', b'&') => return Some((3, 2, |l, r| Arith::LogAnd(l, r))),
+            (b'=', b'=') => return Some((8, 2, |l, r| Arith::Eq(l, r))),
+            (b'!', b'=') => return Some((8, 2, |l, r| Arith::Ne(l, r))),
+            (b'<', b'=') => return Some((9, 2, |l, r| Arith::Le(l, r))),
+            (b'>', b'=') => return Some((9, 2, |l, r| Arith::Ge(l, r))),
+            (b'<', b'<') => return Some((10, 2, |l, r| Arith::Shl(l, r))),
+            (b'>', b'>') => return Some((10, 2, |l, r| Arith::Shr(l, r))),
+            (b'*', b'*') => return Some((14, 2, |l, r| Arith::Pow(l, r))),
             _ => {}
         }
 
         // 1-char ops
         match b1 {
-            b'|' => Some((3, 1, |l, r| Arith::BitOr(l, r))),
-            b'^' => Some((4, 1, |l, r| Arith::BitXor(l, r))),
-            b'&' => Some((5, 1, |l, r| Arith::BitAnd(l, r))),
-            b'<' => Some((8, 1, |l, r| Arith::Lt(l, r))),
-            b'>' => Some((8, 1, |l, r| Arith::Gt(l, r))),
-            b'+' if b2 != b'+' => Some((10, 1, |l, r| Arith::Add(l, r))),
-            b'-' if b2 != b'-' => Some((10, 1, |l, r| Arith::Sub(l, r))),
-            b'*' if b2 != b'*' => Some((11, 1, |l, r| Arith::Mul(l, r))),
-            b'/' => Some((11, 1, |l, r| Arith::Div(l, r))),
-            b'%' => Some((11, 1, |l, r| Arith::Rem(l, r))),
-            b'?' => Some((0, 1, |l, _| *l)), // placeholder — ternary handled in arith()
-            b'=' if b2 != b'=' => Some((0, 1, |l, _| *l)), // placeholder — assignment handled in arith()
+            b'|' => Some((4, 1, |l, r| Arith::BitOr(l, r))),
+            b'^' => Some((5, 1, |l, r| Arith::BitXor(l, r))),
+            b'&' => Some((6, 1, |l, r| Arith::BitAnd(l, r))),
+            b'<' => Some((9, 1, |l, r| Arith::Lt(l, r))),
+            b'>' => Some((9, 1, |l, r| Arith::Gt(l, r))),
+            b'+' if b2 != b'+' => Some((11, 1, |l, r| Arith::Add(l, r))),
+            b'-' if b2 != b'-' => Some((11, 1, |l, r| Arith::Sub(l, r))),
+            b'*' if b2 != b'*' => Some((12, 1, |l, r| Arith::Mul(l, r))),
+            b'/' => Some((12, 1, |l, r| Arith::Div(l, r))),
+            b'%' => Some((12, 1, |l, r| Arith::Rem(l, r))),
+            b'?' => Some((1, 1, |l, _| *l)), // placeholder — ternary handled in arith()
+            b'=' if b2 != b'=' => Some((1, 1, |l, _| *l)), // placeholder — assignment handled in arith()
+            b',' => Some((0, 1, |l, r| Arith::Comma(l, r))),
+            _ => None,
+        }
+    }
+
+    /// Return the length and operator of a compound assignment (`+=`,
+    /// `<<=`, `**=`, ...) at the current position.
+    #[allow(clippy::type_complexity)]
+    fn arith_compound_assign_op(
+        &self,
+    ) -> Option<(usize, fn(Box<Arith<'a>>, Box<Arith<'a>>) -> Arith<'a>)> {
+        let b1 = self.lex.peek();
+        let b2 = self.lex.peek_at(1);
+        let b3 = self.lex.peek_at(2);
+        match (b1, b2, b3) {
+            (b'*', b'*', b'=') => Some((3, Arith::Pow)),
+            (b'<', b'<', b'=') => Some((3, Arith::Shl)),
+            (b'>', b'>', b'=') => Some((3, Arith::Shr)),
+            (b'+', b'=', _) => Some((2, Arith::Add)),
+            (b'-', b'=', _) => Some((2, Arith::Sub)),
+            (b'*', b'=', _) => Some((2, Arith::Mul)),
+            (b'/', b'=', _) => Some((2, Arith::Div)),
+            (b'%', b'=', _) => Some((2, Arith::Rem)),
+            (b'&', b'=', _) => Some((2, Arith::BitAnd)),
+            (b'|', b'=', _) => Some((2, Arith::BitOr)),
+            (b'^', b'=', _) => Some((2, Arith::BitXor)),
             _ => None,
         }
     }
@@ -2224,25 +3162,84 @@ impl<'a> Parser<'a> {
     // Helpers
     // -----------------------------------------------------------------------
 
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            pos: self.lex.pos(),
+            noted: self.noted.len(),
+        }
+    }
+
+    /// Backtrack to `to`, forgetting the nodes noted since.
     #[inline]
-    fn rewind(&mut self, pos: usize) {
-        self.lex.set_pos(pos);
+    fn rewind(&mut self, to: Checkpoint) {
+        self.lex.set_pos(to.pos);
+        self.noted.truncate(to.noted);
+    }
+
+    /// Note that a node of `kind` was parsed from `start` to the cursor.
+    fn note(&mut self, kind: SpannedNode, start: usize) {
+        self.noted.push((kind, Span::new(start, self.lex.pos())));
+    }
+
+    /// A literal atom from `start` to the cursor.
+    fn lit(&mut self, start: usize) -> Atom<'a> {
+        self.note(SpannedNode::Atom, start);
+        Atom::Lit(Cow::Borrowed(self.lex.slice(start)))
+    }
+
+    /// `atom`, parsed from `start` to the cursor, as a bare word part.
+    fn bare(&mut self, atom: Atom<'a>, start: usize) -> WordPart<'a> {
+        self.note(SpannedNode::Atom, start);
+        self.note(SpannedNode::WordPart, start);
+        WordPart::Bare(atom)
+    }
+
+    /// The word of `parts`, parsed from `start` to the cursor.
+    fn finish_word(&mut self, mut parts: Vec<WordPart<'a>>, start: usize) -> Word<'a> {
+        self.note(SpannedNode::Word, start);
+        if parts.len() == 1 {
+            Word::Simple(parts.pop().expect("len checked == 1"))
+        } else {
+            Word::Concat(parts)
+        }
+    }
+
+    /// A parser over `input[start..end]` that keeps absolute byte offsets,
+    /// so spans and errors from re-parsed regions (backticks, heredoc
+    /// bodies) point into the original input.
+    fn sub_parser(&self, start: usize, end: usize) -> Parser<'a> {
+        let mut sub = Parser::new(self.lex.slice_range(0, end));
+        sub.lex.set_pos(start);
+        sub.aliases = self.aliases;
+        sub
+    }
+
+    /// Span of a guard/body pair, from the guard's start to the end of the
+    /// last body (or guard) command.
+    fn guard_body_span(guard: &[Cmd<'a>], body: &[Cmd<'a>], start: usize) -> Span {
+        let end = body
+            .last()
+            .or(guard.last())
+            .map_or(start, |c| c.span().end);
+        Span::new(start, end)
     }
 
-    fn expect(&mut self, kw: &[u8], msg: &'static str) -> Result<(), ParseError> {
+    fn expect(&mut self, kw: &'static [&'static str], msg: &'static str) -> Result<(), ParseError> {
         self.lex.skip_blanks();
-        if self.lex.eat_str(kw) {
+        if kw.iter().any(|k| self.lex.eat_str(k.as_bytes())) {
             Ok(())
         } else {
-            Err(self.lex.err(msg))
+            Err(self.lex.err_expected(msg, kw))
         }
     }
 
     #[inline]
     fn eat_separator(&mut self) {
         self.lex.skip_blanks();
-        if self.lex.peek() == b';' || self.lex.peek() == b'\n' {
-            self.lex.bump();
+        match self.lex.peek() {
+            b';' => self.lex.bump(),
+            b'\n' => self.newline(),
+            _ => {}
         }
     }
 
@@ -2250,49 +3247,79 @@ impl<'a> Parser<'a> {
         loop {
             self.lex.skip_blanks();
             match self.lex.peek() {
-                b';' | b'\n' => self.lex.bump(),
+                b';' => self.lex.bump(),
+                b'\n' => self.newline(),
                 b'#' => self.lex.skip_comment(),
                 _ => break,
             }
         }
     }
 
-    /// Read a heredoc delimiter. Returns `(tag, quoted)`.
-    /// Quoted delimiters (`'EOF'`, `"EOF"`) suppress variable expansion.
-    fn read_heredoc_delimiter(&mut self) -> Result<(&'a str, bool), ParseError> {
-        match self.lex.peek() {
-            b'\'' => {
-                self.lex.bump();
-                let tag = self.lex.scan_squote()?;
-                Ok((tag, true))
-            }
-            b'"' => {
-                self.lex.bump();
-                let start = self.lex.pos();
-                while !self.lex.is_eof() && self.lex.peek() != b'"' {
-                    if self.lex.peek() == b'\\' {
-                        self.lex.bump();
-                    }
+    /// Step over a newline. Heredoc bodies opened on the line just ended
+    /// start here, so jump past them.
+    fn newline(&mut self) {
+        self.lex.bump();
+        if let Some(pos) = self.heredoc_resume.take() {
+            self.lex.set_pos(pos);
+        }
+    }
+
+    /// Read a heredoc delimiter. Returns `(tag, quoted)` with quoting removed.
+    /// Any quoting (`'EOF'`, `"EOF"`, `\EOF`, `E"O"F`) suppresses expansion.
+    fn read_heredoc_delimiter(&mut self) -> Result<(Cow<'a, str>, bool), ParseError> {
+        let start = self.lex.pos();
+        let mut tag = String::new();
+        let mut quoted = false;
+        while !self.lex.is_eof() && !is_meta(self.lex.peek()) {
+            match self.lex.peek() {
+                b'\'' => {
                     self.lex.bump();
+                    tag.push_str(self.lex.scan_squote()?);
+                    quoted = true;
                 }
-                let tag = self.lex.slice(start);
-                if !self.lex.eat(b'"') {
-                    return Err(self.lex.err("unterminated heredoc delimiter"));
+                b'"' => {
+                    self.lex.bump();
+                    let part = self.lex.pos();
+                    while !self.lex.is_eof() && self.lex.peek() != b'"' {
+                        if self.lex.peek() == b'\\' {
+                            self.lex.bump();
+                            if self.lex.is_eof() {
+                                break;
+                            }
+                        }
+                        self.lex.bump();
+                    }
+                    tag.push_str(self.lex.slice(part));
+                    if !self.lex.eat(b'"') {
+                        return Err(self.lex.err("unterminated heredoc delimiter"));
+                    }
+                    quoted = true;
                 }
-                Ok((tag, true))
-            }
-            _ => {
-                let start = self.lex.pos();
-                while !self.lex.is_eof() && !is_meta(self.lex.peek()) {
+                b'\\' => {
                     self.lex.bump();
+                    if self.lex.is_eof() {
+                        return Err(self.lex.err("unterminated heredoc delimiter"));
+                    }
+                    let part = self.lex.pos();
+                    self.lex.bump_char();
+                    tag.push_str(self.lex.slice(part));
+                    quoted = true;
                 }
-                let tag = self.lex.slice(start);
-                if tag.is_empty() {
-                    return Err(self.lex.err("expected heredoc delimiter"));
+                _ => {
+                    let part = self.lex.pos();
+                    self.lex.bump_char();
+                    tag.push_str(self.lex.slice(part));
                 }
-                Ok((tag, false))
             }
         }
+        if self.lex.pos() == start {
+            return Err(self.lex.err("expected heredoc delimiter"));
+        }
+        if quoted {
+            Ok((Cow::Owned(tag), true))
+        } else {
+            Ok((Cow::Borrowed(self.lex.slice(start)), false))
+        }
     }
 
     #[inline]
@@ -2328,30 +3355,344 @@ impl<'a> Parser<'a> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn parse(input: &str) -> Vec<Cmd<'_>> {
-        Parser::new(input).parse().unwrap()
-    }
+/// Input with aliases substituted, and the edits that map it back.
+struct Expansion<'t> {
+    table: &'t HashMap<String, String>,
+    text: String,
+    regions: Vec<AliasRegion>,
+    /// The edits of each batch of substitutions, sorted by position.
+    batches: Vec<Vec<Edit>>,
+}
 
-    fn parse_err(input: &str) -> ParseError {
-        Parser::new(input).parse().unwrap_err()
+impl<'t> Expansion<'t> {
+    fn new(parser: &Parser<'t>) -> Self {
+        let aliases = parser.aliases.expect("alias hit without an alias table");
+        Expansion {
+            table: aliases.table,
+            text: parser.lex.slice_range(0, parser.lex.input_len()).to_owned(),
+            regions: Vec::new(),
+            batches: Vec::new(),
+        }
     }
 
-    #[test]
-    fn simple_command() {
-        let cmds = parse("echo hello world");
-        assert_eq!(cmds.len(), 1);
+    /// Substitute `hits` and run `pass` over the new text from `resume`, the
+    /// start of the statement the first one was found in, again and again
+    /// until a pass finds no alias to expand.
+    fn run<R>(
+        &mut self,
+        mut hits: Vec<AliasHit>,
+        mut resume: usize,
+        mut pass: impl FnMut(&mut Parser<'_>) -> R,
+    ) -> R {
+        loop {
+            self.substitute(hits);
+            let mut p = Parser::new(&self.text);
+            p.lex.set_pos(resume);
+            p.aliases = Some(Aliases {
+                table: self.table,
+                regions: &self.regions,
+            });
+            let result = pass(&mut p);
+            if p.alias_hits.is_empty() {
+                return result;
+            }
+            hits = std::mem::take(&mut p.alias_hits);
+            resume = p.stmt_start;
+        }
     }
 
-    #[test]
-    fn pipeline() {
+    /// Substitute every hit in one pass over the text. A region keeps its
+    /// place around the names substituted inside it.
+    fn substitute(&mut self, mut hits: Vec<AliasHit>) {
+        // A word read twice, after backtracking, is found twice.
+        hits.sort_by_key(|h| h.start);
+        hits.dedup_by_key(|h| h.start);
+        let mut text = String::with_capacity(self.text.len());
+        let mut edits = Vec::with_capacity(hits.len());
+        let mut added = Vec::with_capacity(hits.len());
+        let mut from = 0;
+        for hit in hits {
+            text.push_str(&self.text[from..hit.start]);
+            let name = &self.text[hit.start..hit.end];
+            let value = &self.table[name];
+            edits.push(Edit {
+                old: hit.start,
+                old_len: hit.end - hit.start,
+                new: text.len(),
+                new_len: value.len(),
+            });
+            added.push(AliasRegion {
+                name: name.to_owned(),
+                start: text.len(),
+                end: text.len() + value.len(),
+                trailing_blank: value.ends_with([' ', '\t']),
+                parent: None,
+            });
+            text.push_str(value);
+            from = hit.end;
+        }
+        text.push_str(&self.text[from..]);
+        self.text = text;
+
+        // Names lie inside or between regions, never across a bound.
+        let shift = |pos: usize| match edits.partition_point(|e| e.old + e.old_len <= pos) {
+            0 => pos,
+            i => {
+                let e = &edits[i - 1];
+                pos - (e.old + e.old_len) + e.new + e.new_len
+            }
+        };
+        for r in &mut self.regions {
+            r.start = shift(r.start);
+            r.end = shift(r.end);
+        }
+        // Stable, so a region keeps its place before one that ends up with
+        // the same bounds inside it.
+        self.regions.extend(added);
+        self.regions.sort_by_key(|r| (r.start, std::cmp::Reverse(r.end)));
+        let mut open: Vec<usize> = Vec::new();
+        for i in 0..self.regions.len() {
+            while open.last().is_some_and(|&o| self.regions[o].end <= self.regions[i].start) {
+                open.pop();
+            }
+            self.regions[i].parent = open.last().copied();
+            open.push(i);
+        }
+        self.batches.push(edits);
+    }
+
+    /// Map a position in the expanded text back to the input. One inside an
+    /// alias value lands on the start (or, for `end`, the end) of its name.
+    fn restore(&self, mut pos: usize, end: bool) -> usize {
+        for edits in self.batches.iter().rev() {
+            let i = edits.partition_point(|e| e.new < pos);
+            let Some(e) = i.checked_sub(1).map(|i| &edits[i]) else { continue };
+            pos = if pos >= e.new + e.new_len {
+                pos - (e.new + e.new_len) + e.old + e.old_len
+            } else if end {
+                e.old + e.old_len
+            } else {
+                e.old
+            };
+        }
+        pos
+    }
+
+    fn restore_span(&self, span: Span) -> Span {
+        Span::new(self.restore(span.start, false), self.restore(span.end, true))
+    }
+
+    fn restore_spans<'a>(&self, mut cmds: Vec<Cmd<'a>>) -> Vec<Cmd<'a>> {
+        struct Restore<'e, 't>(&'e Expansion<'t>);
+        impl VisitMut<'_> for Restore<'_, '_> {
+            fn visit_span_mut(&mut self, span: &mut Span) {
+                *span = self.0.restore_span(*span);
+            }
+        }
+        Restore(self).visit_cmds_mut(&mut cmds);
+        cmds
+    }
+
+    fn restore_noted(&self, noted: &mut [(SpannedNode, Span)]) {
+        for (_, span) in noted {
+            *span = self.restore_span(*span);
+        }
+    }
+
+    fn restore_error(&self, err: ParseError) -> ParseError {
+        err.at(self.restore(err.position(), false))
+    }
+}
+
+/// Match the spans `noted` while parsing `cmds` to their nodes. Each node
+/// was noted when it was finished, after its children, so the spans come in
+/// the order a post-order walk meets the nodes.
+fn span_table(cmds: &[Cmd<'_>], noted: &[(SpannedNode, Span)]) -> SpanTable {
+    struct Walk<'n> {
+        noted: std::slice::Iter<'n, (SpannedNode, Span)>,
+        table: SpanTable,
+        matched: bool,
+    }
+    impl Walk<'_> {
+        fn take<T>(&mut self, kind: SpannedNode, node: &T) {
+            match self.noted.next() {
+                Some(&(noted, span)) if noted == kind => self.table.insert(kind, node, span),
+                _ => self.matched = false,
+            }
+        }
+    }
+    impl<'a> Visit<'a> for Walk<'_> {
+        fn visit_word(&mut self, word: &Word<'a>) {
+            walk_word(self, word);
+            self.take(SpannedNode::Word, word);
+        }
+        fn visit_word_part(&mut self, part: &WordPart<'a>) {
+            walk_word_part(self, part);
+            self.take(SpannedNode::WordPart, part);
+        }
+        fn visit_atom(&mut self, atom: &Atom<'a>) {
+            walk_atom(self, atom);
+            self.take(SpannedNode::Atom, atom);
+        }
+        fn visit_param(&mut self, param: &Param<'a>) {
+            self.take(SpannedNode::Param, param);
+        }
+        fn visit_redir(&mut self, redir: &Redir<'a>) {
+            walk_redir(self, redir);
+            self.take(SpannedNode::Redir, redir);
+        }
+    }
+
+    let mut walk = Walk {
+        noted: noted.iter(),
+        table: SpanTable::default(),
+        matched: true,
+    };
+    walk.visit_cmds(cmds);
+    let matched = walk.matched && walk.noted.next().is_none();
+    // A mismatch is a bug in the parser; no spans beat wrong ones
+    debug_assert!(matched, "noted spans don't match the tree");
+    if matched { walk.table } else { SpanTable::default() }
+}
+
+/// Detach parsed commands from the text they borrow.
+fn owned(cmds: Vec<Cmd<'_>>) -> Vec<Cmd<'static>> {
+    cmds.into_iter().map(Cmd::into_owned).collect()
+}
+
+/// Whether the words so far are just a declaration builtin, whose arguments
+/// may be `name=(...)` array assignments.
+fn is_decl_builtin(suffix: &[CmdSuffix<'_>]) -> bool {
+    matches!(
+        suffix.first(),
+        Some(CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::Lit(lit)))))
+            if matches!(lit.as_ref(), "declare" | "typeset" | "local" | "readonly" | "export")
+    )
+}
+
+/// Value of a bash `base#digits` literal. Digits are `0-9`, `a-z`, `A-Z`,
+/// `@`, `_`; for bases up to 36 letters are case-insensitive.
+fn parse_based_int(base: u32, digits: &str) -> Option<i64> {
+    if !(2..=64).contains(&base) || digits.is_empty() {
+        return None;
+    }
+    let mut n: i64 = 0;
+    for b in digits.bytes() {
+        let d = match b {
+            b'0'..=b'9' => u32::from(b - b'0'),
+            b'a'..=b'z' => u32::from(b - b'a') + 10,
+            b'A'..=b'Z' if base <= 36 => u32::from(b - b'A') + 10,
+            b'A'..=b'Z' => u32::from(b - b'A') + 36,
+            b'@' => 62,
+            _ => 63,
+        };
+        if d >= base {
+            return None;
+        }
+        n = n.wrapping_mul(i64::from(base)).wrapping_add(i64::from(d));
+    }
+    Some(n)
+}
+
+/// Scan `src` (starting at `{`) for a brace list. Returns the offsets of the
+/// top-level commas and of the matching `}`, skipping quotes, escapes and
+/// `${...}` / `$(...)` / backtick expansions.
+fn scan_brace_list(src: &[u8]) -> Option<(Vec<usize>, usize)> {
+    /// Offset just past the group opened at `i`, counting nested `open`s.
+    fn skip_group(src: &[u8], i: usize, open: u8, close: u8) -> Option<usize> {
+        let mut depth = 0usize;
+        for (j, &b) in src.iter().enumerate().skip(i) {
+            if b == open {
+                depth += 1;
+            } else if b == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(j);
+                }
+            }
+        }
+        None
+    }
+
+    let mut depth = 0usize;
+    let mut commas = Vec::new();
+    let mut i = 0;
+    while i < src.len() {
+        match src[i] {
+            b'\\' => i += 1,
+            b'\'' => i += 1 + src[i + 1..].iter().position(|&b| b == b'\'')?,
+            b'`' => i += 1 + src[i + 1..].iter().position(|&b| b == b'`')?,
+            b'"' => {
+                i += 1;
+                while *src.get(i)? != b'"' {
+                    i += if src[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'$' if src.get(i + 1) == Some(&b'{') => i = skip_group(src, i + 1, b'{', b'}')?,
+            b'$' if src.get(i + 1) == Some(&b'(') => i = skip_group(src, i + 1, b'(', b')')?,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (!commas.is_empty()).then_some((commas, i));
+                }
+            }
+            b',' if depth == 1 => commas.push(i),
+            b if is_meta(b) => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<Cmd<'_>> {
+        Parser::new(input).parse().unwrap()
+    }
+
+    fn parse_err(input: &str) -> ParseError {
+        Parser::new(input).parse().unwrap_err()
+    }
+
+    #[test]
+    fn simple_command() {
+        let cmds = parse("echo hello world");
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn pipeline() {
         let cmds = parse("cat file | grep foo | wc -l");
         assert_eq!(cmds.len(), 1);
     }
 
+    #[test]
+    fn timed_pipeline() {
+        let cmds = parse("time -p cat file | wc -l && time { a; }");
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Timed { posix: true, pipeline } = &list.first else {
+            panic!("expected time -p");
+        };
+        assert!(matches!(&**pipeline, Pipeline::Pipe(false, execs) if execs.len() == 2));
+        let AndOr::And(Pipeline::Timed { posix: false, pipeline }) = &list.rest[0] else {
+            panic!("expected time");
+        };
+        assert!(matches!(&**pipeline, Pipeline::Single(Executable::Compound(_))));
+    }
+
+    #[test]
+    fn bare_time_is_a_command() {
+        let cmds = parse("time; timeout 1 x");
+        assert_eq!(cmds.len(), 2);
+        assert!(matches!(simple(&cmds).suffix[..], [CmdSuffix::Word(_)]));
+        let cmds = parse("timeout 1 x");
+        assert_eq!(simple(&cmds).suffix.len(), 3);
+    }
+
     #[test]
     fn and_or_chain() {
         let cmds = parse("cmd1 && cmd2 || cmd3");
@@ -2382,6 +3723,57 @@ mod tests {
         assert_eq!(cmds.len(), 1);
     }
 
+    fn simple<'a>(cmds: &'a [Cmd<'a>]) -> &'a SimpleCmd<'a> {
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Simple(cmd)) = &list.first else {
+            panic!("expected simple");
+        };
+        cmd
+    }
+
+    #[test]
+    fn element_assignment() {
+        let cmds = parse("map[$key]=value");
+        let cmd = simple(&cmds);
+        assert!(cmd.suffix.is_empty());
+        let CmdPrefix::ElementAssign(name, index, Some(_)) = &cmd.prefix[0] else {
+            panic!("expected element assignment");
+        };
+        assert_eq!(*name, "map");
+        assert_eq!(*index, Word::Simple(WordPart::Bare(Atom::Param(Param::Var("key".into())))));
+    }
+
+    #[test]
+    fn bracket_word_is_not_assignment() {
+        let cmds = parse("echo[1] x");
+        let cmd = simple(&cmds);
+        assert!(cmd.prefix.is_empty());
+        assert_eq!(cmd.suffix.len(), 2);
+    }
+
+    #[test]
+    fn declare_array_argument() {
+        let cmds = parse("declare -A map=([a]=1 [b]=2) other");
+        let cmd = simple(&cmds);
+        assert_eq!(cmd.suffix.len(), 4);
+        let CmdSuffix::ArrayAssign(name, words) = &cmd.suffix[2] else {
+            panic!("expected array assignment argument");
+        };
+        assert_eq!(*name, "map");
+        assert_eq!(words.len(), 2);
+    }
+
+    #[test]
+    fn array_keys() {
+        let cmds = parse("echo ${!map[@]}");
+        let cmd = simple(&cmds);
+        let CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::Subst(subst)))) = &cmd.suffix[1]
+        else {
+            panic!("expected substitution");
+        };
+        assert_eq!(**subst, Subst::ArrayKeys("map".into()));
+    }
+
     #[test]
     fn export_assignment() {
         let cmds = parse("export PATH=/usr/bin:$PATH");
@@ -2520,6 +3912,45 @@ mod tests {
         assert_eq!(cmds.len(), 1);
     }
 
+    #[test]
+    fn brace_list_tree() {
+        let cmds = parse("echo pre{x,{y,z}}post");
+        let Word::Concat(parts) = first_word(&cmds, 1) else { panic!("expected concat") };
+        assert_eq!(parts.len(), 3);
+        let WordPart::Bare(Atom::BraceList(alts)) = &parts[1] else {
+            panic!("expected brace list");
+        };
+        assert_eq!(alts.len(), 2);
+        assert!(matches!(
+            &alts[1],
+            Word::Simple(WordPart::Bare(Atom::BraceList(inner))) if inner.len() == 2
+        ));
+    }
+
+    #[test]
+    fn brace_list_empty_alternative() {
+        let cmds = parse("cp file{,.bak}");
+        let Word::Concat(parts) = first_word(&cmds, 1) else { panic!("expected concat") };
+        let WordPart::Bare(Atom::BraceList(alts)) = &parts[1] else {
+            panic!("expected brace list");
+        };
+        assert!(matches!(&alts[0], Word::Concat(p) if p.is_empty()));
+    }
+
+    #[test]
+    fn brace_without_comma_is_literal() {
+        for input in ["echo {x}", "echo {a,b", "echo {a, b}", "echo '{a,b}'"] {
+            let cmds = parse(input);
+            let has_list = match first_word(&cmds, 1) {
+                Word::Simple(p) => matches!(p, WordPart::Bare(Atom::BraceList(_))),
+                Word::Concat(parts) => parts
+                    .iter()
+                    .any(|p| matches!(p, WordPart::Bare(Atom::BraceList(_)))),
+            };
+            assert!(!has_list, "{input}");
+        }
+    }
+
     #[test]
     fn double_bracket() {
         let cmds = parse("[[ -f /etc/hosts ]]");
@@ -2532,6 +3963,56 @@ mod tests {
         assert_eq!(cmds.len(), 1);
     }
 
+    #[test]
+    fn double_bracket_precedence() {
+        // && binds tighter than ||; a group overrides it
+        let cmds = parse("[[ -f a || -n $b && $c == y* ]]");
+        let CondExpr::Or(l, r) = cond(&cmds) else { panic!("expected ||") };
+        assert!(matches!(**l, CondExpr::Unary('f', _)));
+        assert!(matches!(**r, CondExpr::And(..)));
+
+        let cmds = parse("[[ ( -f a || -n $b ) && ! $c -lt 3 ]]");
+        let CondExpr::And(l, r) = cond(&cmds) else { panic!("expected &&") };
+        let CondExpr::Group(g) = &**l else { panic!("expected group") };
+        assert!(matches!(**g, CondExpr::Or(..)));
+        let CondExpr::Not(n) = &**r else { panic!("expected !") };
+        assert!(matches!(**n, CondExpr::Binary(_, CondOp::IntLt, _)));
+    }
+
+    #[test]
+    fn double_bracket_unary_vs_word() {
+        let cmds = parse("[[ -n ]]");
+        assert!(matches!(cond(&cmds), CondExpr::Word(_)));
+        let cmds = parse("[[ -n == x ]]");
+        assert!(matches!(cond(&cmds), CondExpr::Binary(_, CondOp::Match, _)));
+        let cmds = parse("[[ $a<$b ]]");
+        assert!(matches!(cond(&cmds), CondExpr::Binary(_, CondOp::StrLt, _)));
+    }
+
+    #[test]
+    fn double_bracket_regex_parens() {
+        let cmds = parse("[[ $x =~ ^(a b|c)+$ && -z $y ]]");
+        let CondExpr::And(l, _) = cond(&cmds) else { panic!("expected &&") };
+        let CondExpr::Binary(_, CondOp::Regex, re) = &**l else { panic!("expected =~") };
+        let text: String = match re {
+            Word::Simple(p) => vec![p],
+            Word::Concat(ps) => ps.iter().collect(),
+        }
+        .into_iter()
+        .map(|p| match p {
+            WordPart::Bare(Atom::Lit(s)) => s.as_ref(),
+            _ => panic!("expected literal regex"),
+        })
+        .collect();
+        assert_eq!(text, "^(a b|c)+$");
+    }
+
+    #[test]
+    fn double_bracket_unbalanced_group() {
+        let err = parse_err("[[ ( -f a ]]");
+        assert!(err.message().contains("')'"));
+    }
+
     #[test]
     fn standalone_arith() {
         let cmds = parse("(( i++ ))");
@@ -2610,6 +4091,96 @@ mod tests {
         assert_eq!(cmds.len(), 1);
     }
 
+    fn arith(input: &str) -> Arith<'_> {
+        Parser::new(input).arith(0).unwrap()
+    }
+
+    #[allow(clippy::unnecessary_box_returns)] // saves a Box::new per operand
+    fn var(name: &str) -> Box<Arith<'_>> {
+        Box::new(Arith::Var(name.into()))
+    }
+
+    #[test]
+    fn arithmetic_comma_is_loosest() {
+        assert_eq!(
+            arith("i = 0, j = 10"),
+            Arith::Comma(
+                Box::new(Arith::Assign("i".into(), Box::new(Arith::Lit(0)))),
+                Box::new(Arith::Assign("j".into(), Box::new(Arith::Lit(10)))),
+            )
+        );
+        assert!(matches!(arith("a ? b : c, d"), Arith::Comma(..)));
+    }
+
+    #[test]
+    fn arithmetic_compound_assignments() {
+        for (op, expected) in [
+            ("**=", Arith::Pow(var("x"), var("y"))),
+            ("<<=", Arith::Shl(var("x"), var("y"))),
+            (">>=", Arith::Shr(var("x"), var("y"))),
+            ("&=", Arith::BitAnd(var("x"), var("y"))),
+            ("|=", Arith::BitOr(var("x"), var("y"))),
+            ("^=", Arith::BitXor(var("x"), var("y"))),
+        ] {
+            let input = format!("x {op} y");
+            assert_eq!(arith(&input), Arith::Assign("x".into(), Box::new(expected)), "{op}");
+        }
+    }
+
+    #[test]
+    fn arithmetic_base_literals() {
+        for (input, n) in [("16#ff", 255), ("2#1010", 10), ("0x1f", 31), ("017", 15), ("64#_", 63)]
+        {
+            assert_eq!(arith(input), Arith::Lit(n), "{input}");
+        }
+        assert!(Parser::new("2#102").arith(0).is_err());
+        assert!(Parser::new("09").arith(0).is_err());
+    }
+
+    #[test]
+    fn arithmetic_array_element() {
+        assert_eq!(
+            arith("arr[i+1]"),
+            Arith::Elem("arr".into(), Box::new(Arith::Add(var("i"), Box::new(Arith::Lit(1)))))
+        );
+    }
+
+    #[test]
+    fn arithmetic_array_element_targets() {
+        let elem = || Arith::Elem("arr".into(), var("i"));
+        assert_eq!(
+            arith("arr[i] = 5"),
+            Arith::ElemAssign("arr".into(), var("i"), Box::new(Arith::Lit(5)))
+        );
+        assert_eq!(
+            arith("arr[i] += 2"),
+            Arith::ElemAssign(
+                "arr".into(),
+                var("i"),
+                Box::new(Arith::Add(Box::new(elem()), Box::new(Arith::Lit(2)))),
+            )
+        );
+        assert_eq!(arith("arr[i]++"), Arith::ElemPostInc("arr".into(), var("i")));
+        assert_eq!(arith("arr[i]--"), Arith::ElemPostDec("arr".into(), var("i")));
+        assert_eq!(arith("++arr[i]"), Arith::ElemPreInc("arr".into(), var("i")));
+        assert_eq!(arith("--arr[i]"), Arith::ElemPreDec("arr".into(), var("i")));
+        assert_eq!(arith("counts[$k]++"), Arith::ElemPostInc("counts".into(), var("k")));
+    }
+
+    #[test]
+    fn arithmetic_legacy_brackets() {
+        let cmds = parse("echo $[1 + 2]");
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Simple(cmd)) = &list.first else {
+            panic!("expected simple command");
+        };
+        let CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::Subst(subst)))) = &cmd.suffix[1]
+        else {
+            panic!("expected substitution");
+        };
+        assert!(matches!(subst.as_ref(), Subst::Arith(Some(Arith::Add(..)))));
+    }
+
     #[test]
     fn param_expansion_len() {
         let cmds = parse("echo ${#HOME}");
@@ -2634,6 +4205,29 @@ mod tests {
         assert_eq!(cmds.len(), 1);
     }
 
+    #[test]
+    fn case_modification_pattern() {
+        let cmds = parse("echo ${var^^[aeiou]} ${var,x}");
+        let Word::Simple(WordPart::Bare(Atom::Subst(subst))) = first_word(&cmds, 1) else {
+            panic!("expected subst");
+        };
+        assert!(matches!(&**subst, Subst::Upper(true, Param::Var(v), Some(_)) if v == "var"));
+        let Word::Simple(WordPart::Bare(Atom::Subst(subst))) = first_word(&cmds, 2) else {
+            panic!("expected subst");
+        };
+        let Subst::Lower(false, _, Some(pattern)) = &**subst else { panic!("expected ,x") };
+        assert_eq!(*pattern, Word::Simple(WordPart::Bare(Atom::Lit("x".into()))));
+    }
+
+    #[test]
+    fn array_element_operators_rejected() {
+        for input in ["echo ${arr[@]@U}", "echo ${arr[@]^^[a]}", "echo ${a[1]@A}"] {
+            let err = parse_err(input);
+            assert_eq!(err.message(), "parameter operators on array elements are not supported");
+        }
+        assert_eq!(parse_err("echo ${a[1]x}").message(), "expected '}'");
+    }
+
     #[test]
     fn replace_first() {
         let cmds = parse("echo ${var/foo/bar}");
@@ -2683,9 +4277,31 @@ mod tests {
     }
 
     #[test]
-    fn process_substitution_out_error() {
-        let err = parse_err("tee >(grep foo)");
-        assert!(err.message().contains("output process substitution"));
+    fn process_substitution_out() {
+        let cmds = parse("tee >(gzip > log.gz) >(sha256sum) > /dev/null");
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Simple(cmd)) = &list.first else {
+            panic!("expected simple");
+        };
+        assert_eq!(cmd.suffix.len(), 4);
+        let CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::ProcSubOut(inner)))) = &cmd.suffix[1]
+        else {
+            panic!("expected output process substitution");
+        };
+        assert_eq!(inner.len(), 1);
+    }
+
+    #[test]
+    fn process_substitution_out_as_redirect_target() {
+        let cmds = parse("make 2> >(tee err.log)");
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Simple(cmd)) = &list.first else {
+            panic!("expected simple");
+        };
+        let CmdSuffix::Redirect(Redir::Write(Some(2), target)) = &cmd.suffix[1] else {
+            panic!("expected 2> redirect");
+        };
+        assert!(matches!(target, Word::Simple(WordPart::Bare(Atom::ProcSubOut(_)))));
     }
 
     #[test]
@@ -2712,16 +4328,114 @@ mod tests {
         assert_eq!(cmds.len(), 1);
     }
 
+    fn heredocs(input: &str) -> Vec<(Option<u16>, Heredoc<'_>)> {
+        let mut docs = Vec::new();
+        for cmd in parse(input) {
+            let Cmd::List(list) = cmd else { continue };
+            let redirects = match list.first {
+                Pipeline::Single(Executable::Simple(simple)) => simple
+                    .suffix
+                    .into_iter()
+                    .filter_map(|s| match s {
+                        CmdSuffix::Redirect(r) => Some(r),
+                        _ => None,
+                    })
+                    .collect(),
+                Pipeline::Single(Executable::Compound(cmd)) => cmd.redirects,
+                _ => Vec::new(),
+            };
+            for redir in redirects {
+                if let Redir::Heredoc(fd, doc) = redir {
+                    docs.push((fd, doc));
+                }
+            }
+        }
+        docs
+    }
+
+    #[test]
+    fn heredoc_keeps_fd_delimiter_and_tabs() {
+        let docs = heredocs("cat 3<<-END\n\tbody\n\tEND");
+        assert_eq!(docs.len(), 1);
+        let (fd, doc) = &docs[0];
+        assert_eq!(*fd, Some(3));
+        assert_eq!(doc.delimiter, "END");
+        assert!(doc.strip_tabs);
+        assert!(matches!(&doc.body, HeredocBody::Interpolated(_)));
+    }
+
+    #[test]
+    fn heredoc_partially_quoted_delimiter() {
+        for input in ["cat <<\\EOF\n$x\nEOF", "cat <<E\"O\"F\n$x\nEOF"] {
+            let docs = heredocs(input);
+            assert_eq!(docs[0].1.delimiter, "EOF", "{input}");
+            assert_eq!(docs[0].1.body, HeredocBody::Literal("$x\n".into()), "{input}");
+        }
+    }
+
+    #[test]
+    fn heredoc_delimiter_ending_in_backslash() {
+        for input in ["cat <<x\\", "cat <<\\", "cat <<\"a\\"] {
+            assert_eq!(parse_err(input).message(), "unterminated heredoc delimiter", "{input}");
+        }
+    }
+
+    #[test]
+    fn heredoc_several_on_one_line() {
+        let docs = heredocs("cmd <<A <<'B'\na\nA\nb\nB\necho after");
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].1.delimiter, "A");
+        assert_eq!(docs[1].1.body, HeredocBody::Literal("b\n".into()));
+        let cmds = parse("cmd <<A <<'B'\na\nA\nb\nB\necho after");
+        assert_eq!(cmds.len(), 2);
+    }
+
+    #[test]
+    fn heredoc_rest_of_line_parsed() {
+        assert_eq!(parse("cat <<A; echo hi\nbody\nA\necho bye").len(), 3);
+        assert_eq!(parse("if cat <<A; then\nx\nA\necho yes; fi").len(), 1);
+        let docs = heredocs("while read l; do echo $l; done <<EOF\none\nEOF");
+        assert_eq!(docs.len(), 1);
+    }
+
+    fn case_terminators(input: &str) -> Vec<CaseTerminator> {
+        let cmds = parse(input);
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(cmd)) = &list.first else {
+            panic!("expected compound");
+        };
+        let CompoundKind::Case { arms, .. } = &cmd.kind else {
+            panic!("expected case");
+        };
+        arms.iter().map(|arm| arm.terminator).collect()
+    }
+
+    #[test]
+    fn case_fallthrough() {
+        assert_eq!(
+            case_terminators("case $x in a) echo a;& b) echo b;; esac"),
+            [CaseTerminator::FallThrough, CaseTerminator::Break]
+        );
+    }
+
     #[test]
-    fn case_fallthrough_error() {
-        let err = parse_err("case $x in a) echo a;& b) echo b;; esac");
-        assert!(err.message().contains("fallthrough"));
+    fn case_continue() {
+        assert_eq!(
+            case_terminators("case $x in a) echo a;;& b) echo b;; esac"),
+            [CaseTerminator::Continue, CaseTerminator::Break]
+        );
     }
 
     #[test]
-    fn case_continue_error() {
-        let err = parse_err("case $x in a) echo a;;& b) echo b;; esac");
-        assert!(err.message().contains(";;&"));
+    fn case_terminators_with_empty_bodies() {
+        assert_eq!(
+            case_terminators("case $x in\n a) ;&\n b) ;;&\n c)\nesac"),
+            [
+                CaseTerminator::FallThrough,
+                CaseTerminator::Continue,
+                CaseTerminator::Break
+            ]
+        );
     }
 
     #[test]
@@ -2737,8 +4451,487 @@ mod tests {
     }
 
     #[test]
-    fn select_error() {
-        let err = parse_err("select opt in a b c; do echo $opt; done");
-        assert!(err.message().contains("select"));
+    fn select_loop() {
+        let cmds = parse("select opt in a b c; do echo $opt; break; done");
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(cmd)) = &list.first else {
+            panic!("expected compound");
+        };
+        let CompoundKind::Select { var, words, body } = &cmd.kind else {
+            panic!("expected select");
+        };
+        assert_eq!(*var, "opt");
+        assert_eq!(words.as_ref().map(Vec::len), Some(3));
+        assert_eq!(body.len(), 2);
+    }
+
+    #[test]
+    fn select_without_in() {
+        let cmds = parse("select opt\ndo echo $opt\ndone");
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn select_missing_done() {
+        let err = parse_err("select opt in a b; do echo $opt");
+        assert_eq!(err.expected(), ["done"]);
+    }
+
+    #[test]
+    fn span_top_level_commands() {
+        let input = "echo a; ls -l  && pwd";
+        let cmds = parse(input);
+        assert_eq!(cmds[0].span().slice(input), "echo a");
+        assert_eq!(cmds[1].span().slice(input), "ls -l  && pwd");
+    }
+
+    #[test]
+    fn span_compound_includes_redirects() {
+        let input = "if true; then echo hi; fi > out.txt; echo done";
+        let cmds = parse(input);
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(cmd)) = &list.first else {
+            panic!("expected compound");
+        };
+        assert_eq!(cmd.span.slice(input), "if true; then echo hi; fi > out.txt");
+        let CompoundKind::If { conditionals, .. } = &cmd.kind else {
+            panic!("expected if");
+        };
+        assert_eq!(conditionals[0].span.slice(input), "true; then echo hi");
+    }
+
+    #[test]
+    fn span_func_def_covers_name() {
+        let input = "function greet() { echo hi; }";
+        let cmds = parse(input);
+        assert_eq!(cmds[0].span().slice(input), input);
+    }
+
+    #[test]
+    fn span_case_arms() {
+        let input = "case $x in a|b) echo ab;; *) echo other;; esac";
+        let cmds = parse(input);
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(cmd)) = &list.first else {
+            panic!("expected compound");
+        };
+        let CompoundKind::Case { arms, .. } = &cmd.kind else {
+            panic!("expected case");
+        };
+        assert_eq!(arms[0].span.slice(input), "a|b) echo ab;;");
+        assert_eq!(arms[1].span.slice(input), "*) echo other;;");
+    }
+
+    #[test]
+    fn span_backtick_is_absolute() {
+        let input = "echo `ls -l`";
+        let cmds = parse(input);
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Simple(cmd)) = &list.first else {
+            panic!("expected simple");
+        };
+        let CmdSuffix::Word(Word::Simple(WordPart::Bare(Atom::Subst(subst)))) = &cmd.suffix[1]
+        else {
+            panic!("expected substitution");
+        };
+        let Subst::Cmd(inner) = subst.as_ref() else {
+            panic!("expected command substitution");
+        };
+        assert_eq!(inner[0].span().slice(input), "ls -l");
+    }
+
+    #[test]
+    fn recovering_clean_input_matches_parse() {
+        let input = "echo a; if true; then echo b; fi\nls | wc -l";
+        let (cmds, errors) = Parser::new(input).parse_recovering();
+        assert!(errors.is_empty());
+        assert_eq!(cmds, parse(input));
+    }
+
+    #[test]
+    fn recovering_reports_every_error() {
+        let input = "echo ${x\necho ok\nfor; do echo; done\necho $((1 +))";
+        let (cmds, errors) = Parser::new(input).parse_recovering();
+        assert_eq!(errors.len(), 3, "errors: {errors:?}");
+        let kept: Vec<_> = cmds.iter().map(|c| c.span().slice(input)).collect();
+        assert_eq!(kept, ["echo ok"]);
+    }
+
+    #[test]
+    fn recovering_drops_broken_bodies() {
+        let input = "for x in a; do\n  if true; then echo ${x; fi\n  rm -rf /tmp/x\ndone\n\
+                     f() { echo $((1 +)); }; echo ok";
+        let (cmds, errors) = Parser::new(input).parse_recovering();
+        assert_eq!(errors.len(), 2, "errors: {errors:?}");
+        let kept: Vec<_> = cmds.iter().map(|c| c.span().slice(input)).collect();
+        assert_eq!(kept, ["echo ok"]);
+    }
+
+    #[test]
+    fn recovering_skips_dangling_closers() {
+        let input = "while true; do echo ${x; done\nif true; then echo ${y; fi; echo ok";
+        let (cmds, errors) = Parser::new(input).parse_recovering();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].span().slice(input), "echo ok");
+    }
+
+    #[test]
+    fn recovering_does_not_split_quoted_separators() {
+        let input = "echo ${x 'a;b' \"c\nd\"\necho ok";
+        let (cmds, errors) = Parser::new(input).parse_recovering();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].span().slice(input), "echo ok");
+    }
+
+    #[test]
+    fn recovering_unicode_after_error() {
+        let (cmds, errors) = Parser::new("echo ${é ü\necho ok").parse_recovering();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for input in [
+            "for f in *; do",
+            "for f in *; do\n  echo $f\n",
+            "if true; then",
+            "if true; then :; else",
+            "while read -r l",
+            "case $x in",
+            "f() {",
+            "(cd /tmp",
+            "echo \"unclosed",
+            "echo 'unclosed",
+            "echo $'unclosed",
+            "echo $(ls",
+            "echo `ls",
+            "echo $((1 +",
+            "cat <<EOF\nline",
+            "a &&",
+            "a ||\n",
+            "a |",
+            "ls -la \\",
+            "[[ -n $x",
+        ] {
+            assert!(Parser::new(input).is_incomplete(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn complete_or_invalid_input() {
+        for input in [
+            "",
+            "for f in *; do echo $f; done",
+            "echo 'a \\'",
+            "echo a\\\\",
+            "a && b",
+            "echo )",
+            "fi",
+            "echo $(if true)",
+            "for f in *; echo $f; end",
+        ] {
+            assert!(!Parser::new(input).is_incomplete(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn incomplete_flag_on_errors() {
+        assert!(Parser::new("if true; then").parse().unwrap_err().is_incomplete());
+        assert!(!Parser::new("for x in a; echo; done").parse().unwrap_err().is_incomplete());
+        let (_, errors) = Parser::new("echo )\nwhile true; do").parse_recovering();
+        let flags: Vec<_> = errors.iter().map(ParseError::is_incomplete).collect();
+        assert_eq!(flags, [false, true]);
+    }
+
+    fn coproc_parts<'a>(cmds: &'a [Cmd<'a>]) -> (Option<&'a str>, &'a Executable<'a>) {
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(cmd)) = &list.first else {
+            panic!("expected compound");
+        };
+        let CompoundKind::Coproc { name, body } = &cmd.kind else {
+            panic!("expected coproc");
+        };
+        (name.as_deref(), body)
+    }
+
+    #[test]
+    fn coproc_simple_command() {
+        let cmds = parse("coproc cat -u");
+        let (name, body) = coproc_parts(&cmds);
+        assert_eq!(name, None);
+        let Executable::Simple(cmd) = body else { panic!("expected simple") };
+        assert_eq!(cmd.suffix.len(), 2);
+    }
+
+    #[test]
+    fn coproc_named_compound() {
+        let cmds = parse("coproc UP { while read -r l; do echo \"${l^^}\"; done; }");
+        let (name, body) = coproc_parts(&cmds);
+        assert_eq!(name, Some("UP"));
+        assert!(matches!(
+            body,
+            Executable::Compound(CompoundCmd { kind: CompoundKind::Brace(_), .. })
+        ));
+    }
+
+    #[test]
+    fn coproc_unnamed_compound() {
+        let cmds = parse("coproc { cat; }");
+        let (name, _) = coproc_parts(&cmds);
+        assert_eq!(name, None);
+        let cmds = parse("coproc while true; do date; sleep 1; done");
+        let (name, body) = coproc_parts(&cmds);
+        assert_eq!(name, None);
+        assert!(matches!(
+            body,
+            Executable::Compound(CompoundCmd { kind: CompoundKind::While(_), .. })
+        ));
+    }
+
+    #[test]
+    fn coproc_followed_by_command() {
+        let input = "coproc NC { nc -l 8080; } 2>/dev/null; echo ${NC[1]}";
+        let cmds = parse(input);
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0].span().slice(input), "coproc NC { nc -l 8080; } 2>/dev/null");
+    }
+
+    #[test]
+    fn coproc_missing_command() {
+        let err = parse_err("coproc");
+        assert!(err.message().contains("coproc"));
+    }
+
+    fn first_word<'a>(cmds: &'a [Cmd<'a>], idx: usize) -> &'a Word<'a> {
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Simple(cmd)) = &list.first else {
+            panic!("expected simple");
+        };
+        let CmdSuffix::Word(w) = &cmd.suffix[idx] else { panic!("expected word") };
+        w
+    }
+
+    #[test]
+    fn extglob_not() {
+        let cmds = parse("ls !(*.tmp)");
+        let Word::Simple(WordPart::Bare(Atom::ExtGlob { kind, alternatives })) =
+            first_word(&cmds, 1)
+        else {
+            panic!("expected extglob");
+        };
+        assert_eq!(*kind, ExtGlobKind::Not);
+        assert_eq!(alternatives.len(), 1);
+    }
+
+    #[test]
+    fn extglob_inside_word() {
+        let cmds = parse("ls *.@(jpg|png|)");
+        let Word::Concat(parts) = first_word(&cmds, 1) else { panic!("expected concat") };
+        assert_eq!(parts.len(), 3);
+        let WordPart::Bare(Atom::ExtGlob { kind, alternatives }) = &parts[2] else {
+            panic!("expected extglob");
+        };
+        assert_eq!(*kind, ExtGlobKind::ExactlyOne);
+        assert_eq!(alternatives.len(), 3);
+        assert_eq!(alternatives[2], Word::Concat(vec![]));
+    }
+
+    #[test]
+    fn extglob_case_pattern() {
+        let cmds = parse("case $x in +([0-9])) echo num;; esac");
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(CompoundCmd {
+            kind: CompoundKind::Case { arms, .. },
+            ..
+        })) = &list.first
+        else {
+            panic!("expected case");
+        };
+        assert!(matches!(
+            &arms[0].patterns[0],
+            Word::Simple(WordPart::Bare(Atom::ExtGlob { kind: ExtGlobKind::OneOrMore, .. }))
+        ));
+    }
+
+    fn cond<'a>(cmds: &'a [Cmd<'a>]) -> &'a CondExpr<'a> {
+        let Cmd::List(list) = &cmds[0] else { panic!("expected list") };
+        let Pipeline::Single(Executable::Compound(CompoundCmd {
+            kind: CompoundKind::DoubleBracket(expr),
+            ..
+        })) = &list.first
+        else {
+            panic!("expected [[");
+        };
+        expr
+    }
+
+    #[test]
+    fn extglob_not_in_regex() {
+        let cmds = parse("[[ $x =~ ^a+(b)$ ]]");
+        let CondExpr::Binary(_, CondOp::Regex, Word::Concat(parts)) = cond(&cmds) else {
+            panic!("expected regex concat");
+        };
+        assert!(
+            !parts
+                .iter()
+                .any(|p| matches!(p, WordPart::Bare(Atom::ExtGlob { .. })))
+        );
+    }
+
+    #[test]
+    fn extglob_unterminated() {
+        let err = parse_err("ls @(a|b");
+        assert!(err.message().contains("extended glob"));
+    }
+
+    // --- Aliases ---
+
+    fn alias_table(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect()
+    }
+
+    fn expand(input: &str, pairs: &[(&str, &str)]) -> String {
+        let table = alias_table(pairs);
+        let cmds = Parser::new(input).with_aliases(&table).parse().unwrap();
+        crate::printer::Printer::new().print(&cmds)
+    }
+
+    #[test]
+    fn alias_first_word() {
+        let aliases = [("ll", "ls -la")];
+        assert_eq!(expand("ll | head", &aliases), "ls -la | head\n");
+        assert_eq!(expand("x=1 ll; ll a && ll", &aliases), "x=1 ls -la\nls -la a && ls -la\n");
+        assert_eq!(expand("echo ll 'll' \"ll\" \\ll", &aliases), "echo ll 'll' \"ll\" \\ll\n");
+        assert_eq!(expand("echo $(ll) `ll`", &aliases), "echo $(ls -la) $(ls -la)\n");
+    }
+
+    #[test]
+    fn alias_to_keywords() {
+        let aliases = [("forever", "while true"), ("loop", "for i in 1 2; do")];
+        assert_eq!(expand("forever; do x; done", &aliases), "while true; do\n    x\ndone\n");
+        assert_eq!(expand("loop echo $i; done", &aliases), "for i in 1 2; do\n    echo $i\ndone\n");
+    }
+
+    #[test]
+    fn alias_trailing_blank() {
+        let aliases = [("sudo", "sudo "), ("ll", "ls -la"), ("cmd", "command")];
+        assert_eq!(expand("sudo ll", &aliases), "sudo ls -la\n");
+        assert_eq!(expand("cmd ll", &aliases), "command ll\n");
+    }
+
+    #[test]
+    fn alias_not_expanded_in_itself() {
+        let aliases = [("ls", "ls --color"), ("ll", "ls -l"), ("a", "b"), ("b", "a")];
+        assert_eq!(expand("ll x", &aliases), "ls --color -l x\n");
+        assert_eq!(expand("a", &aliases), "a\n");
+    }
+
+    #[test]
+    fn alias_many_in_one_input() {
+        let aliases = [("ll", "ls -la"), ("sudo", "sudo "), ("up", "cd ..; ls")];
+        let input = "ll a\nsudo ll b\nup\n".repeat(200);
+        let expected = "ls -la a\nsudo ls -la b\ncd ..\nls\n".repeat(200);
+        assert_eq!(expand(&input, &aliases), expected);
+        assert_eq!(expand("f() {\n  ll\n  up\n  ll\n}", &aliases).matches("ls -la").count(), 2);
+        assert_eq!(expand("cat <<EOF; ll\n$(up)\nEOF\nll", &aliases).matches("ls -la").count(), 2);
+    }
+
+    #[test]
+    fn alias_spans_point_into_input() {
+        let table = alias_table(&[("ll", "ls -la")]);
+        let input = "echo a; ll | head";
+        let cmds = Parser::new(input).with_aliases(&table).parse().unwrap();
+        assert_eq!(cmds[1].span().slice(input), "ll | head");
+
+        let input = "ll; ll x\nll";
+        let cmds = Parser::new(input).with_aliases(&table).parse().unwrap();
+        let spans: Vec<_> = cmds.iter().map(|c| c.span().slice(input)).collect();
+        assert_eq!(spans, ["ll", "ll x", "ll"]);
+
+        let err = Parser::new("ll; )").with_aliases(&table).parse().unwrap_err();
+        assert_eq!(err.position(), 4);
+    }
+
+    #[test]
+    fn alias_recovering_and_incomplete() {
+        let table = alias_table(&[("loop", "while true; do"), ("ll", "ls -la")]);
+        assert!(Parser::new("loop").with_aliases(&table).is_incomplete());
+        assert!(!Parser::new("loop :; done").with_aliases(&table).is_incomplete());
+
+        let (cmds, errors) = Parser::new("echo ${x\nll").with_aliases(&table).parse_recovering();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(crate::printer::Printer::new().print(&cmds), "ls -la\n");
+    }
+    // --- Span table ---
+
+    /// The text at each located word, part, atom, parameter and redirect,
+    /// in the order a visitor meets them.
+    fn located(input: &str, pairs: &[(&str, &str)]) -> Vec<String> {
+        struct Located<'s>(&'s SpanTable, &'s str, Vec<String>);
+        impl Located<'_> {
+            fn push(&mut self, kind: &str, span: Option<Span>) {
+                let text = span.map_or("?", |span| span.slice(self.1));
+                self.2.push(format!("{kind} {text}"));
+            }
+        }
+        impl<'a> Visit<'a> for Located<'_> {
+            fn visit_word(&mut self, word: &Word<'a>) {
+                self.push("word", self.0.word(word));
+                walk_word(self, word);
+            }
+            fn visit_word_part(&mut self, part: &WordPart<'a>) {
+                if !matches!(part, WordPart::Bare(_)) {
+                    self.push("part", self.0.word_part(part));
+                }
+                walk_word_part(self, part);
+            }
+            fn visit_atom(&mut self, atom: &Atom<'a>) {
+                self.push("atom", self.0.atom(atom));
+                walk_atom(self, atom);
+            }
+            fn visit_param(&mut self, param: &Param<'a>) {
+                self.push("param", self.0.param(param));
+            }
+            fn visit_redir(&mut self, redir: &Redir<'a>) {
+                self.push("redir", self.0.redir(redir));
+                walk_redir(self, redir);
+            }
+        }
+        let table = alias_table(pairs);
+        let (cmds, spans) = Parser::new(input).with_aliases(&table).parse_with_spans().unwrap();
+        let mut located = Located(&spans, input, Vec::new());
+        located.visit_cmds(&cmds);
+        located.2
+    }
+
+    #[test]
+    fn span_table_words_and_params() {
+        assert_eq!(located("echo ${a:-\"d\"}x", &[]), [
+            "word echo", "atom echo",
+            "word ${a:-\"d\"}x", "atom ${a:-\"d\"}", "param a", "word \"d\"", "part \"d\"",
+            "atom d", "atom x",
+        ]);
+        assert_eq!(located("[[ $#x ]]", &[]), ["word $#x", "atom $#", "param #", "atom x"]);
+    }
+
+    #[test]
+    fn span_table_redirects() {
+        assert_eq!(located("a 2>&1 |& b <<E\nhi $x\nE", &[]), [
+            "word a", "atom a", "redir 2>&1", "word 1", "atom 1",
+            "redir |&", "word |&", "atom |&",
+            "word b", "atom b", "redir <<E", "atom hi ", "atom $x", "param x", "atom \n",
+        ]);
+    }
+
+    #[test]
+    fn span_table_aliases() {
+        assert_eq!(located("ll x; echo `ll`", &[("ll", "ls -la")]), [
+            "word ll", "atom ll", "word ll", "atom ll", "word x", "atom x",
+            "word echo", "atom echo", "word `ll`", "atom `ll`",
+            "word ll", "atom ll", "word ll", "atom ll",
+        ]);
     }
 }
@@ -22,7 +22,8 @@ const CWD_MARKER: &str = "\0__REEF_CWD__\0";
 /// pipe stdout through `| source` to apply environment changes.
 ///
 /// How it works:
-/// 1. Capture a "before" snapshot of the current environment
+/// 1. Capture a "before" snapshot of the current environment, plus bash's
+///    associative arrays dumped just before the command
 /// 2. Run the command in bash with stderr inherited (streams directly)
 /// 3. Stdout is captured — the command output appears before our markers,
 ///    and we print it back to the real stdout immediately
@@ -72,7 +73,7 @@ pub fn bash_exec(command: &str) -> i32 {
     };
     #[cfg(not(unix))]
     let exit_code = output.status.code().unwrap_or(1);
-    diff_and_print_env(&before, &output.stdout);
+    diff_and_print_env(before, &output.stdout);
     exit_code
 }
 
@@ -108,7 +109,7 @@ pub fn bash_exec_env_diff(command: &str) -> i32 {
         }
     };
 
-    diff_and_print_env(&before, &output.stdout);
+    diff_and_print_env(before, &output.stdout);
 
     if output.status.success() {
         0
@@ -180,30 +181,43 @@ pub fn bash_exec_with_state(command: &str, state_path: &Path) -> i32 {
     };
     #[cfg(not(unix))]
     let exit_code = output.status.code().unwrap_or(1);
-    diff_and_print_env_save_state(&before, &output.stdout, state_path);
+    diff_and_print_env_save_state(before, &output.stdout, state_path);
     exit_code
 }
 
-/// Extract env and cwd sections from bash stdout (after sentinel markers).
-fn extract_env_sections(raw_stdout: &[u8]) -> Option<(String, String)> {
+/// Sections of bash stdout: the associative array dump taken before the
+/// command, and the env and cwd dumps after it (between sentinel markers).
+struct EnvSections {
+    before: String,
+    env: String,
+    cwd: String,
+}
+
+fn extract_env_sections(raw_stdout: &[u8]) -> Option<EnvSections> {
     let stdout = String::from_utf8_lossy(raw_stdout);
     let env_pos = stdout.find(ENV_MARKER)?;
     let cwd_pos = stdout.find(CWD_MARKER)?;
-    let env_section = stdout[env_pos + ENV_MARKER.len()..cwd_pos].to_string();
-    let cwd_section = stdout[cwd_pos + CWD_MARKER.len()..].trim().to_string();
-    Some((env_section, cwd_section))
+    Some(EnvSections {
+        before: stdout[..env_pos].to_string(),
+        env: stdout[env_pos + ENV_MARKER.len()..cwd_pos].to_string(),
+        cwd: stdout[cwd_pos + CWD_MARKER.len()..].trim().to_string(),
+    })
+}
+
+/// Fish commands applying the changes between `before` and the dumps.
+fn env_changes(before: EnvSnapshot, sections: EnvSections) -> String {
+    let before = before.with_assoc_dump(&sections.before);
+    let after = EnvSnapshot::from_dump(&sections.env, sections.cwd);
+    let mut buf = String::new();
+    before.diff_into(&after, &mut buf);
+    buf
 }
 
 /// Parse env data from bash stdout, diff against the before snapshot,
 /// and print fish `set` commands to stdout.
-fn diff_and_print_env(before: &EnvSnapshot, raw_stdout: &[u8]) {
-    if let Some((env_section, cwd_section)) = extract_env_sections(raw_stdout) {
-        let after = EnvSnapshot::new(
-            env_diff::parse_null_separated_env(&env_section),
-            cwd_section,
-        );
-        let mut buf = String::new();
-        before.diff_into(&after, &mut buf);
+fn diff_and_print_env(before: EnvSnapshot, raw_stdout: &[u8]) {
+    if let Some(sections) = extract_env_sections(raw_stdout) {
+        let buf = env_changes(before, sections);
         if !buf.is_empty() {
             let _ = io::stdout().lock().write_all(buf.as_bytes());
         }
@@ -212,25 +226,23 @@ fn diff_and_print_env(before: &EnvSnapshot, raw_stdout: &[u8]) {
 
 /// Like `diff_and_print_env`, but also saves the env snapshot to a state file
 /// so subsequent invocations can restore it.
-fn diff_and_print_env_save_state(before: &EnvSnapshot, raw_stdout: &[u8], state_path: &Path) {
-    if let Some((env_section, cwd_section)) = extract_env_sections(raw_stdout) {
-        let _ = state::save_state(state_path, &env_section);
-        let after = EnvSnapshot::new(
-            env_diff::parse_null_separated_env(&env_section),
-            cwd_section,
-        );
-        let mut buf = String::new();
-        before.diff_into(&after, &mut buf);
+fn diff_and_print_env_save_state(before: EnvSnapshot, raw_stdout: &[u8], state_path: &Path) {
+    if let Some(sections) = extract_env_sections(raw_stdout) {
+        let (env_data, _) = env_diff::split_assoc_dump(&sections.env);
+        let _ = state::save_state(state_path, env_data);
+        let buf = env_changes(before, sections);
         if !buf.is_empty() {
             let _ = io::stdout().lock().write_all(buf.as_bytes());
         }
     }
 }
 
-/// Build a bash script that evals the command with the given redirect suffix,
-/// then dumps env markers + env -0 + cwd for the diff.
+/// Build a bash script that dumps associative arrays, evals the command
+/// with the given redirect suffix, then dumps env markers + env -0 +
+/// associative arrays + cwd for the diff.
 fn build_script(escaped_cmd: &str, redirect: &str, track_exit: bool) -> String {
-    let mut s = String::with_capacity(escaped_cmd.len() + 100);
+    let mut s = String::with_capacity(escaped_cmd.len() + 2 * env_diff::ASSOC_DUMP.len() + 100);
+    s.push_str(env_diff::ASSOC_DUMP);
     s.push_str("eval ");
     s.push_str(escaped_cmd);
     s.push_str(redirect);
@@ -239,7 +251,9 @@ fn build_script(escaped_cmd: &str, redirect: &str, track_exit: bool) -> String {
         s.push_str("__reef_exit=$?\n");
     }
     // Use printf with null bytes for sentinels — prevents collisions with any output
-    s.push_str("printf '\\0__REEF_ENV__\\0'\nenv -0\nprintf '\\0__REEF_CWD__\\0'\npwd");
+    s.push_str("printf '\\0__REEF_ENV__\\0'\nenv -0\n");
+    s.push_str(env_diff::ASSOC_DUMP);
+    s.push_str("printf '\\0__REEF_CWD__\\0'\npwd");
     if track_exit {
         s.push_str("\nexit $__reef_exit");
     }
@@ -306,6 +320,31 @@ mod tests {
         assert_eq!(code, 0);
     }
 
+    #[test]
+    fn assoc_unset_syncs_back() {
+        let script = build_script(&shell_escape_for_bash("unset m; n[k]=w"), " >&2", false);
+        let output = Command::new("bash")
+            .args(["-c", &format!("declare -A m=([k]=v) n=([k]=v)\n{script}")])
+            .output()
+            .unwrap();
+        let before = EnvSnapshot::new(std::collections::HashMap::new(), String::new());
+        let changes = env_changes(before, extract_env_sections(&output.stdout).unwrap());
+        assert!(changes.contains("set -e m\nset -e __reef_keys_m\n"), "got: {changes}");
+        assert!(changes.contains("set -g n w\n"), "got: {changes}");
+    }
+
+    #[test]
+    fn assoc_unchanged_not_resent() {
+        let script = build_script(&shell_escape_for_bash("true"), " >&2", false);
+        let output = Command::new("bash")
+            .args(["-c", &format!("declare -A m=([k]=v)\n{script}")])
+            .output()
+            .unwrap();
+        let before = EnvSnapshot::new(std::collections::HashMap::new(), String::new());
+        let changes = env_changes(before, extract_env_sections(&output.stdout).unwrap());
+        assert!(!changes.contains("__reef_keys_m"), "got: {changes}");
+    }
+
     #[test]
     fn sentinel_uses_null_bytes() {
         // Verify sentinels contain null bytes to prevent collision
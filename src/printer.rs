@@ -0,0 +1,1178 @@
+//! AST-to-bash pretty printer.
+//!
+//! [`Printer`] turns parsed commands back into canonical bash: one command
+//! per line, compound bodies indented, operators spaced, and heredoc bodies
+//! placed after the line that opens them. Words are emitted with the quoting
+//! they were parsed with, so printing and re-parsing yields the same AST
+//! (spans aside). Comments are not part of the AST and are not preserved.
+//!
+//! ```
+//! use reef::parser::Parser;
+//! use reef::printer::Printer;
+//!
+//! let cmds = Parser::new("if true;then echo  \"$x\";fi").parse().unwrap();
+//! assert_eq!(Printer::new().with_indent(2).print(&cmds), "if true; then\n  echo \"$x\"\nfi\n");
+//! ```
+
+use std::fmt;
+
+use crate::ast::*;
+
+/// Pretty-printer configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Printer {
+    indent: usize,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Printer { indent: 4 }
+    }
+}
+
+impl Printer {
+    /// A printer indenting compound bodies by four spaces.
+    #[must_use]
+    pub fn new() -> Self {
+        Printer::default()
+    }
+
+    /// Indent compound bodies by `width` spaces per level.
+    #[must_use]
+    pub fn with_indent(mut self, width: usize) -> Self {
+        self.indent = width;
+        self
+    }
+
+    /// Print a command list, one command per line, each ending in a newline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::parser::Parser;
+    /// use reef::printer::Printer;
+    ///
+    /// let cmds = Parser::new("a&&b|c ; d &").parse().unwrap();
+    /// assert_eq!(Printer::new().print(&cmds), "a && b | c\nd &\n");
+    /// ```
+    #[must_use]
+    pub fn print(&self, cmds: &[Cmd<'_>]) -> String {
+        let mut w = Writer::new(self.indent);
+        for cmd in cmds {
+            w.cmd(cmd);
+            w.newline();
+        }
+        w.out
+    }
+
+    /// Print a single word as it would appear in a command line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::ast::{Atom, Param, Word, WordPart};
+    /// use reef::printer::Printer;
+    ///
+    /// let word = Word::Concat(vec![
+    ///     WordPart::Bare(Atom::Param(Param::Var("dir".into()))),
+    ///     WordPart::Bare(Atom::Lit("_old".into())),
+    /// ]);
+    /// assert_eq!(Printer::new().print_word(&word), "${dir}_old");
+    /// ```
+    #[must_use]
+    pub fn print_word(&self, word: &Word<'_>) -> String {
+        let mut w = Writer::new(self.indent);
+        w.word(word);
+        w.flush_heredocs();
+        w.out
+    }
+}
+
+/// Prints with the default [`Printer`], without a trailing newline.
+impl fmt::Display for Cmd<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = Printer::new().print(std::slice::from_ref(self));
+        f.write_str(text.strip_suffix('\n').unwrap_or(&text))
+    }
+}
+
+/// Prints with the default [`Printer`].
+impl fmt::Display for Word<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::new().print_word(self))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Writer
+// ---------------------------------------------------------------------------
+
+struct Writer {
+    out: String,
+    indent: usize,
+    level: usize,
+    /// Heredocs opened on the current line: `(delimiter, body)`.
+    heredocs: Vec<(String, String)>,
+    /// Heredocs below this index belong to an enclosing line, so a
+    /// newline inside `$(...)` must not flush them.
+    floor: usize,
+}
+
+impl Writer {
+    fn new(indent: usize) -> Self {
+        Writer {
+            out: String::new(),
+            indent,
+            level: 0,
+            heredocs: Vec::new(),
+            floor: 0,
+        }
+    }
+
+    /// End the current line; pending heredoc bodies follow it.
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.flush_heredocs();
+    }
+
+    fn flush_heredocs(&mut self) {
+        for (delim, body) in self.heredocs.drain(self.floor..) {
+            self.out.push_str(&body);
+            if !body.is_empty() && !body.ends_with('\n') {
+                self.out.push('\n');
+            }
+            self.out.push_str(&delim);
+            self.out.push('\n');
+        }
+    }
+
+    fn pad(&mut self) {
+        for _ in 0..self.level * self.indent {
+            self.out.push(' ');
+        }
+    }
+
+    // --- Commands ---
+
+    fn cmd(&mut self, cmd: &Cmd<'_>) {
+        match cmd {
+            Cmd::List(list) => self.and_or(list),
+            Cmd::Job(list) => {
+                self.and_or(list);
+                self.out.push_str(" &");
+            }
+        }
+    }
+
+    /// Commands on one line, for guards and substitutions.
+    fn inline(&mut self, cmds: &[Cmd<'_>]) {
+        for (i, cmd) in cmds.iter().enumerate() {
+            if i > 0 {
+                // `a &; b` is a syntax error — the `&` already separates
+                self.out.push_str(if matches!(cmds[i - 1], Cmd::Job(_)) { " " } else { "; " });
+            }
+            self.cmd(cmd);
+        }
+    }
+
+    /// Commands inside `$(...)`, `<(...)` or `>(...)`. Heredocs opened in
+    /// there must end before the closing paren.
+    fn nested(&mut self, open: &str, cmds: &[Cmd<'_>]) {
+        self.out.push_str(open);
+        let start = self.out.len();
+        let floor = std::mem::replace(&mut self.floor, self.heredocs.len());
+        self.inline(cmds);
+        // `$((` would start arithmetic
+        if self.out[start..].starts_with('(') {
+            self.out.insert(start, ' ');
+        }
+        if self.heredocs.len() > self.floor {
+            self.newline();
+        }
+        self.floor = floor;
+        self.out.push(')');
+    }
+
+    /// An indented block, each command on its own line. The caller closes
+    /// it with a newline, padding and the closing keyword.
+    fn block(&mut self, cmds: &[Cmd<'_>]) {
+        self.level += 1;
+        for cmd in cmds {
+            self.newline();
+            self.pad();
+            self.cmd(cmd);
+        }
+        self.level -= 1;
+    }
+
+    fn close(&mut self, keyword: &str) {
+        self.newline();
+        self.pad();
+        self.out.push_str(keyword);
+    }
+
+    fn and_or(&mut self, list: &AndOrList<'_>) {
+        self.pipeline(&list.first);
+        for link in &list.rest {
+            match link {
+                AndOr::And(p) => {
+                    self.out.push_str(" && ");
+                    self.pipeline(p);
+                }
+                AndOr::Or(p) => {
+                    self.out.push_str(" || ");
+                    self.pipeline(p);
+                }
+            }
+        }
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline<'_>) {
+        match pipeline {
+            Pipeline::Single(exec) => self.executable(exec),
+            Pipeline::Pipe(negated, execs) => {
+                if *negated {
+                    self.out.push_str("! ");
+                }
+                for (i, exec) in execs.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(" | ");
+                    }
+                    self.executable(exec);
+                }
+            }
+            Pipeline::Timed { posix, pipeline } => {
+                self.out.push_str(if *posix { "time -p " } else { "time " });
+                self.pipeline(pipeline);
+            }
+        }
+    }
+
+    fn executable(&mut self, exec: &Executable<'_>) {
+        match exec {
+            Executable::Simple(cmd) => self.simple(cmd),
+            Executable::Compound(cmd) => self.compound(cmd),
+            Executable::FuncDef(name, cmd) => {
+                self.out.push_str(name);
+                self.out.push_str("() ");
+                self.compound(cmd);
+            }
+        }
+    }
+
+    fn simple(&mut self, cmd: &SimpleCmd<'_>) {
+        let mut first = true;
+        let mut sep = |w: &mut Self| {
+            if !std::mem::take(&mut first) {
+                w.out.push(' ');
+            }
+        };
+        for prefix in &cmd.prefix {
+            sep(self);
+            match prefix {
+                CmdPrefix::Assign(name, value) => {
+                    self.out.push_str(name);
+                    self.out.push('=');
+                    if let Some(w) = value {
+                        self.word(w);
+                    }
+                }
+                CmdPrefix::ArrayAssign(name, words) => self.array_assign(name, "=(", words),
+                CmdPrefix::ArrayAppend(name, words) => self.array_assign(name, "+=(", words),
+                CmdPrefix::ElementAssign(name, index, value) => {
+                    self.out.push_str(name);
+                    self.out.push('[');
+                    self.word(index);
+                    self.out.push_str("]=");
+                    if let Some(w) = value {
+                        self.word(w);
+                    }
+                }
+                CmdPrefix::Redirect(r) => self.redir(r),
+            }
+        }
+        for suffix in &cmd.suffix {
+            sep(self);
+            match suffix {
+                CmdSuffix::Word(w) => self.word(w),
+                CmdSuffix::ArrayAssign(name, words) => self.array_assign(name, "=(", words),
+                CmdSuffix::Redirect(r) => self.redir(r),
+            }
+        }
+    }
+
+    fn array_assign(&mut self, name: &str, op: &str, words: &[Word<'_>]) {
+        self.out.push_str(name);
+        self.out.push_str(op);
+        for (i, w) in words.iter().enumerate() {
+            if i > 0 {
+                self.out.push(' ');
+            }
+            self.word(w);
+        }
+        self.out.push(')');
+    }
+
+    fn compound(&mut self, cmd: &CompoundCmd<'_>) {
+        match &cmd.kind {
+            CompoundKind::For { var, words, body } => {
+                self.for_loop("for ", var, words.as_deref(), body);
+            }
+            CompoundKind::Select { var, words, body } => {
+                self.for_loop("select ", var, words.as_deref(), body);
+            }
+            CompoundKind::While(gb) => self.guard_loop("while ", gb),
+            CompoundKind::Until(gb) => self.guard_loop("until ", gb),
+            CompoundKind::If { conditionals, else_branch } => {
+                for (i, gb) in conditionals.iter().enumerate() {
+                    if i > 0 {
+                        self.close("elif ");
+                    } else {
+                        self.out.push_str("if ");
+                    }
+                    self.inline(&gb.guard);
+                    self.out.push_str("; then");
+                    self.block(&gb.body);
+                }
+                if let Some(body) = else_branch {
+                    self.close("else");
+                    self.block(body);
+                }
+                self.close("fi");
+            }
+            CompoundKind::Case { word, arms } => {
+                self.out.push_str("case ");
+                self.word(word);
+                self.out.push_str(" in");
+                self.level += 1;
+                for arm in arms {
+                    self.newline();
+                    self.pad();
+                    for (i, pat) in arm.patterns.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str(" | ");
+                        }
+                        self.word(pat);
+                    }
+                    self.out.push(')');
+                    self.block(&arm.body);
+                    self.level += 1;
+                    self.close(match arm.terminator {
+                        CaseTerminator::FallThrough => ";&",
+                        CaseTerminator::Continue => ";;&",
+                        CaseTerminator::Break => ";;",
+                    });
+                    self.level -= 1;
+                }
+                self.level -= 1;
+                self.close("esac");
+            }
+            CompoundKind::CFor { init, cond, step, body } => {
+                self.out.push_str("for ((");
+                for (i, a) in [init, cond, step].into_iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str("; ");
+                    }
+                    if let Some(a) = a {
+                        self.arith(a);
+                    }
+                }
+                self.out.push_str(")); do");
+                self.block(body);
+                self.close("done");
+            }
+            CompoundKind::Coproc { name, body } => {
+                self.out.push_str("coproc ");
+                if let Some(name) = name {
+                    self.out.push_str(name);
+                    self.out.push(' ');
+                }
+                self.executable(body);
+            }
+            CompoundKind::Brace(body) => {
+                self.out.push('{');
+                self.block(body);
+                self.close("}");
+            }
+            CompoundKind::Subshell(body) => {
+                self.out.push('(');
+                self.block(body);
+                self.close(")");
+            }
+            CompoundKind::DoubleBracket(expr) => {
+                self.out.push_str("[[ ");
+                self.cond(expr);
+                self.out.push_str(" ]]");
+            }
+            CompoundKind::Arithmetic(a) => {
+                self.out.push_str("(( ");
+                self.arith(a);
+                self.out.push_str(" ))");
+            }
+        }
+        for r in &cmd.redirects {
+            self.out.push(' ');
+            self.redir(r);
+        }
+    }
+
+    fn for_loop(&mut self, kw: &str, var: &str, words: Option<&[Word<'_>]>, body: &[Cmd<'_>]) {
+        self.out.push_str(kw);
+        self.out.push_str(var);
+        if let Some(words) = words {
+            self.out.push_str(" in");
+            for w in words {
+                self.out.push(' ');
+                self.word(w);
+            }
+        }
+        self.out.push_str("; do");
+        self.block(body);
+        self.close("done");
+    }
+
+    fn guard_loop(&mut self, kw: &str, gb: &GuardBody<'_>) {
+        self.out.push_str(kw);
+        self.inline(&gb.guard);
+        self.out.push_str("; do");
+        self.block(&gb.body);
+        self.close("done");
+    }
+
+    /// The inside of `[[ ]]`. Groups are kept as written, so no parens are
+    /// added for precedence.
+    fn cond(&mut self, expr: &CondExpr<'_>) {
+        match expr {
+            CondExpr::Unary(op, w) => {
+                self.out.push('-');
+                self.out.push(*op);
+                self.out.push(' ');
+                self.word(w);
+            }
+            CondExpr::Binary(l, op, r) => {
+                self.word(l);
+                self.out.push(' ');
+                self.out.push_str(op.as_str());
+                self.out.push(' ');
+                self.word(r);
+            }
+            CondExpr::Word(w) => self.word(w),
+            CondExpr::Not(e) => {
+                self.out.push_str("! ");
+                self.cond(e);
+            }
+            CondExpr::And(l, r) => {
+                self.cond(l);
+                self.out.push_str(" && ");
+                self.cond(r);
+            }
+            CondExpr::Or(l, r) => {
+                self.cond(l);
+                self.out.push_str(" || ");
+                self.cond(r);
+            }
+            CondExpr::Group(e) => {
+                self.out.push_str("( ");
+                self.cond(e);
+                self.out.push_str(" )");
+            }
+        }
+    }
+
+    // --- Redirects ---
+
+    fn redir(&mut self, redir: &Redir<'_>) {
+        let (fd, op, target, spaced) = match redir {
+            Redir::Read(fd, w) => (*fd, "<", w, true),
+            Redir::Write(fd, w) => (*fd, ">", w, true),
+            Redir::Append(fd, w) => (*fd, ">>", w, true),
+            Redir::ReadWrite(fd, w) => (*fd, "<>", w, true),
+            Redir::Clobber(fd, w) => (*fd, ">|", w, true),
+            Redir::DupRead(fd, w) => (*fd, "<&", w, false),
+            Redir::DupWrite(fd, w) => (*fd, ">&", w, false),
+            Redir::HereString(w) => (None, "<<<", w, true),
+            Redir::WriteAll(w) => (None, "&>", w, true),
+            Redir::AppendAll(w) => (None, "&>>", w, true),
+            Redir::Heredoc(fd, doc) => {
+                if let Some(fd) = fd {
+                    self.out.push_str(&fd.to_string());
+                }
+                self.heredoc(doc);
+                return;
+            }
+        };
+        if let Some(fd) = fd {
+            self.out.push_str(&fd.to_string());
+        }
+        self.out.push_str(op);
+        if spaced {
+            self.out.push(' ');
+        }
+        self.word(target);
+    }
+
+    fn heredoc(&mut self, doc: &Heredoc<'_>) {
+        let (text, quoted) = match &doc.body {
+            HeredocBody::Literal(s) => ((*s).to_string(), true),
+            HeredocBody::Interpolated(atoms) => {
+                let mut w = Writer::new(self.indent);
+                w.atoms(atoms);
+                w.flush_heredocs();
+                (w.out, false)
+            }
+        };
+        // Keep the source delimiter unless it needs quoting or clashes with
+        // a body line; otherwise pick the first free EOF, EOF1, ...
+        let clashes = |delim: &str| {
+            text.lines().any(|line| {
+                let line = if doc.strip_tabs { line.trim_start_matches('\t') } else { line };
+                line == delim
+            })
+        };
+        let plain = !doc.delimiter.is_empty()
+            && doc.delimiter.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        let mut delim = if plain { doc.delimiter.to_string() } else { String::from("EOF") };
+        let mut n = 0;
+        while clashes(&delim) {
+            n += 1;
+            delim = format!("EOF{n}");
+        }
+        self.out.push_str(if doc.strip_tabs { "<<-" } else { "<<" });
+        if quoted {
+            self.out.push('\'');
+            self.out.push_str(&delim);
+            self.out.push('\'');
+        } else {
+            self.out.push_str(&delim);
+        }
+        self.heredocs.push((delim, text));
+    }
+
+    // --- Words ---
+
+    fn word(&mut self, word: &Word<'_>) {
+        match word {
+            Word::Simple(p) => self.word_part(p, None),
+            Word::Concat(parts) => {
+                for (i, p) in parts.iter().enumerate() {
+                    self.word_part(p, parts.get(i + 1));
+                }
+            }
+        }
+    }
+
+    fn word_part(&mut self, part: &WordPart<'_>, next: Option<&WordPart<'_>>) {
+        match part {
+            WordPart::Bare(atom) => {
+                let next = match next {
+                    Some(WordPart::Bare(a)) => Some(a),
+                    _ => None,
+                };
+                self.atom(atom, next);
+            }
+            WordPart::DQuoted(atoms) => {
+                self.out.push('"');
+                self.atoms(atoms);
+                self.out.push('"');
+            }
+            WordPart::SQuoted(s) => {
+                self.out.push('\'');
+                self.out.push_str(s);
+                self.out.push('\'');
+            }
+        }
+    }
+
+    fn atoms(&mut self, atoms: &[Atom<'_>]) {
+        for (i, atom) in atoms.iter().enumerate() {
+            self.atom(atom, atoms.get(i + 1));
+        }
+    }
+
+    /// `next` is the atom printed right after this one, which decides
+    /// whether a parameter needs braces (`${a}b`).
+    fn atom(&mut self, atom: &Atom<'_>, next: Option<&Atom<'_>>) {
+        match atom {
+            Atom::Lit(s) => self.out.push_str(s),
+            Atom::Escaped(s) => {
+                self.out.push('\\');
+                self.out.push_str(s);
+            }
+            Atom::Param(p) => {
+                let next_byte = match next {
+                    Some(Atom::Lit(s)) => s.bytes().next(),
+                    _ => None,
+                };
+                let braces = match p {
+                    Param::Var(_) => {
+                        next_byte.is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_')
+                    }
+                    Param::Positional(n) => *n > 9 || next_byte.is_some_and(|b| b.is_ascii_digit()),
+                    _ => false,
+                };
+                if braces {
+                    self.out.push_str("${");
+                    self.param_name(p);
+                    self.out.push('}');
+                } else {
+                    self.out.push('$');
+                    self.param_name(p);
+                }
+            }
+            Atom::Subst(s) => self.subst(s),
+            Atom::Star => self.out.push('*'),
+            Atom::Question => self.out.push('?'),
+            Atom::SquareOpen => self.out.push('['),
+            Atom::SquareClose => self.out.push(']'),
+            Atom::Tilde => self.out.push('~'),
+            Atom::ProcSubIn(cmds) => self.nested("<(", cmds),
+            Atom::ProcSubOut(cmds) => self.nested(">(", cmds),
+            Atom::AnsiCQuoted(s) => {
+                self.out.push_str("$'");
+                self.out.push_str(s);
+                self.out.push('\'');
+            }
+            Atom::ExtGlob { kind, alternatives } => {
+                self.out.push(match kind {
+                    ExtGlobKind::ZeroOrOne => '?',
+                    ExtGlobKind::ZeroOrMore => '*',
+                    ExtGlobKind::OneOrMore => '+',
+                    ExtGlobKind::ExactlyOne => '@',
+                    ExtGlobKind::Not => '!',
+                });
+                self.out.push('(');
+                self.alternatives(alternatives, '|');
+                self.out.push(')');
+            }
+            Atom::BraceList(alternatives) => {
+                self.out.push('{');
+                self.alternatives(alternatives, ',');
+                self.out.push('}');
+            }
+            Atom::BraceRange { start, end, step } => {
+                self.out.push('{');
+                self.out.push_str(start);
+                self.out.push_str("..");
+                self.out.push_str(end);
+                if let Some(step) = step {
+                    self.out.push_str("..");
+                    self.out.push_str(step);
+                }
+                self.out.push('}');
+            }
+        }
+    }
+
+    fn alternatives(&mut self, words: &[Word<'_>], sep: char) {
+        for (i, w) in words.iter().enumerate() {
+            if i > 0 {
+                self.out.push(sep);
+            }
+            self.word(w);
+        }
+    }
+
+    fn param_name(&mut self, param: &Param<'_>) {
+        match param {
+            Param::Var(name) => self.out.push_str(name),
+            Param::Positional(n) => self.out.push_str(&n.to_string()),
+            Param::At => self.out.push('@'),
+            Param::Star => self.out.push('*'),
+            Param::Pound => self.out.push('#'),
+            Param::Status => self.out.push('?'),
+            Param::Pid => self.out.push('$'),
+            Param::Bang => self.out.push('!'),
+            Param::Dash => self.out.push('-'),
+        }
+    }
+
+    fn subst(&mut self, subst: &Subst<'_>) {
+        match subst {
+            Subst::Cmd(cmds) => self.nested("$(", cmds),
+            Subst::Arith(a) => {
+                self.out.push_str("$((");
+                if let Some(a) = a {
+                    self.arith(a);
+                }
+                self.out.push_str("))");
+            }
+            Subst::Len(p) => self.braced("#", p, ""),
+            Subst::Indirect(name) => self.braced_name("!", name, ""),
+            Subst::PrefixList(prefix) => self.braced_name("!", prefix, "*"),
+            Subst::Transform(name, op) => {
+                self.braced_name("", name, &format!("@{}", char::from(*op)));
+            }
+            Subst::Default(p, w) => self.param_op(p, ":-", w.as_ref()),
+            Subst::Assign(p, w) => self.param_op(p, ":=", w.as_ref()),
+            Subst::Error(p, w) => self.param_op(p, ":?", w.as_ref()),
+            Subst::Alt(p, w) => self.param_op(p, ":+", w.as_ref()),
+            Subst::TrimSuffixSmall(p, w) => self.param_op(p, "%", w.as_ref()),
+            Subst::TrimSuffixLarge(p, w) => self.param_op(p, "%%", w.as_ref()),
+            Subst::TrimPrefixSmall(p, w) => self.param_op(p, "#", w.as_ref()),
+            Subst::TrimPrefixLarge(p, w) => self.param_op(p, "##", w.as_ref()),
+            Subst::Replace(p, pat, rep) => self.replace(p, "/", pat.as_ref(), rep.as_ref()),
+            Subst::ReplaceAll(p, pat, rep) => self.replace(p, "//", pat.as_ref(), rep.as_ref()),
+            Subst::ReplacePrefix(p, pat, rep) => self.replace(p, "/#", pat.as_ref(), rep.as_ref()),
+            Subst::ReplaceSuffix(p, pat, rep) => self.replace(p, "/%", pat.as_ref(), rep.as_ref()),
+            Subst::Substring(p, offset, length) => {
+                self.out.push_str("${");
+                self.param_name(p);
+                self.out.push(':');
+                // `${x:-1}` would be a default value
+                if offset.starts_with(['-', '=', '?', '+']) {
+                    self.out.push(' ');
+                }
+                self.out.push_str(offset);
+                if let Some(length) = length {
+                    self.out.push(':');
+                    self.out.push_str(length);
+                }
+                self.out.push('}');
+            }
+            Subst::Upper(all, p, pat) => {
+                self.param_op(p, if *all { "^^" } else { "^" }, pat.as_ref());
+            }
+            Subst::Lower(all, p, pat) => {
+                self.param_op(p, if *all { ",," } else { "," }, pat.as_ref());
+            }
+            Subst::ArrayElement(name, index) => {
+                self.out.push_str("${");
+                self.out.push_str(name);
+                self.out.push('[');
+                self.word(index);
+                self.out.push_str("]}");
+            }
+            Subst::ArrayAll(name) => self.braced_name("", name, "[@]"),
+            Subst::ArrayKeys(name) => self.braced_name("!", name, "[@]"),
+            Subst::ArrayLen(name) => self.braced_name("#", name, "[@]"),
+            Subst::ArraySlice(name, offset, length) => {
+                self.out.push_str("${");
+                self.out.push_str(name);
+                self.out.push_str("[@]:");
+                self.out.push_str(offset);
+                if let Some(length) = length {
+                    self.out.push(':');
+                    self.out.push_str(length);
+                }
+                self.out.push('}');
+            }
+        }
+    }
+
+    fn braced(&mut self, before: &str, param: &Param<'_>, after: &str) {
+        self.out.push_str("${");
+        self.out.push_str(before);
+        self.param_name(param);
+        self.out.push_str(after);
+        self.out.push('}');
+    }
+
+    fn braced_name(&mut self, before: &str, name: &str, after: &str) {
+        self.out.push_str("${");
+        self.out.push_str(before);
+        self.out.push_str(name);
+        self.out.push_str(after);
+        self.out.push('}');
+    }
+
+    fn param_op(&mut self, param: &Param<'_>, op: &str, word: Option<&Word<'_>>) {
+        self.out.push_str("${");
+        self.param_name(param);
+        self.out.push_str(op);
+        if let Some(w) = word {
+            self.word(w);
+        }
+        self.out.push('}');
+    }
+
+    fn replace(
+        &mut self,
+        param: &Param<'_>,
+        op: &str,
+        pat: Option<&Word<'_>>,
+        rep: Option<&Word<'_>>,
+    ) {
+        self.out.push_str("${");
+        self.param_name(param);
+        self.out.push_str(op);
+        if let Some(w) = pat {
+            self.word(w);
+        }
+        if let Some(w) = rep {
+            self.out.push('/');
+            self.word(w);
+        }
+        self.out.push('}');
+    }
+
+    // --- Arithmetic ---
+
+    fn arith(&mut self, arith: &Arith<'_>) {
+        match arith {
+            Arith::Var(name) => {
+                // `$1` in arithmetic is kept as the name "1"
+                if name.starts_with(|c: char| c.is_ascii_digit()) {
+                    self.out.push('$');
+                }
+                self.out.push_str(name);
+            }
+            Arith::Elem(name, index) => self.arith_elem(name, index),
+            Arith::Lit(n) => self.out.push_str(&n.to_string()),
+            Arith::PreInc(name) => {
+                self.out.push_str("++");
+                self.out.push_str(name);
+            }
+            Arith::PreDec(name) => {
+                self.out.push_str("--");
+                self.out.push_str(name);
+            }
+            Arith::PostInc(name) => {
+                self.out.push_str(name);
+                self.out.push_str("++");
+            }
+            Arith::PostDec(name) => {
+                self.out.push_str(name);
+                self.out.push_str("--");
+            }
+            Arith::ElemPreInc(name, index) => {
+                self.out.push_str("++");
+                self.arith_elem(name, index);
+            }
+            Arith::ElemPreDec(name, index) => {
+                self.out.push_str("--");
+                self.arith_elem(name, index);
+            }
+            Arith::ElemPostInc(name, index) => {
+                self.arith_elem(name, index);
+                self.out.push_str("++");
+            }
+            Arith::ElemPostDec(name, index) => {
+                self.arith_elem(name, index);
+                self.out.push_str("--");
+            }
+            Arith::Pos(e) => self.arith_unary('+', e),
+            Arith::Neg(e) => self.arith_unary('-', e),
+            Arith::LogNot(e) => self.arith_unary('!', e),
+            Arith::BitNot(e) => self.arith_unary('~', e),
+            Arith::Ternary(c, t, f) => {
+                self.arith_operand(c, 2);
+                self.out.push_str(" ? ");
+                self.arith(t);
+                self.out.push_str(" : ");
+                self.arith_operand(f, 1);
+            }
+            Arith::Assign(name, e) => {
+                self.out.push_str(name);
+                self.out.push_str(" = ");
+                self.arith_operand(e, 1);
+            }
+            Arith::ElemAssign(name, index, e) => {
+                self.arith_elem(name, index);
+                self.out.push_str(" = ");
+                self.arith_operand(e, 1);
+            }
+            Arith::Comma(l, r) => {
+                self.arith(l);
+                self.out.push_str(", ");
+                self.arith_operand(r, 1);
+            }
+            _ => {
+                let Some((prec, op, l, r)) = arith_binary(arith) else {
+                    return;
+                };
+                self.arith_operand(l, prec);
+                self.out.push(' ');
+                self.out.push_str(op);
+                self.out.push(' ');
+                // Binary operators are left-associative
+                self.arith_operand(r, prec + 1);
+            }
+        }
+    }
+
+    /// Print `arith`, parenthesized unless it binds at least as tightly
+    /// as `min_prec`.
+    fn arith_operand(&mut self, arith: &Arith<'_>, min_prec: u8) {
+        let prec = match arith {
+            Arith::Comma(..) => 0,
+            Arith::Ternary(..) | Arith::Assign(..) | Arith::ElemAssign(..) => 1,
+            _ => arith_binary(arith).map_or(u8::MAX, |(prec, ..)| prec),
+        };
+        if prec < min_prec {
+            self.out.push('(');
+            self.arith(arith);
+            self.out.push(')');
+        } else {
+            self.arith(arith);
+        }
+    }
+
+    fn arith_elem(&mut self, name: &str, index: &Arith<'_>) {
+        self.out.push_str(name);
+        self.out.push('[');
+        self.arith(index);
+        self.out.push(']');
+    }
+
+    fn arith_unary(&mut self, op: char, operand: &Arith<'_>) {
+        self.out.push(op);
+        // Operands of prefix operators are atoms; `--x` would be a decrement
+        let atomic = matches!(
+            operand,
+            Arith::Var(_)
+                | Arith::PostInc(_)
+                | Arith::PostDec(_)
+                | Arith::ElemPostInc(..)
+                | Arith::ElemPostDec(..)
+        ) || matches!(operand, Arith::Lit(n) if *n >= 0);
+        if atomic {
+            self.arith(operand);
+        } else {
+            self.out.push('(');
+            self.arith(operand);
+            self.out.push(')');
+        }
+    }
+}
+
+/// Precedence (as used by the parser), operator and operands of a binary
+/// arithmetic node.
+type Binary<'x, 'a> = (u8, &'static str, &'x Arith<'a>, &'x Arith<'a>);
+
+fn arith_binary<'x, 'a>(arith: &'x Arith<'a>) -> Option<Binary<'x, 'a>> {
+    let (prec, op, l, r) = match arith {
+        Arith::LogOr(l, r) => (2, "||", l, r),
+        Arith::LogAnd(l, r) => (3, "&&", l, r),
+        Arith::BitOr(l, r) => (4, "|", l, r),
+        Arith::BitXor(l, r) => (5, "^", l, r),
+        Arith::BitAnd(l, r) => (6, "&", l, r),
+        Arith::Eq(l, r) => (8, "==", l, r),
+        Arith::Ne(l, r) => (8, "!=", l, r),
+        Arith::Lt(l, r) => (9, "<", l, r),
+        Arith::Le(l, r) => (9, "<=", l, r),
+        Arith::Gt(l, r) => (9, ">", l, r),
+        Arith::Ge(l, r) => (9, ">=", l, r),
+        Arith::Shl(l, r) => (10, "<<", l, r),
+        Arith::Shr(l, r) => (10, ">>", l, r),
+        Arith::Add(l, r) => (11, "+", l, r),
+        Arith::Sub(l, r) => (11, "-", l, r),
+        Arith::Mul(l, r) => (12, "*", l, r),
+        Arith::Div(l, r) => (12, "/", l, r),
+        Arith::Rem(l, r) => (12, "%", l, r),
+        Arith::Pow(l, r) => (14, "**", l, r),
+        _ => return None,
+    };
+    Some((prec, op, l, r))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::visit::VisitMut;
+
+    struct ZeroSpans;
+
+    impl VisitMut<'_> for ZeroSpans {
+        fn visit_span_mut(&mut self, span: &mut Span) {
+            *span = Span::default();
+        }
+    }
+
+    fn fmt(input: &str) -> String {
+        Printer::new().print(&Parser::new(input).parse().unwrap())
+    }
+
+    /// Parse, print, re-parse and compare the ASTs; returns the printed text.
+    #[track_caller]
+    fn round_trip(input: &str) -> String {
+        let mut before = Parser::new(input).parse().unwrap();
+        let printed = Printer::new().print(&before);
+        let mut after = Parser::new(&printed)
+            .parse()
+            .unwrap_or_else(|e| panic!("reparse of {printed:?} failed: {e}"));
+        ZeroSpans.visit_cmds_mut(&mut before);
+        ZeroSpans.visit_cmds_mut(&mut after);
+        assert_eq!(before, after, "printed as {printed:?}");
+        printed
+    }
+
+    // --- Layout ---
+
+    #[test]
+    fn simple_commands() {
+        assert_eq!(fmt("echo   hi ;ls -la"), "echo hi\nls -la\n");
+        assert_eq!(fmt("a&&b||! c|d"), "a && b || ! c | d\n");
+        assert_eq!(fmt("sleep 1&"), "sleep 1 &\n");
+    }
+
+    #[test]
+    fn if_elif_else() {
+        assert_eq!(
+            fmt("if a; then b; elif c; then d; else e; fi"),
+            "if a; then\n    b\nelif c; then\n    d\nelse\n    e\nfi\n"
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(fmt("for x in a b; do echo $x; done"), "for x in a b; do\n    echo $x\ndone\n");
+        assert_eq!(fmt("for x; do :; done"), "for x; do\n    :\ndone\n");
+        assert_eq!(fmt("while true; do break; done"), "while true; do\n    break\ndone\n");
+        assert_eq!(
+            fmt("for ((i=0;i<3;i++)); do :; done"),
+            "for ((i = 0; i < 3; i++)); do\n    :\ndone\n"
+        );
+    }
+
+    #[test]
+    fn case_arms() {
+        assert_eq!(
+            fmt("case $x in a|b) echo ab;; *) echo other;& esac"),
+            concat!(
+                "case $x in\n    a | b)\n        echo ab\n        ;;\n",
+                "    *)\n        echo other\n        ;&\nesac\n",
+            )
+        );
+    }
+
+    #[test]
+    fn function_and_groups() {
+        assert_eq!(fmt("f() { echo hi; }"), "f() {\n    echo hi\n}\n");
+        assert_eq!(fmt("(cd /; ls) > out"), "(\n    cd /\n    ls\n) > out\n");
+    }
+
+    #[test]
+    fn custom_indent() {
+        let cmds = Parser::new("if a; then while b; do c; done; fi").parse().unwrap();
+        assert_eq!(
+            Printer::new().with_indent(2).print(&cmds),
+            "if a; then\n  while b; do\n    c\n  done\nfi\n"
+        );
+    }
+
+    #[test]
+    fn redirects() {
+        assert_eq!(fmt("cmd >out 2>&1 <in 2>>log"), "cmd > out 2>&1 < in 2>> log\n");
+        assert_eq!(fmt("cmd &>/dev/null <<<word"), "cmd &> /dev/null <<< word\n");
+    }
+
+    #[test]
+    fn heredoc_follows_line() {
+        assert_eq!(fmt("cat <<X\nhi $USER\nX\necho done"), "cat <<X\nhi $USER\nX\necho done\n");
+        assert_eq!(fmt("cat <<'X'\n$lit\nX"), "cat <<'X'\n$lit\nX\n");
+    }
+
+    #[test]
+    fn heredoc_delimiter_avoids_body() {
+        assert_eq!(fmt("cat <<\"a b\"\nEOF\na b"), "cat <<'EOF1'\nEOF\nEOF1\n");
+    }
+
+    #[test]
+    fn heredoc_fd_and_tabs() {
+        assert_eq!(fmt("cat 3<<-X\n\thi\n\tX"), "cat 3<<-X\n\thi\nX\n");
+        assert_eq!(fmt("cat <<A <<B\na\nA\nb\nB"), "cat <<A <<B\na\nA\nb\nB\n");
+    }
+
+    #[test]
+    fn heredoc_inside_substitution() {
+        round_trip("x=$(cat <<E\nhi\nE\n); echo $x");
+    }
+
+    #[test]
+    fn param_braces_when_needed() {
+        assert_eq!(fmt("echo ${a}b ${a}- ${1}0 ${10}"), "echo ${a}b $a- ${1}0 ${10}\n");
+    }
+
+    #[test]
+    fn subshell_in_substitution_spaced() {
+        assert_eq!(fmt("echo $( (a) )"), "echo $( (\n    a\n))\n");
+    }
+
+    #[test]
+    fn display_impls() {
+        let cmds = Parser::new("echo  'a b'  &").parse().unwrap();
+        assert_eq!(cmds[0].to_string(), "echo 'a b' &");
+    }
+
+    #[test]
+    fn arith_parens_follow_precedence() {
+        assert_eq!(fmt("echo $(( (1+2)*3 - (4-5) ))"), "echo $(((1 + 2) * 3 - (4 - 5)))\n");
+        assert_eq!(fmt("(( -(-x) ))"), "(( -(-x) ))\n");
+    }
+
+    #[test]
+    fn arith_comma_and_elements() {
+        assert_eq!(
+            fmt("for ((i=0,j=arr[1]; i<j; i++,j--)); do :; done"),
+            "for ((i = 0, j = arr[1]; i < j; i++, j--)); do\n    :\ndone\n"
+        );
+        round_trip("echo $(( (a, b) + 1 )) $(( x = (y, z) ))");
+        round_trip("(( x ? a, b : (c, d) ))");
+    }
+
+    // --- Round trips ---
+
+    #[test]
+    fn round_trip_commands() {
+        for src in [
+            "a=1 b= cmd arg",
+            "arr=(a 'b c' \"$d\"); arr+=(e); arr[1]=x",
+            "declare -a xs=(1 2)",
+            "! a | b && c || d &",
+            "a & b; c",
+            "coproc worker { cat; }",
+            "select x in a b; do echo $x; done",
+            "until false; do :; done",
+            "f() ( echo sub )",
+            "{ a; b; } 2> /dev/null",
+            "time ! a | b && time -p { c; }",
+            "case x in a) ;;& b) ;; esac",
+        ] {
+            round_trip(src);
+        }
+    }
+
+    #[test]
+    fn round_trip_words() {
+        for src in [
+            r#"echo "a $b ${c}d \$ \" `date`" 'lit' $'tab\t' \x ~/dir"#,
+            "echo *.rs ?x [ab] {a,b{c,}} {1..9..2} @(x|y) !(z)",
+            "echo $@ $* $# $? $$ $! $- $0 ${12}",
+            "echo <(ls) >(cat) $(a; b)",
+        ] {
+            round_trip(src);
+        }
+    }
+
+    #[test]
+    fn round_trip_parameter_expansions() {
+        for src in [
+            "echo ${#x} ${!ref} ${!pre*} ${x@Q}",
+            "echo ${x:-d} ${x:=d} ${x:?msg} ${x:+alt} ${x:-}",
+            "echo ${x%.*} ${x%%.*} ${x#*/} ${x##*/}",
+            "echo ${x/a/b} ${x//a/b} ${x/#a/b} ${x/%a/b} ${x/a}",
+            "echo ${x:1:2} ${x: -3} ${x:1}",
+            "echo ${x^} ${x^^} ${x,} ${x,,} ${x^^[aeiou]} ${x,[A-Z]}",
+            "echo ${a[1]} ${a[$i]} ${a[@]} ${!a[@]} ${#a[@]} ${a[@]:1:2}",
+        ] {
+            round_trip(src);
+        }
+    }
+
+    #[test]
+    fn round_trip_tests_and_arith() {
+        for src in [
+            "[[ -f $x && $y == *.rs || -z $z ]]",
+            "[[ ( -f a && -n $b ) || $c =~ ^(x|y)$ ]]",
+            "[[ ! -d $d && ! ( $n -gt 3 ) ]]",
+            "[ -n \"$x\" ]",
+            "(( x += 1 )) || true",
+            "echo $(( a ? b : c )) $(( x = y = 2 )) $(( 2 ** 3 ** 2 )) $(( a - (b - c) ))",
+            "echo $(( !x + ~y )) $(( i++ + --j )) $(( a << 2 | b & 1 ^ c ))",
+            "echo $((1 - -1)) $(( $1 + 1 ))",
+        ] {
+            round_trip(src);
+        }
+    }
+}
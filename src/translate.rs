@@ -4,25 +4,85 @@
 //! fish shell code. Unsupported constructs produce [`TranslateError::Unsupported`].
 
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::env_diff::ASSOC_KEYS_PREFIX;
 use crate::lexer::ParseError;
 use crate::parser::Parser;
+use crate::visit::{
+    Visit, VisitMut, walk_arith, walk_arith_mut, walk_atom_mut, walk_compound_kind_mut,
+    walk_cond_mut, walk_simple_cmd_mut, walk_subst_mut,
+};
 
 /// Translation context threaded through all emitters.
+#[allow(clippy::struct_excessive_bools)] // independent flags, each saved and restored per scope
 struct Ctx {
     in_subshell: bool,
+    in_function: bool,
+    /// Reader commands for `>(cmd)` atoms in the statement being emitted.
+    /// `None` where a FIFO setup can't be placed around the statement.
+    proc_sub_out: Option<Vec<String>>,
+    /// Set while emitting a condition or a command substitution, where
+    /// statements can't be surrounded with setup and cleanup.
+    inline: bool,
+    /// Names declared with `declare -A`/`local -A`. Each is emulated as a
+    /// values list `NAME` plus a parallel keys list `__reef_keys_NAME`.
+    assoc: HashSet<String>,
+    /// Names assigned as indexed arrays (`a=(...)`, `a[i]=v`, `declare -a`),
+    /// so `${a@a}` can report them.
+    arrays: HashSet<String>,
+    /// Namerefs in scope, mapped to what [`NamerefRenamer`] turns their uses
+    /// into: the target itself, or `$ref[1]` when it's only known at runtime.
+    namerefs: HashMap<String, String>,
+    /// Where the words, atoms and redirections of the input are, to point
+    /// unsupported errors at the construct rather than its command.
+    spans: SpanTable,
+    /// Number of `case` chains (see `emit_case_chain`) enclosing the
+    /// statement being emitted, so nested chains get their own state names.
+    case_depth: usize,
+    /// `ERR`, `DEBUG` and `RETURN` traps set earlier in the current body.
+    traps: Traps,
+    /// Set by `emit_body` for the statement it is about to emit, so the
+    /// statement's and-or list checks its status for the ERR trap.
+    err_check: bool,
 }
 
 impl Ctx {
-    fn new() -> Self {
+    fn new(spans: SpanTable) -> Self {
         Ctx {
+            spans,
             in_subshell: false,
+            in_function: false,
+            proc_sub_out: None,
+            inline: false,
+            assoc: HashSet::new(),
+            arrays: HashSet::new(),
+            namerefs: HashMap::new(),
+            case_depth: 0,
+            traps: Traps::default(),
+            err_check: false,
         }
     }
 }
 
+/// Bash traps that fire around commands rather than on signals. Fish has no
+/// such hooks, so they are emulated at each statement emitted after the
+/// `trap`. Like bash without `set -E`/`set -T`, functions, subshells and
+/// command substitutions start with none set.
+#[derive(Default)]
+struct Traps {
+    /// `trap ... ERR`: a failing statement calls `__reef_trap_ERR`.
+    err: bool,
+    /// `trap ... DEBUG`: each statement is preceded by `__reef_trap_DEBUG`.
+    debug: bool,
+    /// `trap ... RETURN` in a function: fish code run before each `return`
+    /// and at the end of the body.
+    ret: Option<String>,
+}
+
 // ---------------------------------------------------------------------------
 // Error type
 // ---------------------------------------------------------------------------
@@ -32,7 +92,7 @@ impl Ctx {
 #[non_exhaustive]
 pub enum TranslateError {
     /// The input uses a bash feature that has no fish equivalent.
-    Unsupported(&'static str),
+    Unsupported(Unsupported),
     /// The input failed to parse as valid bash.
     Parse(ParseError),
 }
@@ -40,12 +100,63 @@ pub enum TranslateError {
 impl fmt::Display for TranslateError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TranslateError::Unsupported(msg) => write!(f, "unsupported: {msg}"),
+            TranslateError::Unsupported(u) => write!(f, "unsupported: {u}"),
             TranslateError::Parse(e) => write!(f, "{e}"),
         }
     }
 }
 
+impl TranslateError {
+    /// Locate this error in `input` for display. Returns `None` for errors
+    /// that carry no source position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::translate::translate_bash_to_fish;
+    ///
+    /// let input = "if true; then echo hi";
+    /// let err = translate_bash_to_fish(input).unwrap_err();
+    /// let diag = err.diagnostic(input).unwrap();
+    /// assert_eq!(diag.expected(), ["fi"]);
+    /// ```
+    #[must_use]
+    pub fn diagnostic(&self, input: &str) -> Option<Diagnostic> {
+        match self {
+            TranslateError::Parse(e) => Some(e.diagnostic(input)),
+            TranslateError::Unsupported(u) => {
+                u.span.map(|span| Diagnostic::new(input, span, self.to_string()))
+            }
+        }
+    }
+
+    /// Point an unsupported error at `span`, unless an inner command
+    /// already claimed it.
+    fn at(mut self, span: Span) -> Self {
+        if let TranslateError::Unsupported(u) = &mut self {
+            u.span.get_or_insert(span);
+        }
+        self
+    }
+
+    /// Like [`at`](Self::at), for a node that may not be in the span table.
+    fn at_node(self, span: Option<Span>) -> Self {
+        match span {
+            Some(span) => self.at(span),
+            None => self,
+        }
+    }
+
+    /// Forget a position within a string that isn't the input (a trap
+    /// handler), so the enclosing command claims the error instead.
+    fn unlocated(mut self) -> Self {
+        if let TranslateError::Unsupported(u) = &mut self {
+            u.span = None;
+        }
+        self
+    }
+}
+
 impl std::error::Error for TranslateError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -61,6 +172,169 @@ impl From<ParseError> for TranslateError {
     }
 }
 
+/// A bash construct with no fish translation: what it is, where it is, and
+/// what to write instead.
+///
+/// # Examples
+///
+/// ```
+/// use reef::translate::{TranslateError, UnsupportedKind, translate_bash_to_fish};
+///
+/// let Err(TranslateError::Unsupported(u)) = translate_bash_to_fish("echo hi; exec 3>&1")
+/// else {
+///     panic!("expected an unsupported construct");
+/// };
+/// assert_eq!(u.kind(), UnsupportedKind::ExecFd);
+/// assert_eq!(u.span().map(|s| s.start), Some(9));
+/// assert!(u.kind().suggestion().is_some());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported {
+    kind: UnsupportedKind,
+    detail: &'static str,
+    span: Option<Span>,
+}
+
+impl Unsupported {
+    /// The family of bash feature that was rejected.
+    #[must_use]
+    pub fn kind(&self) -> UnsupportedKind {
+        self.kind
+    }
+
+    /// The specific construct, e.g. `nameref without a target`.
+    #[must_use]
+    pub fn detail(&self) -> &'static str {
+        self.detail
+    }
+
+    /// Source span of the innermost command containing the construct.
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.detail)
+    }
+}
+
+/// Families of bash features the translator rejects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum UnsupportedKind {
+    /// `declare -n` / `local -n`.
+    Nameref,
+    /// An associative array form with no list emulation.
+    AssocArray,
+    /// An array literal passed to a command other than `declare`/`local`.
+    ArrayArgument,
+    /// `VAR=value cmd` — a prefix assignment scoped to one command.
+    PrefixAssignment,
+    /// A `trap` form with no fish event handler equivalent.
+    Trap,
+    /// `trap ... ERR` where per-statement status checks can't follow it.
+    TrapErr,
+    /// `exec` opening, closing or moving file descriptors.
+    ExecFd,
+    /// `shopt`.
+    Shopt,
+    /// `getopts`.
+    Getopts,
+    /// `eval` of anything but a command substitution.
+    Eval,
+    /// `coproc`.
+    Coproc,
+    /// A subshell behaviour `begin ... end` can't reproduce.
+    Subshell,
+    /// A `printf` format fish's `printf` doesn't implement.
+    Printf,
+    /// A `time` form fish's `time` can't express.
+    Time,
+    /// A `[[ ]]` operator with no `test` counterpart.
+    TestOperator,
+    /// Arithmetic that `math` or `test` can't evaluate.
+    Arithmetic,
+    /// A brace expansion fish would expand differently.
+    BraceExpansion,
+    /// Process substitution where its setup can't be placed.
+    ProcessSubstitution,
+    /// An extended glob outside a whole word, or a pattern the glob to
+    /// regex translation can't express.
+    ExtGlob,
+    /// A bash-maintained variable such as `$LINENO`.
+    SpecialVariable,
+    /// A `${var@X}` transformation.
+    ParamTransform,
+}
+
+impl UnsupportedKind {
+    /// A fish alternative the user could write by hand, where one exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reef::translate::UnsupportedKind;
+    ///
+    /// assert_eq!(UnsupportedKind::Getopts.suggestion(), Some("parse options with `argparse`"));
+    /// assert_eq!(UnsupportedKind::ParamTransform.suggestion(), None);
+    /// ```
+    #[must_use]
+    pub fn suggestion(self) -> Option<&'static str> {
+        Some(match self {
+            UnsupportedKind::Nameref => {
+                "point the nameref at a plain variable, or pass the name and read it with `$$name`"
+            }
+            UnsupportedKind::AssocArray => {
+                "fish has no associative arrays; keep keys and values in two parallel lists"
+            }
+            UnsupportedKind::PrefixAssignment => {
+                "run the command with `env VAR=value cmd`, or `set -lx` it inside `begin ... end`"
+            }
+            UnsupportedKind::Trap => {
+                "define a handler with `function NAME --on-signal SIG` or `--on-event fish_exit`"
+            }
+            UnsupportedKind::TrapErr => {
+                "fish has no ERR trap; check `$status` after the commands that matter"
+            }
+            UnsupportedKind::ExecFd => {
+                "fish can't open or move file descriptors; redirect each command instead"
+            }
+            UnsupportedKind::Getopts => "parse options with `argparse`",
+            UnsupportedKind::Eval => "build fish code in a string and pipe it to `source`",
+            UnsupportedKind::Coproc => "run the job with `&` and talk to it through `mkfifo` pipes",
+            UnsupportedKind::Subshell => "run the commands in a child shell with `fish -c`",
+            UnsupportedKind::Printf => "repeat text with `string repeat`",
+            UnsupportedKind::Arithmetic => "compute the value with `math` and assign it with `set`",
+            UnsupportedKind::BraceExpansion => "generate the sequence with `seq` or a `for` loop",
+            UnsupportedKind::ProcessSubstitution => {
+                "write to a `mkfifo` pipe read by a background job"
+            }
+            UnsupportedKind::ExtGlob => "filter the matches with `string match -r`",
+            UnsupportedKind::SpecialVariable => {
+                "`status current-line-number` and `status current-function` cover some of these"
+            }
+            UnsupportedKind::ArrayArgument
+            | UnsupportedKind::Shopt
+            | UnsupportedKind::Time
+            | UnsupportedKind::TestOperator
+            | UnsupportedKind::ParamTransform => return None,
+        })
+    }
+}
+
+/// Shorthand for an unsupported-construct error; its span is filled in by
+/// the enclosing command.
+fn unsupported(kind: UnsupportedKind, detail: &'static str) -> TranslateError {
+    TranslateError::Unsupported(Unsupported {
+        kind,
+        detail,
+        span: None,
+    })
+}
+
 /// Module-local result alias — reduces `Result<(), TranslateError>` noise.
 type Res<T> = Result<T, TranslateError>;
 
@@ -79,7 +353,7 @@ type Res<T> = Result<T, TranslateError>;
 ///
 /// Returns [`TranslateError::Parse`] if the input is not valid bash syntax,
 /// or [`TranslateError::Unsupported`] if it uses a bash feature with no fish
-/// equivalent (e.g., `select`, coprocesses, associative arrays).
+/// equivalent (e.g., `coproc`, `exec` fd manipulation).
 ///
 /// # Examples
 ///
@@ -90,19 +364,14 @@ type Res<T> = Result<T, TranslateError>;
 /// assert_eq!(fish, "set -gx FOO bar");
 ///
 /// // Unsupported features return an error
-/// assert!(translate_bash_to_fish("select x in a b; do echo $x; done").is_err());
+/// assert!(translate_bash_to_fish("exec 3>&1").is_err());
 /// ```
 #[must_use = "translation produces a result that should be inspected"]
 pub fn translate_bash_to_fish(input: &str) -> Result<String, TranslateError> {
-    let cmds = Parser::new(input).parse()?;
-    let mut ctx = Ctx::new();
+    let (cmds, spans) = Parser::new(input).parse_with_spans()?;
+    let mut ctx = Ctx::new(spans);
     let mut out = String::with_capacity(input.len());
-    for (i, cmd) in cmds.iter().enumerate() {
-        if i > 0 {
-            out.push('\n');
-        }
-        emit_cmd(&mut ctx, cmd, &mut out)?;
-    }
+    emit_body(&mut ctx, &cmds, &mut out)?;
     Ok(out)
 }
 
@@ -110,34 +379,173 @@ pub fn translate_bash_to_fish(input: &str) -> Result<String, TranslateError> {
 // Command-level emitters
 // ---------------------------------------------------------------------------
 
+/// A command used inside another construct (a condition, a command
+/// substitution, `eval`): nothing can be placed around it.
 fn emit_cmd(ctx: &mut Ctx, cmd: &Cmd<'_>, out: &mut String) -> Res<()> {
+    let outer = ctx.proc_sub_out.take();
+    let prev = std::mem::replace(&mut ctx.inline, true);
+    let res = emit_list_or_job(ctx, cmd, out);
+    ctx.inline = prev;
+    ctx.proc_sub_out = outer;
+    res
+}
+
+/// A statement of a body, which may be surrounded with the setup and
+/// cleanup for its `>(cmd)` atoms.
+fn emit_stmt(ctx: &mut Ctx, cmd: &Cmd<'_>, out: &mut String) -> Res<()> {
+    // `>(cmd)` needs setup before and cleanup after the statement, which
+    // only works when the statement runs exactly once, in the foreground.
+    let readers = match cmd {
+        Cmd::List(list) if list.rest.is_empty() && !ctx.inline => Some(Vec::new()),
+        _ => None,
+    };
+    let outer = std::mem::replace(&mut ctx.proc_sub_out, readers);
+    let start = out.len();
+    let res = emit_list_or_job(ctx, cmd, out);
+    let readers = std::mem::replace(&mut ctx.proc_sub_out, outer);
+    res?;
+    if let Some(readers) = readers.filter(|r| !r.is_empty()) {
+        wrap_proc_sub_out(&readers, start, out);
+    }
+    Ok(())
+}
+
+fn emit_list_or_job(ctx: &mut Ctx, cmd: &Cmd<'_>, out: &mut String) -> Res<()> {
     match cmd {
         Cmd::List(list) => emit_and_or(ctx, list, out),
-        Cmd::Job(list) => {
-            emit_and_or(ctx, list, out)?;
-            out.push_str(" &");
-            Ok(())
-        }
+        Cmd::Job(list) => emit_and_or(ctx, list, out).map(|()| out.push_str(" &")),
     }
 }
 
+/// Surround the statement at `out[start..]` with the FIFO plumbing for its
+/// `>(cmd)` atoms: each atom was emitted as `$__reef_psub/N`, and reader
+/// `N` runs as a background `fish -c` process that opens that FIFO itself:
+/// a block would run in the foreground, and fish opens a command's
+/// redirections before starting it, so either would block before the
+/// writer starts. The variables a reader uses are copied into its script,
+/// as they may be local or not exported. After the statement the readers
+/// are waited for, the temp dir is removed and the statement's status is
+/// restored, by a `fish -c exit` when it isn't 0.
+fn wrap_proc_sub_out(readers: &[String], start: usize, out: &mut String) {
+    let mut setup = String::with_capacity(128 + readers.iter().map(String::len).sum::<usize>());
+    setup.push_str("set -l __reef_psub (mktemp -d)\nmkfifo");
+    for (n, _) in (0..).zip(readers) {
+        setup.push_str(" $__reef_psub/");
+        itoa(&mut setup, n);
+    }
+    setup.push_str("\nset -l __reef_psub_pids");
+    let mut script = String::new();
+    for (n, reader) in (0..).zip(readers) {
+        setup.push_str("\n__reef_fifo=$__reef_psub/");
+        itoa(&mut setup, n);
+        setup.push_str(" fish -c ");
+        // Each `set` is quoted up to its values, which are spliced in escaped
+        let vars = script_vars(reader);
+        for (i, name) in vars.iter().enumerate() {
+            setup.push_str(if i == 0 { "'set " } else { "'\nset " });
+            setup.push_str(name);
+            setup.push_str("'(string join ' ' -- '' (string escape -- $");
+            setup.push_str(name);
+            setup.push_str("))");
+        }
+        script.clear();
+        if !vars.is_empty() {
+            script.push('\n');
+        }
+        script.push_str("begin\n");
+        script.push_str(reader);
+        script.push_str("\nend <$__reef_fifo");
+        push_fish_sq(&mut setup, &script);
+        setup.push_str(" &\nset -a __reef_psub_pids $last_pid");
+    }
+    setup.push('\n');
+    out.insert_str(start, &setup);
+    out.push_str(
+        "\nset -l __reef_rc $status\n\
+         wait $__reef_psub_pids\n\
+         rm -r $__reef_psub\n\
+         test $__reef_rc = 0; or fish -c \"exit $__reef_rc\"",
+    );
+}
+
+/// The variables a `>(cmd)` reader's script expands, except those fish
+/// sets itself and the FIFO plumbing's own.
+fn script_vars(script: &str) -> Vec<&str> {
+    const SKIP: &[&str] = &[
+        "status", "pipestatus", "fish_pid", "last_pid", "hostname", "version", "FISH_VERSION",
+        "PWD", "SHLVL", "_", "history", "status_generation", "fish_kill_signal",
+        "__reef_fifo", "__reef_psub", "__reef_psub_pids", "__reef_rc",
+    ];
+    let mut names = Vec::new();
+    let mut rest = script;
+    while let Some(i) = rest.find('$') {
+        rest = &rest[i + 1..];
+        let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        let name = &rest[..len];
+        if !name.is_empty() && !SKIP.contains(&name) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
 fn emit_and_or(ctx: &mut Ctx, list: &AndOrList<'_>, out: &mut String) -> Res<()> {
+    // Bash's ERR trap ignores failures in the chain except the last pipeline
+    let tail = list.rest.last().map_or(&list.first, |(AndOr::And(p) | AndOr::Or(p))| p);
+    let err_check = std::mem::take(&mut ctx.err_check) && triggers_err(tail);
     emit_pipeline(ctx, &list.first, out)?;
-    for and_or in &list.rest {
-        match and_or {
+    for (i, and_or) in list.rest.iter().enumerate() {
+        let p = match and_or {
             AndOr::And(p) => {
                 out.push_str("; and ");
-                emit_pipeline(ctx, p, out)?;
+                p
             }
             AndOr::Or(p) => {
                 out.push_str("; or ");
-                emit_pipeline(ctx, p, out)?;
+                p
             }
+        };
+        if err_check && i + 1 == list.rest.len() {
+            out.push_str("begin\n");
+            emit_pipeline(ctx, p, out)?;
+            push_err_check(ctx, out);
+            out.push_str("\nend");
+            return Ok(());
         }
+        emit_pipeline(ctx, p, out)?;
+    }
+    if err_check {
+        push_err_check(ctx, out);
     }
     Ok(())
 }
 
+/// Call the ERR trap handler if the pipeline just emitted failed, unless
+/// the pipeline was the `trap - ERR` removing it.
+fn push_err_check(ctx: &Ctx, out: &mut String) {
+    if ctx.traps.err {
+        out.push_str("; or __reef_trap_ERR");
+    }
+}
+
+/// Whether a failure of `pipeline` runs bash's ERR trap. Negated pipelines
+/// never do; compound commands leave it to the commands inside them.
+fn triggers_err(pipeline: &Pipeline<'_>) -> bool {
+    let triggers = |exec: &Executable<'_>| match exec {
+        Executable::Simple(_) => true,
+        Executable::Compound(c) => matches!(
+            c.kind,
+            CompoundKind::Subshell(_) | CompoundKind::DoubleBracket(_) | CompoundKind::Arithmetic(_)
+        ),
+        Executable::FuncDef(..) => false,
+    };
+    match pipeline {
+        Pipeline::Single(exec) => triggers(exec),
+        Pipeline::Pipe(negated, execs) => !negated && execs.last().is_some_and(triggers),
+        Pipeline::Timed { pipeline, .. } => triggers_err(pipeline),
+    }
+}
+
 fn emit_pipeline(ctx: &mut Ctx, pipeline: &Pipeline<'_>, out: &mut String) -> Res<()> {
     match pipeline {
         Pipeline::Single(exec) => emit_exec(ctx, exec, out),
@@ -153,26 +561,61 @@ fn emit_pipeline(ctx: &mut Ctx, pipeline: &Pipeline<'_>, out: &mut String) -> Re
             }
             Ok(())
         }
+        // fish's `time` has a single report format, so `-p` only changes how
+        // the timings look
+        Pipeline::Timed { pipeline, .. } => {
+            if times_redirected_compound(pipeline) {
+                let detail = "time of a redirected compound command";
+                return Err(unsupported(UnsupportedKind::Time, detail).at(pipeline.span()));
+            }
+            out.push_str("time ");
+            emit_pipeline(ctx, pipeline, out)
+        }
+    }
+}
+
+/// Whether a timed pipeline contains a compound command with redirections.
+/// Bash writes the report outside those redirections, which fish's `time`
+/// cannot express.
+fn times_redirected_compound(pipeline: &Pipeline<'_>) -> bool {
+    let redirected =
+        |e: &Executable<'_>| matches!(e, Executable::Compound(c) if !c.redirects.is_empty());
+    match pipeline {
+        Pipeline::Single(exec) => redirected(exec),
+        Pipeline::Pipe(_, execs) => execs.iter().any(redirected),
+        Pipeline::Timed { pipeline, .. } => times_redirected_compound(pipeline),
     }
 }
 
 fn emit_exec(ctx: &mut Ctx, exec: &Executable<'_>, out: &mut String) -> Res<()> {
-    match exec {
+    let res = match exec {
         Executable::Simple(simple) => emit_simple(ctx, simple, out),
         Executable::Compound(compound) => emit_compound(ctx, compound, out),
         Executable::FuncDef(name, body) => {
             out.push_str("function ");
             out.push_str(name);
             out.push('\n');
+            let traps = std::mem::take(&mut ctx.traps);
+            let namerefs = ctx.namerefs.clone();
+            let prev = std::mem::replace(&mut ctx.in_function, true);
             // Unwrap brace group to avoid nested begin/end inside function
             match &body.kind {
                 CompoundKind::Brace(cmds) => emit_body(ctx, cmds, out)?,
                 other => emit_compound_kind(ctx, other, out)?,
             }
+            if let Some(handler) = ctx.traps.ret.take() {
+                out.push_str("\nset -l __reef_rc $status\n");
+                out.push_str(&handler);
+                out.push_str("\nreturn $__reef_rc");
+            }
+            ctx.in_function = prev;
+            ctx.namerefs = namerefs;
+            ctx.traps = traps;
             out.push_str("\nend");
             Ok(())
         }
-    }
+    };
+    res.map_err(|e| e.at(exec.span()))
 }
 
 // ---------------------------------------------------------------------------
@@ -183,24 +626,28 @@ fn emit_simple(ctx: &mut Ctx, cmd: &SimpleCmd<'_>, out: &mut String) -> Res<()>
     let mut env_vars: Vec<(&str, &Option<Word<'_>>)> = Vec::new();
     let mut array_ops: Vec<&CmdPrefix<'_>> = Vec::new();
     let mut cmd_words: Vec<&Word<'_>> = Vec::new();
+    let mut decl_arrays: Vec<(&str, &[Word<'_>])> = Vec::new();
     let mut redirects: Vec<&Redir<'_>> = Vec::new();
     let mut herestring: Option<&Word<'_>> = None;
-    let mut heredoc: Option<&HeredocBody<'_>> = None;
+    let mut heredoc: Option<&Heredoc<'_>> = None;
 
     for item in &cmd.prefix {
         match item {
             CmdPrefix::Assign(name, val) => env_vars.push((name, val)),
-            CmdPrefix::ArrayAssign(..) | CmdPrefix::ArrayAppend(..) => array_ops.push(item),
+            CmdPrefix::ArrayAssign(..)
+            | CmdPrefix::ArrayAppend(..)
+            | CmdPrefix::ElementAssign(..) => array_ops.push(item),
             CmdPrefix::Redirect(Redir::HereString(w)) => herestring = Some(w),
-            CmdPrefix::Redirect(Redir::Heredoc(body)) => heredoc = Some(body),
+            CmdPrefix::Redirect(Redir::Heredoc(None | Some(0), doc)) => heredoc = Some(doc),
             CmdPrefix::Redirect(r) => redirects.push(r),
         }
     }
     for item in &cmd.suffix {
         match item {
             CmdSuffix::Word(w) => cmd_words.push(w),
+            CmdSuffix::ArrayAssign(name, words) => decl_arrays.push((name, words)),
             CmdSuffix::Redirect(Redir::HereString(w)) => herestring = Some(w),
-            CmdSuffix::Redirect(Redir::Heredoc(body)) => heredoc = Some(body),
+            CmdSuffix::Redirect(Redir::Heredoc(None | Some(0), doc)) => heredoc = Some(doc),
             CmdSuffix::Redirect(r) => redirects.push(r),
         }
     }
@@ -215,6 +662,17 @@ fn emit_simple(ctx: &mut Ctx, cmd: &SimpleCmd<'_>, out: &mut String) -> Res<()>
         }
     }
 
+    // `m[k]+=v` isn't parsed as an assignment; on a map it is one
+    if let Some((name, key, value)) = cmd_words.first().and_then(|w| assoc_append_word(ctx, w)) {
+        if cmd_words.len() > 1 || !env_vars.is_empty() || !array_ops.is_empty() {
+            return Err(unsupported(
+                UnsupportedKind::AssocArray,
+                "m[key]+=value alongside other words or assignments",
+            ));
+        }
+        return emit_assoc_store(ctx, name, &key, value.as_ref(), true, out);
+    }
+
     let cmd_name = cmd_words.first().and_then(|w| word_as_str(w));
 
     // mapfile/readarray needs its own redirects before here-string emission
@@ -228,8 +686,8 @@ fn emit_simple(ctx: &mut Ctx, cmd: &SimpleCmd<'_>, out: &mut String) -> Res<()>
         emit_word(ctx, hs_word, out)?;
         out.push_str(" | ");
     }
-    if let Some(body) = heredoc {
-        emit_heredoc_body(ctx, body, out)?;
+    if let Some(doc) = heredoc {
+        emit_heredoc_body(ctx, doc, out)?;
         out.push_str(" | ");
     }
 
@@ -238,7 +696,20 @@ fn emit_simple(ctx: &mut Ctx, cmd: &SimpleCmd<'_>, out: &mut String) -> Res<()>
     // and the scoping semantics are subtle. Let bash handle it.
     // Must check before builtin dispatch to avoid silently dropping the prefix.
     if !env_vars.is_empty() && !cmd_words.is_empty() {
-        return Err(TranslateError::Unsupported("prefix assignment with command"));
+        return Err(unsupported(
+            UnsupportedKind::PrefixAssignment,
+            "prefix assignment with command",
+        ));
+    }
+
+    // `declare arr=(...)` — only the declaration builtins we emit take arrays
+    if !decl_arrays.is_empty() {
+        let args = &cmd_words[1..];
+        return match cmd_name.as_deref() {
+            Some("declare" | "typeset") => emit_declare(ctx, args, &decl_arrays, out),
+            Some("local") => emit_local(ctx, args, &decl_arrays, out),
+            _ => Err(unsupported(UnsupportedKind::ArrayArgument, "array assignment argument")),
+        };
     }
 
     // Builtin dispatch — returns early if handled
@@ -252,7 +723,7 @@ fn emit_simple(ctx: &mut Ctx, cmd: &SimpleCmd<'_>, out: &mut String) -> Res<()>
     // `return` would exit the whole function, not just the begin block.
     // Bail to T2 bash-exec so it runs correctly in a real subprocess.
     if ctx.in_subshell && cmd_name.as_deref() == Some("exit") {
-        return Err(TranslateError::Unsupported("exit in subshell"));
+        return Err(unsupported(UnsupportedKind::Subshell, "exit in subshell"));
     }
 
     // Emit command and arguments
@@ -285,7 +756,7 @@ fn emit_array_assignments(ctx: &mut Ctx,
             out.push('\n');
         }
         first = false;
-        out.push_str(set_kw);
+        out.push_str(set_for(ctx, name, false));
         out.push_str(name);
         if let Some(val) = value {
             out.push(' ');
@@ -298,8 +769,36 @@ fn emit_array_assignments(ctx: &mut Ctx,
         }
         first = false;
         match op {
+            CmdPrefix::ArrayAssign(name, words) if ctx.assoc.contains(name.as_ref()) => {
+                emit_assoc_assign(ctx, set_kw, name, words, out)?;
+            }
+            CmdPrefix::ArrayAppend(name, words) if ctx.assoc.contains(name.as_ref()) => {
+                for (i, (key, value)) in assoc_pairs(words)?.iter().enumerate() {
+                    if i > 0 {
+                        out.push('\n');
+                    }
+                    emit_assoc_store(ctx, name, key, value.as_ref(), false, out)?;
+                }
+            }
+            CmdPrefix::ElementAssign(name, key, value) if ctx.assoc.contains(name.as_ref()) => {
+                emit_assoc_store(ctx, name, key, value.as_ref(), false, out)?;
+            }
+            CmdPrefix::ElementAssign(name, index, value) => {
+                note_array(ctx, name);
+                // arr[i]=v → set arr[i+1] v. Through a runtime nameref the
+                // brackets are glued on as text: set -g $ref[1]'['2']' v
+                let (open, close) = if name.starts_with('$') { ("'['", "']'") } else { ("[", "]") };
+                out.push_str(set_for(ctx, name, false));
+                out.push_str(name);
+                out.push_str(open);
+                emit_array_index(ctx, index, out)?;
+                out.push_str(close);
+                out.push(' ');
+                emit_assoc_value(ctx, value.as_ref(), out)?;
+            }
             CmdPrefix::ArrayAssign(name, words) => {
-                out.push_str(set_kw);
+                note_array(ctx, name);
+                out.push_str(set_for(ctx, name, false));
                 out.push_str(name);
                 for w in words {
                     out.push(' ');
@@ -307,7 +806,8 @@ fn emit_array_assignments(ctx: &mut Ctx,
                 }
             }
             CmdPrefix::ArrayAppend(name, words) => {
-                out.push_str(if ctx.in_subshell { "set -la " } else { "set -a " });
+                note_array(ctx, name);
+                out.push_str(set_for(ctx, name, true));
                 out.push_str(name);
                 for w in words {
                     out.push(' ');
@@ -320,26 +820,89 @@ fn emit_array_assignments(ctx: &mut Ctx,
     Ok(())
 }
 
+/// Remember `name` as an indexed array, unless it's a runtime nameref target.
+fn note_array(ctx: &mut Ctx, name: &str) {
+    if is_valid_var_name(name) {
+        ctx.arrays.insert(name.to_string());
+    }
+}
+
 /// Emit standalone variable assignments: `VAR=val` → `set VAR val`
 fn emit_var_assignments(ctx: &mut Ctx, 
     env_vars: &[(&str, &Option<Word<'_>>)],
     out: &mut String,
 ) -> Res<()> {
-    let set_kw = if ctx.in_subshell { "set -l " } else { "set " };
     for (i, (name, value)) in env_vars.iter().enumerate() {
         if i > 0 {
             out.push('\n');
         }
-        out.push_str(set_kw);
+        out.push_str(set_for(ctx, name, false));
         out.push_str(name);
         if let Some(val) = value {
             out.push(' ');
-            emit_word(ctx, val, out)?;
+            emit_value_word(ctx, val, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// An assignment value. Bash does no brace or pathname expansion there, so
+/// brace lists, wildcards and extended globs stay literal text.
+fn emit_value_word(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    let parts: &[WordPart<'_>] = match word {
+        Word::Simple(p) => std::slice::from_ref(p),
+        Word::Concat(parts) => parts,
+    };
+    let is_literal = |p: &WordPart<'_>| {
+        matches!(
+            p,
+            WordPart::Bare(Atom::BraceList(_) | Atom::Star | Atom::Question | Atom::ExtGlob { .. })
+        )
+    };
+    if !parts.iter().any(is_literal) {
+        return emit_word(ctx, word, out);
+    }
+    for part in parts {
+        match part {
+            WordPart::Bare(Atom::Star) => out.push_str("\\*"),
+            WordPart::Bare(Atom::Question) => out.push_str("\\?"),
+            WordPart::Bare(Atom::ExtGlob { kind, alternatives }) => {
+                out.push_str(match kind {
+                    ExtGlobKind::ZeroOrOne => "\\?",
+                    ExtGlobKind::ZeroOrMore => "\\*",
+                    ExtGlobKind::OneOrMore => "+",
+                    ExtGlobKind::ExactlyOne => "@",
+                    ExtGlobKind::Not => "!",
+                });
+                out.push_str("\\(");
+                for (i, alt) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str("\\|");
+                    }
+                    emit_value_word(ctx, alt, out)?;
+                }
+                out.push_str("\\)");
+            }
+            _ => emit_word_part(ctx, part, out)?,
         }
     }
     Ok(())
 }
 
+/// `set`, with the scope for writing `name`. The target of a runtime nameref
+/// (`$ref[1]`) is written globally: a fish function can't see its caller's
+/// locals, so the target has to be a global for the write to reach it.
+fn set_for(ctx: &Ctx, name: &str, append: bool) -> &'static str {
+    match (name.starts_with('$'), ctx.in_subshell, append) {
+        (true, _, false) => "set -g ",
+        (true, _, true) => "set -ga ",
+        (false, true, false) => "set -l ",
+        (false, true, true) => "set -la ",
+        (false, false, false) => "set ",
+        (false, false, true) => "set -a ",
+    }
+}
+
 /// Dispatch to builtin emitters. Returns `Some(result)` if handled, `None` to fall through.
 fn dispatch_builtin(ctx: &mut Ctx, 
     name: &str,
@@ -350,23 +913,23 @@ fn dispatch_builtin(ctx: &mut Ctx,
     match name {
         "export" => Some(emit_export(ctx, &cmd_words[1..], out)),
         "unset" => Some(emit_unset(ctx, &cmd_words[1..], out)),
-        "local" => Some(emit_local(ctx, &cmd_words[1..], out)),
-        "declare" | "typeset" => Some(emit_declare(ctx, &cmd_words[1..], out)),
+        "local" => Some(emit_local(ctx, &cmd_words[1..], &[], out)),
+        "declare" | "typeset" => Some(emit_declare(ctx, &cmd_words[1..], &[], out)),
         "readonly" => Some(emit_readonly(ctx, &cmd_words[1..], out)),
-        "[[" => Some(emit_double_bracket(ctx, &cmd_words[1..], redirects, out)),
         "let" => Some(emit_let(ctx, &cmd_words[1..], out)),
-        "shopt" => Some(Err(TranslateError::Unsupported("shopt"))),
+        "shopt" => Some(Err(unsupported(UnsupportedKind::Shopt, "shopt"))),
         "trap" => Some(emit_trap(ctx, &cmd_words[1..], out)),
+        "return" if ctx.traps.ret.is_some() => Some(emit_return(ctx, &cmd_words[1..], out)),
         "shift" => Some(emit_shift(ctx, &cmd_words[1..], out)),
         "alias" => Some(emit_alias(ctx, &cmd_words[1..], out)),
         "read" => Some(emit_read(ctx, cmd_words, redirects, out)),
         "set" => Some(emit_bash_set(ctx, &cmd_words[1..], out)),
-        "select" => Some(Err(TranslateError::Unsupported("select loop"))),
-        "getopts" => Some(Err(TranslateError::Unsupported(
-            "getopts (use argparse in fish)",
+        "getopts" => Some(Err(unsupported(
+            UnsupportedKind::Getopts,
+            "getopts outside a while/case loop",
         ))),
         "exec" if cmd_words.len() == 1 && !redirects.is_empty() => {
-            Some(Err(TranslateError::Unsupported("exec fd manipulation")))
+            Some(Err(unsupported(UnsupportedKind::ExecFd, "exec fd manipulation")))
         }
         "eval" => Some(emit_eval(ctx, &cmd_words[1..], out)),
         "printf" => dispatch_printf(ctx, cmd_words, out),
@@ -402,7 +965,8 @@ fn dispatch_printf(ctx: &mut Ctx,
             Cow::Owned(buf)
         };
         if text.contains("%0.s") || text.contains("%.0s") {
-            return Some(Err(TranslateError::Unsupported(
+            return Some(Err(unsupported(
+                UnsupportedKind::Printf,
                 "printf %0.s format (fish printf doesn't support this)",
             )));
         }
@@ -456,7 +1020,7 @@ fn emit_export(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
 /// Split a word at the first `=` sign, returning (`var_name`, `value_as_fish`).
 fn split_word_at_equals(ctx: &mut Ctx, word: &Word<'_>) -> Option<(String, String)> {
     let mut full = String::with_capacity(64);
-    if emit_word(ctx, word, &mut full).is_err() {
+    if emit_value_word(ctx, word, &mut full).is_err() {
         return None;
     }
     let eq_pos = full.find('=')?;
@@ -482,15 +1046,33 @@ fn split_word_at_equals(ctx: &mut Ctx, word: &Word<'_>) -> Option<(String, Strin
 /// `unset VAR` → `set -e VAR`
 fn emit_unset(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
     let mut first = true;
+    let mut nameref = false;
     for arg in args {
         let s = word_as_str(arg);
-        if matches!(s.as_deref(), Some(f) if f.starts_with('-')) {
+        if let Some(f) = s.as_deref().filter(|f| f.starts_with('-')) {
+            nameref |= f.contains('n');
             continue;
         }
         if !first {
             out.push('\n');
         }
         first = false;
+        // `unset -n ref` removes the reference, not its target
+        if nameref && let Some(name) = s.as_deref() {
+            ctx.namerefs.remove(name);
+        }
+        if let Some((name, key)) = assoc_element_word(ctx, arg, s.as_deref()) {
+            emit_assoc_unset(ctx, name, &key, out)?;
+            continue;
+        }
+        if let Some(name) = s.as_deref().filter(|n| ctx.assoc.contains(*n)) {
+            out.push_str("set -e ");
+            out.push_str(name);
+            out.push_str("\nset -e ");
+            out.push_str(ASSOC_KEYS_PREFIX);
+            out.push_str(name);
+            continue;
+        }
         // Check for array element pattern: arr[n]
         if let Some(ref s) = s
             && let Some((name, idx_str)) = parse_array_index_str(s)
@@ -524,19 +1106,39 @@ fn parse_array_index_str(s: &str) -> Option<(&str, &str)> {
 }
 
 /// `local VAR=val` → `set -l VAR val`
-fn emit_local(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
+fn emit_local(
+    ctx: &mut Ctx,
+    args: &[&Word<'_>],
+    arrays: &[(&str, &[Word<'_>])],
+    out: &mut String,
+) -> Res<()> {
+    let has_flag = |flag| {
+        args.iter().any(|a| {
+            matches!(word_as_str(a).as_deref(), Some(f) if f.starts_with('-') && f.contains(flag))
+        })
+    };
+    if has_flag('n') {
+        return emit_nameref(ctx, "set -l ", args, arrays, out);
+    }
+    let assoc = has_flag('A');
+    let indexed = has_flag('a');
     let mut first = true;
     for arg in args {
         let s = word_as_str(arg);
         if matches!(s.as_deref(), Some(f) if f.starts_with('-')) {
             continue;
         }
+        if let Some(s) = s.as_deref().filter(|_| indexed) {
+            note_array(ctx, s.split_once('=').map_or(s, |(name, _)| name));
+        }
         if !first {
             out.push('\n');
         }
         first = false;
 
-        if let Some(s) = s {
+        if assoc {
+            emit_assoc_declare(ctx, "set -l ", s.as_deref(), out)?;
+        } else if let Some(s) = s {
             out.push_str("set -l ");
             if let Some(eq) = s.find('=') {
                 out.push_str(&s[..eq]);
@@ -555,28 +1157,32 @@ fn emit_local(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
             emit_word(ctx, arg, out)?;
         }
     }
-    Ok(())
+    emit_declared_arrays(ctx, "set -l ", assoc, arrays, !first, out)
 }
 
 /// `declare [-x] [-g] VAR=val` → `set [-gx] VAR val`
 /// `declare -p VAR` → `set --show VAR`
-fn emit_declare(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
+/// `declare -A map=([k]=v)` → parallel `__reef_keys_map` / `map` lists
+fn emit_declare(
+    ctx: &mut Ctx,
+    args: &[&Word<'_>],
+    arrays: &[(&str, &[Word<'_>])],
+    out: &mut String,
+) -> Res<()> {
     let mut scope = "-g";
     let mut print_mode = false;
+    let mut assoc = false;
+    let mut indexed = false;
+    let mut nameref = false;
     let mut remaining = Vec::new();
 
     for arg in args {
         if let Some(s) = word_as_str(arg) {
             match &*s {
-                "-n" => {
-                    return Err(TranslateError::Unsupported("declare -n (nameref)"));
-                }
-                "-A" | "-Ag" | "-gA" => {
-                    return Err(TranslateError::Unsupported(
-                        "declare -A (associative array)",
-                    ));
-                }
                 "-p" => print_mode = true,
+                s if s.starts_with('-') && s.contains('n') => nameref = true,
+                s if s.starts_with('-') && s.contains('A') => assoc = true,
+                s if s.starts_with('-') && s.contains('a') => indexed = true,
                 "-x" => scope = "-gx",
                 "-g" => scope = "-g",
                 s if s.starts_with('-') => {}
@@ -587,6 +1193,12 @@ fn emit_declare(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()>
         }
     }
 
+    if nameref && !print_mode {
+        // Like `local`, `declare` in a function is local to it
+        let set_kw = if ctx.in_function { "set -l " } else { "set -g " };
+        return emit_nameref(ctx, set_kw, &remaining, arrays, out);
+    }
+
     if print_mode {
         if remaining.is_empty() {
             out.push_str("set --show");
@@ -602,6 +1214,7 @@ fn emit_declare(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()>
         return Ok(());
     }
 
+    let set_kw = if scope == "-gx" { "set -gx " } else { "set -g " };
     let mut first = true;
     for arg in &remaining {
         if !first {
@@ -609,7 +1222,12 @@ fn emit_declare(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()>
         }
         first = false;
 
-        if let Some((var_name, value_parts)) = split_word_at_equals(ctx, arg) {
+        if let Some(s) = word_as_str(arg).filter(|_| indexed) {
+            note_array(ctx, s.split_once('=').map_or(&*s, |(name, _)| name));
+        }
+        if assoc {
+            emit_assoc_declare(ctx, set_kw, word_as_str(arg).as_deref(), out)?;
+        } else if let Some((var_name, value_parts)) = split_word_at_equals(ctx, arg) {
             out.push_str("set ");
             out.push_str(scope);
             out.push(' ');
@@ -630,44 +1248,682 @@ fn emit_declare(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()>
             emit_word(ctx, arg, out)?;
         }
     }
+    emit_declared_arrays(ctx, set_kw, assoc, arrays, !first, out)
+}
+
+/// `declare -n ref=target` / `local -n ref=target` → `set -l ref target`,
+/// with later statements rewritten by [`NamerefRenamer`]. A literal target
+/// replaces `ref` outright; one computed at runtime (`local -n ref=$1`) is
+/// reached through the name stored in `ref`: `$$ref[1]`, `set -g $ref[1] ...`.
+/// fish functions don't see their caller's locals, so that target must be
+/// a global.
+fn emit_nameref(
+    ctx: &mut Ctx,
+    set_kw: &str,
+    args: &[&Word<'_>],
+    arrays: &[(&str, &[Word<'_>])],
+    out: &mut String,
+) -> Res<()> {
+    if !arrays.is_empty() {
+        return Err(unsupported(UnsupportedKind::Nameref, "nameref with an array value"));
+    }
+    let mut first = true;
+    for arg in args {
+        let text = word_as_str(arg);
+        if matches!(text.as_deref(), Some(f) if f.starts_with('-')) {
+            continue;
+        }
+        if !first {
+            out.push('\n');
+        }
+        first = false;
+
+        let (name, value, binding) = match text.as_deref().map(|t| t.split_once('=')) {
+            Some(Some((name, target))) => {
+                if !is_valid_var_name(target) {
+                    return Err(unsupported(
+                        UnsupportedKind::Nameref,
+                        "nameref to something other than a variable name",
+                    ));
+                }
+                // A nameref to a nameref binds to the final target
+                let binding = ctx.namerefs.get(target).cloned().unwrap_or_else(|| target.into());
+                (name.to_string(), target.to_string(), binding)
+            }
+            Some(None) => {
+                return Err(unsupported(UnsupportedKind::Nameref, "nameref without a target"));
+            }
+            None => {
+                let Some((name, value)) = split_word_at_equals(ctx, arg) else {
+                    return Err(unsupported(
+                        UnsupportedKind::Nameref,
+                        "nameref whose name isn't known until runtime",
+                    ));
+                };
+                let binding = format!("${name}[1]");
+                (name, value, binding)
+            }
+        };
+        if !is_valid_var_name(&name) {
+            return Err(unsupported(
+                UnsupportedKind::Nameref,
+                "nameref whose name isn't known until runtime",
+            ));
+        }
+        out.push_str(set_kw);
+        out.push_str(&name);
+        out.push(' ');
+        out.push_str(&value);
+        ctx.namerefs.insert(name, binding);
+    }
     Ok(())
 }
 
+/// Rewrites the uses of namerefs in a statement to what `Ctx::namerefs`
+/// maps them to, so every emitter sees the target (or `$ref[1]`, which
+/// they write out as `$$ref[1]` and `set -g $ref[1] ...`).
+struct NamerefRenamer<'m> {
+    namerefs: &'m HashMap<String, String>,
+    /// A use of a runtime nameref that has no fish spelling.
+    unsupported: Option<&'static str>,
+}
+
+impl NamerefRenamer<'_> {
+    fn rename<'a>(namerefs: &HashMap<String, String>, cmd: &Cmd<'a>) -> Res<Cmd<'a>> {
+        let mut cmd = cmd.clone();
+        let mut renamer = NamerefRenamer { namerefs, unsupported: None };
+        renamer.visit_cmd_mut(&mut cmd);
+        match renamer.unsupported {
+            Some(detail) => Err(unsupported(UnsupportedKind::Nameref, detail)),
+            None => Ok(cmd),
+        }
+    }
+
+    fn rename_name(&self, name: &mut Cow<'_, str>) {
+        if let Some(binding) = self.namerefs.get(name.as_ref()) {
+            *name = Cow::Owned(binding.clone());
+        }
+    }
+
+    /// Rename a variable name given as text, as in `for ref in` or the
+    /// `ref=(...)` of `declare`, where `$ref[1]` can't stand in for it.
+    fn rename_static(&mut self, name: &mut Cow<'_, str>) {
+        match self.namerefs.get(name.as_ref()) {
+            Some(binding) if binding.starts_with('$') => self.runtime_name_arg(),
+            Some(binding) => *name = Cow::Owned(binding.clone()),
+            None => {}
+        }
+    }
+
+    fn runtime_name_arg(&mut self) {
+        self.unsupported.get_or_insert(
+            "variable named through a runtime nameref in `read`, `printf -v`, `for` or a \
+             declaration",
+        );
+    }
+
+    /// Rename the variable at the start of an argument word: `ref`,
+    /// `ref=value` or `ref[i]`. With `indirect`, a runtime nameref standing
+    /// alone becomes the `$ref[1]` that holds the target's name.
+    fn rename_arg(&mut self, word: &mut Word<'_>, indirect: bool) {
+        let simple = matches!(word, Word::Simple(_));
+        let lit = match word {
+            Word::Simple(WordPart::Bare(Atom::Lit(lit))) => lit,
+            Word::Concat(parts) => match parts.first_mut() {
+                Some(WordPart::Bare(Atom::Lit(lit))) => lit,
+                _ => return,
+            },
+            Word::Simple(_) => return,
+        };
+        let end = lit.find(['=', '+', '[']).unwrap_or(lit.len());
+        let Some(binding) = self.namerefs.get(&lit[..end]) else { return };
+        if !binding.starts_with('$') {
+            *lit = Cow::Owned(format!("{binding}{}", &lit[end..]));
+            return;
+        }
+        if indirect && simple && end == lit.len() {
+            // ${ref[0]} is written out as $ref[1]
+            let name = Cow::Owned(lit.to_string());
+            let first = Word::Simple(WordPart::Bare(Atom::Lit(Cow::Borrowed("0"))));
+            *word = Word::Simple(WordPart::Bare(Atom::Subst(Box::new(Subst::ArrayElement(
+                name, first,
+            )))));
+        } else {
+            self.runtime_name_arg();
+        }
+    }
+
+    /// Rename the arguments of `cmd` that name a variable: the operands of
+    /// `read`, `printf -v`, `unset` and the declaration builtins. `unset -n`
+    /// and `declare -n` act on the reference itself, so they keep the name.
+    fn rename_name_args(&mut self, cmd: &mut SimpleCmd<'_>) {
+        let words: Vec<usize> = (0..cmd.suffix.len())
+            .filter(|&i| !matches!(cmd.suffix[i], CmdSuffix::Redirect(_)))
+            .collect();
+        let text = |i: usize| match &cmd.suffix[i] {
+            CmdSuffix::Word(w) => word_as_str(w).map(Cow::into_owned),
+            _ => None,
+        };
+        let Some(builtin) = words.first().and_then(|&i| text(i)) else { return };
+        if !matches!(
+            builtin.as_str(),
+            "read" | "printf" | "unset" | "declare" | "typeset" | "local" | "export" | "readonly"
+        ) {
+            return;
+        }
+        let mut names = Vec::new();
+        let mut options = true;
+        // An option whose value is the next word
+        let mut flag: Option<String> = None;
+        for &i in &words[1..] {
+            let arg = text(i).unwrap_or_default();
+            if let Some(flag) = flag.take() {
+                if arg_value_is_name(&builtin, &flag) {
+                    names.push(i);
+                }
+            } else if options && arg == "--" {
+                options = false;
+            } else if options && arg.len() > 1 && arg.starts_with(['-', '+']) {
+                let (keeps_name, takes_value) = match builtin.as_str() {
+                    "read" => (false, arg.ends_with(['a', 'd', 'i', 'n', 'N', 'p', 't', 'u'])),
+                    "printf" => (false, arg == "-v"),
+                    "export" => (arg.contains('f'), false),
+                    _ => (arg.contains(['n', 'f', 'F']), false),
+                };
+                if keeps_name {
+                    return;
+                }
+                flag = takes_value.then_some(arg);
+            } else {
+                options = false;
+                if builtin != "printf" {
+                    names.push(i);
+                }
+            }
+        }
+        for i in names {
+            match &mut cmd.suffix[i] {
+                CmdSuffix::Word(word) => self.rename_arg(word, builtin == "unset"),
+                CmdSuffix::ArrayAssign(name, _) => self.rename_static(name),
+                CmdSuffix::Redirect(_) => {}
+            }
+        }
+    }
+}
+
+/// Whether the value of option `flag` of `builtin` names a variable:
+/// `read -a NAME`, `printf -v NAME`.
+fn arg_value_is_name(builtin: &str, flag: &str) -> bool {
+    match builtin {
+        "read" => flag.ends_with('a'),
+        "printf" => flag == "-v",
+        _ => false,
+    }
+}
+
+impl<'a> VisitMut<'a> for NamerefRenamer<'_> {
+    fn visit_simple_cmd_mut(&mut self, cmd: &mut SimpleCmd<'a>) {
+        for item in &mut cmd.prefix {
+            if let CmdPrefix::Assign(name, _)
+            | CmdPrefix::ArrayAssign(name, _)
+            | CmdPrefix::ArrayAppend(name, _)
+            | CmdPrefix::ElementAssign(name, ..) = item
+            {
+                self.rename_name(name);
+            }
+        }
+        walk_simple_cmd_mut(self, cmd);
+        // After the walk, which would take the `ref` in a new `${ref[0]}`
+        // for another use of the nameref
+        self.rename_name_args(cmd);
+    }
+
+    fn visit_compound_kind_mut(&mut self, kind: &mut CompoundKind<'a>) {
+        if let CompoundKind::For { var, .. } | CompoundKind::Select { var, .. } = kind {
+            self.rename_static(var);
+        }
+        walk_compound_kind_mut(self, kind);
+    }
+
+    fn visit_cond_mut(&mut self, expr: &mut CondExpr<'a>) {
+        walk_cond_mut(self, expr);
+        if let CondExpr::Unary('v', word) = expr {
+            self.rename_arg(word, true);
+        }
+    }
+
+    fn visit_atom_mut(&mut self, atom: &mut Atom<'a>) {
+        // `${!ref}` is the target's name: the literal, or what `ref` holds
+        if let Atom::Subst(subst) = atom
+            && let Subst::Indirect(name) = subst.as_ref()
+            && let Some(binding) = self.namerefs.get(name.as_ref())
+        {
+            *atom = if binding.starts_with('$') {
+                Atom::Param(Param::Var(name.clone()))
+            } else {
+                Atom::Lit(Cow::Owned(binding.clone()))
+            };
+            return;
+        }
+        walk_atom_mut(self, atom);
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param<'a>) {
+        if let Param::Var(name) = param {
+            self.rename_name(name);
+        }
+    }
+
+    fn visit_subst_mut(&mut self, subst: &mut Subst<'a>) {
+        if let Subst::Transform(name, _)
+        | Subst::ArrayElement(name, _)
+        | Subst::ArrayAll(name)
+        | Subst::ArrayKeys(name)
+        | Subst::ArrayLen(name)
+        | Subst::ArraySlice(name, ..) = subst
+        {
+            self.rename_name(name);
+        }
+        walk_subst_mut(self, subst);
+    }
+
+    fn visit_arith_mut(&mut self, arith: &mut Arith<'a>) {
+        if let Arith::Var(name)
+        | Arith::Elem(name, _)
+        | Arith::PreInc(name)
+        | Arith::PostInc(name)
+        | Arith::PreDec(name)
+        | Arith::PostDec(name)
+        | Arith::Assign(name, _)
+        | Arith::ElemPreInc(name, _)
+        | Arith::ElemPostInc(name, _)
+        | Arith::ElemPreDec(name, _)
+        | Arith::ElemPostDec(name, _)
+        | Arith::ElemAssign(name, ..) = arith
+        {
+            self.rename_name(name);
+        }
+        walk_arith_mut(self, arith);
+    }
+}
+
+/// `name=(...)` arguments of `declare`/`local`, after the plain arguments.
+fn emit_declared_arrays(
+    ctx: &mut Ctx,
+    set_kw: &str,
+    assoc: bool,
+    arrays: &[(&str, &[Word<'_>])],
+    mut sep: bool,
+    out: &mut String,
+) -> Res<()> {
+    for (name, words) in arrays {
+        if sep {
+            out.push('\n');
+        }
+        sep = true;
+        if assoc {
+            ctx.assoc.insert((*name).to_string());
+            emit_assoc_assign(ctx, set_kw, name, words, out)?;
+        } else {
+            note_array(ctx, name);
+            out.push_str(set_kw);
+            out.push_str(name);
+            for w in *words {
+                out.push(' ');
+                emit_word(ctx, w, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Associative arrays
+// ---------------------------------------------------------------------------
+//
+// Fish has no associative arrays. A map `m` is kept as two parallel lists:
+// values in `m` (so `${m[@]}` and `${#m[@]}` translate like indexed arrays)
+// and keys in `__reef_keys_m`. A key's position comes from `contains -i`.
+
+/// `declare -A m` → empty keys and values lists.
+fn emit_assoc_declare(ctx: &mut Ctx, set_kw: &str, arg: Option<&str>, out: &mut String) -> Res<()> {
+    let Some(name) = arg.filter(|a| is_valid_var_name(a)) else {
+        return Err(unsupported(UnsupportedKind::AssocArray, "declare -A with a scalar value"));
+    };
+    ctx.assoc.insert(name.to_string());
+    out.push_str(set_kw);
+    out.push_str(ASSOC_KEYS_PREFIX);
+    out.push_str(name);
+    out.push('\n');
+    out.push_str(set_kw);
+    out.push_str(name);
+    Ok(())
+}
+
+/// `m=([k1]=v1 [k2]=v2)` → `set __reef_keys_m k1 k2` + `set m v1 v2`
+fn emit_assoc_assign(
+    ctx: &mut Ctx,
+    set_kw: &str,
+    name: &str,
+    words: &[Word<'_>],
+    out: &mut String,
+) -> Res<()> {
+    let pairs = assoc_pairs(words)?;
+    // A repeated key keeps its last value. A key only known at runtime may
+    // repeat any other, so from the first one on pairs are stored one by one.
+    let keys: Vec<_> = pairs.iter().map_while(|(key, _)| word_as_str(key)).collect();
+    // (key, value) positions in `pairs` of each distinct key
+    let mut slots: Vec<(usize, usize)> = Vec::with_capacity(keys.len());
+    let mut seen: HashMap<&str, usize> = HashMap::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(&slot) = seen.get(key.as_ref()) {
+            slots[slot].1 = i;
+        } else {
+            seen.insert(key, slots.len());
+            slots.push((i, i));
+        }
+    }
+    out.push_str(set_kw);
+    out.push_str(ASSOC_KEYS_PREFIX);
+    out.push_str(name);
+    for &(key, _) in &slots {
+        out.push(' ');
+        emit_assoc_key(ctx, &pairs[key].0, out)?;
+    }
+    out.push('\n');
+    out.push_str(set_kw);
+    out.push_str(name);
+    for &(_, value) in &slots {
+        out.push(' ');
+        emit_assoc_value(ctx, pairs[value].1.as_ref(), out)?;
+    }
+    for (key, value) in &pairs[keys.len()..] {
+        out.push('\n');
+        emit_assoc_store(ctx, name, key, value.as_ref(), false, out)?;
+    }
+    Ok(())
+}
+
+/// Key/value pairs of a compound assignment: either all `[key]=value`
+/// elements, or bash 5.1's alternating `key value ...` form.
+fn assoc_pairs<'a>(words: &[Word<'a>]) -> Res<Vec<(Word<'a>, Option<Word<'a>>)>> {
+    let subscripted: Vec<_> = words.iter().map_while(split_subscript_element).collect();
+    if subscripted.len() == words.len() {
+        return Ok(subscripted);
+    }
+    if !subscripted.is_empty() {
+        return Err(unsupported(
+            UnsupportedKind::AssocArray,
+            "associative array literal mixing [key]=value and key value",
+        ));
+    }
+    Ok(words
+        .chunks(2)
+        .map(|kv| (kv[0].clone(), kv.get(1).cloned()))
+        .collect())
+}
+
+/// Split a `[key]=value` compound-assignment element.
+fn split_subscript_element<'a>(word: &Word<'a>) -> Option<(Word<'a>, Option<Word<'a>>)> {
+    let Word::Concat(parts) = word else { return None };
+    let [WordPart::Bare(Atom::SquareOpen), rest @ ..] = parts.as_slice() else {
+        return None;
+    };
+    let close = rest
+        .iter()
+        .position(|p| matches!(p, WordPart::Bare(Atom::SquareClose)))?;
+    let [WordPart::Bare(Atom::Lit(eq_value)), tail @ ..] = &rest[close + 1..] else {
+        return None;
+    };
+    let value: Cow<'a, str> = match eq_value {
+        Cow::Borrowed(s) => Cow::Borrowed(s.strip_prefix('=')?),
+        Cow::Owned(s) => Cow::Owned(s.strip_prefix('=')?.to_owned()),
+    };
+    let key = parts_to_word(rest[..close].to_vec())?;
+    let mut value_parts = Vec::with_capacity(tail.len() + 1);
+    if !value.is_empty() {
+        value_parts.push(WordPart::Bare(Atom::Lit(value)));
+    }
+    value_parts.extend(tail.iter().cloned());
+    Some((key, parts_to_word(value_parts)))
+}
+
+fn parts_to_word(mut parts: Vec<WordPart<'_>>) -> Option<Word<'_>> {
+    match parts.len() {
+        0 => None,
+        1 => parts.pop().map(Word::Simple),
+        _ => Some(Word::Concat(parts)),
+    }
+}
+
+/// An element value; empty values must stay as `''` to keep the lists aligned.
+fn emit_assoc_value(ctx: &mut Ctx, value: Option<&Word<'_>>, out: &mut String) -> Res<()> {
+    let start = out.len();
+    match value {
+        // A bare `$v` that is empty would vanish in fish; quote it
+        Some(Word::Simple(WordPart::Bare(atom @ Atom::Param(_)))) => {
+            emit_word_part(ctx, &WordPart::DQuoted(vec![atom.clone()]), out)?;
+        }
+        Some(v) => emit_word(ctx, v, out)?,
+        None => {}
+    }
+    if out.len() == start {
+        out.push_str("''");
+    }
+    Ok(())
+}
+
+/// A key as a single argument: bash never splits a subscript, so text is
+/// quoted and bare expansions go inside double quotes.
+fn emit_assoc_key(ctx: &mut Ctx, key: &Word<'_>, out: &mut String) -> Res<()> {
+    if let Some(text) = word_as_str(key) {
+        if !text.is_empty()
+            && text.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_./+=:,@%".contains(&b))
+        {
+            out.push_str(&text);
+        } else {
+            push_fish_sq(out, &text);
+        }
+        return Ok(());
+    }
+    let parts: &[WordPart<'_>] = match key {
+        Word::Simple(p) => std::slice::from_ref(p),
+        Word::Concat(parts) => parts,
+    };
+    let atoms: Option<Vec<Atom<'_>>> = parts
+        .iter()
+        .map(|p| match p {
+            WordPart::Bare(atom @ (Atom::Lit(_) | Atom::Param(_) | Atom::Subst(_))) => {
+                Some(atom.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    match atoms {
+        Some(atoms) => emit_word_part(ctx, &WordPart::DQuoted(atoms), out),
+        None => emit_word(ctx, key, out),
+    }
+}
+
+/// `(contains -i -- KEY $__reef_keys_m)` — position of a key in the lists.
+fn emit_assoc_index(ctx: &mut Ctx, name: &str, key: &Word<'_>, out: &mut String) -> Res<()> {
+    out.push_str("(contains -i -- ");
+    emit_assoc_key(ctx, key, out)?;
+    out.push_str(" $");
+    out.push_str(ASSOC_KEYS_PREFIX);
+    out.push_str(name);
+    out.push(')');
+    Ok(())
+}
+
+/// `m[k]=v` → overwrite the value if the key exists, else append both.
+/// With `append` (`m[k]+=v`), an existing value is extended instead.
+fn emit_assoc_store(
+    ctx: &mut Ctx,
+    name: &str,
+    key: &Word<'_>,
+    value: Option<&Word<'_>>,
+    append: bool,
+    out: &mut String,
+) -> Res<()> {
+    out.push_str("if contains -- ");
+    emit_assoc_key(ctx, key, out)?;
+    out.push_str(" $");
+    out.push_str(ASSOC_KEYS_PREFIX);
+    out.push_str(name);
+    out.push_str("\nset ");
+    out.push_str(name);
+    out.push('[');
+    emit_assoc_index(ctx, name, key, out)?;
+    out.push_str("] ");
+    if append {
+        out.push('$');
+        out.push_str(name);
+        out.push('[');
+        emit_assoc_index(ctx, name, key, out)?;
+        out.push(']');
+    }
+    emit_assoc_value(ctx, value, out)?;
+    out.push_str("\nelse\nset -a ");
+    out.push_str(ASSOC_KEYS_PREFIX);
+    out.push_str(name);
+    out.push(' ');
+    emit_assoc_key(ctx, key, out)?;
+    out.push_str("\nset -a ");
+    out.push_str(name);
+    out.push(' ');
+    emit_assoc_value(ctx, value, out)?;
+    out.push_str("\nend");
+    Ok(())
+}
+
+/// `unset 'm[k]'` → erase the value, then the key it was found by.
+fn emit_assoc_unset(ctx: &mut Ctx, name: &str, key: &Word<'_>, out: &mut String) -> Res<()> {
+    out.push_str("if contains -- ");
+    emit_assoc_key(ctx, key, out)?;
+    out.push_str(" $");
+    out.push_str(ASSOC_KEYS_PREFIX);
+    out.push_str(name);
+    for list in ["", ASSOC_KEYS_PREFIX] {
+        out.push_str("\nset -e ");
+        out.push_str(list);
+        out.push_str(name);
+        out.push('[');
+        emit_assoc_index(ctx, name, key, out)?;
+        out.push(']');
+    }
+    out.push_str("\nend");
+    Ok(())
+}
+
+/// A `m[k]+=v` word appending to an element of a known map. Returns the map
+/// name, the key and the value.
+fn assoc_append_word<'a>(
+    ctx: &Ctx,
+    word: &'a Word<'a>,
+) -> Option<(&'a str, Word<'a>, Option<Word<'a>>)> {
+    let Word::Concat(parts) = word else { return None };
+    let [WordPart::Bare(Atom::Lit(name)), WordPart::Bare(Atom::SquareOpen), rest @ ..] =
+        parts.as_slice()
+    else {
+        return None;
+    };
+    if !ctx.assoc.contains(name.as_ref()) {
+        return None;
+    }
+    let close = rest.iter().position(|p| matches!(p, WordPart::Bare(Atom::SquareClose)))?;
+    let [WordPart::Bare(Atom::Lit(op)), tail @ ..] = &rest[close + 1..] else {
+        return None;
+    };
+    let value = op.strip_prefix("+=")?;
+    let mut value_parts = Vec::with_capacity(tail.len() + 1);
+    if !value.is_empty() {
+        value_parts.push(WordPart::Bare(Atom::Lit(Cow::Borrowed(value))));
+    }
+    value_parts.extend(tail.iter().cloned());
+    Some((name, parts_to_word(rest[..close].to_vec())?, parts_to_word(value_parts)))
+}
+
+/// `unset` argument naming an element of a known map: `m[k]`, `'m[k]'`
+/// or `'m[$k]'`. Returns the map name and the key as a word.
+fn assoc_element_word<'a>(
+    ctx: &Ctx,
+    arg: &'a Word<'a>,
+    text: Option<&'a str>,
+) -> Option<(&'a str, Word<'a>)> {
+    // Unquoted `m[$k]` parses as name, `[`, key parts, `]`
+    if let Word::Concat(parts) = arg
+        && let [
+            WordPart::Bare(Atom::Lit(name)),
+            WordPart::Bare(Atom::SquareOpen),
+            key @ ..,
+            WordPart::Bare(Atom::SquareClose),
+        ] = parts.as_slice()
+        && ctx.assoc.contains(name.as_ref())
+    {
+        return Some((name, parts_to_word(key.to_vec())?));
+    }
+    let (name, key) = parse_array_index_str(text?)?;
+    if !ctx.assoc.contains(name) {
+        return None;
+    }
+    // Quoted subscripts are expanded by bash, so `'m[$k]'` means key $k
+    let key = match key.strip_prefix('$') {
+        Some(var) if is_valid_var_name(var) => {
+            WordPart::Bare(Atom::Param(Param::Var(Cow::Borrowed(var))))
+        }
+        _ => WordPart::SQuoted(Cow::Borrowed(key)),
+    };
+    Some((name, Word::Simple(key)))
+}
+
 /// `read` — strip bash-specific flags that don't exist in fish.
 /// `trap 'handler' SIG ...` → `function __reef_trap_SIG --on-signal SIG; handler; end`
 /// `trap 'handler' EXIT` → `function __reef_trap_EXIT --on-event fish_exit; handler; end`
+/// `trap 'handler' ERR` → `function __reef_trap_ERR`, called by each later
+/// statement that fails
+/// `trap 'handler' DEBUG` → `function __reef_trap_DEBUG --on-event fish_preexec`,
+/// also called before each later statement
+/// `trap 'handler' RETURN` → handler inlined before each `return` of the function
 /// `trap - SIG` → `functions -e __reef_trap_SIG`
 fn emit_trap(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
     if args.is_empty() {
-        return Err(TranslateError::Unsupported("bare trap"));
+        return Err(unsupported(UnsupportedKind::Trap, "bare trap"));
     }
 
     let handler_str = word_as_str(args[0]);
 
     if handler_str.as_deref() == Some("-") {
+        out.push_str("functions -e");
         for sig_word in &args[1..] {
             let sig = word_as_str(sig_word)
-                .ok_or(TranslateError::Unsupported("trap with dynamic signal"))?;
+                .ok_or(unsupported(UnsupportedKind::Trap, "trap with dynamic signal"))?;
             let name = sig.strip_prefix("SIG").unwrap_or(&sig);
-            out.push_str("functions -e __reef_trap_");
+            match name {
+                "ERR" => ctx.traps.err = false,
+                "DEBUG" => ctx.traps.debug = false,
+                "RETURN" => ctx.traps.ret = None,
+                _ => {}
+            }
+            out.push_str(" __reef_trap_");
             out.push_str(name);
         }
         return Ok(());
     }
 
     if args.len() < 2 {
-        return Err(TranslateError::Unsupported("trap with missing signal"));
+        return Err(unsupported(UnsupportedKind::Trap, "trap with missing signal"));
     }
 
     // Get fish body from handler: either translate from string or emit directly
     let fish_body = match &handler_str {
         Some(h) if h.is_empty() => String::new(),
-        Some(h) => translate_bash_to_fish(h)?,
+        Some(h) => translate_bash_to_fish(h).map_err(TranslateError::unlocated)?,
         None => {
             // Handler contains variables — emit it as fish command directly
             let mut body = String::with_capacity(128);
             emit_word_unquoted(ctx, args[0], &mut body)?;
-            translate_bash_to_fish(&body)?
+            translate_bash_to_fish(&body).map_err(TranslateError::unlocated)?
         }
     };
 
@@ -676,18 +1932,58 @@ fn emit_trap(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
             out.push('\n');
         }
         let sig =
-            word_as_str(sig_word).ok_or(TranslateError::Unsupported("trap with dynamic signal"))?;
+            word_as_str(sig_word).ok_or(unsupported(
+                UnsupportedKind::Trap,
+                "trap with dynamic signal",
+            ))?;
         let name = sig.strip_prefix("SIG").unwrap_or(&sig);
 
-        // ERR trap has no fish equivalent
-        if name == "ERR" {
-            return Err(TranslateError::Unsupported("trap ERR (no fish equivalent)"));
+        match name {
+            // The status checks only cover the statements emitted after the
+            // trap, but bash keeps it set once the function has returned
+            "ERR" if ctx.in_function => {
+                return Err(unsupported(
+                    UnsupportedKind::TrapErr,
+                    "trap ERR inside a function (it stays set after the function returns)",
+                ));
+            }
+            "ERR" => {
+                // Keep the failed command's status for `$?` after the handler
+                out.push_str("function __reef_trap_ERR\nset -l __reef_rc $status\n");
+                out.push_str(&fish_body);
+                out.push_str("\nreturn $__reef_rc\nend");
+                ctx.traps.err = true;
+                continue;
+            }
+            // fish_preexec fires before each command line, which only
+            // matches DEBUG outside functions
+            "DEBUG" if ctx.in_function => {
+                return Err(unsupported(
+                    UnsupportedKind::Trap,
+                    "trap DEBUG inside a function (fish_preexec only fires per command line)",
+                ));
+            }
+            "DEBUG" => ctx.traps.debug = !fish_body.is_empty(),
+            "RETURN" if !ctx.in_function => {
+                return Err(unsupported(
+                    UnsupportedKind::Trap,
+                    "trap RETURN outside a function (fish can't hook the end of a sourced file)",
+                ));
+            }
+            // Function locals must stay visible, so the handler is inlined
+            "RETURN" => {
+                out.push_str("# trap RETURN: handler runs before each return below");
+                ctx.traps.ret = Some(fish_body.clone()).filter(|b| !b.is_empty());
+                continue;
+            }
+            _ => {}
         }
 
         // EXIT trap inside a subshell: fish's begin/end has no "on-exit" event,
         // so fish_exit won't fire when the begin block ends. Bail to T3.
         if (name == "EXIT" || name == "0") && ctx.in_subshell {
-            return Err(TranslateError::Unsupported(
+            return Err(unsupported(
+                UnsupportedKind::Trap,
                 "trap EXIT in subshell (no fish equivalent)",
             ));
         }
@@ -696,6 +1992,8 @@ fn emit_trap(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
         out.push_str(name);
         if name == "EXIT" || name == "0" {
             out.push_str(" --on-event fish_exit");
+        } else if name == "DEBUG" {
+            out.push_str(" --on-event fish_preexec");
         } else {
             out.push_str(" --on-signal ");
             out.push_str(name);
@@ -711,6 +2009,22 @@ fn emit_trap(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
     Ok(())
 }
 
+/// `return [N]` under a RETURN trap → run the handler first, then return
+/// the status `return` would have.
+fn emit_return(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
+    out.push_str("begin\nset -l __reef_rc ");
+    match args.first() {
+        Some(n) => emit_word(ctx, n, out)?,
+        None => out.push_str("$status"),
+    }
+    out.push('\n');
+    if let Some(handler) = &ctx.traps.ret {
+        out.push_str(handler);
+    }
+    out.push_str("\nreturn $__reef_rc\nend");
+    Ok(())
+}
+
 fn emit_read(ctx: &mut Ctx, 
     cmd_words: &[&Word<'_>],
     redirects: &[&Redir<'_>],
@@ -865,7 +2179,7 @@ fn emit_mapfile(ctx: &mut Ctx,
 /// `eval $var` / other forms → unsupported (fall to T2)
 fn emit_eval(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
     // Extract the command list from eval "$(cmd)" or eval $(cmd)
-    let cmds = extract_eval_cmds(args).ok_or(TranslateError::Unsupported("eval"))?;
+    let cmds = extract_eval_cmds(args).ok_or(unsupported(UnsupportedKind::Eval, "eval"))?;
     for (i, cmd) in cmds.iter().enumerate() {
         if i > 0 {
             out.push_str("; ");
@@ -876,17 +2190,9 @@ fn emit_eval(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
     Ok(())
 }
 
-fn extract_eval_cmds<'a>(args: &[&'a Word<'a>]) -> Option<&'a [Cmd<'a>]> {
-    let [arg] = args else { return None };
-    let subst = match arg {
-        Word::Simple(WordPart::DQuoted(atoms)) => match atoms.as_slice() {
-            [Atom::Subst(s)] => s,
-            _ => return None,
-        },
-        Word::Simple(WordPart::Bare(Atom::Subst(s))) => s,
-        _ => return None,
-    };
-    match subst.as_ref() {
+fn extract_eval_cmds<'a>(args: &[&'a Word<'a>]) -> Option<&'a [Cmd<'a>]> {
+    let [arg] = args else { return None };
+    match lone_subst(arg)? {
         Subst::Cmd(cmds) => Some(cmds),
         _ => None,
     }
@@ -1047,7 +2353,10 @@ fn emit_let(ctx: &mut Ctx, args: &[&Word<'_>], out: &mut String) -> Res<()> {
                 emit_standalone_arith(ctx, &arith, out)?;
             }
             Err(_) => {
-                return Err(TranslateError::Unsupported("'let' with complex expression"));
+                return Err(unsupported(
+                    UnsupportedKind::Arithmetic,
+                    "'let' with complex expression",
+                ));
             }
         }
     }
@@ -1069,6 +2378,17 @@ fn emit_string_match(ctx: &mut Ctx,
             out.push_str("not ");
         }
         out.push_str("set __bash_rematch (string match -r -- ");
+    } else if let [pattern] = rhs {
+        if negated {
+            out.push_str("not ");
+        }
+        out.push_str("string match ");
+        emit_match_pattern(ctx, pattern, out)?;
+        for w in lhs {
+            out.push(' ');
+            emit_word(ctx, w, out)?;
+        }
+        return Ok(());
     } else {
         if negated {
             out.push_str("not ");
@@ -1080,7 +2400,11 @@ fn emit_string_match(ctx: &mut Ctx,
         if i > 0 {
             pat_buf.push(' ');
         }
-        emit_word_unquoted(ctx, w, &mut pat_buf)?;
+        if word_has_brace(w) {
+            emit_pattern_word(ctx, w, &mut pat_buf)?;
+        } else {
+            emit_word_unquoted(ctx, w, &mut pat_buf)?;
+        }
     }
     push_sq_escaped(out, &pat_buf);
     out.push(' ');
@@ -1093,70 +2417,387 @@ fn emit_string_match(ctx: &mut Ctx,
     Ok(())
 }
 
-/// `[[ cond ]]` → `test cond` or `string match -q pattern subject`
-fn emit_double_bracket(ctx: &mut Ctx, 
-    args: &[&Word<'_>],
-    redirects: &[&Redir<'_>],
+/// `[[ expr ]]` → `test`, `string match` and `set -q` tests chained with
+/// `; and` / `; or`. fish evaluates those left to right, so a right operand
+/// of the other kind (`a || b && c`) is wrapped in `begin; ...; end`.
+fn emit_double_bracket(
+    ctx: &mut Ctx,
+    expr: &CondExpr<'_>,
+    negated: bool,
     out: &mut String,
 ) -> Res<()> {
-    // Strip trailing ]]
-    let filtered = if args.last().and_then(|a| word_as_str(a)).as_deref() == Some("]]") {
-        &args[..args.len() - 1]
-    } else {
-        args
-    };
+    match expr {
+        CondExpr::Group(e) => emit_double_bracket(ctx, e, negated, out),
+        CondExpr::Not(e) => emit_double_bracket(ctx, e, !negated, out),
+        CondExpr::And(l, r) | CondExpr::Or(l, r) => {
+            if negated {
+                out.push_str("not ");
+                return emit_cond_block(ctx, expr, out);
+            }
+            let is_and = matches!(expr, CondExpr::And(..));
+            emit_double_bracket(ctx, l, false, out)?;
+            out.push_str(if is_and { "; and " } else { "; or " });
+            match ungroup(r) {
+                CondExpr::And(..) if !is_and => emit_cond_block(ctx, r, out),
+                CondExpr::Or(..) if is_and => emit_cond_block(ctx, r, out),
+                _ => emit_double_bracket(ctx, r, false, out),
+            }
+        }
+        CondExpr::Unary('v', w) => {
+            // [[ -v var ]] → set -q var; a map's element is set if it has the key
+            if negated {
+                out.push_str("not ");
+            }
+            if let Some((name, key)) = assoc_element_word(ctx, w, word_as_str(w).as_deref()) {
+                out.push_str("contains -- ");
+                emit_assoc_key(ctx, &key, out)?;
+                out.push_str(" $");
+                out.push_str(ASSOC_KEYS_PREFIX);
+                out.push_str(name);
+                return Ok(());
+            }
+            out.push_str("set -q ");
+            emit_word(ctx, w, out)
+        }
+        CondExpr::Unary(op, w) => {
+            let flag = match op {
+                'a' => 'e',
+                'b' | 'c' | 'd' | 'e' | 'f' | 'g' | 'h' | 'k' | 'n' | 'p' | 'r' | 's' | 't'
+                | 'u' | 'w' | 'x' | 'z' | 'G' | 'L' | 'O' | 'S' => *op,
+                'o' => return Err(unsupported(UnsupportedKind::TestOperator, "[[ -o option ]]")),
+                _ => return Err(unsupported(UnsupportedKind::TestOperator, "[[ ]] unary test")),
+            };
+            if negated {
+                out.push_str("not ");
+            }
+            out.push_str("test -");
+            out.push(flag);
+            out.push(' ');
+            emit_cond_operand(ctx, w, out)
+        }
+        CondExpr::Binary(l, op @ (CondOp::Match | CondOp::NotMatch), r) => {
+            // XOR: `[[ ! x != y ]]` → double negation cancels out
+            let negated = negated ^ (*op == CondOp::NotMatch);
+            emit_string_match(ctx, &[l], &[r], false, negated, out)
+        }
+        CondExpr::Binary(l, CondOp::Regex, r) => {
+            emit_string_match(ctx, &[l], &[r], true, negated, out)
+        }
+        CondExpr::Binary(_, CondOp::StrLt | CondOp::StrGt, _) => Err(
+            unsupported(
+                UnsupportedKind::TestOperator,
+                "[[ < ]] / [[ > ]] string ordering (fish test has none)",
+            ),
+        ),
+        CondExpr::Binary(l, op, r) => {
+            if negated {
+                out.push_str("not ");
+            }
+            out.push_str("test ");
+            emit_cond_operand(ctx, l, out)?;
+            out.push(' ');
+            out.push_str(op.as_str());
+            out.push(' ');
+            emit_cond_operand(ctx, r, out)
+        }
+        CondExpr::Word(w) => {
+            if negated {
+                out.push_str("not ");
+            }
+            out.push_str("test ");
+            emit_cond_operand(ctx, w, out)
+        }
+    }
+}
+
+/// `begin; expr; end` — keeps a nested `&&` / `||` together.
+fn emit_cond_block(ctx: &mut Ctx, expr: &CondExpr<'_>, out: &mut String) -> Res<()> {
+    out.push_str("begin; ");
+    emit_double_bracket(ctx, expr, false, out)?;
+    out.push_str("; end");
+    Ok(())
+}
 
-    // Strip leading `!` negation operator
-    let (filtered, bang_negated) =
-        if !filtered.is_empty() && word_as_str(filtered[0]).as_deref() == Some("!") {
-            (&filtered[1..], true)
+/// A `test` operand. `[[ ]]` never splits `$var`, and an empty one must
+/// stay an argument (`test -n $x` is true when `$x` is empty), so quote it.
+fn emit_cond_operand(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    if let Word::Simple(WordPart::Bare(Atom::Param(_))) = word {
+        let mut buf = String::with_capacity(16);
+        emit_word(ctx, word, &mut buf)?;
+        if buf.starts_with('$') {
+            out.push('"');
+            out.push_str(&buf);
+            out.push('"');
         } else {
-            (filtered, false)
-        };
+            out.push_str(&buf);
+        }
+        return Ok(());
+    }
+    emit_word(ctx, word, out)
+}
 
-    // Find =~ operator position for regex matching
-    let regex_pos = filtered
-        .iter()
-        .position(|a| word_as_str(a).as_deref() == Some("=~"));
+fn ungroup<'x, 'a>(mut expr: &'x CondExpr<'a>) -> &'x CondExpr<'a> {
+    while let CondExpr::Group(e) = expr {
+        expr = e;
+    }
+    expr
+}
 
-    // Find == or != operator position for glob pattern matching
-    let op_pos = filtered.iter().position(|a| {
-        let s = word_as_str(a);
-        matches!(s.as_deref(), Some("==" | "!="))
-    });
+// ---------------------------------------------------------------------------
+// getopts
+// ---------------------------------------------------------------------------
+//
+// `while getopts SPEC NAME; do case $NAME in ... esac; done` becomes a single
+// `argparse -s` call. Each option's arm then runs once per occurrence, from a
+// loop over its `_flag_X` list with the value in `OPTARG` (options taking an
+// argument are declared `X=+` so every value is kept). Arms run grouped by
+// option rather than in command-line order.
+
+/// A recognised getopts loop.
+struct GetoptsLoop<'a> {
+    /// NAME, the variable getopts stores each option letter in.
+    name: Cow<'a, str>,
+    /// Option letters from SPEC, each with whether it takes an argument.
+    opts: Vec<(char, bool)>,
+    /// SPEC starts with `:`: bash stays quiet and reports errors through
+    /// the `?` and `:` arms, with the option letter in `OPTARG`.
+    silent: bool,
+    arms: &'a [CaseArm<'a>],
+}
 
-    // [[ -v var ]] → set -q var
-    if filtered.len() == 2
-        && let Some(flag) = word_as_str(filtered[0])
-        && flag.as_ref() == "-v"
+/// Match `cmd` against the getopts loop idiom. Loops that parse anything
+/// but the positional parameters, or do more than the `case`, go through
+/// the generic emitters, which reject `getopts` itself.
+fn getopts_loop<'a>(cmd: &'a Cmd<'a>) -> Option<GetoptsLoop<'a>> {
+    let Executable::Compound(CompoundCmd {
+        kind: CompoundKind::While(guard_body),
+        redirects,
+        ..
+    }) = lone_exec(cmd)?
+    else {
+        return None;
+    };
+    let ([guard], [body]) = (guard_body.guard.as_slice(), guard_body.body.as_slice()) else {
+        return None;
+    };
+    let Executable::Simple(guard) = lone_exec(guard)? else {
+        return None;
+    };
+    let words = plain_words(guard)?;
+    let [getopts, spec, name, rest @ ..] = words.as_slice() else {
+        return None;
+    };
+    // Only the positional parameters: `getopts SPEC NAME ["$@"]`
+    let all_args = matches!(rest, [Word::Simple(WordPart::DQuoted(atoms))]
+        if matches!(atoms.as_slice(), [Atom::Param(Param::At)]));
+    if !redirects.is_empty() || word_as_str(getopts)? != "getopts" || !rest.is_empty() && !all_args
     {
-        if bang_negated {
-            out.push_str("not ");
+        return None;
+    }
+    let name = word_as_str(name)?;
+    let Executable::Compound(CompoundCmd {
+        kind: CompoundKind::Case { word, arms },
+        redirects,
+        ..
+    }) = lone_exec(body)?
+    else {
+        return None;
+    };
+    let subject = match word {
+        Word::Simple(WordPart::Bare(atom)) => atom,
+        Word::Simple(WordPart::DQuoted(atoms)) if atoms.len() == 1 => &atoms[0],
+        _ => return None,
+    };
+    if !redirects.is_empty()
+        || !matches!(subject, Atom::Param(Param::Var(v)) if *v == name)
+        || arms.iter().any(|a| a.terminator != CaseTerminator::Break)
+    {
+        return None;
+    }
+
+    let spec = word_as_str(spec)?;
+    let (silent, letters) = match spec.strip_prefix(':') {
+        Some(letters) => (true, letters),
+        None => (false, spec.as_ref()),
+    };
+    let mut opts = Vec::new();
+    let mut chars = letters.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_alphanumeric() {
+            return None;
         }
-        out.push_str("set -q ");
-        emit_word(ctx, filtered[1], out)?;
-        emit_redirects(ctx, redirects, out)?;
-        return Ok(());
+        opts.push((c, chars.next_if_eq(&':').is_some()));
     }
+    Some(GetoptsLoop { name, opts, silent, arms })
+}
 
-    if let Some(pos) = regex_pos {
-        emit_string_match(ctx, &filtered[..pos], &filtered[pos + 1..], true, bang_negated, out)?;
-    } else if let Some(pos) = op_pos {
-        let negated = word_as_str(filtered[pos]).as_deref() == Some("!=");
-        // XOR: `[[ ! x != y ]]` → double negation cancels out
-        emit_string_match(ctx, &filtered[..pos], &filtered[pos + 1..], false, negated ^ bang_negated, out)?;
-    } else {
-        if bang_negated {
-            out.push_str("not ");
+/// `shift $((OPTIND-1))`, quoted or not — the usual end of a getopts loop.
+fn is_optind_shift(cmd: &Cmd<'_>) -> bool {
+    let Some(Executable::Simple(simple)) = lone_exec(cmd) else {
+        return false;
+    };
+    let words = plain_words(simple);
+    let Some([shift, amount]) = words.as_deref() else {
+        return false;
+    };
+    word_as_str(shift).as_deref() == Some("shift")
+        && lone_subst(amount).is_some_and(|subst| match subst {
+            Subst::Arith(Some(Arith::Sub(l, r))) => {
+                matches!((l.as_ref(), r.as_ref()), (Arith::Var(v), Arith::Lit(1)) if v == "OPTIND")
+            }
+            _ => false,
+        })
+}
+
+/// Emit a getopts loop as `argparse`. With `shifted`, the loop was followed
+/// by `shift $((OPTIND-1))` and argparse removing the options from `$argv`
+/// stands in for it; otherwise `OPTIND` is set and `$argv` restored.
+fn emit_getopts(
+    ctx: &mut Ctx,
+    getopts: &GetoptsLoop<'_>,
+    shifted: bool,
+    out: &mut String,
+) -> Res<()> {
+    // Each letter runs the first arm listing it. `*` (or a bare `?`) matches
+    // whatever is left, so nothing after it is reachable.
+    let mut letter_arms = Vec::new();
+    let mut seen = Vec::new();
+    let (mut unknown, mut missing, mut fallback) = (None, None, None);
+    'arms: for arm in getopts.arms {
+        let mut letters = Vec::new();
+        for pat in &arm.patterns {
+            if matches!(pat, Word::Simple(WordPart::Bare(Atom::Star | Atom::Question))) {
+                fallback = Some(arm.body.as_slice());
+                break;
+            }
+            let text = word_as_str(pat);
+            let mut chars = text.as_deref().unwrap_or_default().chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(unsupported(
+                    UnsupportedKind::Getopts,
+                    "getopts case pattern that isn't a single option letter",
+                ));
+            };
+            match c {
+                '?' => unknown = unknown.or(Some(arm.body.as_slice())),
+                ':' => missing = missing.or(Some(arm.body.as_slice())),
+                _ => {
+                    if let Some(&opt) = getopts.opts.iter().find(|o| o.0 == c)
+                        && !seen.contains(&c)
+                    {
+                        seen.push(c);
+                        letters.push(opt);
+                    }
+                }
+            }
         }
-        out.push_str("test");
-        for arg in filtered {
-            out.push(' ');
-            emit_word(ctx, arg, out)?;
+        if !letters.is_empty() && !arm.body.is_empty() {
+            if letters.iter().any(|l| l.1) && !letters.iter().all(|l| l.1) {
+                return Err(unsupported(
+                    UnsupportedKind::Getopts,
+                    "getopts arm mixing options with and without an argument",
+                ));
+            }
+            letter_arms.push((letters, &arm.body));
+        }
+        if fallback.is_some() {
+            if getopts.opts.iter().any(|o| !seen.contains(&o.0)) {
+                return Err(unsupported(
+                    UnsupportedKind::Getopts,
+                    "getopts option handled by a catch-all arm",
+                ));
+            }
+            break 'arms;
         }
     }
+    let flags: String = getopts.opts.iter().map(|o| o.0).collect();
+    // Regex for the flags that may precede another in a cluster like `-ab`
+    let mut bools: String = getopts.opts.iter().filter(|o| !o.1).map(|o| o.0).collect();
+    if !bools.is_empty() {
+        bools.insert(0, '[');
+        bools.push_str("]*");
+    }
+    let with_arg: String = getopts.opts.iter().filter(|o| o.1).map(|o| o.0).collect();
+    let unknown = unknown.or(fallback);
+    // Without the leading `:`, bash reports a missing argument as `?` too
+    let missing = missing.or(fallback).filter(|_| getopts.silent && !with_arg.is_empty());
 
-    emit_redirects(ctx, redirects, out)?;
+    if !shifted {
+        out.push_str("set -l __reef_argv $argv\n");
+    }
+    let handled = unknown.is_some() || missing.is_some();
+    if handled {
+        out.push_str("if not ");
+    }
+    out.push_str("argparse -s");
+    for (c, arg) in &getopts.opts {
+        out.push(' ');
+        out.push(*c);
+        if *arg {
+            out.push_str("=+");
+        }
+    }
+    out.push_str(" -- $argv");
+    if getopts.silent {
+        out.push_str(" 2>/dev/null");
+    }
+
+    // argparse fails before any arm runs. A missing argument can only be
+    // an argument option ending the last word; anything else is unknown.
+    if let Some(body) = missing {
+        out.push_str("\nif string match -qr -- '^-");
+        out.push_str(&bools);
+        out.push('[');
+        out.push_str(&with_arg);
+        out.push_str("]$' $argv[-1]\nset -l OPTARG (string sub -s -1 -- $argv[-1])\nset ");
+        out.push_str(&getopts.name);
+        out.push_str(" :\n");
+        emit_body(ctx, body, out)?;
+        if unknown.is_some() {
+            out.push_str("\nelse");
+        }
+    }
+    if let Some(body) = unknown {
+        out.push('\n');
+        if getopts.silent {
+            out.push_str("set -l OPTARG (string match -r -- '^-");
+            out.push_str(&bools);
+            out.push_str("([^");
+            out.push_str(&flags);
+            out.push_str("-])' $argv)[2]\n");
+        }
+        out.push_str("set ");
+        out.push_str(&getopts.name);
+        out.push_str(" '?'\n");
+        emit_body(ctx, body, out)?;
+    }
+    if missing.is_some() {
+        out.push_str("\nend");
+    }
+    if handled {
+        out.push_str("\nend");
+    }
+
+    if !shifted {
+        out.push_str(
+            "\nset OPTIND (math (count $__reef_argv) - (count $argv) + 1)\nset argv $__reef_argv",
+        );
+    }
+    // One loop per letter, so NAME can be set to it
+    for (letters, body) in letter_arms {
+        for (c, arg) in letters {
+            out.push_str(if arg { "\nfor OPTARG in" } else { "\nfor __reef_opt in" });
+            out.push_str(" $_flag_");
+            out.push(c);
+            out.push_str("\nset ");
+            out.push_str(&getopts.name);
+            out.push(' ');
+            out.push(c);
+            out.push('\n');
+            emit_body(ctx, body, out)?;
+            out.push_str("\nend");
+        }
+    }
     Ok(())
 }
 
@@ -1169,8 +2810,9 @@ fn emit_compound(ctx: &mut Ctx, cmd: &CompoundCmd<'_>, out: &mut String) -> Res<
         Redir::HereString(w) => Some(w),
         _ => None,
     });
-    let heredoc = cmd.redirects.iter().find_map(|r| match r {
-        Redir::Heredoc(body) => Some(body),
+    // Like bash, the last heredoc on stdin wins
+    let heredoc = cmd.redirects.iter().rev().find_map(|r| match r {
+        Redir::Heredoc(None | Some(0), doc) => Some(doc),
         _ => None,
     });
     if let Some(hs_word) = herestring {
@@ -1178,13 +2820,13 @@ fn emit_compound(ctx: &mut Ctx, cmd: &CompoundCmd<'_>, out: &mut String) -> Res<
         emit_word(ctx, hs_word, out)?;
         out.push_str(" | ");
     }
-    if let Some(body) = heredoc {
-        emit_heredoc_body(ctx, body, out)?;
+    if let Some(doc) = heredoc {
+        emit_heredoc_body(ctx, doc, out)?;
         out.push_str(" | ");
     }
     emit_compound_kind(ctx, &cmd.kind, out)?;
     for redir in &cmd.redirects {
-        if matches!(redir, Redir::HereString(..) | Redir::Heredoc(..)) {
+        if matches!(redir, Redir::HereString(..) | Redir::Heredoc(None | Some(0), _)) {
             continue;
         }
         out.push(' ');
@@ -1230,31 +2872,16 @@ fn emit_compound_kind(ctx: &mut Ctx, kind: &CompoundKind<'_>, out: &mut String)
             out.push_str("for ");
             out.push_str(var);
             out.push_str(" in ");
-            if let Some(words) = words {
-                for (i, w) in words.iter().enumerate() {
-                    if i > 0 {
-                        out.push(' ');
-                    }
-                    if let Some(cmds) = get_bare_command_subst(w) {
-                        emit_command_subst_with_split(ctx, cmds, out)?;
-                    } else if is_bare_var_ref(w) {
-                        // Bash word-splits unquoted $var; fish doesn't.
-                        // Wrap in string split to match bash semantics.
-                        out.push_str("(string split -n -- ' ' ");
-                        emit_word(ctx, w, out)?;
-                        out.push(')');
-                    } else {
-                        emit_word(ctx, w, out)?;
-                    }
-                }
-            } else {
-                out.push_str("$argv");
-            }
+            emit_loop_words(ctx, words.as_deref(), out)?;
             out.push('\n');
             emit_body(ctx, body, out)?;
             out.push_str("\nend");
         }
 
+        CompoundKind::Select { var, words, body } => {
+            emit_select(ctx, var, words.as_deref(), body, out)?;
+        }
+
         CompoundKind::While(guard_body) => {
             out.push_str("while ");
             emit_guard(ctx, &guard_body.guard, out)?;
@@ -1293,6 +2920,15 @@ fn emit_compound_kind(ctx: &mut Ctx, kind: &CompoundKind<'_>, out: &mut String)
         }
 
         CompoundKind::Case { word, arms } => {
+            // `;&` / `;;&` have no `switch` equivalent. A terminator on the
+            // last arm has nothing to continue into, so it doesn't count.
+            // Extglob patterns need `string match -r`, which `switch` can't do.
+            let needs_chain = arms.split_last().is_some_and(|(_, init)| {
+                init.iter().any(|arm| arm.terminator != CaseTerminator::Break)
+            }) || arms.iter().flat_map(|arm| &arm.patterns).any(word_has_extglob);
+            if needs_chain {
+                return emit_case_chain(ctx, word, arms, out);
+            }
             out.push_str("switch ");
             emit_word(ctx, word, out)?;
             out.push('\n');
@@ -1304,11 +2940,11 @@ fn emit_compound_kind(ctx: &mut Ctx, kind: &CompoundKind<'_>, out: &mut String)
                         out.push(' ');
                     }
                     pat_buf.clear();
-                    emit_word(ctx, pattern, &mut pat_buf)?;
+                    emit_pattern_word(ctx, pattern, &mut pat_buf)?;
 
                     if let Some(expanded) = expand_bracket_pattern(&pat_buf) {
                         out.push_str(&expanded);
-                    } else if pat_buf.contains('*') || pat_buf.contains('?') {
+                    } else if pat_buf.contains(['*', '?', '{']) {
                         push_sq_escaped(out, &pat_buf);
                     } else {
                         out.push_str(&pat_buf);
@@ -1354,30 +2990,194 @@ fn emit_compound_kind(ctx: &mut Ctx, kind: &CompoundKind<'_>, out: &mut String)
 
         CompoundKind::Subshell(cmds) => {
             if cmds.is_empty() {
-                return Err(TranslateError::Unsupported("empty subshell"));
+                return Err(unsupported(UnsupportedKind::Subshell, "empty subshell"));
             }
             out.push_str("begin\n");
             out.push_str("set -l __reef_pwd (pwd)\n");
             let prev = ctx.in_subshell;
+            let traps = std::mem::take(&mut ctx.traps);
             ctx.in_subshell = true;
             emit_body(ctx, cmds, out)?;
             ctx.in_subshell = prev;
+            ctx.traps = traps;
             out.push_str(
                 "\nset -l __reef_rc $status; cd $__reef_pwd 2>/dev/null\nend",
             );
         }
 
-        CompoundKind::DoubleBracket(cmds) => {
-            emit_body(ctx, cmds, out)?;
+        CompoundKind::DoubleBracket(expr) => {
+            // A chain is one command in bash: `!`, pipes, redirects and `&`
+            // apply to all of it
+            if let CondExpr::And(..) | CondExpr::Or(..) = ungroup(expr) {
+                emit_cond_block(ctx, expr, out)?;
+            } else {
+                emit_double_bracket(ctx, expr, false, out)?;
+            }
         }
 
         CompoundKind::Arithmetic(arith) => {
             emit_standalone_arith(ctx, arith, out)?;
         }
+
+        CompoundKind::Coproc { .. } => {
+            return Err(unsupported(
+                UnsupportedKind::Coproc,
+                "coproc (fish has no coprocesses; NAME[0]/NAME[1] fds need bash)",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `case` with `;&`/`;;&` arms or extglob patterns → a chain of `if` blocks driven by a
+/// state variable instead of `switch`:
+///
+/// - `test`: no arm has claimed the word yet, keep matching patterns
+/// - `run`: run the next body (pattern matched, or `;&` fell through)
+/// - `stop`: an arm ended with `;;`, skip everything else
+///
+/// `;;&` sets the state back to `test` so later arms are matched again.
+/// Extglob patterns are matched as regexes, so they also take this path.
+///
+/// A chain nested in an arm body uses `__reef_case_N`/`__reef_case_N_word`:
+/// its `set -l` would otherwise shadow the outer state for the rest of the
+/// arm, including the arm's own terminator.
+fn emit_case_chain(
+    ctx: &mut Ctx,
+    word: &Word<'_>,
+    arms: &[CaseArm<'_>],
+    out: &mut String,
+) -> Res<()> {
+    let state = match ctx.case_depth {
+        0 => "__reef_case".to_string(),
+        depth => format!("__reef_case_{depth}"),
+    };
+    out.push_str("set -l ");
+    out.push_str(&state);
+    out.push_str("_word ");
+    emit_word(ctx, word, out)?;
+    out.push_str("\nset -l ");
+    out.push_str(&state);
+    out.push_str(" test");
+    let mut pat_buf = String::with_capacity(32);
+    for arm in arms {
+        for pattern in &arm.patterns {
+            out.push_str("\ntest $");
+            out.push_str(&state);
+            out.push_str(" = test; and ");
+            pat_buf.clear();
+            emit_pattern_word(ctx, pattern, &mut pat_buf)?;
+            if let Some(expanded) = expand_bracket_pattern(&pat_buf) {
+                out.push_str("contains -- $");
+                out.push_str(&state);
+                out.push_str("_word ");
+                out.push_str(&expanded);
+                out.push_str("; and set ");
+                out.push_str(&state);
+                out.push_str(" run");
+                continue;
+            }
+            out.push_str("string match ");
+            emit_match_pattern(ctx, pattern, out)?;
+            out.push_str(" $");
+            out.push_str(&state);
+            out.push_str("_word; and set ");
+            out.push_str(&state);
+            out.push_str(" run");
+        }
+        out.push_str("\nif test $");
+        out.push_str(&state);
+        out.push_str(" = run\n");
+        if !arm.body.is_empty() {
+            ctx.case_depth += 1;
+            let res = emit_body(ctx, &arm.body, out);
+            ctx.case_depth -= 1;
+            res?;
+            out.push('\n');
+        }
+        out.push_str("set ");
+        out.push_str(&state);
+        out.push_str(match arm.terminator {
+            CaseTerminator::FallThrough => " run",
+            CaseTerminator::Continue => " test",
+            _ => " stop",
+        });
+        out.push_str("\nend");
+    }
+    Ok(())
+}
+
+/// Word list of a `for`/`select` header. `None` (no `in` clause) means
+/// the positional parameters.
+fn emit_loop_words(ctx: &mut Ctx, words: Option<&[Word<'_>]>, out: &mut String) -> Res<()> {
+    let Some(words) = words else {
+        out.push_str("$argv");
+        return Ok(());
+    };
+    for (i, w) in words.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        if let Some(cmds) = get_bare_command_subst(w) {
+            emit_command_subst_with_split(ctx, cmds, out)?;
+        } else if is_bare_var_ref(w) {
+            // Bash word-splits unquoted $var; fish doesn't.
+            // Wrap in string split to match bash semantics.
+            out.push_str("(string split -n -- ' ' ");
+            emit_word(ctx, w, out)?;
+            out.push(')');
+        } else {
+            emit_word(ctx, w, out)?;
+        }
     }
     Ok(())
 }
 
+/// `select var in words; do body; done` → a `while` loop that prints a
+/// numbered menu to stderr, prompts with `$PS3` (default `#? `, re-read
+/// at every prompt), and sets `REPLY` and `var` like bash: the menu is
+/// shown up front and again after an empty reply, an out-of-range reply
+/// leaves `var` empty, and EOF ends the loop.
+fn emit_select(
+    ctx: &mut Ctx,
+    var: &str,
+    words: Option<&[Word<'_>]>,
+    body: &[Cmd<'_>],
+    out: &mut String,
+) -> Res<()> {
+    out.push_str("set -l __reef_sel_items ");
+    emit_loop_words(ctx, words, out)?;
+    out.push_str(
+        "\nset -l __reef_sel_menu 1\n\
+         while true\n\
+         if test $__reef_sel_menu -eq 1\n\
+         for __reef_sel_i in (seq (count $__reef_sel_items))\n\
+         printf '%s) %s\\n' $__reef_sel_i $__reef_sel_items[$__reef_sel_i] >&2\n\
+         end\n\
+         set __reef_sel_menu 0\n\
+         end\n\
+         set -l __reef_sel_ps3 '#? '\n\
+         set -q PS3; and set __reef_sel_ps3 $PS3\n\
+         read -P \"$__reef_sel_ps3\" REPLY; or break\n\
+         if test -z \"$REPLY\"\n\
+         set __reef_sel_menu 1\n\
+         continue\n\
+         end\n\
+         set ",
+    );
+    out.push_str(var);
+    out.push_str(
+        "\nif string match -qr '^[0-9]+$' -- $REPLY; \
+         and test $REPLY -ge 1 -a $REPLY -le (count $__reef_sel_items)\n\
+         set ",
+    );
+    out.push_str(var);
+    out.push_str(" $__reef_sel_items[$REPLY]\nend\n");
+    emit_body(ctx, body, out)?;
+    out.push_str("\nend");
+    Ok(())
+}
+
 /// Expand a pure bracket pattern [chars] to space-separated alternatives.
 fn expand_bracket_pattern(pat: &str) -> Option<String> {
     if !pat.starts_with('[') || !pat.ends_with(']') || pat.len() < 3 {
@@ -1420,11 +3220,31 @@ fn emit_guard(ctx: &mut Ctx, guard: &[Cmd<'_>], out: &mut String) -> Res<()> {
 }
 
 fn emit_body(ctx: &mut Ctx, cmds: &[Cmd<'_>], out: &mut String) -> Res<()> {
-    for (i, cmd) in cmds.iter().enumerate() {
+    let mut i = 0;
+    while let Some(cmd) = cmds.get(i) {
         if i > 0 {
             out.push('\n');
         }
-        emit_cmd(ctx, cmd, out)?;
+        i += 1;
+        if ctx.traps.debug {
+            out.push_str("__reef_trap_DEBUG\n");
+        }
+        let renamed;
+        let cmd = if ctx.namerefs.is_empty() {
+            cmd
+        } else {
+            renamed = NamerefRenamer::rename(&ctx.namerefs, cmd).map_err(|e| e.at(cmd.span()))?;
+            &renamed
+        };
+        // A getopts loop takes the `shift $((OPTIND-1))` after it along
+        if let Some(getopts) = getopts_loop(cmd) {
+            let shifted = cmds.get(i).is_some_and(is_optind_shift);
+            emit_getopts(ctx, &getopts, shifted, out).map_err(|e| e.at(cmd.span()))?;
+            i += usize::from(shifted);
+        } else {
+            ctx.err_check = ctx.traps.err && matches!(cmd, Cmd::List(_));
+            emit_stmt(ctx, cmd, out)?;
+        }
     }
     Ok(())
 }
@@ -1434,31 +3254,67 @@ fn emit_body(ctx: &mut Ctx, cmds: &[Cmd<'_>], out: &mut String) -> Res<()> {
 // ---------------------------------------------------------------------------
 
 fn emit_word(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
-    // Check for nested brace expansion that fish handles in different order
-    if word_has_nested_braces(word) {
-        return Err(TranslateError::Unsupported(
-            "nested brace expansion (fish expands in different order)",
+    emit_word_unlocated(ctx, word, out).map_err(|e| e.at_node(ctx.spans.word(word)))
+}
+
+fn emit_word_unlocated(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    if word_has_brace_list(word) {
+        return emit_brace_expansion(ctx, word, out);
+    }
+    if word_has_multiple_brace_ranges(word) {
+        return Err(unsupported(
+            UnsupportedKind::BraceExpansion,
+            "adjacent brace ranges (fish expands in different order)",
         ));
     }
     // Brace range combined with non-literal parts (e.g. {a..c}$(cmd)) —
     // bash expands brace range first, creating separate words each getting
     // the suffix. Fish doesn't distribute the suffix across brace-expanded words.
     if word_has_brace_range_concat(word) {
-        return Err(TranslateError::Unsupported(
+        return Err(unsupported(
+            UnsupportedKind::BraceExpansion,
             "brace range with concatenated expansion",
         ));
     }
+    if word_has_extglob(word) {
+        return emit_extglob_expansion(ctx, word, out);
+    }
     match word {
         Word::Simple(p) => emit_word_part(ctx, p, out),
         Word::Concat(parts) => {
+            let mut var_at = None;
             for p in parts {
+                let start = out.len();
                 emit_word_part(ctx, p, out)?;
+                // `${x}a` must not become `$xa`: fish separates with `{$x}a`
+                if let Some(at) = var_at.take()
+                    && continues_var_ref(&out[start..])
+                {
+                    out.insert(start, '}');
+                    out.insert(at, '{');
+                }
+                if matches!(p, WordPart::Bare(Atom::Param(_))) && is_var_ref(&out[start..]) {
+                    var_at = Some(start);
+                }
             }
             Ok(())
         }
     }
 }
 
+/// Whether `text` is exactly a `$name` reference.
+fn is_var_ref(text: &str) -> bool {
+    text.strip_prefix('$').is_some_and(|name| {
+        !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    })
+}
+
+/// Whether `text` would extend a `$name` reference it directly follows,
+/// either as more name characters or as a fish index.
+fn continues_var_ref(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '[')
+}
+
 /// Emit a word with its outer quoting layer stripped.
 fn emit_word_unquoted(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
     match word {
@@ -1480,8 +3336,12 @@ fn emit_word_part(ctx: &mut Ctx, part: &WordPart<'_>, out: &mut String) -> Res<(
     match part {
         WordPart::Bare(atom) => emit_atom(ctx, atom, out),
         WordPart::DQuoted(parts) => {
-            let mut in_quotes = true;
-            out.push('"');
+            // A leading substitution needs no empty `""` in front of it
+            let mut in_quotes = !matches!(parts.first(), Some(Atom::Subst(_)));
+            if in_quotes {
+                out.push('"');
+            }
+            let mut var_end = None;
             for atom in parts {
                 if let Atom::Subst(_) = atom {
                     if in_quotes {
@@ -1492,7 +3352,15 @@ fn emit_word_part(ctx: &mut Ctx, part: &WordPart<'_>, out: &mut String) -> Res<(
                     out.push('"');
                     in_quotes = true;
                 }
+                let start = out.len();
                 emit_atom(ctx, atom, out)?;
+                // `"${x}a"` must not become `"$xa"`: close and reopen the quotes
+                if var_end.take() == Some(start) && continues_var_ref(&out[start..]) {
+                    out.insert_str(start, "\"\"");
+                }
+                if matches!(atom, Atom::Param(_)) && is_var_ref(&out[start..]) {
+                    var_end = Some(out.len());
+                }
             }
             if in_quotes {
                 out.push('"');
@@ -1509,6 +3377,10 @@ fn emit_word_part(ctx: &mut Ctx, part: &WordPart<'_>, out: &mut String) -> Res<(
 }
 
 fn emit_atom(ctx: &mut Ctx, atom: &Atom<'_>, out: &mut String) -> Res<()> {
+    emit_atom_unlocated(ctx, atom, out).map_err(|e| e.at_node(ctx.spans.atom(atom)))
+}
+
+fn emit_atom_unlocated(ctx: &mut Ctx, atom: &Atom<'_>, out: &mut String) -> Res<()> {
     match atom {
         Atom::Lit(s) => {
             out.push_str(s);
@@ -1547,23 +3419,68 @@ fn emit_atom(ctx: &mut Ctx, atom: &Atom<'_>, out: &mut String) -> Res<()> {
         }
         Atom::ProcSubIn(cmds) => {
             out.push('(');
+            let traps = std::mem::take(&mut ctx.traps);
             for (i, cmd) in cmds.iter().enumerate() {
                 if i > 0 {
                     out.push_str("; ");
                 }
                 emit_cmd(ctx, cmd, out)?;
             }
+            ctx.traps = traps;
             out.push_str(" | psub)");
             Ok(())
         }
+        Atom::ProcSubOut(cmds) => {
+            let mut reader = String::with_capacity(64);
+            let traps = std::mem::take(&mut ctx.traps);
+            emit_body(ctx, cmds, &mut reader)?;
+            ctx.traps = traps;
+            let Some(readers) = ctx.proc_sub_out.as_mut() else {
+                return Err(unsupported(
+                    UnsupportedKind::ProcessSubstitution,
+                    "output process substitution >(...) in an and-or list, background job, \
+                     condition or command substitution",
+                ));
+            };
+            out.push_str("$__reef_psub/");
+            itoa(out, i64::try_from(readers.len()).unwrap_or(i64::MAX));
+            readers.push(reader);
+            Ok(())
+        }
         Atom::AnsiCQuoted(s) => {
             emit_ansi_c_quoted(s, out);
             Ok(())
         }
         Atom::BraceRange { start, end, step } => {
-            emit_brace_range(start, end, *step, out);
+            emit_brace_range(start, end, step.as_deref(), out);
             Ok(())
         }
+        // Only reached in assignment values, where bash does no brace
+        // expansion; words are expanded by `emit_brace_expansion` and
+        // patterns keep the braces via `emit_pattern_word`
+        Atom::BraceList(alternatives) => {
+            out.push_str("\\{");
+            for (i, alt) in alternatives.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                match alt {
+                    Word::Simple(p) => emit_word_part(ctx, p, out)?,
+                    Word::Concat(parts) => {
+                        for p in parts {
+                            emit_word_part(ctx, p, out)?;
+                        }
+                    }
+                }
+            }
+            out.push_str("\\}");
+            Ok(())
+        }
+        // Whole words are handled by `emit_extglob_expansion`
+        Atom::ExtGlob { .. } => Err(unsupported(
+            UnsupportedKind::ExtGlob,
+            "extended glob in this position",
+        )),
     }
 }
 
@@ -1581,71 +3498,156 @@ fn word_has_brace_range_concat(word: &Word<'_>) -> bool {
     // Check if any other part contains an expansion (param, subst, etc.).
     // Pure literals like `{a..c}"hello"` are fine — fish handles those correctly.
     parts.iter().any(|p| match p {
-        WordPart::Bare(Atom::Param(_) | Atom::Subst(_) | Atom::ProcSubIn(_)) => true,
+        WordPart::Bare(
+            Atom::Param(_) | Atom::Subst(_) | Atom::ProcSubIn(_) | Atom::ProcSubOut(_),
+        ) => true,
         WordPart::DQuoted(atoms) => atoms.iter().any(|a| !matches!(a, Atom::Lit(_))),
         _ => false,
     })
 }
 
-/// Detect adjacent brace comma expansions like `{a,b}{1,2}` which fish expands
-/// in a different order than bash. Walks AST literal slices directly — zero
-/// allocation.
-fn word_has_nested_braces(word: &Word<'_>) -> bool {
-    let mut state = BraceState::default();
+/// A brace list or range anywhere at the top level of `word`.
+fn word_has_brace(word: &Word<'_>) -> bool {
+    let is_brace = |p: &WordPart<'_>| {
+        matches!(p, WordPart::Bare(Atom::BraceList(_) | Atom::BraceRange { .. }))
+    };
+    match word {
+        Word::Simple(p) => is_brace(p),
+        Word::Concat(parts) => parts.iter().any(is_brace),
+    }
+}
+
+/// A `case` or `[[ == ]]` pattern. Bash does no brace expansion there, so
+/// brace lists and ranges stay literal text for the pattern to match.
+fn emit_pattern_word(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    if !word_has_brace(word) {
+        return emit_word(ctx, word, out);
+    }
     let parts: &[WordPart<'_>] = match word {
         Word::Simple(p) => std::slice::from_ref(p),
         Word::Concat(parts) => parts,
     };
-    for p in parts {
-        if scan_part_braces(p, &mut state) {
-            return true;
+    for part in parts {
+        match part {
+            WordPart::Bare(Atom::BraceList(alternatives)) => {
+                out.push('{');
+                for (i, alt) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    emit_pattern_word(ctx, alt, out)?;
+                }
+                out.push('}');
+            }
+            WordPart::Bare(Atom::BraceRange { start, end, step }) => {
+                out.push('{');
+                out.push_str(start);
+                out.push_str("..");
+                out.push_str(end);
+                if let Some(step) = step {
+                    out.push_str("..");
+                    out.push_str(step);
+                }
+                out.push('}');
+            }
+            _ => emit_word_part(ctx, part, out)?,
         }
     }
-    state.count >= 2
+    Ok(())
 }
 
-#[derive(Default)]
-struct BraceState {
-    /// Number of consecutive `{...,..}` groups seen.
-    count: u32,
-    /// True while scanning inside a `{` and before seeing `}`.
-    in_brace: bool,
-    /// True if we've seen a `,` inside the current brace group.
-    has_comma: bool,
-}
-
-/// Feed one word-part's literal bytes into the brace state machine.
-/// Returns `true` early if two adjacent brace groups are detected.
-fn scan_part_braces(part: &WordPart<'_>, st: &mut BraceState) -> bool {
-    let slice: &str = match part {
-        WordPart::Bare(Atom::Lit(s)) | WordPart::SQuoted(s) => s,
-        _ => return false, // non-literal parts break adjacency
+fn word_has_brace_list(word: &Word<'_>) -> bool {
+    let is_list = |p: &WordPart<'_>| matches!(p, WordPart::Bare(Atom::BraceList(_)));
+    match word {
+        Word::Simple(p) => is_list(p),
+        Word::Concat(parts) => parts.iter().any(is_list),
+    }
+}
+
+/// Two brace ranges in one word, e.g. `{1..2}{a..b}`. Each range becomes a
+/// separate fish expansion, whose cartesian product runs in the other order.
+fn word_has_multiple_brace_ranges(word: &Word<'_>) -> bool {
+    let Word::Concat(parts) = word else {
+        return false;
     };
-    for &b in slice.as_bytes() {
-        if st.in_brace {
-            match b {
-                b',' => st.has_comma = true,
-                b'}' => {
-                    st.in_brace = false;
-                    if st.has_comma {
-                        st.count += 1;
-                        if st.count >= 2 {
-                            return true;
-                        }
-                    } else {
-                        st.count = 0;
-                    }
-                }
-                _ => {}
-            }
-        } else if b == b'{' {
-            st.in_brace = true;
-            st.has_comma = false;
+    parts
+        .iter()
+        .filter(|p| matches!(p, WordPart::Bare(Atom::BraceRange { .. })))
+        .nth(1)
+        .is_some()
+}
+
+/// Brace lists in argument position → expand statically into separate
+/// words. Fish's own brace expansion varies the rightmost group slowest, so
+/// `{a,b}{1,2}` must not be passed through.
+fn emit_brace_expansion(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    let parts: &[WordPart<'_>] = match word {
+        Word::Simple(p) => std::slice::from_ref(p),
+        Word::Concat(parts) => parts,
+    };
+    let mut first = true;
+    for parts in expand_brace_lists(parts)? {
+        // bash drops words that expand to nothing, e.g. the `{,a}` head
+        if parts.is_empty() {
+            continue;
+        }
+        if !first {
+            out.push(' ');
+        }
+        first = false;
+        let word = if parts.len() == 1 {
+            Word::Simple(parts.into_iter().next().expect("len checked == 1"))
         } else {
-            st.count = 0;
-        }
+            Word::Concat(parts)
+        };
+        emit_word(ctx, &word, out)?;
     }
-    false
+    Ok(())
+}
+
+/// Cartesian product of the brace lists in `parts`, in bash order: the
+/// leftmost list varies slowest and nested lists flatten in place.
+fn expand_brace_lists<'a>(parts: &[WordPart<'a>]) -> Res<Vec<Vec<WordPart<'a>>>> {
+    let mut words = vec![Vec::new()];
+    for part in parts {
+        let WordPart::Bare(Atom::BraceList(alternatives)) = part else {
+            for w in &mut words {
+                w.push(part.clone());
+            }
+            continue;
+        };
+        // A range stays a single fish expansion, so words after it would
+        // come out grouped by the list instead of by the range.
+        if words
+            .iter()
+            .flatten()
+            .any(|p| matches!(p, WordPart::Bare(Atom::BraceRange { .. })))
+        {
+            return Err(unsupported(
+                UnsupportedKind::BraceExpansion,
+                "brace list after a brace range",
+            ));
+        }
+        let mut suffixes = Vec::new();
+        for alt in alternatives {
+            let alt_parts: &[WordPart<'a>] = match alt {
+                Word::Simple(p) => std::slice::from_ref(p),
+                Word::Concat(parts) => parts,
+            };
+            suffixes.extend(expand_brace_lists(alt_parts)?);
+        }
+        words = words
+            .iter()
+            .flat_map(|prefix| {
+                suffixes.iter().map(move |suffix| {
+                    let mut w = prefix.clone();
+                    w.extend(suffix.iter().cloned());
+                    w
+                })
+            })
+            .collect();
+    }
+    Ok(words)
 }
 
 /// Translate bash `$'...'` ANSI-C quoting to fish.
@@ -1806,12 +3808,15 @@ fn expand_alpha_range(start: char, end: char) -> String {
 /// Reject bash-specific variables that have no fish equivalent.
 fn check_untranslatable_var(param: &Param<'_>) -> Res<()> {
     if let Param::Var(name) = param {
-        match *name {
-            "LINENO" => return Err(TranslateError::Unsupported("$LINENO")),
-            "FUNCNAME" => return Err(TranslateError::Unsupported("$FUNCNAME")),
-            "SECONDS" => return Err(TranslateError::Unsupported("$SECONDS")),
+        match name.as_ref() {
+            "LINENO" => return Err(unsupported(UnsupportedKind::SpecialVariable, "$LINENO")),
+            "FUNCNAME" => return Err(unsupported(UnsupportedKind::SpecialVariable, "$FUNCNAME")),
+            "SECONDS" => return Err(unsupported(UnsupportedKind::SpecialVariable, "$SECONDS")),
             "COMP_WORDS" | "COMP_CWORD" | "COMP_LINE" | "COMP_POINT" => {
-                return Err(TranslateError::Unsupported("bash completion variable"));
+                return Err(unsupported(
+                    UnsupportedKind::SpecialVariable,
+                    "bash completion variable",
+                ));
             }
             _ => {}
         }
@@ -1821,16 +3826,16 @@ fn check_untranslatable_var(param: &Param<'_>) -> Res<()> {
 
 fn emit_param(param: &Param<'_>, out: &mut String) {
     match param {
-        Param::Var("RANDOM") => out.push_str("(random)"),
-        Param::Var("HOSTNAME") => out.push_str("$hostname"),
-        Param::Var("BASH_SOURCE" | "BASH_SOURCE[@]") => {
-            out.push_str("(status filename)");
-        }
-        Param::Var("PIPESTATUS") => out.push_str("$pipestatus"),
-        Param::Var(name) => {
-            out.push('$');
-            out.push_str(name);
-        }
+        Param::Var(name) => match name.as_ref() {
+            "RANDOM" => out.push_str("(random)"),
+            "HOSTNAME" => out.push_str("$hostname"),
+            "BASH_SOURCE" | "BASH_SOURCE[@]" => out.push_str("(status filename)"),
+            "PIPESTATUS" => out.push_str("$pipestatus"),
+            _ => {
+                out.push('$');
+                out.push_str(name);
+            }
+        },
         Param::Positional(n) => {
             if *n == 0 {
                 out.push_str("(status filename)");
@@ -1853,19 +3858,22 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
     match subst {
         Subst::Cmd(cmds) => {
             out.push('(');
+            let traps = std::mem::take(&mut ctx.traps);
             for (i, cmd) in cmds.iter().enumerate() {
                 if i > 0 {
                     out.push_str("; ");
                 }
                 emit_cmd(ctx, cmd, out)?;
             }
+            ctx.traps = traps;
             out.push(')');
             Ok(())
         }
 
         Subst::Arith(Some(arith)) => {
             if arith_has_unsupported(arith) {
-                return Err(TranslateError::Unsupported(
+                return Err(unsupported(
+                    UnsupportedKind::Arithmetic,
                     "unsupported arithmetic (bitwise, increment, or assignment)",
                 ));
             }
@@ -1930,12 +3938,56 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
                     out.push(')');
                     Ok(())
                 }
-                b'E' => Err(TranslateError::Unsupported("${var@E} escape expansion")),
-                b'P' => Err(TranslateError::Unsupported("${var@P} prompt expansion")),
-                b'A' => Err(TranslateError::Unsupported("${var@A} assignment form")),
-                b'K' => Err(TranslateError::Unsupported("${var@K} quoted key-value")),
-                b'a' => Err(TranslateError::Unsupported("${var@a} attribute flags")),
-                _ => Err(TranslateError::Unsupported(
+                b'E' => {
+                    // ${var@E} → (printf '%b' "$var" | string collect -N -a),
+                    // which decodes the same backslash escapes as $'...' and
+                    // keeps the result one argument, newlines and all
+                    out.push_str("(printf '%b' \"$");
+                    out.push_str(name);
+                    out.push_str("\" | string collect -N -a)");
+                    Ok(())
+                }
+                b'A' | b'K' if ctx.assoc.contains(name.as_ref()) => Err(
+                    unsupported(
+                        UnsupportedKind::AssocArray,
+                        "${m@A} / ${m@K} on an associative array",
+                    ),
+                ),
+                b'A' => {
+                    // ${var@A} → a fish `set` command that recreates var
+                    out.push_str("(string join ' ' -- set ");
+                    out.push_str(name);
+                    out.push_str(" (string escape -- $");
+                    out.push_str(name);
+                    out.push_str("))");
+                    Ok(())
+                }
+                b'K' => {
+                    // On a scalar, ${var@K} quotes like ${var@Q}
+                    out.push_str("(string escape -- $");
+                    out.push_str(name);
+                    out.push(')');
+                    Ok(())
+                }
+                b'P' => Err(unsupported(
+                    UnsupportedKind::ParamTransform,
+                    "${var@P} prompt expansion",
+                )),
+                b'a' => {
+                    // ${var@a} → the array flag known from earlier
+                    // assignments, then `x` if the variable is exported
+                    if ctx.assoc.contains(name.as_ref()) {
+                        out.push('A');
+                    } else if ctx.arrays.contains(name.as_ref()) {
+                        out.push('a');
+                    }
+                    out.push_str("(set -qx ");
+                    out.push_str(name);
+                    out.push_str("; and echo x; or echo)");
+                    Ok(())
+                }
+                _ => Err(unsupported(
+                    UnsupportedKind::ParamTransform,
                     "unsupported parameter transformation",
                 )),
             }
@@ -1964,7 +4016,10 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
         Subst::Assign(param, word) => {
             out.push_str("(set -q ");
             emit_param_name(param, out);
-            out.push_str("; or set ");
+            out.push_str(match param {
+                Param::Var(name) if name.starts_with('$') => "; or set -g ",
+                _ => "; or set ",
+            });
             emit_param_name(param, out);
             out.push(' ');
             if let Some(w) = word {
@@ -2017,7 +4072,13 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
             emit_string_op(ctx, param, pattern.as_ref(), "prefix", true, out)
         }
 
-        Subst::Upper(all, param) => {
+        Subst::Upper(all, param, Some(pattern)) => {
+            emit_case_pattern(ctx, *all, param, pattern, "upper", out)
+        }
+        Subst::Lower(all, param, Some(pattern)) => {
+            emit_case_pattern(ctx, *all, param, pattern, "lower", out)
+        }
+        Subst::Upper(all, param, None) => {
             if !all {
                 // ${var^} capitalize first char: upper first char + rest
                 out.push_str("(string sub -l 1 -- $");
@@ -2032,7 +4093,7 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
             out.push_str("\")");
             Ok(())
         }
-        Subst::Lower(all, param) => {
+        Subst::Lower(all, param, None) => {
             if !all {
                 out.push_str("(string sub -l 1 -- $");
                 emit_param_name(param, out);
@@ -2085,6 +4146,13 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
                 out.push_str("$pipestatus[");
                 emit_array_index(ctx, idx, out)?;
                 out.push(']');
+            } else if ctx.assoc.contains(name.as_ref()) {
+                // ${m[k]} → $m[(contains -i -- k $__reef_keys_m)]
+                out.push('$');
+                out.push_str(name);
+                out.push('[');
+                emit_assoc_index(ctx, name, idx, out)?;
+                out.push(']');
             } else {
                 // ${arr[n]} → $arr[n+1]  (bash 0-indexed → fish 1-indexed)
                 out.push('$');
@@ -2105,6 +4173,21 @@ fn emit_subst(ctx: &mut Ctx, subst: &Subst<'_>, out: &mut String) -> Res<()> {
             }
             Ok(())
         }
+        Subst::ArrayKeys(name) => {
+            if ctx.assoc.contains(name.as_ref()) {
+                // ${!m[@]} → $__reef_keys_m
+                out.push('$');
+                out.push_str(ASSOC_KEYS_PREFIX);
+                out.push_str(name);
+            } else {
+                // ${!arr[@]} → (seq 0 (math (count $arr) - 1)); fish lists
+                // have no holes, so the indices are always dense
+                out.push_str("(seq 0 (math (count $");
+                out.push_str(name);
+                out.push_str(") - 1))");
+            }
+            Ok(())
+        }
         Subst::ArrayLen(name) => {
             // ${#arr[@]} → (count $arr)
             out.push_str("(count $");
@@ -2205,6 +4288,37 @@ fn emit_string_op(ctx: &mut Ctx,
     Ok(())
 }
 
+/// `${var^^pat}` / `${var,pat}`: change the case of each character (or just
+/// the first) that matches `pat`, testing one character at a time.
+fn emit_case_pattern(
+    ctx: &mut Ctx,
+    all: bool,
+    param: &Param<'_>,
+    pattern: &Word<'_>,
+    conv: &str,
+    out: &mut String,
+) -> Res<()> {
+    out.push_str(if all {
+        "(for __reef_c in (string split '' -- \""
+    } else {
+        "(for __reef_c in (string sub -l 1 -- \""
+    });
+    emit_param(param, out);
+    out.push_str("\"); if string match -rq -- '^(?:");
+    emit_word_as_pattern(ctx, pattern, out, true)?;
+    out.push_str(")$' $__reef_c; string ");
+    out.push_str(conv);
+    out.push_str(" -- $__reef_c; else; echo $__reef_c; end; end");
+    if all {
+        out.push_str(" | string join '')");
+    } else {
+        out.push_str(")(string sub -s 2 -- \"");
+        emit_param(param, out);
+        out.push_str("\")");
+    }
+    Ok(())
+}
+
 /// Emit `${var/pat/rep}` family using fish `string replace`.
 // Each parameter corresponds to a distinct semantic role (mode flags,
 // param, pattern, replacement, output, context, quoting) — collapsing
@@ -2219,6 +4333,12 @@ fn emit_string_replace(ctx: &mut Ctx,
     suffix: bool,
     out: &mut String,
 ) -> Res<()> {
+    if pattern.is_some_and(word_has_extglob_text) {
+        return Err(unsupported(
+            UnsupportedKind::ExtGlob,
+            "extended glob in a ${var/pattern/replacement} pattern",
+        ));
+    }
     let needs_regex = prefix || suffix || pattern.is_some_and(word_has_glob);
 
     out.push_str("(string replace ");
@@ -2256,6 +4376,10 @@ fn emit_string_replace(ctx: &mut Ctx,
 /// Emit a word as a regex pattern (basic glob→regex conversion).
 /// Uses lookahead to correctly convert non-greedy `*` by examining
 /// the character that follows the glob star.
+///
+/// The output goes inside a fish single-quoted string: quotes and
+/// backslashes are escaped for it, and a variable closes the quote to
+/// splice in its value, regex-escaped.
 fn emit_word_as_pattern(ctx: &mut Ctx, 
     word: &Word<'_>,
     out: &mut String,
@@ -2271,21 +4395,39 @@ fn emit_word_as_pattern(ctx: &mut Ctx,
             }
         }
     }
+    emit_pattern_pieces(ctx, &pieces, out, greedy)
+}
 
-    // Emit with lookahead
-    for piece in &pieces {
+fn emit_pattern_pieces(
+    ctx: &mut Ctx,
+    pieces: &[PatPiece<'_>],
+    out: &mut String,
+    greedy: bool,
+) -> Res<()> {
+    for (i, piece) in pieces.iter().enumerate() {
         match piece {
             PatPiece::Lit(s) => {
-                for &b in s.as_bytes() {
-                    match b {
-                        b'.' | b'+' | b'(' | b')' | b'{' | b'}' | b'|' | b'\\' | b'^' | b'$' => {
-                            out.push('\\');
-                            out.push(b as char);
-                        }
-                        _ => out.push(b as char),
+                let mut text: &str = s;
+                // `[!abc]` is the glob spelling of `[^abc]`
+                if i > 0
+                    && matches!(pieces[i - 1], PatPiece::Other(Atom::SquareOpen))
+                    && let Some(rest) = text.strip_prefix(['!', '^'])
+                {
+                    out.push('^');
+                    text = rest;
+                }
+                let mut negate = false;
+                for c in text.chars() {
+                    match c {
+                        '!' if negate => out.push('^'),
+                        '*' | '?' | '[' | ']' => out.push(c),
+                        _ => push_regex_char(c, out),
                     }
+                    negate = c == '[';
                 }
             }
+            // Quoted text matches itself, glob characters and all.
+            PatPiece::Quoted(s) => s.chars().for_each(|c| push_regex_char(c, out)),
             PatPiece::Star => {
                 if greedy {
                     out.push_str(".*");
@@ -2294,16 +4436,94 @@ fn emit_word_as_pattern(ctx: &mut Ctx,
                 }
             }
             PatPiece::Question => out.push('.'),
+            PatPiece::ExtGlob(kind, alternatives) => {
+                out.push_str("(?:");
+                if *kind == ExtGlobKind::Not {
+                    // No regex complement: refuse any prefix that the
+                    // excluded patterns plus the rest of the glob would match.
+                    out.push_str("(?!(?:");
+                    emit_pattern_alternatives(ctx, alternatives, out, greedy)?;
+                    out.push(')');
+                    emit_pattern_pieces(ctx, &pieces[i + 1..], out, greedy)?;
+                    out.push_str("$).*");
+                } else {
+                    emit_pattern_alternatives(ctx, alternatives, out, greedy)?;
+                }
+                out.push(')');
+                match kind {
+                    ExtGlobKind::ZeroOrOne => out.push('?'),
+                    ExtGlobKind::ZeroOrMore => out.push('*'),
+                    ExtGlobKind::OneOrMore => out.push('+'),
+                    _ => {}
+                }
+            }
+            PatPiece::Other(Atom::Param(param)) => {
+                out.push_str("'(string escape --style=regex -- \"");
+                emit_param(param, out);
+                out.push_str("\")'");
+            }
+            PatPiece::Other(Atom::Subst(_)) => {
+                return Err(unsupported(
+                    UnsupportedKind::ExtGlob,
+                    "substitution inside a pattern matched as a regex",
+                ));
+            }
+            // Patterns get no brace expansion: the braces match themselves
+            PatPiece::Other(Atom::BraceList(alternatives)) => {
+                out.push_str("\\{");
+                for (i, alt) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    emit_word_as_pattern(ctx, alt, out, greedy)?;
+                }
+                out.push_str("\\}");
+            }
+            PatPiece::Other(Atom::BraceRange { start, end, step }) => {
+                let step = step.as_ref().map_or(String::new(), |step| format!("..{step}"));
+                format!("{{{start}..{end}{step}}}").chars().for_each(|c| push_regex_char(c, out));
+            }
             PatPiece::Other(atom) => emit_atom(ctx, atom, out)?,
         }
     }
     Ok(())
 }
 
+fn emit_pattern_alternatives(
+    ctx: &mut Ctx,
+    alternatives: &[Word<'_>],
+    out: &mut String,
+    greedy: bool,
+) -> Res<()> {
+    for (i, alt) in alternatives.iter().enumerate() {
+        if i > 0 {
+            out.push('|');
+        }
+        emit_word_as_pattern(ctx, alt, out, greedy)?;
+    }
+    Ok(())
+}
+
+/// A regex-escaped character, inside fish single quotes.
+fn push_regex_char(c: char, out: &mut String) {
+    match c {
+        // `\\\\` in single quotes is the regex `\\`
+        '\\' => out.push_str("\\\\\\\\"),
+        '\'' => out.push_str("\\'"),
+        '.' | '+' | '(' | ')' | '{' | '}' | '|' | '^' | '$' | '*' | '?' | '[' | ']' => {
+            out.push('\\');
+            out.push(c);
+        }
+        _ => out.push(c),
+    }
+}
+
 enum PatPiece<'a> {
     Lit(&'a str),
+    Quoted(&'a str),
     Star,
     Question,
+    ExtGlob(ExtGlobKind, &'a [Word<'a>]),
     Other(&'a Atom<'a>),
 }
 
@@ -2311,15 +4531,19 @@ fn collect_pattern_pieces<'a>(part: &'a WordPart<'a>, pieces: &mut Vec<PatPiece<
     match part {
         WordPart::Bare(atom) => match atom {
             Atom::Lit(s) => pieces.push(PatPiece::Lit(s)),
+            Atom::Escaped(s) => pieces.push(PatPiece::Quoted(s)),
             Atom::Star => pieces.push(PatPiece::Star),
             Atom::Question => pieces.push(PatPiece::Question),
+            Atom::ExtGlob { kind, alternatives } => {
+                pieces.push(PatPiece::ExtGlob(*kind, alternatives));
+            }
             other => pieces.push(PatPiece::Other(other)),
         },
-        WordPart::SQuoted(s) => pieces.push(PatPiece::Lit(s)),
+        WordPart::SQuoted(s) => pieces.push(PatPiece::Quoted(s)),
         WordPart::DQuoted(atoms) => {
             for atom in atoms {
                 match atom {
-                    Atom::Lit(s) => pieces.push(PatPiece::Lit(s)),
+                    Atom::Lit(s) | Atom::Escaped(s) => pieces.push(PatPiece::Quoted(s)),
                     other => pieces.push(PatPiece::Other(other)),
                 }
             }
@@ -2327,16 +4551,175 @@ fn collect_pattern_pieces<'a>(part: &'a WordPart<'a>, pieces: &mut Vec<PatPiece<
     }
 }
 
+/// Options and pattern for `string match` testing a `case` or `[[ == ]]`
+/// pattern: a glob when it is only text and `*`/`?` wildcards, otherwise an
+/// anchored regex, so quoted text and variables match themselves.
+fn emit_match_pattern(ctx: &mut Ctx, pattern: &Word<'_>, out: &mut String) -> Res<()> {
+    if let Some(glob) = plain_glob(pattern) {
+        out.push_str("-q -- ");
+        if glob.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_./+=:,@%".contains(&b)) {
+            out.push_str(&glob);
+        } else {
+            push_fish_sq(out, &glob);
+        }
+        return Ok(());
+    }
+    out.push_str("-qr -- ");
+    emit_anchored_pattern(ctx, pattern, out)
+}
+
+/// A pattern as a `string match` glob, if it has no syntax that glob lacks
+/// (brackets, extglobs) and its quoted text has no wildcards. Braces stay
+/// literal text, which glob matches as itself.
+fn plain_glob(pattern: &Word<'_>) -> Option<String> {
+    let parts: &[WordPart<'_>] = match pattern {
+        Word::Simple(p) => std::slice::from_ref(p),
+        Word::Concat(parts) => parts,
+    };
+    let quoted = |s: &str, glob: &mut String| {
+        if s.contains(['*', '?', '[', '\\']) {
+            return None;
+        }
+        glob.push_str(s);
+        Some(())
+    };
+    let mut glob = String::with_capacity(16);
+    for part in parts {
+        match part {
+            WordPart::Bare(Atom::Lit(s)) if !s.contains(['[', ']', '\\']) => glob.push_str(s),
+            WordPart::Bare(Atom::Star) => glob.push('*'),
+            WordPart::Bare(Atom::Question) => glob.push('?'),
+            WordPart::Bare(Atom::Escaped(s)) | WordPart::SQuoted(s) => quoted(s, &mut glob)?,
+            WordPart::Bare(Atom::BraceList(alternatives)) => {
+                glob.push('{');
+                for (i, alt) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        glob.push(',');
+                    }
+                    glob.push_str(&plain_glob(alt)?);
+                }
+                glob.push('}');
+            }
+            WordPart::Bare(Atom::BraceRange { start, end, step }) => {
+                glob.push('{');
+                glob.push_str(start);
+                glob.push_str("..");
+                glob.push_str(end);
+                if let Some(step) = step {
+                    glob.push_str("..");
+                    glob.push_str(step);
+                }
+                glob.push('}');
+            }
+            WordPart::DQuoted(atoms) => {
+                for atom in atoms {
+                    match atom {
+                        Atom::Lit(s) | Atom::Escaped(s) => quoted(s, &mut glob)?,
+                        _ => return None,
+                    }
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(glob)
+}
+
+/// `^…$`-anchored regex for a glob word, single-quoted for fish.
+fn emit_anchored_pattern(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    out.push_str("'^");
+    emit_word_as_pattern(ctx, word, out, true)?;
+    out.push_str("$'");
+    Ok(())
+}
+
+/// Whether a word contains an unquoted extended glob.
+fn word_has_extglob(word: &Word<'_>) -> bool {
+    let is_ext = |p: &WordPart<'_>| matches!(p, WordPart::Bare(Atom::ExtGlob { .. }));
+    match word {
+        Word::Simple(p) => is_ext(p),
+        Word::Concat(parts) => parts.iter().any(is_ext),
+    }
+}
+
+/// Whether a word has extglob syntax left as literal text, as in the
+/// pattern of `${var/pat/rep}`, which the parser doesn't split into globs.
+fn word_has_extglob_text(word: &Word<'_>) -> bool {
+    let parts: &[WordPart<'_>] = match word {
+        Word::Simple(p) => std::slice::from_ref(p),
+        Word::Concat(parts) => parts,
+    };
+    let mut after_glob = false;
+    for part in parts {
+        if let WordPart::Bare(atom) = part {
+            match atom {
+                Atom::Lit(s)
+                    if (after_glob && s.starts_with('('))
+                        || ["?(", "*(", "+(", "@(", "!("].iter().any(|op| s.contains(op)) =>
+                {
+                    return true;
+                }
+                Atom::ExtGlob { .. } => return true,
+                _ => {}
+            }
+            after_glob = matches!(atom, Atom::Star | Atom::Question);
+        } else {
+            after_glob = false;
+        }
+    }
+    false
+}
+
+/// Extended glob in argument position → expand a plain glob (each extglob
+/// widened to `*`) and keep the names matching the pattern's regex. A lone
+/// `!(pat)` filters `*` with an inverted match instead. The glob is expanded
+/// by `for`, which (unlike a command argument) may match nothing.
+fn emit_extglob_expansion(ctx: &mut Ctx, word: &Word<'_>, out: &mut String) -> Res<()> {
+    if let Word::Simple(WordPart::Bare(Atom::ExtGlob {
+        kind: ExtGlobKind::Not,
+        alternatives,
+    })) = word
+    {
+        out.push_str("(for __reef_f in *; string match -rv -- '^(?:");
+        emit_pattern_alternatives(ctx, alternatives, out, true)?;
+        out.push_str(")$' $__reef_f; end)");
+        return Ok(());
+    }
+    let mut re = String::with_capacity(32);
+    emit_anchored_pattern(ctx, word, &mut re)?;
+    out.push_str("(for __reef_f in ");
+    let parts: &[WordPart<'_>] = match word {
+        Word::Simple(p) => std::slice::from_ref(p),
+        Word::Concat(parts) => parts,
+    };
+    // Adjacent stars would turn into fish's recursive `**`
+    let mut prev_star = false;
+    for part in parts {
+        if matches!(part, WordPart::Bare(Atom::Star | Atom::ExtGlob { .. })) {
+            if !prev_star {
+                out.push('*');
+            }
+            prev_star = true;
+        } else {
+            emit_word_part(ctx, part, out)?;
+            prev_star = false;
+        }
+    }
+    out.push_str("; string match -r -- ");
+    out.push_str(&re);
+    out.push_str(" $__reef_f; end)");
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Arithmetic
 // ---------------------------------------------------------------------------
 
 /// Emit standalone `(( expr ))` as a fish assignment.
 fn emit_standalone_arith(ctx: &mut Ctx, arith: &Arith<'_>, out: &mut String) -> Res<()> {
-    let set_kw = if ctx.in_subshell { "set -l " } else { "set " };
     match arith {
         Arith::PostInc(var) | Arith::PreInc(var) => {
-            out.push_str(set_kw);
+            out.push_str(set_for(ctx, var, false));
             out.push_str(var);
             out.push_str(" (math \"$");
             out.push_str(var);
@@ -2344,7 +4727,7 @@ fn emit_standalone_arith(ctx: &mut Ctx, arith: &Arith<'_>, out: &mut String) ->
             Ok(())
         }
         Arith::PostDec(var) | Arith::PreDec(var) => {
-            out.push_str(set_kw);
+            out.push_str(set_for(ctx, var, false));
             out.push_str(var);
             out.push_str(" (math \"$");
             out.push_str(var);
@@ -2352,13 +4735,56 @@ fn emit_standalone_arith(ctx: &mut Ctx, arith: &Arith<'_>, out: &mut String) ->
             Ok(())
         }
         Arith::Assign(var, expr) => {
-            out.push_str(set_kw);
+            out.push_str(set_for(ctx, var, false));
             out.push_str(var);
             out.push_str(" (math \"");
             emit_arith(expr, out);
             out.push_str("\")");
             Ok(())
         }
+        Arith::ElemPostInc(name, index)
+        | Arith::ElemPreInc(name, index)
+        | Arith::ElemPostDec(name, index)
+        | Arith::ElemPreDec(name, index)
+        | Arith::ElemAssign(name, index, _) => {
+            if ctx.assoc.contains(name.as_ref()) {
+                return Err(unsupported(
+                    UnsupportedKind::Arithmetic,
+                    "arithmetic assignment to an associative array element",
+                ));
+            }
+            out.push_str(set_for(ctx, name, false));
+            out.push_str(name);
+            if name.starts_with('$') {
+                // Glued on as text, like `ref[i]=v`: set -g $ref[1]'['2']' ...
+                let mut idx = String::new();
+                emit_arith_index(index, &mut idx);
+                out.push_str("'['");
+                out.push_str(&idx[1..idx.len() - 1]);
+                out.push_str("']'");
+            } else {
+                emit_arith_index(index, out);
+            }
+            out.push_str(" (math \"");
+            match arith {
+                Arith::ElemAssign(.., expr) => emit_arith(expr, out),
+                Arith::ElemPostInc(..) | Arith::ElemPreInc(..) => {
+                    emit_arith_elem_value(name, index, out);
+                    out.push_str(" + 1");
+                }
+                _ => {
+                    emit_arith_elem_value(name, index, out);
+                    out.push_str(" - 1");
+                }
+            }
+            out.push_str("\")");
+            Ok(())
+        }
+        Arith::Comma(l, r) => {
+            emit_standalone_arith(ctx, l, out)?;
+            out.push('\n');
+            emit_standalone_arith(ctx, r, out)
+        }
         Arith::Lt(..)
         | Arith::Le(..)
         | Arith::Gt(..)
@@ -2369,7 +4795,8 @@ fn emit_standalone_arith(ctx: &mut Ctx, arith: &Arith<'_>, out: &mut String) ->
         | Arith::LogOr(..)
         | Arith::LogNot(..) => emit_arith_condition(arith, out),
 
-        _ => Err(TranslateError::Unsupported(
+        _ => Err(unsupported(
+            UnsupportedKind::Arithmetic,
             "unsupported standalone arithmetic expression",
         )),
     }
@@ -2388,6 +4815,7 @@ fn emit_arith(arith: &Arith<'_>, out: &mut String) {
                 out.push_str(name);
             }
         }
+        Arith::Elem(name, index) => emit_arith_elem_value(name, index, out),
         Arith::Lit(n) => {
             itoa(out, *n);
         }
@@ -2483,6 +4911,16 @@ fn emit_arith(arith: &Arith<'_>, out: &mut String) {
             out.push_str(var);
             out.push_str(" - 1)");
         }
+        Arith::ElemPostInc(name, index) | Arith::ElemPreInc(name, index) => {
+            out.push('(');
+            emit_arith_elem_value(name, index, out);
+            out.push_str(" + 1)");
+        }
+        Arith::ElemPostDec(name, index) | Arith::ElemPreDec(name, index) => {
+            out.push('(');
+            emit_arith_elem_value(name, index, out);
+            out.push_str(" - 1)");
+        }
 
         Arith::Ternary(cond, then_val, else_val) => {
             out.push('(');
@@ -2499,6 +4937,61 @@ fn emit_arith(arith: &Arith<'_>, out: &mut String) {
             out.push_str(" = ");
             emit_arith(expr, out);
         }
+        Arith::ElemAssign(name, index, expr) => {
+            out.push_str(name);
+            emit_arith_index(index, out);
+            out.push_str(" = ");
+            emit_arith(expr, out);
+        }
+
+        // The left side has no side effects here (see arith_has_unsupported)
+        Arith::Comma(_, r) => emit_arith(r, out),
+    }
+}
+
+/// `arr[index]` inside a `math` string. A computed index needs its own
+/// `math`, outside the quotes.
+fn emit_arith_elem_value(name: &str, index: &Arith<'_>, out: &mut String) {
+    let computed = arith_const_index(index).is_none();
+    if computed {
+        out.push('"');
+    }
+    out.push('$');
+    out.push_str(name);
+    emit_arith_index(index, out);
+    if computed {
+        out.push('"');
+    }
+}
+
+/// `[index]` → `[n]`, shifting bash's 0-based index to fish's 1-based
+/// one. Negative indices count from the end in both shells, so a computed
+/// index only shifts when it comes out non-negative: `min(0, max(-1, i))`
+/// takes the one back off for a negative `i`.
+fn emit_arith_index(index: &Arith<'_>, out: &mut String) {
+    out.push('[');
+    match arith_const_index(index) {
+        Some(n) if n < 0 => itoa(out, n),
+        Some(n) => itoa(out, n + 1),
+        None => {
+            out.push_str("(math \"");
+            emit_arith(index, out);
+            out.push_str(" + 1 + min(0, max(-1, ");
+            emit_arith(index, out);
+            out.push_str("))\")");
+        }
+    }
+    out.push(']');
+}
+
+fn arith_const_index(index: &Arith<'_>) -> Option<i64> {
+    match index {
+        Arith::Lit(n) => Some(*n),
+        Arith::Neg(e) => match **e {
+            Arith::Lit(n) => Some(-n),
+            _ => None,
+        },
+        _ => None,
     }
 }
 
@@ -2552,47 +5045,35 @@ fn is_arith_binop(arith: &Arith<'_>) -> bool {
 
 /// Check if an arithmetic expression contains operations that fish math can't handle.
 fn arith_has_unsupported(arith: &Arith<'_>) -> bool {
-    match arith {
-        Arith::PostInc(..)
-        | Arith::PreInc(..)
-        | Arith::PostDec(..)
-        | Arith::PreDec(..)
-        | Arith::Assign(..) => true,
-
-        Arith::Add(l, r)
-        | Arith::Sub(l, r)
-        | Arith::Mul(l, r)
-        | Arith::Div(l, r)
-        | Arith::Rem(l, r)
-        | Arith::Pow(l, r)
-        | Arith::Lt(l, r)
-        | Arith::Le(l, r)
-        | Arith::Gt(l, r)
-        | Arith::Ge(l, r)
-        | Arith::Eq(l, r)
-        | Arith::Ne(l, r)
-        | Arith::LogAnd(l, r)
-        | Arith::LogOr(l, r)
-        | Arith::BitAnd(l, r)
-        | Arith::BitOr(l, r)
-        | Arith::BitXor(l, r)
-        | Arith::Shl(l, r)
-        | Arith::Shr(l, r) => arith_has_unsupported(l) || arith_has_unsupported(r),
-
-        Arith::Pos(e) | Arith::Neg(e) | Arith::LogNot(e) | Arith::BitNot(e) => {
-            arith_has_unsupported(e)
-        }
-
-        Arith::Ternary(c, t, f) => {
-            arith_has_unsupported(c) || arith_has_unsupported(t) || arith_has_unsupported(f)
+    /// Finds increments, decrements and assignments anywhere in the tree.
+    struct Mutates(bool);
+    impl Visit<'_> for Mutates {
+        fn visit_arith(&mut self, arith: &Arith<'_>) {
+            match arith {
+                Arith::PostInc(..)
+                | Arith::PreInc(..)
+                | Arith::PostDec(..)
+                | Arith::PreDec(..)
+                | Arith::Assign(..)
+                | Arith::ElemPostInc(..)
+                | Arith::ElemPreInc(..)
+                | Arith::ElemPostDec(..)
+                | Arith::ElemPreDec(..)
+                | Arith::ElemAssign(..) => self.0 = true,
+                _ => walk_arith(self, arith),
+            }
         }
-
-        Arith::Var(_) | Arith::Lit(_) => false,
     }
+    let mut v = Mutates(false);
+    v.visit_arith(arith);
+    v.0
 }
 
 /// Check if an arithmetic expression requires test-based evaluation.
 fn arith_needs_test(arith: &Arith<'_>) -> bool {
+    if let Arith::Comma(_, r) = arith {
+        return arith_needs_test(r);
+    }
     matches!(
         arith,
         Arith::Lt(..)
@@ -2647,6 +5128,15 @@ fn emit_arith_condition(arith: &Arith<'_>, out: &mut String) -> Res<()> {
             out.push_str("not ");
             emit_arith_condition(e, out)
         }
+        Arith::Comma(l, r) => {
+            if arith_has_unsupported(l) {
+                return Err(unsupported(
+                    UnsupportedKind::Arithmetic,
+                    "side effects before ',' in a condition",
+                ));
+            }
+            emit_arith_condition(r, out)
+        }
         _ => {
             out.push_str("test ");
             emit_arith_value(arith, out)?;
@@ -2677,6 +5167,12 @@ fn emit_arith_value(arith: &Arith<'_>, out: &mut String) -> Res<()> {
             out.push_str(name);
             Ok(())
         }
+        Arith::Elem(name, index) => {
+            out.push('$');
+            out.push_str(name);
+            emit_arith_index(index, out);
+            Ok(())
+        }
         Arith::Lit(n) => {
             itoa(out, *n);
             Ok(())
@@ -2704,6 +5200,10 @@ fn emit_redirects(ctx: &mut Ctx, redirects: &[&Redir<'_>], out: &mut String) ->
 }
 
 fn emit_redir(ctx: &mut Ctx, redir: &Redir<'_>, out: &mut String) -> Res<()> {
+    emit_redir_unlocated(ctx, redir, out).map_err(|e| e.at_node(ctx.spans.redir(redir)))
+}
+
+fn emit_redir_unlocated(ctx: &mut Ctx, redir: &Redir<'_>, out: &mut String) -> Res<()> {
     fn write_fd(fd: Option<u16>, out: &mut String) {
         if let Some(n) = fd {
             itoa(out, i64::from(n));
@@ -2746,9 +5246,17 @@ fn emit_redir(ctx: &mut Ctx, redir: &Redir<'_>, out: &mut String) -> Res<()> {
             out.push_str(">&");
             emit_word(ctx, word, out)?;
         }
-        Redir::HereString(_) | Redir::Heredoc(_) => {
+        Redir::HereString(_) | Redir::Heredoc(None | Some(0), _) => {
             // Handled at a higher level (emit_simple / emit_compound)
         }
+        Redir::Heredoc(fd, doc) => {
+            // Another fd reads the body from a temp file. The space matters:
+            // fish rejects `<(` as bash process substitution.
+            write_fd(*fd, out);
+            out.push_str("< (");
+            emit_heredoc_body(ctx, doc, out)?;
+            out.push_str(" | psub -f)");
+        }
         Redir::WriteAll(word) => {
             out.push('>');
             emit_word(ctx, word, out)?;
@@ -2792,7 +5300,7 @@ fn extract_brace_range_count(args: &[&Word<'_>]) -> Option<i64> {
         if let Word::Simple(WordPart::Bare(Atom::BraceRange { start, end, step })) = arg {
             let s: i64 = start.parse().ok()?;
             let e: i64 = end.parse().ok()?;
-            let st: i64 = step.and_then(|s| s.parse().ok()).unwrap_or(1);
+            let st: i64 = step.as_ref().and_then(|s| s.parse().ok()).unwrap_or(1);
             if st == 0 {
                 return None;
             }
@@ -2823,6 +5331,55 @@ fn word_as_str<'a>(word: &'a Word<'a>) -> Option<Cow<'a, str>> {
     }
 }
 
+/// The substitution making up all of `word`, quoted or not: `$(cmd)`,
+/// `"$((n))"`, ...
+fn lone_subst<'a>(word: &'a Word<'a>) -> Option<&'a Subst<'a>> {
+    match word {
+        Word::Simple(WordPart::DQuoted(atoms)) => match atoms.as_slice() {
+            [Atom::Subst(s)] => Some(s),
+            _ => None,
+        },
+        Word::Simple(WordPart::Bare(Atom::Subst(s))) => Some(s),
+        _ => None,
+    }
+}
+
+/// The executable of a command that is neither a job, a pipe nor an
+/// and-or chain.
+fn lone_exec<'a>(cmd: &'a Cmd<'a>) -> Option<&'a Executable<'a>> {
+    match cmd {
+        Cmd::List(AndOrList {
+            first: Pipeline::Single(exec),
+            rest,
+            ..
+        }) if rest.is_empty() => Some(exec),
+        _ => None,
+    }
+}
+
+/// The words of a simple command with no assignments or redirections.
+fn plain_words<'a>(cmd: &'a SimpleCmd<'a>) -> Option<Vec<&'a Word<'a>>> {
+    if !cmd.prefix.is_empty() {
+        return None;
+    }
+    cmd.suffix
+        .iter()
+        .map(|item| match item {
+            CmdSuffix::Word(w) => Some(w),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether `s` is a valid shell variable name.
+fn is_valid_var_name(s: &str) -> bool {
+    let mut bytes = s.bytes();
+    bytes
+        .next()
+        .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
 #[inline]
 fn word_has_glob(word: &Word<'_>) -> bool {
     match word {
@@ -2876,11 +5433,7 @@ fn part_to_string(part: &WordPart<'_>, out: &mut String) -> bool {
 
 fn atom_to_string(atom: &Atom<'_>, out: &mut String) -> bool {
     match atom {
-        Atom::Lit(s) => {
-            out.push_str(s);
-            true
-        }
-        Atom::Escaped(s) => {
+        Atom::Lit(s) | Atom::Escaped(s) => {
             out.push_str(s);
             true
         }
@@ -2948,23 +5501,50 @@ fn push_sq_escaped(out: &mut String, s: &str) {
     out.push('\'');
 }
 
-/// Emit a heredoc body. Literal bodies use single quotes, interpolated bodies
-/// use double quotes with variable/command expansion.
-fn emit_heredoc_body(ctx: &mut Ctx, body: &HeredocBody<'_>, out: &mut String) -> Res<()> {
-    match body {
+/// Push `s` into `out` as a fish single-quoted string: unlike
+/// [`push_sq_escaped`], backslashes are escaped too, so fish reads back
+/// exactly `s` (e.g. a script for `fish -c`).
+fn push_fish_sq(out: &mut String, s: &str) {
+    out.push('\'');
+    for c in s.chars() {
+        if matches!(c, '\'' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('\'');
+}
+
+/// Emit a heredoc body as a `printf` command. Literal bodies use single
+/// quotes, interpolated bodies use double quotes with variable/command
+/// expansion. `<<-` strips leading tabs from every line.
+fn emit_heredoc_body(ctx: &mut Ctx, doc: &Heredoc<'_>, out: &mut String) -> Res<()> {
+    match &doc.body {
         HeredocBody::Literal(text) => {
             out.push_str("printf '%s\\n' ");
-            push_sq_escaped(out, text.strip_suffix('\n').unwrap_or(text));
+            let text = text.strip_suffix('\n').unwrap_or(text);
+            if doc.strip_tabs {
+                let stripped: Vec<&str> =
+                    text.split('\n').map(|line| line.trim_start_matches('\t')).collect();
+                push_sq_escaped(out, &stripped.join("\n"));
+            } else {
+                push_sq_escaped(out, text);
+            }
             Ok(())
         }
         HeredocBody::Interpolated(atoms) => {
             // Build the body content with literal newlines (fish double quotes
             // support embedded newlines), then strip the trailing newline.
             let mut body_str = String::with_capacity(256);
+            let mut line_start = true;
             for atom in atoms {
                 match atom {
                     Atom::Lit(s) => {
                         for &b in s.as_bytes() {
+                            if doc.strip_tabs && line_start && b == b'\t' {
+                                continue;
+                            }
+                            line_start = b == b'\n';
                             match b {
                                 b'"' => body_str.push_str("\\\""),
                                 b'\\' => body_str.push_str("\\\\"),
@@ -2972,6 +5552,7 @@ fn emit_heredoc_body(ctx: &mut Ctx, body: &HeredocBody<'_>, out: &mut String) ->
                                 _ => body_str.push(b as char),
                             }
                         }
+                        continue;
                     }
                     Atom::Escaped(s) => {
                         // \$ → literal $, \\ → literal \, \` → literal `
@@ -2990,6 +5571,7 @@ fn emit_heredoc_body(ctx: &mut Ctx, body: &HeredocBody<'_>, out: &mut String) ->
                     }
                     _ => emit_atom(ctx, atom, &mut body_str)?,
                 }
+                line_start = false;
             }
             // Strip trailing newline (the one before the delimiter line)
             let trimmed = body_str.strip_suffix('\n').unwrap_or(&body_str);
@@ -3003,9 +5585,11 @@ fn emit_heredoc_body(ctx: &mut Ctx, body: &HeredocBody<'_>, out: &mut String) ->
 
 fn emit_param_name(param: &Param<'_>, out: &mut String) {
     match param {
-        Param::Var("HOSTNAME") => out.push_str("hostname"),
-        Param::Var("PIPESTATUS") => out.push_str("pipestatus"),
-        Param::Var(name) => out.push_str(name),
+        Param::Var(name) => out.push_str(match name.as_ref() {
+            "HOSTNAME" => "hostname",
+            "PIPESTATUS" => "pipestatus",
+            name => name,
+        }),
         Param::Positional(n) => {
             out.push_str("argv[");
             itoa(out, i64::from(*n));
@@ -3025,6 +5609,7 @@ fn emit_param_name(param: &Param<'_>, out: &mut String) {
 // ---------------------------------------------------------------------------
 
 #[cfg(test)]
+#[allow(clippy::uninlined_format_args)] // older tests use `"got: {}", result`
 mod tests {
     use super::*;
 
@@ -3232,6 +5817,21 @@ mod tests {
         assert_eq!(t("! grep -q pattern file"), "not grep -q pattern file");
     }
 
+    // --- time ---
+
+    #[test]
+    fn timed_pipeline() {
+        assert_eq!(t("time sort big | uniq -c"), "time sort big | uniq -c");
+        assert_eq!(t("time -p make && time ! false"), "time make; and time not false");
+        assert_eq!(t("time { a; b; }"), "time begin\na\nb\nend");
+    }
+
+    #[test]
+    fn timed_redirected_group_unsupported() {
+        t_unsupported("time { a; b; } 2>/dev/null");
+        t_unsupported("time x | while read l; do :; done > out");
+    }
+
     // --- Case ---
 
     #[test]
@@ -3723,7 +6323,7 @@ mod tests {
     fn double_bracket_test() {
         // [[ ]] is bash-specific — translate to test and strip ]]
         let result = t("[[ -n $HOME ]]");
-        assert!(result.contains("test -n $HOME"), "got: {}", result);
+        assert!(result.contains(r#"test -n "$HOME""#), "got: {}", result);
         assert!(!result.contains("[["));
         assert!(!result.contains("]]"));
     }
@@ -3750,6 +6350,19 @@ mod tests {
         assert!(result.contains("not string match -q"), "got: {}", result);
     }
 
+    #[test]
+    fn double_bracket_pattern_operands() {
+        let spliced = r#"(string escape --style=regex -- "$y")"#;
+        assert_eq!(t("[[ $x == $y ]]"), format!("string match -qr -- '^'{spliced}'$' $x"));
+        assert_eq!(t(r#"[[ $x == "$y" ]]"#), format!("string match -qr -- '^'{spliced}'$' $x"));
+        assert_eq!(
+            t("[[ $x != *$y* ]]"),
+            format!("not string match -qr -- '^.*'{spliced}'.*$' $x")
+        );
+        assert_eq!(t(r#"[[ $x == "a b"* ]]"#), "string match -q -- 'a b*' $x");
+        assert_eq!(t(r#"[[ $x == "a*"? ]]"#), r"string match -qr -- '^a\*.$' $x");
+    }
+
     #[test]
     fn double_bracket_and() {
         // [[ -f x && -r x ]] → test -f x; and test -r x
@@ -4233,6 +6846,45 @@ mod tests {
         assert!(result.contains("string sub -s 2"));
     }
 
+    #[test]
+    fn upper_all_matching() {
+        assert_eq!(
+            t("echo ${var^^[aeiou]}"),
+            concat!(
+                "echo (for __reef_c in (string split '' -- \"$var\"); ",
+                "if string match -rq -- '^(?:[aeiou])$' $__reef_c; string upper -- $__reef_c; ",
+                "else; echo $__reef_c; end; end | string join '')",
+            )
+        );
+    }
+
+    #[test]
+    fn lower_first_matching() {
+        assert_eq!(
+            t("echo ${var,[!X]}"),
+            concat!(
+                "echo (for __reef_c in (string sub -l 1 -- \"$var\"); ",
+                "if string match -rq -- '^(?:[^X])$' $__reef_c; string lower -- $__reef_c; ",
+                "else; echo $__reef_c; end; end)(string sub -s 2 -- \"$var\")",
+            )
+        );
+    }
+
+    #[test]
+    fn case_pattern_quoting() {
+        // A variable is spliced in between the single quotes, escaped
+        let result = t("echo ${var^^$p}");
+        assert!(
+            result.contains(r#"'^(?:'(string escape --style=regex -- "$p")')$'"#),
+            "got: {}",
+            result
+        );
+        // Quoted glob characters and quotes match themselves
+        let result = t(r#"echo ${var^^'*'} ${var,,"it's"}"#);
+        assert!(result.contains(r"'^(?:\*)$'"), "got: {}", result);
+        assert!(result.contains(r"'^(?:it\'s)$'"), "got: {}", result);
+    }
+
     // --- Pattern replacement ---
 
     #[test]
@@ -4302,9 +6954,85 @@ mod tests {
     }
 
     #[test]
-    fn process_substitution_out_unsupported() {
-        let result = translate_bash_to_fish("tee >(grep foo)");
-        assert!(result.is_err());
+    fn process_substitution_out() {
+        let result = t("tee >(gzip > log.gz) >(sha256sum) < data");
+        assert!(result.starts_with("set -l __reef_psub (mktemp -d)\n"), "got: {result}");
+        assert!(result.contains("\nmkfifo $__reef_psub/0 $__reef_psub/1\n"), "got: {result}");
+        assert!(
+            result.contains(
+                "\n__reef_fifo=$__reef_psub/0 fish -c 'begin\ngzip >log.gz\nend <$__reef_fifo' &\n"
+            ),
+            "got: {result}"
+        );
+        assert!(
+            result.contains("__reef_psub/1 fish -c 'begin\nsha256sum\nend <$__reef_fifo' &\n"),
+            "got: {result}"
+        );
+        assert!(result.contains("\ntee $__reef_psub/0 $__reef_psub/1 <data\n"), "got: {result}");
+        assert!(
+            result.ends_with(
+                "\nset -l __reef_rc $status\nwait $__reef_psub_pids\nrm -r $__reef_psub\n\
+                 test $__reef_rc = 0; or fish -c \"exit $__reef_rc\""
+            ),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn process_substitution_out_redirect_target() {
+        let result = t("make 2> >(tee err.log)");
+        assert!(result.contains("make 2>$__reef_psub/0"), "got: {result}");
+    }
+
+    #[test]
+    fn process_substitution_out_in_pipeline() {
+        let result = t("cat data | tee >(wc -l) | sort");
+        assert!(
+            result.contains("cat data | tee $__reef_psub/0 | sort\nset -l __reef_rc"),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn process_substitution_out_in_body() {
+        let result = t("for f in *.log; do tee >(wc -l) < $f; done");
+        assert!(result.starts_with("for f in *.log\nset -l __reef_psub"), "got: {result}");
+        assert!(result.ends_with("fish -c \"exit $__reef_rc\"\nend"), "got: {result}");
+    }
+
+    #[test]
+    fn process_substitution_out_reader_quoting() {
+        let result = t(r#"tee >(printf '%s\n' "it's") <f"#);
+        let script = "'begin\nprintf \\'%s\\\\n\\' \"it\\'s\"\nend <$__reef_fifo'";
+        assert!(result.contains(&format!("fish -c {script} &")), "got: {result}");
+    }
+
+    #[test]
+    fn process_substitution_out_reader_variables() {
+        let result = t(r#"pat=foo; tee >(grep "$pat" $1 >out) <f"#);
+        assert!(
+            result.contains(concat!(
+                "fish -c 'set pat'(string join ' ' -- '' (string escape -- $pat))'\n",
+                "set argv'(string join ' ' -- '' (string escape -- $argv))'\n",
+                "begin\ngrep \"$pat\" $argv[1] >out\nend <$__reef_fifo' &",
+            )),
+            "got: {result}"
+        );
+        assert!(!result.contains("function"), "got: {result}");
+    }
+
+    #[test]
+    fn process_substitution_out_and_or_unsupported() {
+        t_unsupported("make > >(tee log) && echo ok");
+        t_unsupported("tee >(wc -l) < f &");
+    }
+
+    #[test]
+    fn process_substitution_out_condition_unsupported() {
+        t_unsupported("if grep foo f > >(wc -l); then echo found; fi");
+        t_unsupported("while tee >(wc -l) <f; do :; done");
+        t_unsupported("x=$(tee >(wc -l) <f)");
+        t_unsupported("echo $(for f in a; do tee >(wc -l) <$f; done)");
     }
 
     // --- C-style for ---
@@ -4352,18 +7080,167 @@ mod tests {
         assert!(result.contains("| cat"), "got: {}", result);
     }
 
-    // --- Case fallthrough errors ---
+    // --- Case fallthrough ---
+
+    #[test]
+    fn case_fallthrough() {
+        let result = t("case $x in a) echo a;& b) echo b;; esac");
+        assert!(!result.contains("switch"), "got: {result}");
+        assert!(result.starts_with("set -l __reef_case_word $x\nset -l __reef_case test\n"));
+        assert!(
+            result.contains("test $__reef_case = test; and string match -q -- a $__reef_case_word"),
+            "got: {result}"
+        );
+        assert!(
+            result.contains("if test $__reef_case = run\necho a\nset __reef_case run\nend"),
+            "got: {result}"
+        );
+        assert!(result.contains("echo b\nset __reef_case stop\nend"), "got: {result}");
+    }
+
+    #[test]
+    fn case_continue() {
+        let result = t("case $x in a*) echo a;;& *b) echo b;; esac");
+        assert!(result.contains("string match -q -- 'a*' $__reef_case_word"), "got: {result}");
+        assert!(result.contains("echo a\nset __reef_case test\nend"), "got: {result}");
+        assert!(result.contains("string match -q -- '*b' $__reef_case_word"), "got: {result}");
+    }
+
+    #[test]
+    fn case_fallthrough_multiple_patterns() {
+        let result = t("case $1 in -h|--help) usage;& *) exit;; esac");
+        assert!(result.contains("string match -q -- -h $__reef_case_word"), "got: {result}");
+        assert!(result.contains("string match -q -- --help $__reef_case_word"), "got: {result}");
+    }
+
+    #[test]
+    fn case_fallthrough_bracket_pattern() {
+        let result = t("case $c in [ab]) echo ab;& c) echo c;; esac");
+        assert!(result.contains("contains -- $__reef_case_word 'a' 'b'"), "got: {result}");
+    }
+
+    #[test]
+    fn case_fallthrough_quoted_and_variable_patterns() {
+        let result = t(r#"case $x in "a*") a;;& "$y"*) b;;& "a b"*) c;; esac"#);
+        assert!(result.contains(r"string match -qr -- '^a\*$' $__reef_case_word"), "got: {result}");
+        assert!(
+            result.contains(concat!(
+                r#"string match -qr -- '^'(string escape --style=regex -- "$y")'.*$'"#,
+                " $__reef_case_word",
+            )),
+            "got: {result}"
+        );
+        assert!(result.contains("string match -q -- 'a b*' $__reef_case_word"), "got: {result}");
+    }
+
+    #[test]
+    fn case_trailing_fallthrough_uses_switch() {
+        let result = t("case $x in a) echo a;; b) echo b;& esac");
+        assert!(result.contains("switch $x"), "got: {result}");
+    }
+
+    // --- Extended globs ---
+
+    #[test]
+    fn extglob_in_assignment_is_literal() {
+        assert_eq!(t("x=!(a)"), r"set x !\(a\)");
+        assert_eq!(t("y=a@(b|c*)d"), r"set y a@\(b\|c\*\)d");
+        assert_eq!(t("local z=+(q)"), r"set -l z +\(q\)");
+        assert_eq!(t("w=*.c"), r"set w \*.c");
+    }
+
+    #[test]
+    fn extglob_not_argument() {
+        assert_eq!(
+            t("ls !(*.tmp)"),
+            "ls (for __reef_f in *; string match -rv -- '^(?:.*\\.tmp)$' $__reef_f; end)"
+        );
+    }
+
+    #[test]
+    fn extglob_argument_filters_glob() {
+        assert_eq!(
+            t("ls *.@(jpg|png)"),
+            "ls (for __reef_f in *.*; string match -r -- '^.*\\.(?:jpg|png)$' $__reef_f; end)"
+        );
+    }
+
+    #[test]
+    fn extglob_argument_no_double_star() {
+        let result = t("echo @(a|b)*");
+        assert!(result.starts_with("echo (for __reef_f in *; "), "got: {result}");
+    }
+
+    #[test]
+    fn extglob_not_with_suffix() {
+        let result = t("cp !(foo|bar).c /tmp");
+        assert!(
+            result.contains("*.c; string match -r -- '^(?:(?!(?:foo|bar)\\.c$).*)\\.c$' $__reef_f"),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn extglob_case() {
+        let result = t("case $x in +([0-9])) echo num;; *) echo other;; esac");
+        assert!(!result.contains("switch"), "got: {result}");
+        assert!(
+            result.contains("string match -qr -- '^(?:[0-9])+$' $__reef_case_word"),
+            "got: {result}"
+        );
+        assert!(result.contains("string match -q -- '*' $__reef_case_word"), "got: {result}");
+    }
+
+    #[test]
+    fn extglob_case_negated_class() {
+        let result = t("case $x in +([!0-9])) echo word;; esac");
+        assert!(result.contains("'^(?:[^0-9])+$'"), "got: {result}");
+    }
+
+    #[test]
+    fn extglob_double_bracket() {
+        assert_eq!(
+            t("[[ $v == ?(-)+([0-9]) ]]"),
+            "string match -qr -- '^(?:-)?(?:[0-9])+$' $v"
+        );
+        assert_eq!(
+            t("[[ $f != *.@(gz|xz) ]]"),
+            "not string match -qr -- '^.*\\.(?:gz|xz)$' $f"
+        );
+    }
+
+    #[test]
+    fn extglob_splices_variables() {
+        assert_eq!(
+            t("[[ $x == +($p) ]]"),
+            "string match -qr -- '^(?:'(string escape --style=regex -- \"$p\")')+$' $x"
+        );
+        let result = t("case $x in @($a|b)) echo y;; esac");
+        assert!(
+            result.contains("'^(?:'(string escape --style=regex -- \"$a\")'|b)$'"),
+            "got: {result}"
+        );
+        t_unsupported("[[ $x == +($(cmd)) ]]");
+    }
 
     #[test]
-    fn case_fallthrough_error() {
-        let result = translate_bash_to_fish("case $x in a) echo a;& b) echo b;; esac");
-        assert!(result.is_err());
+    fn extglob_quotes_in_alternatives() {
+        assert_eq!(
+            t(r#"[[ $x == @("it's"|'a\b') ]]"#),
+            r"string match -qr -- '^(?:it\'s|a\\\\b)$' $x"
+        );
+    }
+
+    #[test]
+    fn extglob_in_replace_pattern_unsupported() {
+        t_unsupported("echo ${x//+([0-9])/N}");
+        t_unsupported("echo ${x/*(a|b)}");
     }
 
     #[test]
-    fn case_continue_error() {
-        let result = translate_bash_to_fish("case $x in a) echo a;;& b) echo b;; esac");
-        assert!(result.is_err());
+    fn extglob_regex_operand_untouched() {
+        let result = t("[[ $x =~ ^a+(b)$ ]]");
+        assert!(result.contains("'^a+(b)$'"), "got: {result}");
     }
 
     // --- Arrays ---
@@ -4404,50 +7281,284 @@ mod tests {
         assert!(result.contains("$arr["), "got: {}", result);
     }
 
+    // --- Associative arrays ---
+
+    #[test]
+    fn assoc_declare_empty() {
+        assert_eq!(t("declare -A m"), "set -g __reef_keys_m\nset -g m");
+    }
+
+    #[test]
+    fn assoc_declare_literal() {
+        assert_eq!(
+            t(r#"declare -A m=([a]=1 ["b c"]="x y" [e]= [a]=2)"#),
+            "set -g __reef_keys_m a 'b c' e\nset -g m 2 \"x y\" ''"
+        );
+        // A runtime key may repeat any other one, so it is stored in order
+        assert_eq!(
+            t("declare -A m=([a]=1 [$k]=$v)"),
+            "set -g __reef_keys_m a\nset -g m 1\n\
+             if contains -- \"$k\" $__reef_keys_m\n\
+             set m[(contains -i -- \"$k\" $__reef_keys_m)] \"$v\"\n\
+             else\n\
+             set -a __reef_keys_m \"$k\"\n\
+             set -a m \"$v\"\n\
+             end"
+        );
+    }
+
+    #[test]
+    fn assoc_declare_alternating_pairs() {
+        assert_eq!(
+            t("declare -A m=(k1 v1 k2)"),
+            "set -g __reef_keys_m k1 k2\nset -g m v1 ''"
+        );
+    }
+
+    #[test]
+    fn assoc_local() {
+        assert_eq!(t("local -A m=([x]=1)"), "set -l __reef_keys_m x\nset -l m 1");
+    }
+
+    #[test]
+    fn assoc_element_assign() {
+        let result = t("declare -A m; m[$k]=v");
+        assert!(
+            result.ends_with(
+                "if contains -- \"$k\" $__reef_keys_m\n\
+                 set m[(contains -i -- \"$k\" $__reef_keys_m)] v\n\
+                 else\n\
+                 set -a __reef_keys_m \"$k\"\n\
+                 set -a m v\n\
+                 end"
+            ),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn assoc_append() {
+        let result = t("declare -A m; m+=([a]=1 [b]=2)");
+        assert!(result.contains("set -a __reef_keys_m a\n"), "got: {result}");
+        assert!(result.contains("set -a __reef_keys_m b\n"), "got: {result}");
+    }
+
+    #[test]
+    fn assoc_element_append() {
+        let result = t("declare -A m; m[$k]+=x");
+        assert!(
+            result.ends_with(
+                "if contains -- \"$k\" $__reef_keys_m\n\
+                 set m[(contains -i -- \"$k\" $__reef_keys_m)] \
+                 $m[(contains -i -- \"$k\" $__reef_keys_m)]x\n\
+                 else\n\
+                 set -a __reef_keys_m \"$k\"\n\
+                 set -a m x\n\
+                 end"
+            ),
+            "got: {result}"
+        );
+        t_unsupported("declare -A m; m[a]+=x echo");
+    }
+
+    #[test]
+    fn assoc_element_test() {
+        let result = t("declare -A m; [[ -v m[k] ]]; [[ ! -v m[$k] ]]");
+        assert!(result.ends_with(
+            "contains -- k $__reef_keys_m\nnot contains -- \"$k\" $__reef_keys_m"),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn assoc_key_with_space() {
+        let result = t("declare -A m; echo ${m[a b]}");
+        assert!(
+            result.ends_with("echo $m[(contains -i -- 'a b' $__reef_keys_m)]"),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn assoc_element_access() {
+        let result = t("declare -A m; echo ${m[foo]}");
+        assert!(
+            result.ends_with("echo $m[(contains -i -- foo $__reef_keys_m)]"),
+            "got: {result}"
+        );
+    }
+
+    #[test]
+    fn assoc_keys_values_length() {
+        let result =
+            t(r#"declare -A m; for k in "${!m[@]}"; do echo $k; done; echo ${m[@]} ${#m[@]}"#);
+        assert!(result.contains("for k in $__reef_keys_m\n"), "got: {result}");
+        assert!(result.ends_with("echo $m (count $m)"), "got: {result}");
+    }
+
+    #[test]
+    fn assoc_unset_element() {
+        let result = t("declare -A m; unset 'm[$k]'");
+        assert!(
+            result.ends_with(
+                "if contains -- \"$k\" $__reef_keys_m\n\
+                 set -e m[(contains -i -- \"$k\" $__reef_keys_m)]\n\
+                 set -e __reef_keys_m[(contains -i -- \"$k\" $__reef_keys_m)]\n\
+                 end"
+            ),
+            "got: {result}"
+        );
+        let result = t("declare -A m; unset m[foo]");
+        assert!(result.contains("if contains -- foo $__reef_keys_m\n"), "got: {result}");
+    }
+
+    #[test]
+    fn assoc_unset_whole() {
+        let result = t("declare -A m; unset m");
+        assert!(result.ends_with("set -e m\nset -e __reef_keys_m"), "got: {result}");
+    }
+
+    #[test]
+    fn indexed_keys() {
+        assert_eq!(t("echo ${!arr[@]}"), "echo (seq 0 (math (count $arr) - 1))");
+    }
+
+    #[test]
+    fn indexed_element_assign() {
+        assert_eq!(t("arr[2]=x"), "set arr[3] x");
+        assert_eq!(t("declare -a arr=(1 2)"), "set -g arr 1 2");
+    }
+
     // --- Trap ---
 
     #[test]
-    fn trap_exit() {
+    fn trap_exit() {
+        assert_eq!(
+            t("trap 'echo bye' EXIT"),
+            "function __reef_trap_EXIT --on-event fish_exit\necho bye\nend"
+        );
+    }
+
+    #[test]
+    fn trap_signal() {
+        assert_eq!(
+            t("trap 'cleanup' INT"),
+            "function __reef_trap_INT --on-signal INT\ncleanup\nend"
+        );
+    }
+
+    #[test]
+    fn trap_sigprefix() {
+        assert_eq!(
+            t("trap 'cleanup' SIGTERM"),
+            "function __reef_trap_TERM --on-signal TERM\ncleanup\nend"
+        );
+    }
+
+    #[test]
+    fn trap_reset() {
+        assert_eq!(t("trap - INT"), "functions -e __reef_trap_INT");
+    }
+
+    #[test]
+    fn trap_ignore() {
+        assert_eq!(
+            t("trap '' INT"),
+            "function __reef_trap_INT --on-signal INT; end"
+        );
+    }
+
+    #[test]
+    fn trap_multiple_signals() {
+        let result = t("trap 'cleanup' INT TERM");
+        assert!(result.contains("__reef_trap_INT --on-signal INT"));
+        assert!(result.contains("__reef_trap_TERM --on-signal TERM"));
+    }
+
+    #[test]
+    fn trap_reset_multiple() {
         assert_eq!(
-            t("trap 'echo bye' EXIT"),
-            "function __reef_trap_EXIT --on-event fish_exit\necho bye\nend"
+            t("trap - INT TERM"),
+            "functions -e __reef_trap_INT __reef_trap_TERM"
         );
     }
 
     #[test]
-    fn trap_signal() {
+    fn trap_err_checks_later_statements() {
         assert_eq!(
-            t("trap 'cleanup' INT"),
-            "function __reef_trap_INT --on-signal INT\ncleanup\nend"
+            t("ls; trap 'echo failed $?' ERR; false; x=$(false)"),
+            "ls\n\
+             function __reef_trap_ERR\nset -l __reef_rc $status\necho failed $status\n\
+             return $__reef_rc\nend\n\
+             false; or __reef_trap_ERR\n\
+             set x (false); or __reef_trap_ERR"
         );
     }
 
     #[test]
-    fn trap_sigprefix() {
+    fn trap_err_exempt_commands() {
+        let result = t("trap h ERR; ! grep -q x f; if false; then ls; fi; f() { false; }; a | b");
+        // The condition and the negated pipeline are exempt, the if body isn't
+        assert!(result.contains("\nnot grep -q x f\nif false\nls; or __reef_trap_ERR\nend\n"));
+        // Functions don't inherit the trap
+        assert!(result.contains("function f\nfalse\nend\na | b; or __reef_trap_ERR"));
+    }
+
+    #[test]
+    fn trap_err_only_checks_end_of_chain() {
         assert_eq!(
-            t("trap 'cleanup' SIGTERM"),
-            "function __reef_trap_TERM --on-signal TERM\ncleanup\nend"
+            t("trap h ERR\ncd /src && make || echo failed"),
+            "function __reef_trap_ERR\nset -l __reef_rc $status\nh\nreturn $__reef_rc\nend\n\
+             cd /src; and make; or begin\necho failed; or __reef_trap_ERR\nend"
         );
     }
 
     #[test]
-    fn trap_reset() {
-        assert_eq!(t("trap - INT"), "functions -e __reef_trap_INT");
+    fn trap_err_removed() {
+        let result = t("trap h ERR; trap - ERR; false");
+        assert!(result.ends_with("\nfunctions -e __reef_trap_ERR\nfalse"), "got: {result}");
     }
 
     #[test]
-    fn trap_ignore() {
+    fn trap_err_in_function_unsupported() {
+        t_unsupported("f() { trap h ERR; false; }");
+    }
+
+    #[test]
+    fn trap_debug_preexec() {
         assert_eq!(
-            t("trap '' INT"),
-            "function __reef_trap_INT --on-signal INT; end"
+            t("trap 'echo next' DEBUG; ls; (pwd)"),
+            "function __reef_trap_DEBUG --on-event fish_preexec\necho next\nend\n\
+             __reef_trap_DEBUG\nls\n\
+             __reef_trap_DEBUG\nbegin\nset -l __reef_pwd (pwd)\npwd\n\
+             set -l __reef_rc $status; cd $__reef_pwd 2>/dev/null\nend"
         );
+        t_unsupported("f() { trap 'echo next' DEBUG; }");
     }
 
     #[test]
-    fn trap_multiple_signals() {
-        let result = t("trap 'cleanup' INT TERM");
-        assert!(result.contains("__reef_trap_INT --on-signal INT"));
-        assert!(result.contains("__reef_trap_TERM --on-signal TERM"));
+    fn trap_return_inlined_before_returns() {
+        assert_eq!(
+            t("f() { local t=$(mktemp); trap 'rm -f \"$t\"' RETURN; \
+               [ -s \"$t\" ] || return 1; cat \"$t\"; }"),
+            "function f\nset -l t (mktemp)\n\
+             # trap RETURN: handler runs before each return below\n\
+             [ -s \"$t\" ]; or begin\nset -l __reef_rc 1\nrm -f \"$t\"\nreturn $__reef_rc\nend\n\
+             cat \"$t\"\n\
+             set -l __reef_rc $status\nrm -f \"$t\"\nreturn $__reef_rc\nend"
+        );
+    }
+
+    #[test]
+    fn trap_return_scoped_to_function() {
+        let result = t("f() { trap 'echo bye' RETURN; }; g() { return 2; }");
+        assert!(result.ends_with("function g\nreturn 2\nend"), "got: {result}");
+        let u = match translate_bash_to_fish("trap 'echo bye' RETURN") {
+            Err(TranslateError::Unsupported(u)) => u,
+            other => panic!("expected unsupported, got {other:?}"),
+        };
+        assert_eq!(u.kind(), UnsupportedKind::Trap);
+        assert!(u.detail().contains("outside a function"), "got: {u}");
     }
 
     // --- declare -p ---
@@ -4504,11 +7615,101 @@ mod tests {
         assert_eq!(t("set -- a b c"), "set argv a b c");
     }
 
+    // --- Unsupported diagnostics ---
+
+    fn unsupported_err(bash: &str) -> Unsupported {
+        match translate_bash_to_fish(bash) {
+            Err(TranslateError::Unsupported(u)) => u,
+            other => panic!("expected unsupported, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unsupported_kind_and_span() {
+        let u = unsupported_err("echo hi; declare -n ref");
+        assert_eq!(u.kind(), UnsupportedKind::Nameref);
+        assert_eq!(u.detail(), "nameref without a target");
+        assert_eq!(u.span(), Some(Span::new(9, 23)));
+        assert!(u.kind().suggestion().unwrap().contains("$$name"));
+    }
+
+    #[test]
+    fn unsupported_span_is_innermost_construct() {
+        let input = "if true; then\n  echo $LINENO\nfi";
+        let u = unsupported_err(input);
+        assert_eq!(u.kind(), UnsupportedKind::SpecialVariable);
+        assert_eq!(u.span().map(|s| &input[s.start..s.end]), Some("$LINENO"));
+        let u = unsupported_err("echo a ${x@P}");
+        assert_eq!(u.span(), Some(Span::new(7, 13)));
+    }
+
+    #[test]
+    fn unsupported_diagnostic_location() {
+        let input = "ls\nshopt -s nullglob";
+        let err = translate_bash_to_fish(input).unwrap_err();
+        let diag = err.diagnostic(input).unwrap();
+        assert_eq!((diag.line(), diag.column()), (2, 1));
+        assert_eq!(diag.message(), "unsupported: shopt");
+        assert_eq!(UnsupportedKind::Shopt.suggestion(), None);
+    }
+
+    #[test]
+    fn unsupported_timed_pipeline_span() {
+        let u = unsupported_err("time { a; } > log");
+        assert_eq!(u.kind(), UnsupportedKind::Time);
+        assert_eq!(u.span(), Some(Span::new(5, 17)));
+    }
+
+    #[test]
+    fn unsupported_in_trap_handler_points_at_trap() {
+        let u = unsupported_err("ls; trap 'shopt -s extglob' INT");
+        assert_eq!(u.kind(), UnsupportedKind::Shopt);
+        assert_eq!(u.span(), Some(Span::new(4, 31)));
+    }
+
     // --- select / getopts / exec fd / eval ---
 
     #[test]
-    fn select_unsupported() {
-        assert!(translate_bash_to_fish("select opt in a b c; do echo $opt; done").is_err());
+    fn coproc_unsupported() {
+        t_unsupported("coproc cat");
+        t_unsupported("coproc UP { tr a-z A-Z; }");
+        t_unsupported("coproc UP { tr a-z A-Z; }; echo hi >&${UP[1]}");
+        let Err(TranslateError::Unsupported(u)) = translate_bash_to_fish("coproc cat") else {
+            panic!("expected unsupported");
+        };
+        assert_eq!(u.kind(), UnsupportedKind::Coproc);
+        assert!(u.detail().starts_with("coproc"), "got: {u}");
+    }
+
+    #[test]
+    fn select_loop() {
+        let result = t("select opt in a b c; do echo $opt; break; done");
+        assert!(result.starts_with("set -l __reef_sel_items a b c\n"));
+        assert!(result.contains("printf '%s) %s\\n' $__reef_sel_i $__reef_sel_items[$__reef_sel_i] >&2"));
+        assert!(result.contains("read -P \"$__reef_sel_ps3\" REPLY; or break"));
+        assert!(result.contains("set opt $__reef_sel_items[$REPLY]"));
+        assert!(result.contains("echo $opt\nbreak\nend"));
+    }
+
+    #[test]
+    fn select_uses_ps3() {
+        let result = t("PS3='Pick: '; select f in *.txt; do echo $f; done");
+        assert!(result.contains("set -q PS3; and set __reef_sel_ps3 $PS3"));
+        assert!(result.contains("set -l __reef_sel_items *.txt"));
+    }
+
+    #[test]
+    fn select_rereads_ps3_at_each_prompt() {
+        let result = t("select f in a b; do PS3='Again: '; done");
+        let prompt = result.find("set -q PS3").unwrap();
+        assert!(result.find("while true").unwrap() < prompt, "got: {result}");
+        assert!(result[prompt..].contains("read -P \"$__reef_sel_ps3\" REPLY"), "got: {result}");
+    }
+
+    #[test]
+    fn select_positional_args() {
+        let result = t("select opt; do echo $REPLY; done");
+        assert!(result.starts_with("set -l __reef_sel_items $argv\n"));
     }
 
     #[test]
@@ -4516,6 +7717,97 @@ mod tests {
         assert!(translate_bash_to_fish("getopts 'abc' opt").is_err());
     }
 
+    #[test]
+    fn getopts_loop_to_argparse() {
+        assert_eq!(
+            t("while getopts 'ab:' opt; do case $opt in a) A=1 ;; b) B=\"$OPTARG\" ;; esac; done\n\
+               shift $((OPTIND-1))"),
+            "argparse -s a b=+ -- $argv\n\
+             for __reef_opt in $_flag_a\nset opt a\nset A 1\nend\n\
+             for OPTARG in $_flag_b\nset opt b\nset B \"$OPTARG\"\nend"
+        );
+    }
+
+    #[test]
+    fn getopts_unknown_option_arm() {
+        assert_eq!(
+            t("while getopts v opt \"$@\"; do case \"$opt\" in v) verbose ;; \\?) exit 2 ;; \
+               esac; done\nshift \"$(( OPTIND - 1 ))\"; echo \"$1\""),
+            "if not argparse -s v -- $argv\nset opt '?'\nexit 2\nend\n\
+             for __reef_opt in $_flag_v\nset opt v\nverbose\nend\necho \"$argv[1]\""
+        );
+    }
+
+    #[test]
+    fn getopts_silent_error_arms() {
+        let result = t("while getopts ':hf:' o; do case $o in h) usage ;; f) F=$OPTARG ;; \
+                        :) echo \"-$OPTARG needs a value\" ;; '?') echo \"bad -$OPTARG\" ;; \
+                        esac; done; shift $((OPTIND-1))");
+        assert_eq!(
+            result,
+            "if not argparse -s h f=+ -- $argv 2>/dev/null\n\
+             if string match -qr -- '^-[h]*[f]$' $argv[-1]\n\
+             set -l OPTARG (string sub -s -1 -- $argv[-1])\n\
+             set o :\n\
+             echo \"-$OPTARG needs a value\"\n\
+             else\n\
+             set -l OPTARG (string match -r -- '^-[h]*([^hf-])' $argv)[2]\n\
+             set o '?'\n\
+             echo \"bad -$OPTARG\"\n\
+             end\n\
+             end\n\
+             for __reef_opt in $_flag_h\nset o h\nusage\nend\n\
+             for OPTARG in $_flag_f\nset o f\nset F $OPTARG\nend"
+        );
+    }
+
+    #[test]
+    fn getopts_without_shift_keeps_argv() {
+        let result = t("while getopts x o; do case $o in x) X=1 ;; esac; done; echo $OPTIND");
+        assert!(result.starts_with("set -l __reef_argv $argv\nargparse -s x -- $argv\n"));
+        assert!(result.contains(
+            "set OPTIND (math (count $__reef_argv) - (count $argv) + 1)\nset argv $__reef_argv"
+        ));
+        assert!(result.ends_with("end\necho $OPTIND"), "got: {result}");
+    }
+
+    #[test]
+    fn getopts_shared_arm_and_catch_all() {
+        let result = t("f() { while getopts 'hq' o; do case $o in h|q) quiet ;; *) usage ;; \
+                        esac; done; shift $((OPTIND-1)); }");
+        assert!(
+            result.contains("if not argparse -s h q -- $argv\nset o '?'\nusage\nend"),
+            "got: {result}"
+        );
+        assert!(result.contains("for __reef_opt in $_flag_h\nset o h\nquiet\nend"));
+        assert!(result.contains("for __reef_opt in $_flag_q\nset o q\nquiet\nend"));
+    }
+
+    #[test]
+    fn getopts_sets_name_per_letter() {
+        let result = t("while getopts 'ab:c' o; do case $o in a|c) mode=$o ;; b) B=$OPTARG ;; \
+                        esac; done; shift $((OPTIND-1))");
+        assert_eq!(
+            result,
+            "argparse -s a b=+ c -- $argv\n\
+             for __reef_opt in $_flag_a\nset o a\nset mode $o\nend\n\
+             for __reef_opt in $_flag_c\nset o c\nset mode $o\nend\n\
+             for OPTARG in $_flag_b\nset o b\nset B $OPTARG\nend"
+        );
+    }
+
+    #[test]
+    fn getopts_loop_unsupported_shapes() {
+        // Catch-all reached by a valid option
+        t_unsupported("while getopts ab o; do case $o in a) x ;; *) y ;; esac; done");
+        // Body does more than the case
+        t_unsupported("while getopts a o; do case $o in a) x ;; esac; n=1; done");
+        // Parses something other than the positional parameters
+        t_unsupported("while getopts a o \"${args[@]}\"; do case $o in a) x ;; esac; done");
+        t_unsupported("while getopts a o; do case $o in a) x ;& esac; done");
+        t_unsupported("while getopts a: o; do case $o in a|[bc]) x ;; esac; done");
+    }
+
     #[test]
     fn exec_fd_unsupported() {
         assert!(translate_bash_to_fish("exec 3>&1").is_err());
@@ -4551,16 +7843,24 @@ mod tests {
         assert!(translate_bash_to_fish("echo $SECONDS").is_err());
     }
 
-    // --- @E/@A transformations unsupported ---
+    // --- @E/@A transformations ---
 
     #[test]
-    fn transform_e_unsupported() {
-        assert!(translate_bash_to_fish("echo ${var@E}").is_err());
+    fn transform_escapes() {
+        assert_eq!(t("echo ${var@E}"), r#"echo (printf '%b' "$var" | string collect -N -a)"#);
+        assert_eq!(
+            t(r#"echo "${var@E}x""#),
+            r#"echo (printf '%b' "$var" | string collect -N -a)"x""#
+        );
     }
 
     #[test]
-    fn transform_a_unsupported() {
-        assert!(translate_bash_to_fish("echo ${var@A}").is_err());
+    fn transform_assignment() {
+        assert_eq!(
+            t("echo ${var@A}"),
+            "echo (string join ' ' -- set var (string escape -- $var))"
+        );
+        t_unsupported("declare -A m; echo ${m@A}");
     }
 
     // --- Regression: previously fixed bugs ---
@@ -4656,6 +7956,73 @@ mod tests {
         assert!(translate_bash_to_fish(r#"echo {a..c}"hello""#).is_ok());
     }
 
+    // --- Brace lists ---
+
+    #[test]
+    fn brace_list_backup_copy() {
+        assert_eq!(t("cp file{,.bak}"), "cp file file.bak");
+    }
+
+    #[test]
+    fn brace_list_bash_order() {
+        assert_eq!(
+            t("mkdir -p src/{a,b}/{x,y}"),
+            "mkdir -p src/a/x src/a/y src/b/x src/b/y"
+        );
+        assert_eq!(t("echo {a,b}{c,d}"), "echo ac ad bc bd");
+    }
+
+    #[test]
+    fn brace_list_nested() {
+        assert_eq!(t("echo {a,{b,c}}d"), "echo ad bd cd");
+        assert_eq!(t("echo pre{x,y}post"), "echo prexpost preypost");
+    }
+
+    #[test]
+    fn brace_list_distributes_expansions() {
+        assert_eq!(t(r#"echo "$HOME"{/a,/b}"#), r#"echo "$HOME"/a "$HOME"/b"#);
+        assert_eq!(t("echo {a,b}{1..3}"), "echo a(seq 1 3) b(seq 1 3)");
+    }
+
+    #[test]
+    fn brace_list_after_parameter() {
+        assert_eq!(t("echo ${x}{a,b}"), "echo {$x}a {$x}b");
+        assert_eq!(t("echo pre${x}{a,b}post"), "echo pre{$x}apost pre{$x}bpost");
+        assert_eq!(t("echo ${x}{-a,-b}"), "echo $x-a $x-b");
+    }
+
+    #[test]
+    fn parameter_followed_by_name_characters() {
+        assert_eq!(t("echo ${x}a ${x}[1]"), "echo {$x}a {$x}[1]");
+        assert_eq!(t(r#"echo "${x}_y""#), r#"echo "$x""_y""#);
+    }
+
+    #[test]
+    fn brace_list_drops_empty_words() {
+        assert_eq!(t("echo {,a}"), "echo a");
+    }
+
+    #[test]
+    fn brace_list_in_assignment_is_literal() {
+        assert_eq!(t("x={a,b}"), r"set x \{a,b\}");
+    }
+
+    #[test]
+    fn brace_list_in_pattern_is_literal() {
+        assert_eq!(
+            t("case $x in {a,b}*) echo y;; esac"),
+            "switch $x\ncase '{a,b}*'\necho y\nend"
+        );
+        assert_eq!(t("[[ $x == {a,b}c ]]"), "string match -q -- '{a,b}c' $x");
+        assert_eq!(t("[[ $x == {1..3} ]]"), "string match -q -- '{1..3}' $x");
+    }
+
+    #[test]
+    fn brace_list_after_range_bails() {
+        t_unsupported("echo {1..3}{a,b}");
+        t_unsupported("echo {1..2}{3..4}");
+    }
+
     // --- Complex real-world translations ---
 
     #[test]
@@ -4804,6 +8171,51 @@ mod tests {
         assert!(result.contains("| grep pattern"), "got: {}", result);
     }
 
+    #[test]
+    fn heredoc_strip_tabs() {
+        assert_eq!(
+            t("cat <<-EOF\n\thello $x\n\t\tnested\n\tEOF"),
+            "printf '%s\\n' \"hello $x\nnested\" | cat"
+        );
+        assert_eq!(
+            t("cat <<-'EOF'\n\t$lit\n\tEOF"),
+            "printf '%s\\n' '$lit' | cat"
+        );
+    }
+
+    #[test]
+    fn heredoc_other_fd() {
+        assert_eq!(
+            t("cmd 3<<EOF\nhi\nEOF"),
+            "cmd 3< (printf '%s\\n' \"hi\" | psub -f)"
+        );
+    }
+
+    #[test]
+    fn heredoc_last_on_stdin_wins() {
+        assert_eq!(
+            t("cmd <<A <<B\na\nA\nb\nB\necho after"),
+            "printf '%s\\n' \"b\" | cmd\necho after"
+        );
+    }
+
+    #[test]
+    fn heredoc_on_while_loop() {
+        let result = t("while read -r line; do\n  echo \"$line\"\ndone <<EOF\none\ntwo\nEOF");
+        assert!(result.starts_with("printf '%s\\n' \"one\ntwo\" | while read"), "got: {result}");
+        assert!(result.ends_with("end"), "got: {result}");
+    }
+
+    #[test]
+    fn heredoc_rest_of_line_kept() {
+        assert_eq!(
+            t("cat <<A; echo hi\nbody\nA"),
+            "printf '%s\\n' \"body\" | cat\necho hi"
+        );
+        let result = t("if cat <<A; then\nx\nA\necho yes; fi");
+        assert!(result.contains("| cat\necho yes\nend"), "got: {result}");
+    }
+
     // --- Process substitution ---
 
     #[test]
@@ -4841,6 +8253,80 @@ mod tests {
         assert!(result.contains("set x (math"), "got: {}", result);
     }
 
+    #[test]
+    fn arith_comma_in_c_for() {
+        assert_eq!(
+            t("for ((i=0, j=10; i<j; i++, j--)); do echo $i; done"),
+            concat!(
+                "set i (math \"0\")\nset j (math \"10\")\n",
+                "while test $i -lt $j\necho $i\n",
+                "set i (math \"$i + 1\")\nset j (math \"$j - 1\")\nend",
+            )
+        );
+        assert_eq!(t("echo $(( a, b ))"), "echo (math \"$b\")");
+        t_unsupported("echo $(( a++, b ))");
+    }
+
+    #[test]
+    fn arith_bitwise_compound_assign() {
+        assert_eq!(t("(( x **= 2 ))"), "set x (math \"$x ^ 2\")");
+        assert_eq!(t("(( m &= 0xf ))"), "set m (math \"bitand($m, 15)\")");
+        assert_eq!(t("(( m |= 1 ))"), "set m (math \"bitor($m, 1)\")");
+        assert_eq!(t("(( m ^= 3 ))"), "set m (math \"bitxor($m, 3)\")");
+        assert_eq!(t("(( m <<= 1 ))"), "set m (math \"($m * 2 ^ 1)\")");
+        assert_eq!(t("(( m >>= 1 ))"), "set m (math \"floor($m / 2 ^ 1)\")");
+    }
+
+    #[test]
+    fn arith_base_literals() {
+        assert_eq!(t("echo $(( 16#ff + 2#1010 ))"), "echo (math \"255 + 10\")");
+        assert_eq!(t("echo $(( 0x1f | 017 ))"), "echo (math \"bitor(31, 15)\")");
+    }
+
+    #[test]
+    fn arith_legacy_brackets() {
+        assert_eq!(t("echo $[1 + 2]"), "echo (math \"1 + 2\")");
+    }
+
+    #[test]
+    fn arith_array_elements() {
+        assert_eq!(t("echo $(( arr[2] ))"), "echo (math \"$arr[3]\")");
+        assert_eq!(t("echo $(( arr[-1] ))"), "echo (math \"$arr[-1]\")");
+        assert_eq!(
+            t("echo $(( arr[i+1] * 2 ))"),
+            "echo (math \"\"$arr[(math \"$i + 1 + 1 + min(0, max(-1, $i + 1))\")]\" * 2\")"
+        );
+        assert_eq!(
+            t("if (( arr[i] > 3 )); then echo y; fi"),
+            "if test $arr[(math \"$i + 1 + min(0, max(-1, $i))\")] -gt 3\necho y\nend"
+        );
+    }
+
+    #[test]
+    fn arith_array_element_assignments() {
+        assert_eq!(t("(( arr[2] = 5 ))"), "set arr[3] (math \"5\")");
+        assert_eq!(t("(( arr[-1] += 2 ))"), "set arr[-1] (math \"$arr[-1] + 2\")");
+        assert_eq!(
+            t("(( counts[$k]++ ))"),
+            "set counts[(math \"$k + 1 + min(0, max(-1, $k))\")] \
+             (math \"\"$counts[(math \"$k + 1 + min(0, max(-1, $k))\")]\" + 1\")"
+        );
+        assert_eq!(t("(( --arr[0] ))"), "set arr[1] (math \"$arr[1] - 1\")");
+    }
+
+    #[test]
+    fn arith_negative_computed_index_counts_from_end() {
+        assert_eq!(
+            t("(( arr[i-1] = 0 ))"),
+            "set arr[(math \"$i - 1 + 1 + min(0, max(-1, $i - 1))\")] (math \"0\")"
+        );
+    }
+
+    #[test]
+    fn arith_assoc_element_assignment_unsupported() {
+        t_unsupported("declare -A m; (( m[x]++ ))");
+    }
+
     // --- Double bracket operators ---
 
     #[test]
@@ -5049,8 +8535,20 @@ mod tests {
     }
 
     #[test]
-    fn transform_k_unsupported() {
-        assert!(translate_bash_to_fish(r#"echo "${var@K}""#).is_err());
+    fn transform_k() {
+        assert_eq!(t("echo ${var@K}"), "echo (string escape -- $var)");
+        t_unsupported("declare -A m; echo ${m@K}");
+    }
+
+    #[test]
+    fn transform_attributes() {
+        assert_eq!(t("echo ${x@a}"), "echo (set -qx x; and echo x; or echo)");
+        assert_eq!(
+            t("a=(1 2); echo ${a@a}"),
+            "set a 1 2\necho a(set -qx a; and echo x; or echo)"
+        );
+        assert!(t("declare -A m; echo ${m@a}").ends_with("echo A(set -qx m; and echo x; or echo)"));
+        assert!(t("declare -a b; echo ${b@a}").ends_with("echo a(set -qx b; and echo x; or echo)"));
     }
 
     // --- Real-world one-liners ---
@@ -5352,6 +8850,77 @@ mod tests {
         let _ = result;
     }
 
+    // --- Namerefs ---
+
+    #[test]
+    fn nameref_static_target_is_renamed() {
+        assert_eq!(
+            t("declare -n r=arr; r=(1 2); r+=(3); echo ${r[1]} ${#r[@]} ${!r}"),
+            "set -g r arr\nset arr 1 2\nset -a arr 3\necho $arr[2] (count $arr) arr"
+        );
+    }
+
+    #[test]
+    fn nameref_chain_resolves_to_final_target() {
+        assert_eq!(t("declare -n a=x; declare -n b=a; b=1"), "set -g a x\nset -g b a\nset x 1");
+    }
+
+    #[test]
+    fn nameref_dynamic_target_uses_indirection() {
+        assert_eq!(
+            t(r#"f() { local -n ref=$1; ref+=(x); echo "${ref[@]}" ${#ref[@]}; }"#),
+            "function f\nset -l ref $argv[1]\nset -ga $ref[1] x\n\
+             echo $$ref[1] (count $$ref[1])\nend"
+        );
+    }
+
+    #[test]
+    fn nameref_dynamic_writes() {
+        assert_eq!(
+            t("f() { local -n ref=$1; ref=z; ref[0]=y; (( ref++ )); echo ${!ref}; }"),
+            "function f\nset -l ref $argv[1]\nset -g $ref[1] z\nset -g $ref[1]'['1']' y\n\
+             set -g $ref[1] (math \"$$ref[1] + 1\")\necho $ref\nend"
+        );
+        assert_eq!(
+            t("f() { local -n out=$1; out=$(date); (( out[2] = 1 )); }"),
+            "function f\nset -l out $argv[1]\nset -g $out[1] (date)\n\
+             set -g $out[1]'['3']' (math \"1\")\nend"
+        );
+    }
+
+    #[test]
+    fn nameref_name_arguments() {
+        assert_eq!(
+            t("declare -n r=x; read -r r; read -a r; unset r; [[ -v r ]]; export r; \
+               for r in 1; do :; done"),
+            "set -g r x\nread x\nread --list x\nset -e x\nset -q x\nset -gx x $x\n\
+             for x in 1\n:\nend"
+        );
+        assert_eq!(
+            t("f() { local -n r=$1; unset r; [[ -v r ]]; }"),
+            "function f\nset -l r $argv[1]\nset -e $r[1]\nset -q $r[1]\nend"
+        );
+        // `unset -n` drops the reference itself
+        assert_eq!(t("declare -n r=x; unset -n r; echo $r"), "set -g r x\nset -e r\necho $r");
+        for form in ["read r", "printf -v r x", "for r in 1; do :; done", "local r=2"] {
+            t_unsupported(&format!("f() {{ local -n r=$1; {form}; }}"));
+        }
+    }
+
+    #[test]
+    fn nameref_scoped_to_function() {
+        let out = t("f() { local -n r=arr; r=1; }; r=2");
+        assert!(out.ends_with("set r 2"), "got: {out}");
+    }
+
+    #[test]
+    fn nameref_unresolvable_targets() {
+        t_unsupported("declare -n ref");
+        t_unsupported("local -n ref='arr[1]'");
+        t_unsupported("declare -n ref=(a b)");
+        t_unsupported("local -n \"$1\"=x");
+    }
+
     // --- Read command ---
 
     #[test]
@@ -5393,6 +8962,51 @@ mod tests {
         assert!(result.contains("-z"), "got: {}", result);
     }
 
+    #[test]
+    fn double_bracket_grouped() {
+        assert_eq!(
+            t("[[ ( -f a && -n $b ) || $c =~ ^x ]]"),
+            concat!(
+                r#"begin; test -f a; and test -n "$b"; "#,
+                "or set __bash_rematch (string match -r -- '^x' $c); end",
+            )
+        );
+    }
+
+    #[test]
+    fn double_bracket_and_binds_tighter() {
+        assert_eq!(
+            t("[[ -f a || -n $b && $c == y* ]]"),
+            r#"begin; test -f a; or begin; test -n "$b"; and string match -q -- 'y*' $c; end; end"#
+        );
+    }
+
+    #[test]
+    fn double_bracket_chain_is_one_command() {
+        assert_eq!(t("! [[ -f a || -f b ]]"), "not begin; test -f a; or test -f b; end");
+        assert_eq!(t("[[ -f a || -f b ]] | cat"), "begin; test -f a; or test -f b; end | cat");
+        assert_eq!(
+            t("[[ -e a && -f b ]] >/dev/null"),
+            "begin; test -e a; and test -f b; end >/dev/null"
+        );
+        assert_eq!(
+            t("true || [[ -e a && -f b ]]"),
+            "true; or begin; test -e a; and test -f b; end"
+        );
+    }
+
+    #[test]
+    fn double_bracket_negated_group() {
+        assert_eq!(t("[[ ! ( -f a || -d a ) ]]"), "not begin; test -f a; or test -d a; end");
+        assert_eq!(t("[[ ! $n -gt 3 ]]"), r#"not test "$n" -gt 3"#);
+    }
+
+    #[test]
+    fn double_bracket_string_order_unsupported() {
+        t_unsupported("[[ $a < $b ]]");
+        t_unsupported("[[ -o errexit ]]");
+    }
+
     // --- Redirect edge cases ---
 
     #[test]
@@ -5418,9 +9032,9 @@ mod tests {
 
     #[test]
     fn trap_on_err() {
-        let result = translate_bash_to_fish("trap 'echo error' ERR");
-        // ERR trap may or may not be supported
-        let _ = result;
+        let result = t("trap 'echo error' ERR");
+        assert!(result.starts_with("function __reef_trap_ERR\n"), "got: {result}");
+        assert!(result.contains("\necho error\n"), "got: {result}");
     }
 
     #[test]
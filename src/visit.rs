@@ -0,0 +1,1000 @@
+//! AST traversal with [`Visit`] and [`VisitMut`].
+//!
+//! Every trait method has a default that recurses into the node's children
+//! through the matching free `walk_*` function. An implementation overrides
+//! only the nodes it cares about and calls the `walk_*` function from its
+//! override when it still wants to descend:
+//!
+//! ```
+//! use reef::ast::Param;
+//! use reef::parser::Parser;
+//! use reef::visit::Visit;
+//!
+//! #[derive(Default)]
+//! struct Vars(Vec<String>);
+//!
+//! impl<'a> Visit<'a> for Vars {
+//!     fn visit_param(&mut self, param: &Param<'a>) {
+//!         if let Param::Var(name) = param {
+//!             self.0.push(name.to_string());
+//!         }
+//!     }
+//! }
+//!
+//! let cmds = Parser::new("echo $a; for x in 1; do echo \"${b:-$c}\"; done").parse().unwrap();
+//! let mut vars = Vars::default();
+//! vars.visit_cmds(&cmds);
+//! assert_eq!(vars.0, ["a", "b", "c"]);
+//! ```
+//!
+//! Children are visited in source order. Leaves without children of their
+//! own (names, literal text, [`Span`]s) are only reported where they carry
+//! meaning: [`Param`]s through `visit_param` and spans through `visit_span`.
+
+use crate::ast::*;
+
+// ---------------------------------------------------------------------------
+// Visit
+// ---------------------------------------------------------------------------
+
+/// Read-only traversal of a borrowed AST.
+///
+/// The trait lifetime is the lifetime of the parsed input. Names and
+/// literals are `Cow<'a, str>`: a visitor can keep the `&'a str` inside a
+/// `Cow::Borrowed` after the walk, but must clone text that is owned.
+pub trait Visit<'a> {
+    /// Visit a command list, such as a script or a loop body.
+    fn visit_cmds(&mut self, cmds: &[Cmd<'a>]) {
+        for cmd in cmds {
+            self.visit_cmd(cmd);
+        }
+    }
+    /// Visit a foreground or background command.
+    fn visit_cmd(&mut self, cmd: &Cmd<'a>) {
+        walk_cmd(self, cmd);
+    }
+    /// Visit an `&&` / `||` chain.
+    fn visit_and_or_list(&mut self, list: &AndOrList<'a>) {
+        walk_and_or_list(self, list);
+    }
+    /// Visit a pipeline.
+    fn visit_pipeline(&mut self, pipeline: &Pipeline<'a>) {
+        walk_pipeline(self, pipeline);
+    }
+    /// Visit a simple command, compound command or function definition.
+    fn visit_executable(&mut self, exec: &Executable<'a>) {
+        walk_executable(self, exec);
+    }
+    /// Visit a simple command.
+    fn visit_simple_cmd(&mut self, cmd: &SimpleCmd<'a>) {
+        walk_simple_cmd(self, cmd);
+    }
+    /// Visit a compound command and its redirections.
+    fn visit_compound_cmd(&mut self, cmd: &CompoundCmd<'a>) {
+        walk_compound_cmd(self, cmd);
+    }
+    /// Visit the body of a compound command.
+    fn visit_compound_kind(&mut self, kind: &CompoundKind<'a>) {
+        walk_compound_kind(self, kind);
+    }
+    /// Visit an `if` / `while` / `until` condition and body.
+    fn visit_guard_body(&mut self, gb: &GuardBody<'a>) {
+        walk_guard_body(self, gb);
+    }
+    /// Visit a `case` arm.
+    fn visit_case_arm(&mut self, arm: &CaseArm<'a>) {
+        walk_case_arm(self, arm);
+    }
+    /// Visit a word.
+    fn visit_word(&mut self, word: &Word<'a>) {
+        walk_word(self, word);
+    }
+    /// Visit a bare, double-quoted or single-quoted word fragment.
+    fn visit_word_part(&mut self, part: &WordPart<'a>) {
+        walk_word_part(self, part);
+    }
+    /// Visit an atom.
+    fn visit_atom(&mut self, atom: &Atom<'a>) {
+        walk_atom(self, atom);
+    }
+    /// Visit a parameter reference. Leaf: the default does nothing.
+    fn visit_param(&mut self, _param: &Param<'a>) {}
+    /// Visit a substitution or parameter expansion.
+    fn visit_subst(&mut self, subst: &Subst<'a>) {
+        walk_subst(self, subst);
+    }
+    /// Visit an arithmetic expression.
+    fn visit_arith(&mut self, arith: &Arith<'a>) {
+        walk_arith(self, arith);
+    }
+    /// Visit a `[[ ]]` conditional expression.
+    fn visit_cond(&mut self, expr: &CondExpr<'a>) {
+        walk_cond(self, expr);
+    }
+    /// Visit a redirection.
+    fn visit_redir(&mut self, redir: &Redir<'a>) {
+        walk_redir(self, redir);
+    }
+    /// Visit a heredoc body.
+    fn visit_heredoc_body(&mut self, body: &HeredocBody<'a>) {
+        walk_heredoc_body(self, body);
+    }
+    /// Visit the span of a node. Leaf: the default does nothing.
+    fn visit_span(&mut self, _span: &Span) {}
+}
+
+/// Visit the and-or list of `cmd`.
+pub fn walk_cmd<'a, V: Visit<'a> + ?Sized>(v: &mut V, cmd: &Cmd<'a>) {
+    match cmd {
+        Cmd::List(list) | Cmd::Job(list) => v.visit_and_or_list(list),
+    }
+}
+
+/// Visit every pipeline of `list`, then its span.
+pub fn walk_and_or_list<'a, V: Visit<'a> + ?Sized>(v: &mut V, list: &AndOrList<'a>) {
+    v.visit_pipeline(&list.first);
+    for link in &list.rest {
+        match link {
+            AndOr::And(p) | AndOr::Or(p) => v.visit_pipeline(p),
+        }
+    }
+    v.visit_span(&list.span);
+}
+
+/// Visit every command of `pipeline`.
+pub fn walk_pipeline<'a, V: Visit<'a> + ?Sized>(v: &mut V, pipeline: &Pipeline<'a>) {
+    match pipeline {
+        Pipeline::Single(exec) => v.visit_executable(exec),
+        Pipeline::Pipe(_, execs) => {
+            for exec in execs {
+                v.visit_executable(exec);
+            }
+        }
+        Pipeline::Timed { pipeline, .. } => v.visit_pipeline(pipeline),
+    }
+}
+
+/// Visit the command inside `exec`.
+pub fn walk_executable<'a, V: Visit<'a> + ?Sized>(v: &mut V, exec: &Executable<'a>) {
+    match exec {
+        Executable::Simple(cmd) => v.visit_simple_cmd(cmd),
+        Executable::Compound(cmd) | Executable::FuncDef(_, cmd) => v.visit_compound_cmd(cmd),
+    }
+}
+
+/// Visit prefix assignments and redirections, suffix words and redirections,
+/// then the span.
+pub fn walk_simple_cmd<'a, V: Visit<'a> + ?Sized>(v: &mut V, cmd: &SimpleCmd<'a>) {
+    for prefix in &cmd.prefix {
+        match prefix {
+            CmdPrefix::Assign(_, value) => {
+                if let Some(w) = value {
+                    v.visit_word(w);
+                }
+            }
+            CmdPrefix::ArrayAssign(_, words) | CmdPrefix::ArrayAppend(_, words) => {
+                for w in words {
+                    v.visit_word(w);
+                }
+            }
+            CmdPrefix::ElementAssign(_, index, value) => {
+                v.visit_word(index);
+                if let Some(w) = value {
+                    v.visit_word(w);
+                }
+            }
+            CmdPrefix::Redirect(r) => v.visit_redir(r),
+        }
+    }
+    for suffix in &cmd.suffix {
+        match suffix {
+            CmdSuffix::Word(w) => v.visit_word(w),
+            CmdSuffix::ArrayAssign(_, words) => {
+                for w in words {
+                    v.visit_word(w);
+                }
+            }
+            CmdSuffix::Redirect(r) => v.visit_redir(r),
+        }
+    }
+    v.visit_span(&cmd.span);
+}
+
+/// Visit the body, the trailing redirections, then the span.
+pub fn walk_compound_cmd<'a, V: Visit<'a> + ?Sized>(v: &mut V, cmd: &CompoundCmd<'a>) {
+    v.visit_compound_kind(&cmd.kind);
+    for r in &cmd.redirects {
+        v.visit_redir(r);
+    }
+    v.visit_span(&cmd.span);
+}
+
+/// Visit the words, expressions and command lists of `kind`.
+pub fn walk_compound_kind<'a, V: Visit<'a> + ?Sized>(v: &mut V, kind: &CompoundKind<'a>) {
+    match kind {
+        CompoundKind::For { words, body, .. } | CompoundKind::Select { words, body, .. } => {
+            for w in words.iter().flatten() {
+                v.visit_word(w);
+            }
+            v.visit_cmds(body);
+        }
+        CompoundKind::While(gb) | CompoundKind::Until(gb) => v.visit_guard_body(gb),
+        CompoundKind::If { conditionals, else_branch } => {
+            for gb in conditionals {
+                v.visit_guard_body(gb);
+            }
+            if let Some(body) = else_branch {
+                v.visit_cmds(body);
+            }
+        }
+        CompoundKind::Case { word, arms } => {
+            v.visit_word(word);
+            for arm in arms {
+                v.visit_case_arm(arm);
+            }
+        }
+        CompoundKind::CFor { init, cond, step, body } => {
+            for a in [init, cond, step].into_iter().flatten() {
+                v.visit_arith(a);
+            }
+            v.visit_cmds(body);
+        }
+        CompoundKind::Coproc { body, .. } => v.visit_executable(body),
+        CompoundKind::Brace(body) | CompoundKind::Subshell(body) => v.visit_cmds(body),
+        CompoundKind::DoubleBracket(expr) => v.visit_cond(expr),
+        CompoundKind::Arithmetic(a) => v.visit_arith(a),
+    }
+}
+
+/// Visit the guard, the body, then the span.
+pub fn walk_guard_body<'a, V: Visit<'a> + ?Sized>(v: &mut V, gb: &GuardBody<'a>) {
+    v.visit_cmds(&gb.guard);
+    v.visit_cmds(&gb.body);
+    v.visit_span(&gb.span);
+}
+
+/// Visit the patterns, the body, then the span.
+pub fn walk_case_arm<'a, V: Visit<'a> + ?Sized>(v: &mut V, arm: &CaseArm<'a>) {
+    for w in &arm.patterns {
+        v.visit_word(w);
+    }
+    v.visit_cmds(&arm.body);
+    v.visit_span(&arm.span);
+}
+
+/// Visit every part of `word`.
+pub fn walk_word<'a, V: Visit<'a> + ?Sized>(v: &mut V, word: &Word<'a>) {
+    match word {
+        Word::Simple(p) => v.visit_word_part(p),
+        Word::Concat(parts) => {
+            for p in parts {
+                v.visit_word_part(p);
+            }
+        }
+    }
+}
+
+/// Visit the atoms of `part`.
+pub fn walk_word_part<'a, V: Visit<'a> + ?Sized>(v: &mut V, part: &WordPart<'a>) {
+    match part {
+        WordPart::Bare(atom) => v.visit_atom(atom),
+        WordPart::DQuoted(atoms) => {
+            for atom in atoms {
+                v.visit_atom(atom);
+            }
+        }
+        WordPart::SQuoted(_) => {}
+    }
+}
+
+/// Visit the parameter, substitution, commands or words inside `atom`.
+pub fn walk_atom<'a, V: Visit<'a> + ?Sized>(v: &mut V, atom: &Atom<'a>) {
+    match atom {
+        Atom::Param(p) => v.visit_param(p),
+        Atom::Subst(s) => v.visit_subst(s),
+        Atom::ProcSubIn(cmds) | Atom::ProcSubOut(cmds) => v.visit_cmds(cmds),
+        Atom::ExtGlob { alternatives, .. } | Atom::BraceList(alternatives) => {
+            for w in alternatives {
+                v.visit_word(w);
+            }
+        }
+        Atom::Lit(_)
+        | Atom::Escaped(_)
+        | Atom::Star
+        | Atom::Question
+        | Atom::SquareOpen
+        | Atom::SquareClose
+        | Atom::Tilde
+        | Atom::AnsiCQuoted(_)
+        | Atom::BraceRange { .. } => {}
+    }
+}
+
+/// Visit the commands, expression, parameter and words inside `subst`.
+pub fn walk_subst<'a, V: Visit<'a> + ?Sized>(v: &mut V, subst: &Subst<'a>) {
+    match subst {
+        Subst::Cmd(cmds) => v.visit_cmds(cmds),
+        Subst::Arith(a) => {
+            if let Some(a) = a {
+                v.visit_arith(a);
+            }
+        }
+        Subst::Len(p) | Subst::Substring(p, ..) => v.visit_param(p),
+        Subst::Upper(_, p, w)
+        | Subst::Lower(_, p, w)
+        | Subst::Default(p, w)
+        | Subst::Assign(p, w)
+        | Subst::Error(p, w)
+        | Subst::Alt(p, w)
+        | Subst::TrimSuffixSmall(p, w)
+        | Subst::TrimSuffixLarge(p, w)
+        | Subst::TrimPrefixSmall(p, w)
+        | Subst::TrimPrefixLarge(p, w) => {
+            v.visit_param(p);
+            if let Some(w) = w {
+                v.visit_word(w);
+            }
+        }
+        Subst::Replace(p, pat, rep)
+        | Subst::ReplaceAll(p, pat, rep)
+        | Subst::ReplacePrefix(p, pat, rep)
+        | Subst::ReplaceSuffix(p, pat, rep) => {
+            v.visit_param(p);
+            for w in [pat, rep].into_iter().flatten() {
+                v.visit_word(w);
+            }
+        }
+        Subst::ArrayElement(_, index) => v.visit_word(index),
+        Subst::Indirect(_)
+        | Subst::PrefixList(_)
+        | Subst::Transform(..)
+        | Subst::ArrayAll(_)
+        | Subst::ArrayKeys(_)
+        | Subst::ArrayLen(_)
+        | Subst::ArraySlice(..) => {}
+    }
+}
+
+/// Visit the operands of `arith`.
+pub fn walk_arith<'a, V: Visit<'a> + ?Sized>(v: &mut V, arith: &Arith<'a>) {
+    match arith {
+        Arith::Add(l, r)
+        | Arith::Sub(l, r)
+        | Arith::Mul(l, r)
+        | Arith::Div(l, r)
+        | Arith::Rem(l, r)
+        | Arith::Pow(l, r)
+        | Arith::Lt(l, r)
+        | Arith::Le(l, r)
+        | Arith::Gt(l, r)
+        | Arith::Ge(l, r)
+        | Arith::Eq(l, r)
+        | Arith::Ne(l, r)
+        | Arith::BitAnd(l, r)
+        | Arith::BitOr(l, r)
+        | Arith::BitXor(l, r)
+        | Arith::LogAnd(l, r)
+        | Arith::LogOr(l, r)
+        | Arith::Shl(l, r)
+        | Arith::Shr(l, r)
+        | Arith::Comma(l, r) => {
+            v.visit_arith(l);
+            v.visit_arith(r);
+        }
+        Arith::Pos(e)
+        | Arith::Neg(e)
+        | Arith::LogNot(e)
+        | Arith::BitNot(e)
+        | Arith::Assign(_, e)
+        | Arith::Elem(_, e)
+        | Arith::ElemPreInc(_, e)
+        | Arith::ElemPostInc(_, e)
+        | Arith::ElemPreDec(_, e)
+        | Arith::ElemPostDec(_, e) => v.visit_arith(e),
+        Arith::ElemAssign(_, index, e) => {
+            v.visit_arith(index);
+            v.visit_arith(e);
+        }
+        Arith::Ternary(c, t, f) => {
+            v.visit_arith(c);
+            v.visit_arith(t);
+            v.visit_arith(f);
+        }
+        Arith::Var(_)
+        | Arith::Lit(_)
+        | Arith::PreInc(_)
+        | Arith::PostInc(_)
+        | Arith::PreDec(_)
+        | Arith::PostDec(_) => {}
+    }
+}
+
+/// Visit the operands of `expr`.
+pub fn walk_cond<'a, V: Visit<'a> + ?Sized>(v: &mut V, expr: &CondExpr<'a>) {
+    match expr {
+        CondExpr::Unary(_, w) | CondExpr::Word(w) => v.visit_word(w),
+        CondExpr::Binary(l, _, r) => {
+            v.visit_word(l);
+            v.visit_word(r);
+        }
+        CondExpr::Not(e) | CondExpr::Group(e) => v.visit_cond(e),
+        CondExpr::And(l, r) | CondExpr::Or(l, r) => {
+            v.visit_cond(l);
+            v.visit_cond(r);
+        }
+    }
+}
+
+/// Visit the target word or heredoc body of `redir`.
+pub fn walk_redir<'a, V: Visit<'a> + ?Sized>(v: &mut V, redir: &Redir<'a>) {
+    match redir {
+        Redir::Read(_, w)
+        | Redir::Write(_, w)
+        | Redir::Append(_, w)
+        | Redir::ReadWrite(_, w)
+        | Redir::Clobber(_, w)
+        | Redir::DupRead(_, w)
+        | Redir::DupWrite(_, w)
+        | Redir::HereString(w)
+        | Redir::WriteAll(w)
+        | Redir::AppendAll(w) => v.visit_word(w),
+        Redir::Heredoc(_, doc) => v.visit_heredoc_body(&doc.body),
+    }
+}
+
+/// Visit the atoms of an interpolated heredoc body.
+pub fn walk_heredoc_body<'a, V: Visit<'a> + ?Sized>(v: &mut V, body: &HeredocBody<'a>) {
+    match body {
+        HeredocBody::Literal(_) => {}
+        HeredocBody::Interpolated(atoms) => {
+            for atom in atoms {
+                v.visit_atom(atom);
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// VisitMut
+// ---------------------------------------------------------------------------
+
+/// In-place traversal of a mutable AST, for rewriters.
+///
+/// Mirrors [`Visit`] method for method; the `walk_*_mut` functions recurse.
+///
+/// # Examples
+///
+/// ```
+/// use reef::ast::Span;
+/// use reef::parser::Parser;
+/// use reef::visit::VisitMut;
+///
+/// struct ZeroSpans;
+///
+/// impl VisitMut<'_> for ZeroSpans {
+///     fn visit_span_mut(&mut self, span: &mut Span) {
+///         *span = Span::default();
+///     }
+/// }
+///
+/// let mut a = Parser::new("echo hi").parse().unwrap();
+/// let mut b = Parser::new("echo   hi").parse().unwrap();
+/// assert_ne!(a, b);
+/// ZeroSpans.visit_cmds_mut(&mut a);
+/// ZeroSpans.visit_cmds_mut(&mut b);
+/// assert_eq!(a, b);
+/// ```
+pub trait VisitMut<'a> {
+    /// Visit a command list, such as a script or a loop body.
+    fn visit_cmds_mut(&mut self, cmds: &mut [Cmd<'a>]) {
+        for cmd in cmds {
+            self.visit_cmd_mut(cmd);
+        }
+    }
+    /// Visit a foreground or background command.
+    fn visit_cmd_mut(&mut self, cmd: &mut Cmd<'a>) {
+        walk_cmd_mut(self, cmd);
+    }
+    /// Visit an `&&` / `||` chain.
+    fn visit_and_or_list_mut(&mut self, list: &mut AndOrList<'a>) {
+        walk_and_or_list_mut(self, list);
+    }
+    /// Visit a pipeline.
+    fn visit_pipeline_mut(&mut self, pipeline: &mut Pipeline<'a>) {
+        walk_pipeline_mut(self, pipeline);
+    }
+    /// Visit a simple command, compound command or function definition.
+    fn visit_executable_mut(&mut self, exec: &mut Executable<'a>) {
+        walk_executable_mut(self, exec);
+    }
+    /// Visit a simple command.
+    fn visit_simple_cmd_mut(&mut self, cmd: &mut SimpleCmd<'a>) {
+        walk_simple_cmd_mut(self, cmd);
+    }
+    /// Visit a compound command and its redirections.
+    fn visit_compound_cmd_mut(&mut self, cmd: &mut CompoundCmd<'a>) {
+        walk_compound_cmd_mut(self, cmd);
+    }
+    /// Visit the body of a compound command.
+    fn visit_compound_kind_mut(&mut self, kind: &mut CompoundKind<'a>) {
+        walk_compound_kind_mut(self, kind);
+    }
+    /// Visit an `if` / `while` / `until` condition and body.
+    fn visit_guard_body_mut(&mut self, gb: &mut GuardBody<'a>) {
+        walk_guard_body_mut(self, gb);
+    }
+    /// Visit a `case` arm.
+    fn visit_case_arm_mut(&mut self, arm: &mut CaseArm<'a>) {
+        walk_case_arm_mut(self, arm);
+    }
+    /// Visit a word.
+    fn visit_word_mut(&mut self, word: &mut Word<'a>) {
+        walk_word_mut(self, word);
+    }
+    /// Visit a bare, double-quoted or single-quoted word fragment.
+    fn visit_word_part_mut(&mut self, part: &mut WordPart<'a>) {
+        walk_word_part_mut(self, part);
+    }
+    /// Visit an atom.
+    fn visit_atom_mut(&mut self, atom: &mut Atom<'a>) {
+        walk_atom_mut(self, atom);
+    }
+    /// Visit a parameter reference. Leaf: the default does nothing.
+    fn visit_param_mut(&mut self, _param: &mut Param<'a>) {}
+    /// Visit a substitution or parameter expansion.
+    fn visit_subst_mut(&mut self, subst: &mut Subst<'a>) {
+        walk_subst_mut(self, subst);
+    }
+    /// Visit an arithmetic expression.
+    fn visit_arith_mut(&mut self, arith: &mut Arith<'a>) {
+        walk_arith_mut(self, arith);
+    }
+    /// Visit a `[[ ]]` conditional expression.
+    fn visit_cond_mut(&mut self, expr: &mut CondExpr<'a>) {
+        walk_cond_mut(self, expr);
+    }
+    /// Visit a redirection.
+    fn visit_redir_mut(&mut self, redir: &mut Redir<'a>) {
+        walk_redir_mut(self, redir);
+    }
+    /// Visit a heredoc body.
+    fn visit_heredoc_body_mut(&mut self, body: &mut HeredocBody<'a>) {
+        walk_heredoc_body_mut(self, body);
+    }
+    /// Visit the span of a node. Leaf: the default does nothing.
+    fn visit_span_mut(&mut self, _span: &mut Span) {}
+}
+
+/// Visit the and-or list of `cmd`.
+pub fn walk_cmd_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, cmd: &mut Cmd<'a>) {
+    match cmd {
+        Cmd::List(list) | Cmd::Job(list) => v.visit_and_or_list_mut(list),
+    }
+}
+
+/// Visit every pipeline of `list`, then its span.
+pub fn walk_and_or_list_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, list: &mut AndOrList<'a>) {
+    v.visit_pipeline_mut(&mut list.first);
+    for link in &mut list.rest {
+        match link {
+            AndOr::And(p) | AndOr::Or(p) => v.visit_pipeline_mut(p),
+        }
+    }
+    v.visit_span_mut(&mut list.span);
+}
+
+/// Visit every command of `pipeline`.
+pub fn walk_pipeline_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, pipeline: &mut Pipeline<'a>) {
+    match pipeline {
+        Pipeline::Single(exec) => v.visit_executable_mut(exec),
+        Pipeline::Pipe(_, execs) => {
+            for exec in execs {
+                v.visit_executable_mut(exec);
+            }
+        }
+        Pipeline::Timed { pipeline, .. } => v.visit_pipeline_mut(pipeline),
+    }
+}
+
+/// Visit the command inside `exec`.
+pub fn walk_executable_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, exec: &mut Executable<'a>) {
+    match exec {
+        Executable::Simple(cmd) => v.visit_simple_cmd_mut(cmd),
+        Executable::Compound(cmd) | Executable::FuncDef(_, cmd) => v.visit_compound_cmd_mut(cmd),
+    }
+}
+
+/// Visit prefix assignments and redirections, suffix words and redirections,
+/// then the span.
+pub fn walk_simple_cmd_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, cmd: &mut SimpleCmd<'a>) {
+    for prefix in &mut cmd.prefix {
+        match prefix {
+            CmdPrefix::Assign(_, value) => {
+                if let Some(w) = value {
+                    v.visit_word_mut(w);
+                }
+            }
+            CmdPrefix::ArrayAssign(_, words) | CmdPrefix::ArrayAppend(_, words) => {
+                for w in words {
+                    v.visit_word_mut(w);
+                }
+            }
+            CmdPrefix::ElementAssign(_, index, value) => {
+                v.visit_word_mut(index);
+                if let Some(w) = value {
+                    v.visit_word_mut(w);
+                }
+            }
+            CmdPrefix::Redirect(r) => v.visit_redir_mut(r),
+        }
+    }
+    for suffix in &mut cmd.suffix {
+        match suffix {
+            CmdSuffix::Word(w) => v.visit_word_mut(w),
+            CmdSuffix::ArrayAssign(_, words) => {
+                for w in words {
+                    v.visit_word_mut(w);
+                }
+            }
+            CmdSuffix::Redirect(r) => v.visit_redir_mut(r),
+        }
+    }
+    v.visit_span_mut(&mut cmd.span);
+}
+
+/// Visit the body, the trailing redirections, then the span.
+pub fn walk_compound_cmd_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, cmd: &mut CompoundCmd<'a>) {
+    v.visit_compound_kind_mut(&mut cmd.kind);
+    for r in &mut cmd.redirects {
+        v.visit_redir_mut(r);
+    }
+    v.visit_span_mut(&mut cmd.span);
+}
+
+/// Visit the words, expressions and command lists of `kind`.
+pub fn walk_compound_kind_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    kind: &mut CompoundKind<'a>,
+) {
+    match kind {
+        CompoundKind::For { words, body, .. } | CompoundKind::Select { words, body, .. } => {
+            for w in words.iter_mut().flatten() {
+                v.visit_word_mut(w);
+            }
+            v.visit_cmds_mut(body);
+        }
+        CompoundKind::While(gb) | CompoundKind::Until(gb) => v.visit_guard_body_mut(gb),
+        CompoundKind::If { conditionals, else_branch } => {
+            for gb in conditionals {
+                v.visit_guard_body_mut(gb);
+            }
+            if let Some(body) = else_branch {
+                v.visit_cmds_mut(body);
+            }
+        }
+        CompoundKind::Case { word, arms } => {
+            v.visit_word_mut(word);
+            for arm in arms {
+                v.visit_case_arm_mut(arm);
+            }
+        }
+        CompoundKind::CFor { init, cond, step, body } => {
+            for a in [init, cond, step].into_iter().flatten() {
+                v.visit_arith_mut(a);
+            }
+            v.visit_cmds_mut(body);
+        }
+        CompoundKind::Coproc { body, .. } => v.visit_executable_mut(body),
+        CompoundKind::Brace(body) | CompoundKind::Subshell(body) => v.visit_cmds_mut(body),
+        CompoundKind::DoubleBracket(expr) => v.visit_cond_mut(expr),
+        CompoundKind::Arithmetic(a) => v.visit_arith_mut(a),
+    }
+}
+
+/// Visit the guard, the body, then the span.
+pub fn walk_guard_body_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, gb: &mut GuardBody<'a>) {
+    v.visit_cmds_mut(&mut gb.guard);
+    v.visit_cmds_mut(&mut gb.body);
+    v.visit_span_mut(&mut gb.span);
+}
+
+/// Visit the patterns, the body, then the span.
+pub fn walk_case_arm_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, arm: &mut CaseArm<'a>) {
+    for w in &mut arm.patterns {
+        v.visit_word_mut(w);
+    }
+    v.visit_cmds_mut(&mut arm.body);
+    v.visit_span_mut(&mut arm.span);
+}
+
+/// Visit every part of `word`.
+pub fn walk_word_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, word: &mut Word<'a>) {
+    match word {
+        Word::Simple(p) => v.visit_word_part_mut(p),
+        Word::Concat(parts) => {
+            for p in parts {
+                v.visit_word_part_mut(p);
+            }
+        }
+    }
+}
+
+/// Visit the atoms of `part`.
+pub fn walk_word_part_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, part: &mut WordPart<'a>) {
+    match part {
+        WordPart::Bare(atom) => v.visit_atom_mut(atom),
+        WordPart::DQuoted(atoms) => {
+            for atom in atoms {
+                v.visit_atom_mut(atom);
+            }
+        }
+        WordPart::SQuoted(_) => {}
+    }
+}
+
+/// Visit the parameter, substitution, commands or words inside `atom`.
+pub fn walk_atom_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, atom: &mut Atom<'a>) {
+    match atom {
+        Atom::Param(p) => v.visit_param_mut(p),
+        Atom::Subst(s) => v.visit_subst_mut(s),
+        Atom::ProcSubIn(cmds) | Atom::ProcSubOut(cmds) => v.visit_cmds_mut(cmds),
+        Atom::ExtGlob { alternatives, .. } | Atom::BraceList(alternatives) => {
+            for w in alternatives {
+                v.visit_word_mut(w);
+            }
+        }
+        Atom::Lit(_)
+        | Atom::Escaped(_)
+        | Atom::Star
+        | Atom::Question
+        | Atom::SquareOpen
+        | Atom::SquareClose
+        | Atom::Tilde
+        | Atom::AnsiCQuoted(_)
+        | Atom::BraceRange { .. } => {}
+    }
+}
+
+/// Visit the commands, expression, parameter and words inside `subst`.
+pub fn walk_subst_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, subst: &mut Subst<'a>) {
+    match subst {
+        Subst::Cmd(cmds) => v.visit_cmds_mut(cmds),
+        Subst::Arith(a) => {
+            if let Some(a) = a {
+                v.visit_arith_mut(a);
+            }
+        }
+        Subst::Len(p) | Subst::Substring(p, ..) => v.visit_param_mut(p),
+        Subst::Upper(_, p, w)
+        | Subst::Lower(_, p, w)
+        | Subst::Default(p, w)
+        | Subst::Assign(p, w)
+        | Subst::Error(p, w)
+        | Subst::Alt(p, w)
+        | Subst::TrimSuffixSmall(p, w)
+        | Subst::TrimSuffixLarge(p, w)
+        | Subst::TrimPrefixSmall(p, w)
+        | Subst::TrimPrefixLarge(p, w) => {
+            v.visit_param_mut(p);
+            if let Some(w) = w {
+                v.visit_word_mut(w);
+            }
+        }
+        Subst::Replace(p, pat, rep)
+        | Subst::ReplaceAll(p, pat, rep)
+        | Subst::ReplacePrefix(p, pat, rep)
+        | Subst::ReplaceSuffix(p, pat, rep) => {
+            v.visit_param_mut(p);
+            for w in [pat, rep].into_iter().flatten() {
+                v.visit_word_mut(w);
+            }
+        }
+        Subst::ArrayElement(_, index) => v.visit_word_mut(index),
+        Subst::Indirect(_)
+        | Subst::PrefixList(_)
+        | Subst::Transform(..)
+        | Subst::ArrayAll(_)
+        | Subst::ArrayKeys(_)
+        | Subst::ArrayLen(_)
+        | Subst::ArraySlice(..) => {}
+    }
+}
+
+/// Visit the operands of `arith`.
+pub fn walk_arith_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, arith: &mut Arith<'a>) {
+    match arith {
+        Arith::Add(l, r)
+        | Arith::Sub(l, r)
+        | Arith::Mul(l, r)
+        | Arith::Div(l, r)
+        | Arith::Rem(l, r)
+        | Arith::Pow(l, r)
+        | Arith::Lt(l, r)
+        | Arith::Le(l, r)
+        | Arith::Gt(l, r)
+        | Arith::Ge(l, r)
+        | Arith::Eq(l, r)
+        | Arith::Ne(l, r)
+        | Arith::BitAnd(l, r)
+        | Arith::BitOr(l, r)
+        | Arith::BitXor(l, r)
+        | Arith::LogAnd(l, r)
+        | Arith::LogOr(l, r)
+        | Arith::Shl(l, r)
+        | Arith::Shr(l, r)
+        | Arith::Comma(l, r) => {
+            v.visit_arith_mut(l);
+            v.visit_arith_mut(r);
+        }
+        Arith::Pos(e)
+        | Arith::Neg(e)
+        | Arith::LogNot(e)
+        | Arith::BitNot(e)
+        | Arith::Assign(_, e)
+        | Arith::Elem(_, e)
+        | Arith::ElemPreInc(_, e)
+        | Arith::ElemPostInc(_, e)
+        | Arith::ElemPreDec(_, e)
+        | Arith::ElemPostDec(_, e) => v.visit_arith_mut(e),
+        Arith::ElemAssign(_, index, e) => {
+            v.visit_arith_mut(index);
+            v.visit_arith_mut(e);
+        }
+        Arith::Ternary(c, t, f) => {
+            v.visit_arith_mut(c);
+            v.visit_arith_mut(t);
+            v.visit_arith_mut(f);
+        }
+        Arith::Var(_)
+        | Arith::Lit(_)
+        | Arith::PreInc(_)
+        | Arith::PostInc(_)
+        | Arith::PreDec(_)
+        | Arith::PostDec(_) => {}
+    }
+}
+
+/// Visit the operands of `expr`.
+pub fn walk_cond_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, expr: &mut CondExpr<'a>) {
+    match expr {
+        CondExpr::Unary(_, w) | CondExpr::Word(w) => v.visit_word_mut(w),
+        CondExpr::Binary(l, _, r) => {
+            v.visit_word_mut(l);
+            v.visit_word_mut(r);
+        }
+        CondExpr::Not(e) | CondExpr::Group(e) => v.visit_cond_mut(e),
+        CondExpr::And(l, r) | CondExpr::Or(l, r) => {
+            v.visit_cond_mut(l);
+            v.visit_cond_mut(r);
+        }
+    }
+}
+
+/// Visit the target word or heredoc body of `redir`.
+pub fn walk_redir_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, redir: &mut Redir<'a>) {
+    match redir {
+        Redir::Read(_, w)
+        | Redir::Write(_, w)
+        | Redir::Append(_, w)
+        | Redir::ReadWrite(_, w)
+        | Redir::Clobber(_, w)
+        | Redir::DupRead(_, w)
+        | Redir::DupWrite(_, w)
+        | Redir::HereString(w)
+        | Redir::WriteAll(w)
+        | Redir::AppendAll(w) => v.visit_word_mut(w),
+        Redir::Heredoc(_, doc) => v.visit_heredoc_body_mut(&mut doc.body),
+    }
+}
+
+/// Visit the atoms of an interpolated heredoc body.
+pub fn walk_heredoc_body_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    body: &mut HeredocBody<'a>,
+) {
+    match body {
+        HeredocBody::Literal(_) => {}
+        HeredocBody::Interpolated(atoms) => {
+            for atom in atoms {
+                v.visit_atom_mut(atom);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// Counts simple commands, wherever they are nested.
+    #[derive(Default)]
+    struct CountCmds(usize);
+
+    impl Visit<'_> for CountCmds {
+        fn visit_simple_cmd(&mut self, cmd: &SimpleCmd<'_>) {
+            self.0 += 1;
+            walk_simple_cmd(self, cmd);
+        }
+    }
+
+    fn count_cmds(input: &str) -> usize {
+        let cmds = Parser::new(input).parse().unwrap();
+        let mut v = CountCmds::default();
+        v.visit_cmds(&cmds);
+        v.0
+    }
+
+    #[test]
+    fn visits_nested_commands() {
+        assert_eq!(count_cmds("a | b && c"), 3);
+        assert_eq!(count_cmds("if a; then b; elif c; then d; else e; fi"), 5);
+        assert_eq!(count_cmds("f() { a; (b); }; case x in y) c ;; esac"), 3);
+        assert_eq!(count_cmds("echo $(a; echo \"$(b)\") <(c) >(d)"), 6);
+        assert_eq!(count_cmds("x=${y:-$(a)} cat <<EOF\n$(b)\nEOF"), 3);
+        assert_eq!(count_cmds("coproc { a; }"), 1);
+    }
+
+    #[test]
+    fn visits_arith_vars() {
+        #[derive(Default)]
+        struct ArithVars(Vec<String>);
+        impl<'a> Visit<'a> for ArithVars {
+            fn visit_arith(&mut self, arith: &Arith<'a>) {
+                if let Arith::Var(name) = arith {
+                    self.0.push(name.to_string());
+                }
+                walk_arith(self, arith);
+            }
+        }
+        let cmds = Parser::new("for ((i = a; i < b; i++)); do echo $((c ? d : -e)); done")
+            .parse()
+            .unwrap();
+        let mut v = ArithVars::default();
+        v.visit_cmds(&cmds);
+        assert_eq!(v.0, ["a", "i", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn visit_mut_rewrites_params() {
+        struct Rename;
+        impl<'a> VisitMut<'a> for Rename {
+            fn visit_param_mut(&mut self, param: &mut Param<'a>) {
+                if *param == Param::Var("old".into()) {
+                    *param = Param::Var("new".into());
+                }
+            }
+        }
+        let mut a = Parser::new("echo $old \"${old%x}\" $keep").parse().unwrap();
+        let b = Parser::new("echo $new \"${new%x}\" $keep").parse().unwrap();
+        Rename.visit_cmds_mut(&mut a);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn visit_mut_reaches_every_span() {
+        struct Zero;
+        impl VisitMut<'_> for Zero {
+            fn visit_span_mut(&mut self, span: &mut Span) {
+                *span = Span::default();
+            }
+        }
+        struct NonZero(usize);
+        impl Visit<'_> for NonZero {
+            fn visit_span(&mut self, span: &Span) {
+                if *span != Span::default() {
+                    self.0 += 1;
+                }
+            }
+        }
+        let input = "while a; do case $x in y) b | c ;; esac; done > log; if d; then e; fi";
+        let mut cmds = Parser::new(input).parse().unwrap();
+        let mut before = NonZero(0);
+        before.visit_cmds(&cmds);
+        assert!(before.0 > 0);
+        Zero.visit_cmds_mut(&mut cmds);
+        let mut after = NonZero(0);
+        after.visit_cmds(&cmds);
+        assert_eq!(after.0, 0);
+    }
+}